@@ -17,4 +17,19 @@ class BackendEventDispatcher(val reactContext: ReactApplicationContext) : EventD
     override fun sendTickUpdate(jsonString: String) {
         EventEmitter.emitTickUpdate(jsonString)
     }
+
+    // This is called from rust side
+    override fun sendRsTransferProgress(jsonString: String) {
+        EventEmitter.emitRsTransferProgress(jsonString)
+    }
+
+    // This is called from rust side
+    override fun sendDbMirrorPushFailed(jsonString: String) {
+        EventEmitter.emitDbMirrorPushFailed(jsonString)
+    }
+
+    // This is called from rust side
+    override fun sendDbSessionTimeout(jsonString: String) {
+        EventEmitter.emitDbSessionTimeout(jsonString)
+    }
 }
\ No newline at end of file
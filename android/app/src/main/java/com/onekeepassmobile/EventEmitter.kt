@@ -17,6 +17,9 @@ object EventEmitter {
     private const val EVENT_ENTRY_OTP_UPDATE = "onEntryOtpUpdate"
     private const val EVENT_APP_BECOMES_ACTIVE = "onAppBecomingActive"
     private const val EVENT_APP_BECOMES_INACTIVE = "onAppBecomingInActive"
+    private const val EVENT_RS_TRANSFER_PROGRESS = "onRsTransferProgress"
+    private const val EVENT_DB_MIRROR_PUSH_FAILED = "onDbMirrorPushFailed"
+    private const val EVENT_DB_SESSION_TIMEOUT = "onDbSessionTimeout"
 
 
     fun initialize(reactContext: ReactApplicationContext) {
@@ -57,6 +60,21 @@ object EventEmitter {
                 .emit(EVENT_ON_TIME_TICK, jsonString)
     }
 
+    fun emitRsTransferProgress(jsonString: String) {
+        reactApplicationContext.getJSModule(RCTDeviceEventEmitter::class.java)
+                .emit(EVENT_RS_TRANSFER_PROGRESS, jsonString)
+    }
+
+    fun emitDbMirrorPushFailed(jsonString: String) {
+        reactApplicationContext.getJSModule(RCTDeviceEventEmitter::class.java)
+                .emit(EVENT_DB_MIRROR_PUSH_FAILED, jsonString)
+    }
+
+    fun emitDbSessionTimeout(jsonString: String) {
+        reactApplicationContext.getJSModule(RCTDeviceEventEmitter::class.java)
+                .emit(EVENT_DB_SESSION_TIMEOUT, jsonString)
+    }
+
     fun emitAppBecomesActive() {
         reactApplicationContext.getJSModule(RCTDeviceEventEmitter::class.java)
             .emit(EVENT_APP_BECOMES_ACTIVE, "{}")
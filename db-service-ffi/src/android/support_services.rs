@@ -7,7 +7,8 @@ use url::Url;
 
 use crate::{
     app_state::AppState,
-    as_api_response, backup,
+    as_api_response,
+    backup::{self, BackupReason},
     commands::{
         self, error_json_str, remove_app_files, result_json_str, CommandArg, InvokeResult,
         ResponseJson,
@@ -42,6 +43,11 @@ impl AndroidSupportServiceExtra {
     }
 
     pub fn invoke(&self, command_name: &str, json_args: &str) -> ResponseJson {
+        // The autofill and passkey calls are also rejected on a db locked on its session timeout
+        if let Err(e) = crate::session_timeout::check_and_touch_from_args(command_name, json_args) {
+            return InvokeResult::<()>::with_error(e).json_str();
+        }
+
         let r = match command_name {
             "autofill_filtered_entries" => self.autofill_filtered_entries(json_args),
             "complete_autofill" => self.complete_autofill(json_args),
@@ -102,7 +108,7 @@ impl AndroidSupportServiceExtra {
                 )?;
 
                 // Need to create the backup file for the newly created database
-                let mut new_db_bk_file =
+                let (mut new_db_bk_file, new_db_bk_file_name) =
                     backup::generate_and_open_backup_file(&new_full_file_name_uri, &file_name)?;
 
                 modified_bk_file_reader.rewind()?;
@@ -110,6 +116,12 @@ impl AndroidSupportServiceExtra {
                 // Copies data from the latest modification of old db that we could not save to the new db's backup
                 std::io::copy(&mut modified_bk_file_reader, &mut new_db_bk_file)?;
                 let _ = new_db_bk_file.sync_all();
+                backup::record_backup(
+                    &new_full_file_name_uri,
+                    &new_db_bk_file_name,
+                    BackupReason::OnCreate,
+                    None,
+                );
 
                 // For now we remove all reference of the db file that failed to save
                 remove_app_files(&old_full_file_name_uri);
@@ -181,6 +193,10 @@ impl AndroidSupportServiceExtra {
                 // sync_all ensures the file is created and synced in case of dropbbox and one drive
                 let _ = file.sync_all();
 
+                if let Some(bk) = backup_file_name.as_deref() {
+                    backup::record_backup(&full_file_name_uri, bk, BackupReason::OnCreate, None);
+                }
+
                 r
             }
             Ok(_) => Err(OkpError::UnexpectedError(
@@ -288,6 +304,12 @@ impl AndroidSupportServiceExtra {
         let inner_fn = || -> OkpResult<db_service::EntrySearchResult> {
             let (db_key,) = parse_command_args_or_err!(json_args, DbKey { db_key });
 
+            if !AppState::db_allowed_in_autofill(&db_key) {
+                return Err(OkpError::DataError(
+                    "This database is not allowed to be used in autofill. Please change it in the database settings",
+                ));
+            }
+
             let identifiers =
                 AndroidApiCallbackImpl::api_service().autofill_client_app_url_info()?;
 
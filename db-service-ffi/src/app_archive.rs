@@ -0,0 +1,402 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use data_encoding::BASE64;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use onekeepass_core::db_service::service_util;
+
+use crate::{
+    app_preference::Preference,
+    app_state::AppState,
+    backup::{self, BackupManifestEntry},
+    bundle_crypto::{self, BundleFormat, KdfParams},
+    commands::{CommandArg, ExportDataInfo},
+    parse_command_args_or_err,
+    remote_storage::{self, ImportedConnectionConfigs},
+    util, OkpError, OkpResult,
+};
+
+// All app data required to move to another device are exported to a single password encrypted archive.
+// The preference, remote connections (with the sftp private keys), key files and optionally the backups are included.
+// The biometric credentials and the app lock PIN are kept in the device's secure storage and are not included.
+
+const ARCHIVE_FORMAT: BundleFormat = BundleFormat {
+    name: "OneKeePass-AppArchive",
+    version: 1,
+    invalid_file_error: "The file is not a valid app archive",
+    newer_version_error: "The app archive is created by a newer version of the app",
+    decryption_error: "Invalid password or the app archive is corrupted",
+};
+
+const APP_ARCHIVE_FILE_NAME: &str = "OneKeePass-AppArchive.okparchive";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedFile {
+    file_name: String,
+    // Base64 encoded content of the file
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedBackup {
+    entry: BackupManifestEntry,
+    // Base64 encoded content of the backup file
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedDbBackups {
+    db_key: String,
+    backups: Vec<ArchivedBackup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ArchiveContent {
+    app_version: String,
+    // The os of the device where the archive is created
+    platform: String,
+    // In seconds
+    created: i64,
+    // The json content of 'preference.json'
+    preference: String,
+    // The connections bundle (see 'config_bundle' module) encrypted with the archive password
+    connections_bundle: Option<String>,
+    key_files: Vec<ArchivedFile>,
+    #[serde(default)]
+    backups: Vec<ArchivedDbBackups>,
+}
+
+impl ArchiveContent {
+    fn encrypt(&self, password: &str, kdf: KdfParams) -> OkpResult<Vec<u8>> {
+        let plain_data = serde_json::to_vec(self)?;
+        bundle_crypto::encrypt(&ARCHIVE_FORMAT, &plain_data, password, kdf)
+    }
+
+    fn decrypt(archive_data: &[u8], password: &str) -> OkpResult<Self> {
+        let plain_data = bundle_crypto::decrypt(&ARCHIVE_FORMAT, archive_data, password)?;
+        Ok(serde_json::from_slice(&plain_data)?)
+    }
+
+    // Parses the archived preference. Any older preference version is converted as done for the preference file
+    fn preference(&self) -> OkpResult<Preference> {
+        Preference::from_archived_json(&self.preference).map_err(|e| {
+            debug!("Parsing the archived preference failed {}", e);
+            OkpError::DataError("The preference in the app archive is not valid")
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ArchivedDatabase {
+    db_key: String,
+    file_name: String,
+    // The local file uris are device specific and the user needs to pick the db file again in this device
+    needs_remap: bool,
+}
+
+// The content of an archive shown to the user before the import
+#[derive(Serialize, Debug)]
+pub(crate) struct AppArchiveInfo {
+    app_version: String,
+    platform: String,
+    created: i64,
+    databases: Vec<ArchivedDatabase>,
+    connections_included: bool,
+    key_files: Vec<String>,
+    backups_included: bool,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct ImportedAppArchive {
+    databases: usize,
+    // The dbs that are not remapped to this device and are removed from the recent list
+    dropped_db_keys: Vec<String>,
+    key_files: usize,
+    // The archived key files that are not imported as a different key file with the same name is in this device
+    conflicting_key_files: Vec<String>,
+    connections: Option<ImportedConnectionConfigs>,
+    backups: usize,
+}
+
+// Remote storage db_keys (Sftp and Webdav) work in any device once their connections are imported.
+// Local file uris (e.g content:// in Android and file:// in iOS) and git repository paths are device specific
+fn is_portable_db_key(db_key: &str) -> bool {
+    remote_storage::is_portable_remote_db_key(db_key)
+}
+
+fn remap_db_key(db_key: &str, db_key_remaps: &HashMap<String, String>) -> Option<String> {
+    match db_key_remaps.get(db_key) {
+        Some(new_db_key) => Some(new_db_key.clone()),
+        None if is_portable_db_key(db_key) => Some(db_key.to_string()),
+        None => None,
+    }
+}
+
+fn archived_file(path: &Path) -> OkpResult<ArchivedFile> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or(OkpError::DataError("Invalid file name"))?;
+    Ok(ArchivedFile {
+        file_name,
+        content: BASE64.encode(&fs::read(path)?),
+    })
+}
+
+fn decode(content: &str) -> OkpResult<Vec<u8>> {
+    BASE64
+        .decode(content.as_bytes())
+        .map_err(|_| OkpError::DataError("Invalid file content in the app archive"))
+}
+
+fn archived_backups(preference: &Preference) -> OkpResult<Vec<ArchivedDbBackups>> {
+    let mut all_backups = vec![];
+    for recent in preference.recent_dbs_info_ref() {
+        let db_key = &recent.db_file_path;
+        let mut backups = vec![];
+        for (entry, path) in backup::backup_files_with_entries(db_key) {
+            backups.push(ArchivedBackup {
+                entry,
+                content: BASE64.encode(&fs::read(path)?),
+            });
+        }
+        if !backups.is_empty() {
+            all_backups.push(ArchivedDbBackups {
+                db_key: db_key.clone(),
+                backups,
+            });
+        }
+    }
+    Ok(all_backups)
+}
+
+pub(crate) fn export_app_archive(json_args: &str) -> OkpResult<ExportDataInfo> {
+    let (archive_password, include_backups) = parse_command_args_or_err!(
+        json_args,
+        AppArchiveExportArg {
+            archive_password,
+            include_backups
+        }
+    );
+
+    if archive_password.is_empty() {
+        return Err(OkpError::DataError(
+            "A password is required to export the app archive",
+        ));
+    }
+
+    // The dbs hidden under the duress mode are not included
+    let preference = AppState::preference_view();
+
+    // The connections are decrypted using the device specific key and encrypted again using the archive password
+    let connections_bundle = remote_storage::export_all_connection_configs(&archive_password)?
+        .map(|data| String::from_utf8_lossy(&data).to_string());
+
+    let key_files = util::list_dir_files(AppState::key_files_dir_path())
+        .iter()
+        .map(|f| archived_file(Path::new(f)))
+        .collect::<OkpResult<Vec<_>>>()?;
+
+    let backups = if include_backups {
+        archived_backups(&preference)?
+    } else {
+        vec![]
+    };
+
+    let content = ArchiveContent {
+        app_version: AppState::app_version().to_string(),
+        platform: std::env::consts::OS.to_string(),
+        created: service_util::now_utc_seconds(),
+        preference: serde_json::to_string_pretty(&preference)?,
+        connections_bundle,
+        key_files,
+        backups,
+    };
+
+    let data = content.encrypt(&archive_password, KdfParams::default())?;
+
+    // Any previously exported files are removed
+    let _ = util::clean_export_data_dir();
+
+    let export_file_path = AppState::export_data_dir_path().join(APP_ARCHIVE_FILE_NAME);
+    fs::write(&export_file_path, data)?;
+
+    let export_file_path_opt = export_file_path.to_str().map(|s| s.to_string());
+    let exported_data_full_file_name = if cfg!(target_os = "ios") {
+        crate::ios::to_ios_file_uri_str(&export_file_path_opt)
+    } else {
+        export_file_path_opt
+    };
+
+    Ok(ExportDataInfo {
+        full_file_name_uri: None,
+        file_name: Some(APP_ARCHIVE_FILE_NAME.into()),
+        exported_data_full_file_name,
+    })
+}
+
+// The 'archive_file_name' is the full path of the picked archive file copied to the temp dir
+// (see PickedFileHandler::AppArchiveFile)
+pub(crate) fn read_app_archive(json_args: &str) -> OkpResult<AppArchiveInfo> {
+    let (archive_file_name, archive_password) = parse_command_args_or_err!(
+        json_args,
+        AppArchiveArg {
+            archive_file_name,
+            archive_password
+        }
+    );
+
+    let content = ArchiveContent::decrypt(&fs::read(&archive_file_name)?, &archive_password)?;
+
+    let databases = content
+        .preference()?
+        .recent_dbs_info()
+        .into_iter()
+        .map(|r| ArchivedDatabase {
+            needs_remap: !is_portable_db_key(&r.db_file_path),
+            db_key: r.db_file_path,
+            file_name: r.file_name,
+        })
+        .collect();
+
+    Ok(AppArchiveInfo {
+        app_version: content.app_version,
+        platform: content.platform,
+        created: content.created,
+        databases,
+        connections_included: content.connections_bundle.is_some(),
+        key_files: content.key_files.into_iter().map(|k| k.file_name).collect(),
+        backups_included: !content.backups.is_empty(),
+    })
+}
+
+// The arg 'db_key_remaps' has the db_keys of this device for the archived local db_keys.
+// These are formed from the db files picked by the user in this device
+pub(crate) fn import_app_archive(json_args: &str) -> OkpResult<ImportedAppArchive> {
+    let (archive_file_name, archive_password, db_key_remaps) = parse_command_args_or_err!(
+        json_args,
+        AppArchiveImportArg {
+            archive_file_name,
+            archive_password,
+            db_key_remaps
+        }
+    );
+
+    let content = ArchiveContent::decrypt(&fs::read(&archive_file_name)?, &archive_password)?;
+    let archived_preference = content.preference()?;
+
+    let mut imported = ImportedAppArchive::default();
+
+    // Connections are imported first so that the remote db_keys in the preference are valid
+    if let Some(ref bundle) = content.connections_bundle {
+        imported.connections = Some(remote_storage::import_all_connection_configs(
+            bundle.as_bytes(),
+            &archive_password,
+        )?);
+    }
+
+    let key_files_dir = AppState::key_files_dir_path();
+    for key_file in &content.key_files {
+        let Some(file_name) = Path::new(&key_file.file_name).file_name() else {
+            continue;
+        };
+        let content = decode(&key_file.content)?;
+        let key_file_path = key_files_dir.join(file_name);
+        if key_file_path.exists() {
+            // An existing key file is never overwritten as the dbs of this device may use it
+            if fs::read(&key_file_path)? != content {
+                info!("Key file {:?} is not imported as a different file exists", file_name);
+                imported
+                    .conflicting_key_files
+                    .push(file_name.to_string_lossy().to_string());
+            }
+            continue;
+        }
+        fs::write(key_file_path, content)?;
+        imported.key_files += 1;
+    }
+
+    for db_backups in content.backups {
+        let Some(db_key) = remap_db_key(&db_backups.db_key, &db_key_remaps) else {
+            continue;
+        };
+        let backups = db_backups
+            .backups
+            .into_iter()
+            .map(|b| Ok((b.entry, decode(&b.content)?)))
+            .collect::<OkpResult<Vec<_>>>()?;
+        imported.backups += backup::restore_backup_files(&db_key, backups)?;
+    }
+
+    imported.dropped_db_keys = archived_preference
+        .recent_dbs_info_ref()
+        .iter()
+        .filter(|r| remap_db_key(&r.db_file_path, &db_key_remaps).is_none())
+        .map(|r| r.db_file_path.clone())
+        .collect();
+
+    let preference = archived_preference.remapped_for_import(&AppState::preference_clone(), |k| {
+        remap_db_key(k, &db_key_remaps)
+    });
+    imported.databases = preference.recent_dbs_info_ref().len();
+    AppState::replace_preference(preference);
+
+    // The temp copy of the picked archive file is not required after the import
+    let _ = fs::remove_file(&archive_file_name);
+
+    info!(
+        "Imported the app archive with {} databases, {} key files and {} backups",
+        imported.databases, imported.key_files, imported.backups
+    );
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_archive_encrypt_decrypt_and_db_key_remap() {
+        let content = ArchiveContent {
+            app_version: "0.16.0".into(),
+            platform: "android".into(),
+            preference: "{}".into(),
+            key_files: vec![ArchivedFile {
+                file_name: "MyKey.keyx".into(),
+                content: BASE64.encode(b"key file content"),
+            }],
+            ..Default::default()
+        };
+
+        // Small kdf params are used to keep the test fast
+        let data = content.encrypt("archive pwd", KdfParams::new(64, 1, 1)).unwrap();
+
+        let decrypted = ArchiveContent::decrypt(&data, "archive pwd").unwrap();
+        assert_eq!("android", decrypted.platform);
+        assert_eq!(b"key file content".to_vec(), decode(&decrypted.key_files[0].content).unwrap());
+
+        assert!(ArchiveContent::decrypt(&data, "wrong pwd").is_err());
+
+        let remaps = HashMap::from([(
+            "content://com.android.externalstorage.documents/document/primary%3ATest1.kdbx".to_string(),
+            "file:///private/var/mobile/Documents/Test1.kdbx".to_string(),
+        )]);
+
+        assert_eq!(
+            Some("file:///private/var/mobile/Documents/Test1.kdbx".to_string()),
+            remap_db_key(
+                "content://com.android.externalstorage.documents/document/primary%3ATest1.kdbx",
+                &remaps
+            )
+        );
+        let sftp_key = "Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/Test2.kdbx";
+        assert_eq!(Some(sftp_key.to_string()), remap_db_key(sftp_key, &remaps));
+        assert_eq!(None, remap_db_key("content://other/Test3.kdbx", &remaps));
+        assert_eq!(
+            None,
+            remap_db_key("Git-264226dc-be96-462a-a386-79adb6291ad7-/Test4.kdbx", &remaps)
+        );
+    }
+}
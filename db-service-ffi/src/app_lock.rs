@@ -1,37 +1,250 @@
-use log::{self, debug};
-use onekeepass_core::{db_service, error};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use log::{self, debug, info};
+use onekeepass_core::{
+    db_service::{self, service_util},
+    error,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{app_state::AppState, udl_types::SecureKeyOperationError, OkpResult};
 use crate::{biometric_auth, ios, key_secure};
 
+use crate::commands::remove_app_files;
 use crate::util::{remove_dir_contents, remove_files};
 
 //////////
 
-// Stores the encrypted pin for later authentication
+// Stores the hash of the pin for later authentication
 pub fn pin_entered(pin: usize) -> OkpResult<()> {
-    let app_lock_credential = AppLockCredential { pin };
-    let r = app_lock_credential.encrypt_and_store();
+    secret_entered(&pin.to_string(), AppLockSecretKind::Pin)
+}
+
+// Alphanumeric passphrase used instead of the numeric PIN
+pub fn passphrase_entered(passphrase: &str) -> OkpResult<()> {
+    if passphrase.chars().count() < PASSPHRASE_MIN_LENGTH {
+        return Err(error::Error::DataError(
+            "The passphrase should have at least 4 characters",
+        ));
+    }
+    secret_entered(passphrase, AppLockSecretKind::Passphrase)
+}
+
+fn secret_entered(secret: &str, kind: AppLockSecretKind) -> OkpResult<()> {
+    let salt = install_salt()?;
+
+    if AppState::app_lock_preference().duress_pin_enabled() {
+        if let Ok(duress) = AppLockCredential::read_stored(APP_LOCK_DURESS_PIN_TAG) {
+            if duress.matches(secret, &salt) {
+                return Err(error::Error::DataError(
+                    "The app lock PIN should be different from the duress PIN",
+                ));
+            }
+        }
+    }
+
+    let app_lock_credential = AppLockCredential::new(secret, kind, &salt)?;
+    app_lock_credential.encrypt_and_store(APP_LOCK_PIN_TAG)?;
 
     // Need to ensure that the preference is enabled and persisted accordingly
-    AppState::update_app_lock_with_pin_enabled(true);
-    r
+    AppState::update_app_lock_with_secret(kind);
+    Ok(())
 }
 
 pub fn pin_removed() -> OkpResult<()> {
-    let r = AppLockCredential::remove_app_lock_credential();
+    let r = AppLockCredential::remove_app_lock_credential(APP_LOCK_PIN_TAG);
+    // The duress PIN can be used only along with the app lock PIN
+    let _ = duress_pin_removed();
     // Need to ensure that the preference is disabled and persisted accordingly
     AppState::update_app_lock_with_pin_enabled(false);
     r
 }
 
-pub fn pin_verify(pin: usize) -> OkpResult<bool> {
-    let app_lock_credential = AppLockCredential { pin };
-    let r = app_lock_credential.verify();
+// Stores a second PIN. Unlocking the app with this PIN hides the dbs flagged with 'hide_under_duress'
+pub fn duress_pin_entered(pin: usize) -> OkpResult<()> {
+    let secret = pin.to_string();
+    let salt = install_salt()?;
+
+    let stored = AppLockCredential::read_stored(APP_LOCK_PIN_TAG).map_err(|_| {
+        error::Error::DataError("The app lock PIN should be set before setting the duress PIN")
+    })?;
+    if stored.matches(&secret, &salt) {
+        return Err(error::Error::DataError(
+            "The duress PIN should be different from the app lock PIN",
+        ));
+    }
+
+    let duress = AppLockCredential::new(&secret, AppLockSecretKind::Pin, &salt)?;
+    duress.encrypt_and_store(APP_LOCK_DURESS_PIN_TAG)?;
+
+    AppState::update_app_lock_with_duress(true);
+    Ok(())
+}
+
+pub fn duress_pin_removed() -> OkpResult<()> {
+    let r = AppLockCredential::remove_app_lock_credential(APP_LOCK_DURESS_PIN_TAG);
+    AppState::update_app_lock_with_duress(false);
     r
 }
 
+// Kept for the callers that expect only the verification result
+// The failed attempts are counted in the same way as in 'pin_verify_attempt'. Any wait required before the next
+// attempt and the app reset are returned as errors as a 'false' result would not tell these to the user
+pub fn pin_verify(pin: usize) -> OkpResult<bool> {
+    let r = pin_verify_attempt(pin)?;
+    if r.app_reset {
+        return Err(error::Error::DataError(
+            "Too many failed PIN attempts and the app is reset",
+        ));
+    }
+    if !r.verified && r.retry_after > 0 && r.remaining_attempts > 0 {
+        return Err(error::Error::UnexpectedError(format!(
+            "Too many failed PIN attempts. Please try again after {} seconds",
+            (r.retry_after + 999) / 1000
+        )));
+    }
+    Ok(r.verified)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PinVerifyResult {
+    verified: bool,
+    // Number of failed attempts remaining before the app is reset
+    remaining_attempts: usize,
+    // The next attempt is allowed only after these milli seconds. 0 when no wait is required
+    retry_after: i64,
+    // Set when the allowed attempts are exceeded and the app is reset
+    app_reset: bool,
+    // Set in the autofill extension when the allowed attempts are exceeded. The app data can be removed
+    // only by the main app and the reset is done on the next attempt there
+    app_reset_pending: bool,
+}
+
+impl PinVerifyResult {
+    fn success(attempts_allowed: usize) -> Self {
+        Self {
+            verified: true,
+            remaining_attempts: attempts_allowed,
+            retry_after: 0,
+            app_reset: false,
+            app_reset_pending: false,
+        }
+    }
+
+    fn attempts_exceeded() -> Self {
+        Self {
+            verified: false,
+            remaining_attempts: 0,
+            retry_after: 0,
+            app_reset: true,
+            app_reset_pending: false,
+        }
+    }
+
+    pub(crate) fn verified(&self) -> bool {
+        self.verified
+    }
+
+    pub(crate) fn app_reset(&self) -> bool {
+        self.app_reset
+    }
+
+    pub(crate) fn app_reset_pending(&self) -> bool {
+        self.app_reset_pending
+    }
+}
+
+// Verifies the PIN and counts the failed attempts. The count is stored along with the PIN in the encrypted
+// credential so that it can not be reset by changing the app files. After a few failed attempts each attempt
+// is allowed only after an exponentially increasing delay and the app is reset once 'attempts_allowed' is exceeded
+pub fn pin_verify_attempt(pin: usize) -> OkpResult<PinVerifyResult> {
+    verify_attempt(&pin.to_string(), false)
+}
+
+pub fn passphrase_verify_attempt(passphrase: &str) -> OkpResult<PinVerifyResult> {
+    verify_attempt(passphrase, false)
+}
+
+// Used in the iOS autofill extension where the app is not reset when the allowed attempts are exceeded
+pub(crate) fn extension_pin_verify_attempt(pin: usize) -> OkpResult<PinVerifyResult> {
+    verify_attempt(&pin.to_string(), true)
+}
+
+pub(crate) fn extension_passphrase_verify_attempt(passphrase: &str) -> OkpResult<PinVerifyResult> {
+    verify_attempt(passphrase, true)
+}
+
+fn verify_attempt(secret: &str, in_app_extension: bool) -> OkpResult<PinVerifyResult> {
+    let mut stored = AppLockCredential::read_stored(APP_LOCK_PIN_TAG)?;
+    let salt = install_salt()?;
+    let app_lock_preference = AppState::app_lock_preference();
+    let attempts_allowed = app_lock_preference.attempts_allowed();
+    let now = service_util::now_utc_milli_seconds();
+
+    // When the attempts are already exceeded (in the autofill extension), the reset is done in 'attempt' below
+    if !stored.attempts_exceeded(attempts_allowed) {
+        if let Some(r) = stored.waiting_result(attempts_allowed, now) {
+            return Ok(r);
+        }
+
+        if app_lock_preference.duress_pin_enabled() && duress_pin_matches(secret, &salt) {
+            // The result is same as the one for the app lock PIN so that the UI shows no difference
+            if stored.reset_failed_attempts() {
+                stored.encrypt_and_store(APP_LOCK_PIN_TAG)?;
+            }
+            enter_duress_mode(app_lock_preference.duress_wipe());
+            return Ok(PinVerifyResult::success(attempts_allowed));
+        }
+    }
+
+    let (mut result, changed) = stored.attempt(secret, &salt, attempts_allowed, now);
+
+    if changed {
+        // In case of the app reset, the stored credential is removed next
+        stored.encrypt_and_store(APP_LOCK_PIN_TAG)?;
+    }
+
+    if result.app_reset {
+        if in_app_extension {
+            info!("The allowed PIN attempts are exceeded and the app will be reset when it is opened");
+            result.app_reset = false;
+            result.app_reset_pending = true;
+        } else {
+            info!("The allowed PIN attempts are exceeded and the app is reset");
+            app_reset()?;
+        }
+    } else if result.verified {
+        // Any hidden dbs are shown again
+        AppState::set_duress_active(false);
+    }
+
+    Ok(result)
+}
+
+fn duress_pin_matches(secret: &str, salt: &[u8]) -> bool {
+    AppLockCredential::read_stored(APP_LOCK_DURESS_PIN_TAG)
+        .map(|d| d.matches(secret, salt))
+        .unwrap_or(false)
+}
+
+// The dbs flagged with 'hide_under_duress' are removed from the app's view (see 'Preference::view') till
+// the app is unlocked with the app lock PIN. When 'wipe' is true, all app data of these dbs are removed
+fn enter_duress_mode(wipe: bool) {
+    AppState::set_duress_active(true);
+
+    for db_key in AppState::duress_hidden_db_keys() {
+        // Any opened hidden db is closed
+        let _ = db_service::close_kdbx(&db_key);
+        AppState::remove_db_activity(&db_key);
+
+        if wipe {
+            // Removes the backups, stored credentials, autofill copies and the recent db info of this db
+            remove_app_files(&db_key);
+        }
+    }
+    debug!("Duress mode is entered");
+}
+
 // Called to remove all app dirs and files to bring it to a default state
 pub fn app_reset() -> OkpResult<()> {
     for db in AppState::recent_dbs_info() {
@@ -43,7 +256,8 @@ pub fn app_reset() -> OkpResult<()> {
     }
 
     // Remove all stored app lock credentials (PIN)
-    let _r = AppLockCredential::remove_app_lock_credential();
+    let _r = AppLockCredential::remove_app_lock_credential(APP_LOCK_PIN_TAG);
+    let _r = AppLockCredential::remove_app_lock_credential(APP_LOCK_DURESS_PIN_TAG);
 
     // Deletes contents of backups/history dir including sub dirs found under this dir
     let _ = remove_dir_contents(AppState::backup_history_dir_path());
@@ -80,14 +294,265 @@ pub fn app_reset() -> OkpResult<()> {
 // The encrypted data is stored under this key
 const APP_LOCK_PIN_TAG: &str = "OKP_APP_LOCK_PIN_KEY";
 
-#[derive(Serialize, Deserialize, PartialEq)]
+// The encrypted duress PIN credential is stored under this key
+const APP_LOCK_DURESS_PIN_TAG: &str = "OKP_APP_LOCK_DURESS_PIN_KEY";
+
+// The failed attempts till which no delay is required before the next attempt
+const PIN_ATTEMPTS_WITHOUT_DELAY: usize = 3;
+
+// The delay after the first delayed attempt. This doubles with each failed attempt
+const PIN_ATTEMPT_BASE_DELAY: i64 = 30_000;
+
+const PIN_ATTEMPT_MAX_DELAY: i64 = 15 * 60_000;
+
+// The random salt used in hashing the PIN or passphrase. This is generated once per install and
+// kept in the key store so that it is not changed when the PIN is changed
+const APP_LOCK_SALT_TAG: &str = "OKP_APP_LOCK_SALT_KEY";
+
+const SALT_SIZE: usize = 32;
+const HASH_SIZE: usize = 32;
+
+// Argon2id parameters for the new hashes. These are kept lower than the values used for the export
+// bundles as the hash is calculated on each unlock. The values used are stored along with the hash
+const KDF_MEMORY_KIB: u32 = 19 * 1024;
+const KDF_ITERATIONS: u32 = 2;
+const KDF_PARALLELISM: u32 = 1;
+
+const PASSPHRASE_MIN_LENGTH: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub(crate) enum AppLockSecretKind {
+    #[default]
+    Pin,
+    Passphrase,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SecretKdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Default for SecretKdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: KDF_MEMORY_KIB,
+            iterations: KDF_ITERATIONS,
+            parallelism: KDF_PARALLELISM,
+        }
+    }
+}
+
+impl SecretKdfParams {
+    // Returns the hex encoded hash
+    fn hash(&self, secret: &str, salt: &[u8]) -> OkpResult<String> {
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(HASH_SIZE),
+        )
+        .map_err(|e| error::Error::UnexpectedError(format!("Invalid kdf params {}", e)))?;
+
+        let mut hash = [0u8; HASH_SIZE];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(secret.as_bytes(), salt, &mut hash)
+            .map_err(|e| error::Error::UnexpectedError(format!("Hashing the secret failed {}", e)))?;
+
+        Ok(hex::encode(hash))
+    }
+}
+
+// The key store entries of the app lock credentials that are found and their sizes
+// The per install salt is not included as it is kept till the app is uninstalled
+pub(crate) fn key_store_entries() -> Vec<(String, usize)> {
+    [APP_LOCK_PIN_TAG, APP_LOCK_DURESS_PIN_TAG]
+        .iter()
+        .filter_map(|tag| key_secure::keystore_get_value(tag).map(|v| (tag.to_string(), v.len())))
+        .collect()
+}
+
+// Gets the per install salt and generates one on the first use
+fn install_salt() -> OkpResult<Vec<u8>> {
+    if let Some(salt) = key_secure::keystore_get_value(APP_LOCK_SALT_TAG) {
+        return Ok(salt);
+    }
+
+    let mut salt = vec![0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    if !key_secure::keystore_insert_or_update(APP_LOCK_SALT_TAG, &salt) {
+        return Err(error::Error::SecureKeyOperationError(
+            "Storing the app lock salt in key store failed".into(),
+        ));
+    }
+    debug!("App lock salt is generated and stored");
+    Ok(salt)
+}
+
+// Compares in a time that does not depend on the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Serialize, Deserialize)]
 pub(crate) struct AppLockCredential {
-    pin: usize,
+    // The plain PIN found only in the credential stored by the earlier app versions.
+    // This is replaced by the hash on the next successful unlock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pin: Option<usize>,
+    #[serde(default)]
+    kind: AppLockSecretKind,
+    // Hex encoded Argon2id hash of the PIN or passphrase
+    #[serde(default)]
+    secret_hash: Option<String>,
+    #[serde(default)]
+    kdf: SecretKdfParams,
+    // Failed attempts since the last successful verification
+    #[serde(default)]
+    failed_attempts: usize,
+    // In milli seconds
+    #[serde(default)]
+    last_failed_time: Option<i64>,
+}
+
+// The wait required after these many failed attempts
+fn pin_attempt_delay(failed_attempts: usize) -> i64 {
+    if failed_attempts < PIN_ATTEMPTS_WITHOUT_DELAY {
+        return 0;
+    }
+    let exp = (failed_attempts - PIN_ATTEMPTS_WITHOUT_DELAY).min(16) as u32;
+    PIN_ATTEMPT_BASE_DELAY
+        .saturating_mul(2_i64.pow(exp))
+        .min(PIN_ATTEMPT_MAX_DELAY)
 }
 
 impl AppLockCredential {
-    fn encrypt_and_store(&self) -> OkpResult<()> {
-        // Note: We are using APP_LOCK_PIN_TAG in both secure_enclave_cb_service calls and key store calls
+    fn new(secret: &str, kind: AppLockSecretKind, salt: &[u8]) -> OkpResult<Self> {
+        let kdf = SecretKdfParams::default();
+        let secret_hash = Some(kdf.hash(secret, salt)?);
+        Ok(Self {
+            pin: None,
+            kind,
+            secret_hash,
+            kdf,
+            failed_attempts: 0,
+            last_failed_time: None,
+        })
+    }
+
+    fn matches(&self, secret: &str, salt: &[u8]) -> bool {
+        if let Some(stored_hash) = &self.secret_hash {
+            match self.kdf.hash(secret, salt) {
+                Ok(h) => constant_time_eq(h.as_bytes(), stored_hash.as_bytes()),
+                Err(e) => {
+                    log::error!("Hashing the entered secret failed {}", e);
+                    false
+                }
+            }
+        } else if let Some(pin) = self.pin {
+            constant_time_eq(pin.to_string().as_bytes(), secret.as_bytes())
+        } else {
+            false
+        }
+    }
+
+    // The plain PIN stored by the earlier app versions is replaced with its hash
+    // Returns true if the credential is changed
+    fn migrate_legacy_pin(&mut self, secret: &str, salt: &[u8]) -> bool {
+        if self.pin.is_none() {
+            return false;
+        }
+        let kdf = SecretKdfParams::default();
+        match kdf.hash(secret, salt) {
+            Ok(h) => {
+                self.secret_hash = Some(h);
+                self.kdf = kdf;
+                self.kind = AppLockSecretKind::Pin;
+                self.pin = None;
+                info!("The stored app lock PIN is migrated to the hash");
+                true
+            }
+            Err(e) => {
+                // The legacy PIN is kept and the migration is tried on the next unlock
+                log::error!("Migrating the stored app lock PIN failed {}", e);
+                false
+            }
+        }
+    }
+
+    // An attempt during the wait is rejected without verifying the PIN and is not counted
+    // Any time before the last failure (e.g device clock moved back) is taken as no time elapsed
+    fn waiting_result(&self, attempts_allowed: usize, now: i64) -> Option<PinVerifyResult> {
+        let last = self.last_failed_time?;
+        let elapsed = (now - last).max(0);
+        let delay = pin_attempt_delay(self.failed_attempts);
+        if elapsed >= delay {
+            return None;
+        }
+        Some(PinVerifyResult {
+            verified: false,
+            remaining_attempts: attempts_allowed.saturating_sub(self.failed_attempts),
+            retry_after: delay - elapsed,
+            app_reset: false,
+            app_reset_pending: false,
+        })
+    }
+
+    fn attempts_exceeded(&self, attempts_allowed: usize) -> bool {
+        self.failed_attempts >= attempts_allowed
+    }
+
+    // Returns true if there were any failed attempts
+    fn reset_failed_attempts(&mut self) -> bool {
+        let changed = self.failed_attempts > 0 || self.last_failed_time.is_some();
+        self.failed_attempts = 0;
+        self.last_failed_time = None;
+        changed
+    }
+
+    // Returns the result of this attempt and whether the stored credential needs to be updated
+    fn attempt(
+        &mut self,
+        secret: &str,
+        salt: &[u8],
+        attempts_allowed: usize,
+        now: i64,
+    ) -> (PinVerifyResult, bool) {
+        let remaining = |failed: usize| attempts_allowed.saturating_sub(failed);
+
+        // The attempts were exceeded in the autofill extension and no more attempts are allowed
+        if self.attempts_exceeded(attempts_allowed) {
+            return (PinVerifyResult::attempts_exceeded(), false);
+        }
+
+        if let Some(r) = self.waiting_result(attempts_allowed, now) {
+            return (r, false);
+        }
+
+        if self.matches(secret, salt) {
+            let migrated = self.migrate_legacy_pin(secret, salt);
+            let changed = self.reset_failed_attempts() || migrated;
+            return (PinVerifyResult::success(attempts_allowed), changed);
+        }
+
+        self.failed_attempts += 1;
+        self.last_failed_time = Some(now);
+
+        let r = PinVerifyResult {
+            verified: false,
+            remaining_attempts: remaining(self.failed_attempts),
+            retry_after: pin_attempt_delay(self.failed_attempts),
+            app_reset: self.attempts_exceeded(attempts_allowed),
+            app_reset_pending: false,
+        };
+        (r, true)
+    }
+
+    // The credential is stored under 'store_tag' (app lock PIN or duress PIN) in the key store
+    fn encrypt_and_store(&self, store_tag: &str) -> OkpResult<()> {
+        // Note: We are using APP_LOCK_PIN_TAG in all secure_enclave_cb_service calls
 
         // Serialize to string
         let plain_data = serde_json::to_string(&self)?;
@@ -97,17 +562,23 @@ impl AppLockCredential {
             .encrypt_bytes(APP_LOCK_PIN_TAG.to_string(), plain_data.as_bytes().to_vec())?;
 
         // Store the encrypted data in the key store
-        let r = key_secure::keystore_insert_or_update(APP_LOCK_PIN_TAG, &encrypted_data);
+        // The failed attempts count is also stored here and a failed write should not be ignored
+        if !key_secure::keystore_insert_or_update(store_tag, &encrypted_data) {
+            return Err(error::Error::SecureKeyOperationError(
+                "Storing the app lock credential in key store failed".into(),
+            ));
+        }
 
-        debug!("keystore_insert_or_update is done to store PIN {}", r);
+        debug!("keystore_insert_or_update is done to store PIN");
 
         Ok(())
     }
 
-    fn verify(&self) -> OkpResult<bool> {
+    // Gets the previously stored credential from the key store
+    fn read_stored(store_tag: &str) -> OkpResult<Self> {
         // First we need to get the previously stored encrypted data from key store
         let decoded_enc_data =
-            key_secure::keystore_get_value(APP_LOCK_PIN_TAG).ok_or_else(|| {
+            key_secure::keystore_get_value(store_tag).ok_or_else(|| {
                 error::Error::SecureKeyOperationError(format!(
                     "Getting expected encrypted app lock data from key store failed"
                 ))
@@ -121,17 +592,159 @@ impl AppLockCredential {
         let decrypted_data_str = String::from_utf8_lossy(&decrypted_data);
         let stored_app_lock = serde_json::from_str::<AppLockCredential>(&decrypted_data_str)?;
 
-        Ok(self == &stored_app_lock)
+        Ok(stored_app_lock)
     }
 
     // Called to remove any previously stored encrypted app lock credentials from the key store
-    fn remove_app_lock_credential() -> OkpResult<()> {
-        let r = key_secure::keystore_delete_key(APP_LOCK_PIN_TAG);
+    fn remove_app_lock_credential(store_tag: &str) -> OkpResult<()> {
+        let r = key_secure::keystore_delete_key(store_tag);
         log::debug!("App lock enc data from key store is deleted..");
         r
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_pin_attempt_delay() {
+        assert_eq!(0, pin_attempt_delay(0));
+        assert_eq!(0, pin_attempt_delay(2));
+        assert_eq!(30_000, pin_attempt_delay(3));
+        assert_eq!(60_000, pin_attempt_delay(4));
+        assert_eq!(PIN_ATTEMPT_MAX_DELAY, pin_attempt_delay(40));
+    }
+
+    const TEST_SALT: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    // Low cost kdf params to keep the tests fast
+    fn test_credential(secret: &str, kind: AppLockSecretKind) -> AppLockCredential {
+        let kdf = SecretKdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        AppLockCredential {
+            pin: None,
+            kind,
+            secret_hash: Some(kdf.hash(secret, TEST_SALT).unwrap()),
+            kdf,
+            failed_attempts: 0,
+            last_failed_time: None,
+        }
+    }
+
+    #[test]
+    fn verify_pin_attempts_backoff_and_reset() {
+        let mut c = test_credential("1234", AppLockSecretKind::Pin);
+        let s = TEST_SALT;
+
+        let (r, changed) = c.attempt("1111", s, 5, 1_000);
+        assert!(changed);
+        assert_eq!((false, 4, 0, false), (r.verified, r.remaining_attempts, r.retry_after, r.app_reset));
+
+        let _ = c.attempt("1111", s, 5, 2_000);
+        let (r, _) = c.attempt("1111", s, 5, 3_000);
+        assert_eq!((2, 30_000), (r.remaining_attempts, r.retry_after));
+
+        // Even the correct PIN is rejected during the wait and the attempt is not counted
+        let (r, changed) = c.attempt("1234", s, 5, 10_000);
+        assert!(!changed);
+        assert!(!r.verified);
+        assert_eq!(23_000, r.retry_after);
+        assert_eq!(3, c.failed_attempts);
+
+        // Clock moved back
+        let (r, _) = c.attempt("1234", s, 5, 0);
+        assert!(!r.verified);
+
+        // Success resets the count
+        let (r, changed) = c.attempt("1234", s, 5, 33_000);
+        assert!(changed);
+        assert!(r.verified);
+        assert_eq!(0, c.failed_attempts);
+        assert_eq!(None, c.last_failed_time);
+
+        // Reset after the allowed attempts
+        let mut c = test_credential("1234", AppLockSecretKind::Pin);
+        let mut now = 0;
+        for _ in 0..4 {
+            let (r, _) = c.attempt("1111", s, 5, now);
+            assert!(!r.app_reset);
+            now += PIN_ATTEMPT_MAX_DELAY;
+        }
+        let (r, _) = c.attempt("1111", s, 5, now);
+        assert!(r.app_reset);
+        assert_eq!(0, r.remaining_attempts);
+
+        // The count exceeded in the autofill extension is kept and even the correct PIN is not accepted
+        now += PIN_ATTEMPT_MAX_DELAY;
+        let (r, changed) = c.attempt("1234", s, 5, now);
+        assert!(!changed);
+        assert!(!r.verified);
+        assert!(r.app_reset);
+    }
+
+    #[test]
+    fn verify_passphrase_hash() {
+        let mut c = test_credential("Correct horse 9", AppLockSecretKind::Passphrase);
+        assert!(!c.matches("correct horse 9", TEST_SALT));
+        // Same passphrase with a different salt
+        assert!(!c.matches("Correct horse 9", b"fedcba9876543210fedcba9876543210"));
+
+        let (r, changed) = c.attempt("Correct horse 9", TEST_SALT, 5, 1_000);
+        assert!(r.verified);
+        assert!(!changed);
+
+        // The plain secret is not stored
+        let json = serde_json::to_string(&c).unwrap();
+        assert!(!json.contains("Correct horse 9"));
+        assert!(!json.contains("\"pin\""));
+    }
+
+    #[test]
+    fn verify_stored_credential_without_attempts() {
+        // Credential stored by the earlier app version
+        let c: AppLockCredential = serde_json::from_str(r#"{"pin":1234}"#).unwrap();
+        assert_eq!(0, c.failed_attempts);
+        assert_eq!(None, c.last_failed_time);
+        assert_eq!(AppLockSecretKind::Pin, c.kind);
+        assert_eq!(None, c.secret_hash);
+    }
+
+    #[test]
+    fn verify_legacy_pin_migration() {
+        let mut c: AppLockCredential =
+            serde_json::from_str(r#"{"pin":1234,"failed_attempts":1}"#).unwrap();
+        c.kdf.memory_kib = 64;
+        c.kdf.iterations = 1;
+
+        // A failed attempt does not migrate
+        let (r, _) = c.attempt("4321", TEST_SALT, 5, 1_000);
+        assert!(!r.verified);
+        assert_eq!(Some(1234), c.pin);
+
+        let (r, changed) = c.attempt("1234", TEST_SALT, 5, 2_000);
+        assert!(r.verified);
+        assert!(changed);
+        assert_eq!(None, c.pin);
+        assert!(c.secret_hash.is_some());
+        assert_eq!(SecretKdfParams::default(), c.kdf);
+
+        // Verified with the hash after the migration
+        assert!(c.matches("1234", TEST_SALT));
+        assert!(!c.matches("1235", TEST_SALT));
+    }
+
+    #[test]
+    fn verify_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
+
 /*
 fn keystore_insert_or_update(acct_key: &str, encrypted_data: &Vec<u8>) -> bool {
     let ops = AppState::secure_key_operation();
@@ -3,13 +3,15 @@ use std::{fs, path::Path};
 use log::{debug, error, info};
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use onekeepass_core::db_service as kp_service;
+use onekeepass_core::{db_service as kp_service, service_util};
 
 use crate::{
-    app_lock,
+    app_lock::{self, AppLockSecretKind},
     app_state::AppState,
     biometric_auth,
+    json_migration::{insert_if_missing, Migration, MigrationStatus, VersionedJson},
     udl_types::{CommonDeviceService, EventDispatch, FileInfo},
     util, OkpError, OkpResult,
 };
@@ -28,6 +30,29 @@ pub struct PreferenceData {
     app_lock_attempts_allowed: Option<usize>,
     app_lock_lock_app_settings: Option<bool>,
     //app_lock_preference: Option<AppLockPreference>,
+    session_timeout_action: Option<SessionTimeoutAction>,
+
+    // When db_key is set, 'db_session_timeout' and 'clipboard_timeout' are used as the overrides of this db
+    // instead of updating the global values
+    db_key: Option<String>,
+    db_lock_on_background: Option<bool>,
+    db_allow_in_autofill: Option<bool>,
+    // Removes all existing overrides of the db before applying any passed ones
+    db_reset_overrides: Option<bool>,
+    // Removes only these overrides of the db before applying any passed ones
+    db_clear_overrides: Option<Vec<DbOverrideField>>,
+    db_hide_under_duress: Option<bool>,
+    // A value of 0 removes the limit
+    db_biometric_max_unlocks: Option<u32>,
+    db_biometric_max_days: Option<u32>,
+
+    app_lock_duress_wipe: Option<bool>,
+}
+
+impl PreferenceData {
+    pub(crate) fn db_key(&self) -> Option<&str> {
+        self.db_key.as_deref()
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Default, Debug)]
@@ -52,23 +77,154 @@ pub struct RecentlyUsed {
     pub file_size: Option<i64>,
 }
 
+// A remote location to which the database is copied after each successful save
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct DatabaseMirror {
+    // The remote storage db_key of the mirror file e.g Sftp-<connection_id>-/backups/Test1.kdbx
+    pub(crate) mirror_db_key: String,
+    // In milli seconds
+    pub(crate) last_synced: Option<i64>,
+    // In milli seconds
+    pub(crate) last_attempted: Option<i64>,
+    // Set when the last push to this mirror failed
+    pub(crate) last_error: Option<String>,
+}
+
+impl DatabaseMirror {
+    fn new(mirror_db_key: &str) -> Self {
+        Self {
+            mirror_db_key: mirror_db_key.into(),
+            last_synced: None,
+            last_attempted: None,
+            last_error: None,
+        }
+    }
+}
+
+// What is done to an opened db when its session times out
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub(crate) enum SessionTimeoutAction {
+    // The db is kept in memory and the UI shows the unlock screen
+    #[default]
+    Lock,
+    // The db is closed and removed from memory. Any unsaved changes are lost
+    Close,
+}
+
+// The key file that was used in the last successful open of a db
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct DbKeyFileUse {
+    // Just the file name part of the key file in the app's key files dir
+    pub(crate) file_name: String,
+    // Hex encoded SHA-256 of the key file content. Used to find the key file even when it is renamed
+    pub(crate) fingerprint: String,
+}
+
+// The db overrides of the global values that can be cleared individually. See 'PreferenceData::db_clear_overrides'
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) enum DbOverrideField {
+    SessionTimeout,
+    ClipboardTimeout,
+    LockOnBackground,
+    AllowInAutofill,
+}
+
 // Database specific preferences
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub(crate) struct DatabasePreference {
     db_key: String,
     db_open_biometric_enabled: bool,
     db_unlock_biometric_enabled: bool,
+    // Mirrors are added or removed only through the mirror specific calls
+    #[serde(default)]
+    mirrors: Vec<DatabaseMirror>,
+
+    // Overrides of the global preference values for this db. None means the global value is used
+    // Session will time out in these milli seconds
+    session_timeout: Option<i64>,
+    // clipboard will be cleared in these milli seconds
+    clipboard_timeout: Option<i64>,
+    // The db is locked whenever the app goes to background
+    lock_on_background: Option<bool>,
+    // Whether this db may be opened in autofill
+    allow_in_autofill: Option<bool>,
+
+    // This db is removed from the app's view when the app is unlocked with the duress PIN
+    #[serde(default)]
+    hide_under_duress: bool,
+
+    // The password is required again after these many biometric opens since the last password entry
+    biometric_max_unlocks: Option<u32>,
+    // The password is required again after these many days since the last password entry
+    biometric_max_days: Option<u32>,
+
+    // Set on each successful open. None when the db was last opened without a key file
+    key_file_used: Option<DbKeyFileUse>,
     //TDOO:
-    // Add after how many times of using biometric, we need to ask user to enter password something similar MacOS does
     // Add PIN protection for each db  - db_open_pin_enabled:bool,; Need to store the PIN in secure enclave
     // Flag to indicate whether to use biometric during autofill (iOS specific?)
 }
 
+impl DatabasePreference {
+    fn new(db_key: &str) -> Self {
+        Self {
+            db_key: db_key.into(),
+            db_open_biometric_enabled: false,
+            db_unlock_biometric_enabled: false,
+            mirrors: vec![],
+            session_timeout: None,
+            clipboard_timeout: None,
+            lock_on_background: None,
+            allow_in_autofill: None,
+            hide_under_duress: false,
+            biometric_max_unlocks: None,
+            biometric_max_days: None,
+            key_file_used: None,
+        }
+    }
+
+    fn reset_overrides(&mut self) {
+        self.session_timeout = None;
+        self.clipboard_timeout = None;
+        self.lock_on_background = None;
+        self.allow_in_autofill = None;
+    }
+
+    fn clear_override(&mut self, field: DbOverrideField) {
+        match field {
+            DbOverrideField::SessionTimeout => self.session_timeout = None,
+            DbOverrideField::ClipboardTimeout => self.clipboard_timeout = None,
+            DbOverrideField::LockOnBackground => self.lock_on_background = None,
+            DbOverrideField::AllowInAutofill => self.allow_in_autofill = None,
+        }
+    }
+}
+
+// The limits on the biometric use of a db after which the password entry is mandatory
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct BiometricPolicy {
+    pub(crate) max_unlocks: Option<u32>,
+    pub(crate) max_days: Option<u32>,
+}
+
+// The values to use for a db after applying its overrides on the global values
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub(crate) struct ResolvedDbSettings {
+    pub(crate) db_session_timeout: i64,
+    pub(crate) clipboard_timeout: i64,
+    pub(crate) lock_on_background: bool,
+    pub(crate) allow_in_autofill: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub(crate) struct AppLockPreference {
     // PIN based app lock is enabled or disabled
     pin_lock_enabled: bool,
 
+    // Whether the app lock uses a numeric PIN or an alphanumeric passphrase
+    #[serde(default)]
+    secret_kind: AppLockSecretKind,
+
     // app will be locked on timeout expiry after these milli seconds.
     // Value of 0 means immedeiatley whenever app is goes background
     lock_timeout: i64,
@@ -79,6 +235,14 @@ pub(crate) struct AppLockPreference {
     // When this is true and pin_lock_enabled is true, user can access the 'App Settings' only
     // after reentering the PIN again
     lock_app_settings: bool,
+
+    // A second PIN is set and unlocking with it hides the dbs flagged with 'hide_under_duress'
+    #[serde(default)]
+    duress_pin_enabled: bool,
+
+    // The hidden dbs are also wiped when the app is unlocked with the duress PIN
+    #[serde(default)]
+    duress_wipe: bool,
     // May be used in the future
     // When this is enabled, the app will be reset when the no of pin auth failures > attempts_allowed
     // reset_on_lock_failures:bool,
@@ -88,15 +252,30 @@ impl Default for AppLockPreference {
     fn default() -> Self {
         Self {
             pin_lock_enabled: false,
+            secret_kind: AppLockSecretKind::default(),
             lock_timeout: Default::default(),
             attempts_allowed: 10,
             lock_app_settings: Default::default(),
+            duress_pin_enabled: false,
+            duress_wipe: false,
         }
     }
 }
 
 impl AppLockPreference {
     // Called in ios autofill
+    pub(crate) fn attempts_allowed(&self) -> usize {
+        self.attempts_allowed
+    }
+
+    pub(crate) fn duress_pin_enabled(&self) -> bool {
+        self.duress_pin_enabled
+    }
+
+    pub(crate) fn duress_wipe(&self) -> bool {
+        self.duress_wipe
+    }
+
     #[cfg(target_os = "ios")]
     pub(crate) fn disable_pin_lock(&mut self) {
         // This should not be persisted
@@ -141,6 +320,18 @@ pub(crate) struct Preference {
     database_preferences: Vec<DatabasePreference>,
 
     app_lock_preference: AppLockPreference,
+
+    #[serde(default)]
+    session_timeout_action: SessionTimeoutAction,
+
+    // Set when the app is unlocked with the duress PIN and cleared on the next unlock with the app lock PIN
+    // This is persisted so that the dbs stay hidden after an app restart and in the autofill extension
+    #[serde(default)]
+    duress_active: bool,
+
+    // Set when the preference file is from a newer app version
+    #[serde(skip)]
+    read_only: bool,
 }
 
 impl Default for Preference {
@@ -159,6 +350,9 @@ impl Default for Preference {
             // biometric_enabled_dbs: vec![],
             database_preferences: vec![],
             app_lock_preference: AppLockPreference::default(),
+            session_timeout_action: SessionTimeoutAction::default(),
+            duress_active: false,
+            read_only: false,
         }
     }
 }
@@ -180,8 +374,60 @@ macro_rules! pref_update {
 impl Preference {
     // Update the preference with any non null values
     pub(crate) fn update(&mut self, preference_data: PreferenceData) -> OkpResult<()> {
+        if self.apply_update(preference_data) {
+            self.write_to_app_dir();
+        }
+        Ok(())
+    }
+
+    // Returns true if any value is updated
+    fn apply_update(&mut self, mut preference_data: PreferenceData) -> bool {
         let mut updated = false;
 
+        if let Some(db_key) = preference_data.db_key.take() {
+            // The timeouts passed are the overrides of this db
+            let db_pref = self.database_preference_mut(&db_key);
+
+            if preference_data.db_reset_overrides.unwrap_or(false) {
+                db_pref.reset_overrides();
+                updated = true;
+            }
+
+            for field in preference_data.db_clear_overrides.take().unwrap_or_default() {
+                db_pref.clear_override(field);
+                updated = true;
+            }
+
+            if let Some(t) = preference_data.db_session_timeout.take() {
+                db_pref.session_timeout = Some(t);
+                updated = true;
+            }
+            if let Some(t) = preference_data.clipboard_timeout.take() {
+                db_pref.clipboard_timeout = Some(t);
+                updated = true;
+            }
+            if let Some(v) = preference_data.db_lock_on_background {
+                db_pref.lock_on_background = Some(v);
+                updated = true;
+            }
+            if let Some(v) = preference_data.db_allow_in_autofill {
+                db_pref.allow_in_autofill = Some(v);
+                updated = true;
+            }
+            if let Some(v) = preference_data.db_hide_under_duress {
+                db_pref.hide_under_duress = v;
+                updated = true;
+            }
+            if let Some(v) = preference_data.db_biometric_max_unlocks {
+                db_pref.biometric_max_unlocks = (v > 0).then_some(v);
+                updated = true;
+            }
+            if let Some(v) = preference_data.db_biometric_max_days {
+                db_pref.biometric_max_days = (v > 0).then_some(v);
+                updated = true;
+            }
+        }
+
         pref_update!(self, preference_data.language, language, updated);
 
         pref_update!(self, preference_data.theme, theme, updated);
@@ -228,16 +474,26 @@ impl Preference {
             updated
         );
 
+        pref_update!(
+            self,
+            preference_data.app_lock_duress_wipe,
+            app_lock_preference.duress_wipe,
+            updated
+        );
+
+        pref_update!(
+            self,
+            preference_data.session_timeout_action,
+            session_timeout_action,
+            updated
+        );
+
         if let Some(db_pref) = preference_data.database_preference {
             self.upate_or_insert_database_preference(db_pref);
             updated = true;
         }
 
-        if updated {
-            self.write_to_app_dir();
-        }
-
-        Ok(())
+        updated
     }
 }
 
@@ -248,71 +504,71 @@ impl Preference {
 
         info!("pref_file_name is {:?} ", &pref_file_name);
 
-        let json_str = fs::read_to_string(pref_file_name).unwrap_or("".into());
-
-        debug!("Pref json_str is {}", &json_str);
+        // The previous copy is used when the preference file is truncated or can not be upgraded
+        let ((pref, status), read_file_name) =
+            match util::read_with_prev_fallback(&pref_file_name, Self::from_json_data) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
+                    info!("Preference is empty and default used ");
+                    return Self::default();
+                }
+                Err(e) => {
+                    // This may happen if the 'preference.json' found is older than the oldest known version.
+                    // Returns the latest default pref
+                    debug!("Returning the default pref because of json parsing error {}", e);
+                    return Self::default();
+                }
+            };
 
-        let mut pref = if json_str.is_empty() {
-            info!("Preference is empty and default used ");
-            Self::default()
-        } else {
-            // If the new field added are Option<.> type, then
-            // the parsing will be successful with None set for all Option fields.
-            // In that case, we need to ensure version number is correct
-            // See at the end before returning 'final_pref'
-            serde_json::from_str(&json_str).unwrap_or_else(|_| {
-                // Json parsing failed as the file loaded may be from a previous version
-
-                // Need to use serde_json::from_str::<PreferenceV400>(&json_str) if we do not use @ Bindings
-                // See https://doc.rust-lang.org/book/ch18-03-pattern-syntax.html#-bindings
-
-                match serde_json::from_str(&json_str) {
-                    // Prior version
-                    Ok(prev_pref @ PreferenceV400 { .. }) => {
-                        debug!("Returning the new pref with old pref values from PreferenceV400");
-                        // Self::from_prev_version_v400_to_recent(&mut pref_new, &p);
-
-                        let pref_new: Self = prev_pref.into();
-
-                        debug!("Converted pref_new is {:?}", &pref_new);
-
-                        // Update the preference json with the copied values from old preference
-                        pref_new.write(preference_home_dir.as_ref());
-                        pref_new
-                    }
-                    Err(e) => {
-                        // We could not parse the existing json file even to the prior version.
-                        // This may happen if the 'preference.json' found is older than the previous version.
-                        // Returns the latest default pref
-                        // Should we write this default one?
-                        debug!(
-                            "Returning the default pref because of json parsing error {}",
-                            e
-                        );
-                        Self::default()
-                    }
+        match status {
+            MigrationStatus::Current => {
+                if read_file_name != pref_file_name {
+                    pref.write(preference_home_dir.as_ref());
                 }
-            })
-        };
+            }
+            MigrationStatus::Upgraded(from_version) => {
+                debug!("Converted pref from version {} is {:?}", &from_version, &pref);
+                // The original file is kept as a backup before writing the upgraded one
+                if PREFERENCE_JSON.backup_original(&read_file_name, &from_version) {
+                    pref.write(preference_home_dir.as_ref());
+                }
+            }
+            // The preference file is from a newer app version and nothing is written back
+            // so that the newer app version can still use it
+            MigrationStatus::ReadOnly(_) => {}
+        }
+
+        pref
+    }
 
-        // Ensure that version is updated if required
-        let final_pref = if pref.version == PREFERENCE_JSON_FILE_VERSION {
-            // debug!("Version Checked: Returning the current pref as version is the latest");
+    fn from_json_data(data: &[u8]) -> OkpResult<(Self, MigrationStatus)> {
+        let json_str = std::str::from_utf8(data)
+            .map_err(|_| OkpError::DataError("Invalid preference file content"))?;
+
+        debug!("Pref json_str is {}", json_str);
+
+        let (value, status) = PREFERENCE_JSON.migrate_json_str(json_str)?;
+
+        let pref = if let MigrationStatus::ReadOnly(_) = status {
+            // The known fields are used if possible
+            let mut pref = serde_json::from_value::<Self>(value).unwrap_or_else(|e| {
+                debug!("Using the default pref as read only because of json parsing error {}", e);
+                Self::default()
+            });
+            pref.read_only = true;
             pref
         } else {
-            // If the new field added are only Option<> type in Preference,
-            // then parsing goes through with new Preference itself, but the version will be old
-            pref.version = PREFERENCE_JSON_FILE_VERSION.into();
-            pref.write(preference_home_dir.as_ref());
-            
-            // debug!("Returning the current pref after conversion from old pref as versions are not the same");
-            
-            pref
+            serde_json::from_value::<Self>(value)?
         };
 
-        // debug!("Returning pref obj in read {:?}", &final_pref);
+        Ok((pref, status))
+    }
 
-        final_pref
+    // Parses the preference json found in an app archive. Any older version is converted as done for the
+    // preference file. Unlike 'read', an error is returned instead of using the default preference
+    pub(crate) fn from_archived_json(json_str: &str) -> OkpResult<Self> {
+        let (value, _status) = PREFERENCE_JSON.migrate_json_str(json_str)?;
+        Ok(serde_json::from_value::<Self>(value)?)
     }
 
     pub(crate) fn write_default() -> Self {
@@ -322,6 +578,11 @@ impl Preference {
     }
 
     fn write<P: AsRef<Path>>(&self, preference_home_dir: P) {
+        if self.read_only {
+            info!("Preference is read only as it is from a newer app version and is not written");
+            return;
+        }
+
         // Remove old file names from the list before writing
         //self.remove_old_db_use_info();
 
@@ -333,7 +594,7 @@ impl Preference {
         // debug!("Writing preference file to {:?}  and content is {:?}", &pref_file_name, &json_str_result);
 
         if let Ok(json_str) = json_str_result {
-            if let Err(err) = fs::write(pref_file_name, json_str.as_bytes()) {
+            if let Err(err) = util::atomic_write(pref_file_name, json_str.as_bytes()) {
                 error!(
                     "Preference file write failed and error is {}",
                     err.to_string()
@@ -342,7 +603,7 @@ impl Preference {
         }
     }
 
-    fn write_to_app_dir(&self) {
+    pub(crate) fn write_to_app_dir(&self) {
         self.write(AppState::preference_home_dir());
     }
 
@@ -354,7 +615,18 @@ impl Preference {
             .iter_mut()
             .find(|d| d.db_key == db_pref.db_key)
         {
-            *m = db_pref;
+            // The db preference from UI does not include the mirrors and the existing ones are kept
+            // The overrides are updated only through the db specific fields of PreferenceData
+            let existing = std::mem::replace(m, db_pref);
+            m.mirrors = existing.mirrors;
+            m.session_timeout = existing.session_timeout;
+            m.clipboard_timeout = existing.clipboard_timeout;
+            m.lock_on_background = existing.lock_on_background;
+            m.allow_in_autofill = existing.allow_in_autofill;
+            m.hide_under_duress = existing.hide_under_duress;
+            m.biometric_max_unlocks = existing.biometric_max_unlocks;
+            m.biometric_max_days = existing.biometric_max_days;
+            m.key_file_used = existing.key_file_used;
         } else {
             self.database_preferences.push(db_pref);
         }
@@ -365,16 +637,75 @@ impl Preference {
         }
     }
 
+    // Forms the preference to use in this device from the one restored from an app archive (see 'app_archive' module).
+    // The db_keys are remapped using 'remap' and the dbs that can not be remapped are removed.
+    // The app lock preference of this device is kept as the PIN is stored in this device's secure storage
+    // The archived preference is merged with this device's preference. The archived dbs are added to the
+    // recent list and the dbs already known in this device keep their db preference (e.g the biometric
+    // settings which depend on the credentials stored in this device)
+    pub(crate) fn remapped_for_import<F: Fn(&str) -> Option<String>>(
+        mut self,
+        current: &Preference,
+        remap: F,
+    ) -> Self {
+        let mut recent_dbs_info: Vec<RecentlyUsed> = self
+            .recent_dbs_info
+            .into_iter()
+            .filter_map(|mut r| {
+                r.db_file_path = remap(&r.db_file_path)?;
+                Some(r)
+            })
+            .collect();
+        for r in &current.recent_dbs_info {
+            if !recent_dbs_info.iter().any(|a| a.db_file_path == r.db_file_path) {
+                recent_dbs_info.push(r.clone());
+            }
+        }
+        self.recent_dbs_info = recent_dbs_info;
+
+        let mut database_preferences = current.database_preferences.clone();
+        for mut d in self.database_preferences {
+            // The biometric credentials are not in the archive and these are stored again
+            // on the next open with the password
+            let Some(db_key) = remap(&d.db_key) else {
+                continue;
+            };
+            if !database_preferences.iter().any(|c| c.db_key == db_key) {
+                d.db_key = db_key;
+                database_preferences.push(d);
+            }
+        }
+        self.database_preferences = database_preferences;
+
+        self.app_lock_preference = current.app_lock_preference.clone();
+        self.duress_active = current.duress_active;
+        // The archive may be from a newer app version. The imported preference becomes this device's preference
+        self.read_only = false;
+        self
+    }
+
+    // The timeouts are set as the overrides of the db when 'db_key' is passed
     pub(crate) fn update_session_timeout(
         &mut self,
+        db_key: Option<&str>,
         db_session_timeout: Option<i64>,
         clipboard_timeout: Option<i64>,
     ) -> OkpResult<()> {
-        if let Some(t) = db_session_timeout {
-            self.db_session_timeout = t;
-        }
-        if let Some(t) = clipboard_timeout {
-            self.clipboard_timeout = t;
+        if let Some(db_key) = db_key {
+            let db_pref = self.database_preference_mut(db_key);
+            if db_session_timeout.is_some() {
+                db_pref.session_timeout = db_session_timeout;
+            }
+            if clipboard_timeout.is_some() {
+                db_pref.clipboard_timeout = clipboard_timeout;
+            }
+        } else {
+            if let Some(t) = db_session_timeout {
+                self.db_session_timeout = t;
+            }
+            if let Some(t) = clipboard_timeout {
+                self.clipboard_timeout = t;
+            }
         }
 
         self.write_to_app_dir();
@@ -386,6 +717,12 @@ impl Preference {
         self.write_to_app_dir();
     }
 
+    pub(crate) fn update_app_lock_with_secret(&mut self, secret_kind: AppLockSecretKind) {
+        self.app_lock_preference.pin_lock_enabled = true;
+        self.app_lock_preference.secret_kind = secret_kind;
+        self.write_to_app_dir();
+    }
+
     pub(crate) fn remove_database_preference(&mut self, db_key: &str) {
         self.database_preferences.retain(|s| s.db_key != db_key);
     }
@@ -421,6 +758,28 @@ impl Preference {
         self.write(&AppState::preference_home_dir());
     }
 
+    // Called when a db file is renamed so that the recent db info and the db preference use the new db_key
+    pub(crate) fn rename_db_key(&mut self, db_key: &str, new_db_key: &str, new_file_name: &str) {
+        if let Some(r) = self
+            .recent_dbs_info
+            .iter_mut()
+            .find(|r| r.db_file_path == db_key)
+        {
+            r.db_file_path = new_db_key.into();
+            r.file_name = new_file_name.into();
+        }
+
+        if let Some(d) = self
+            .database_preferences
+            .iter_mut()
+            .find(|d| d.db_key == db_key)
+        {
+            d.db_key = new_db_key.into();
+        }
+
+        self.write_to_app_dir();
+    }
+
     // pub(crate) fn set_db_open_biometric(&mut self, db_key: &str, enabled: bool) {
     //     let key = db_key.to_string();
 
@@ -446,10 +805,271 @@ impl Preference {
             .map_or(false, |d| d.db_open_biometric_enabled)
     }
 
+    pub(crate) fn biometric_policy(&self, db_key: &str) -> BiometricPolicy {
+        self.database_preferences
+            .iter()
+            .find(|p| p.db_key == db_key)
+            .map_or_else(BiometricPolicy::default, |d| BiometricPolicy {
+                max_unlocks: d.biometric_max_unlocks,
+                max_days: d.biometric_max_days,
+            })
+    }
+
+    pub(crate) fn db_mirrors(&self, db_key: &str) -> Vec<DatabaseMirror> {
+        self.database_preferences
+            .iter()
+            .find(|p| p.db_key == db_key)
+            .map_or_else(|| vec![], |d| d.mirrors.clone())
+    }
+
+    // Gets the preference of this db adding a new one if required
+    fn database_preference_mut(&mut self, db_key: &str) -> &mut DatabasePreference {
+        match self
+            .database_preferences
+            .iter()
+            .position(|d| d.db_key == db_key)
+        {
+            Some(i) => &mut self.database_preferences[i],
+            None => {
+                self.database_preferences
+                    .push(DatabasePreference::new(db_key));
+                self.database_preferences.last_mut().unwrap()
+            }
+        }
+    }
+
+    // The db specific overrides are used first and then the global values
+    pub(crate) fn resolved_db_settings(&self, db_key: &str) -> ResolvedDbSettings {
+        let db_pref = self.database_preferences.iter().find(|d| d.db_key == db_key);
+
+        ResolvedDbSettings {
+            db_session_timeout: db_pref
+                .and_then(|d| d.session_timeout)
+                .unwrap_or(self.db_session_timeout),
+            clipboard_timeout: db_pref
+                .and_then(|d| d.clipboard_timeout)
+                .unwrap_or(self.clipboard_timeout),
+            lock_on_background: db_pref.and_then(|d| d.lock_on_background).unwrap_or(false),
+            allow_in_autofill: !self.hidden_under_duress(db_key)
+                && db_pref.and_then(|d| d.allow_in_autofill).unwrap_or(true),
+        }
+    }
+
+    // Only true when the app is unlocked with the duress PIN
+    pub(crate) fn hidden_under_duress(&self, db_key: &str) -> bool {
+        self.duress_active
+            && self
+                .database_preferences
+                .iter()
+                .any(|d| d.db_key == db_key && d.hide_under_duress)
+    }
+
+    // All db_keys found in the recent list and in the db preferences
+    pub(crate) fn known_db_keys(&self) -> Vec<String> {
+        let mut db_keys: Vec<String> = self
+            .recent_dbs_info
+            .iter()
+            .map(|r| r.db_file_path.clone())
+            .collect();
+        for d in &self.database_preferences {
+            if !db_keys.contains(&d.db_key) {
+                db_keys.push(d.db_key.clone());
+            }
+        }
+        db_keys
+    }
+
+    // Called after each successful open of a db. The preference is written only when the key file used is changed
+    pub(crate) fn set_db_key_file_used(&mut self, db_key: &str, key_file_used: Option<DbKeyFileUse>) {
+        if self.apply_db_key_file_used(db_key, key_file_used) {
+            self.write_to_app_dir();
+        }
+    }
+
+    // Returns true if the key file used is changed
+    fn apply_db_key_file_used(&mut self, db_key: &str, key_file_used: Option<DbKeyFileUse>) -> bool {
+        let exists = self.database_preferences.iter().any(|d| d.db_key == db_key);
+        if !exists && key_file_used.is_none() {
+            return false;
+        }
+
+        let db_pref = self.database_preference_mut(db_key);
+        if db_pref.key_file_used == key_file_used {
+            return false;
+        }
+        db_pref.key_file_used = key_file_used;
+        true
+    }
+
+    // The key files used by the dbs. Only the dbs in the recent list are considered when 'recent_only' is true
+    // Otherwise all known dbs (see 'known_db_keys') are considered
+    pub(crate) fn db_key_files_used(&self, recent_only: bool) -> Vec<(String, DbKeyFileUse)> {
+        let db_keys: Vec<String> = if recent_only {
+            self.recent_dbs_info
+                .iter()
+                .map(|r| r.db_file_path.clone())
+                .collect()
+        } else {
+            self.known_db_keys()
+        };
+
+        self.database_preferences
+            .iter()
+            .filter(|d| db_keys.contains(&d.db_key))
+            .filter_map(|d| Some((d.db_key.clone(), d.key_file_used.clone()?)))
+            .collect()
+    }
+
+    // All dbs flagged to be hidden irrespective of the duress mode
+    pub(crate) fn duress_hidden_db_keys(&self) -> Vec<String> {
+        self.database_preferences
+            .iter()
+            .filter(|d| d.hide_under_duress)
+            .map(|d| d.db_key.clone())
+            .collect()
+    }
+
+    // The default preference with the app lock settings and the duress mode of this preference
+    pub(crate) fn default_keeping_app_lock(&self) -> Preference {
+        Preference {
+            app_lock_preference: self.app_lock_preference.clone(),
+            duress_active: self.duress_active,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn duress_active(&self) -> bool {
+        self.duress_active
+    }
+
+    pub(crate) fn set_duress_active(&mut self, duress_active: bool) {
+        if self.duress_active != duress_active {
+            self.duress_active = duress_active;
+            self.write_to_app_dir();
+        }
+    }
+
+    pub(crate) fn update_app_lock_with_duress(&mut self, duress_pin_enabled: bool) {
+        self.apply_app_lock_duress(duress_pin_enabled);
+        self.write_to_app_dir();
+    }
+
+    fn apply_app_lock_duress(&mut self, duress_pin_enabled: bool) {
+        self.app_lock_preference.duress_pin_enabled = duress_pin_enabled;
+        if !duress_pin_enabled {
+            self.app_lock_preference.duress_wipe = false;
+            self.duress_active = false;
+        }
+    }
+
+    // The preference as seen by the UI. In the duress mode, the hidden dbs and all duress settings are removed
+    // so that nothing in the app shows that there are hidden dbs
+    pub(crate) fn view(&self) -> Preference {
+        let mut pref = self.clone();
+        if !self.duress_active {
+            return pref;
+        }
+
+        let hidden = self.duress_hidden_db_keys();
+        pref.recent_dbs_info
+            .retain(|r| !hidden.contains(&r.db_file_path));
+        pref.database_preferences
+            .retain(|d| !hidden.contains(&d.db_key));
+
+        pref.duress_active = false;
+        pref.app_lock_preference.duress_pin_enabled = false;
+        pref.app_lock_preference.duress_wipe = false;
+        pref
+    }
+
+    pub(crate) fn add_db_mirror(&mut self, db_key: &str, mirror_db_key: &str) {
+        if self.apply_add_db_mirror(db_key, mirror_db_key) {
+            self.write_to_app_dir();
+        }
+    }
+
+    // Returns true if the mirror is added
+    fn apply_add_db_mirror(&mut self, db_key: &str, mirror_db_key: &str) -> bool {
+        let db_pref = self.database_preference_mut(db_key);
+
+        if db_pref
+            .mirrors
+            .iter()
+            .any(|m| m.mirror_db_key == mirror_db_key)
+        {
+            return false;
+        }
+        db_pref.mirrors.push(DatabaseMirror::new(mirror_db_key));
+        true
+    }
+
+    pub(crate) fn remove_db_mirror(&mut self, db_key: &str, mirror_db_key: &str) {
+        if self.apply_remove_db_mirror(db_key, mirror_db_key) {
+            self.write_to_app_dir();
+        }
+    }
+
+    // Returns true if the mirror is removed
+    fn apply_remove_db_mirror(&mut self, db_key: &str, mirror_db_key: &str) -> bool {
+        let Some(d) = self
+            .database_preferences
+            .iter_mut()
+            .find(|d| d.db_key == db_key)
+        else {
+            return false;
+        };
+
+        let count = d.mirrors.len();
+        d.mirrors.retain(|m| m.mirror_db_key != mirror_db_key);
+        d.mirrors.len() != count
+    }
+
+    // Records the result of the last push of the db content to this mirror
+    pub(crate) fn update_db_mirror_status(
+        &mut self,
+        db_key: &str,
+        mirror_db_key: &str,
+        error: Option<String>,
+    ) {
+        let now = service_util::now_utc_milli_seconds();
+        if self.apply_db_mirror_status(db_key, mirror_db_key, error, now) {
+            self.write_to_app_dir();
+        }
+    }
+
+    // Returns true if the mirror is found and its status is updated
+    fn apply_db_mirror_status(
+        &mut self,
+        db_key: &str,
+        mirror_db_key: &str,
+        error: Option<String>,
+        now: i64,
+    ) -> bool {
+        let Some(m) = self
+            .database_preferences
+            .iter_mut()
+            .find(|d| d.db_key == db_key)
+            .and_then(|d| d.mirrors.iter_mut().find(|m| m.mirror_db_key == mirror_db_key))
+        else {
+            return false;
+        };
+
+        m.last_attempted = Some(now);
+        if error.is_none() {
+            m.last_synced = Some(now);
+        }
+        m.last_error = error;
+        true
+    }
+
     pub(crate) fn database_preferences(&self) -> &Vec<DatabasePreference> {
         &self.database_preferences
     }
 
+    pub(crate) fn session_timeout_action(&self) -> SessionTimeoutAction {
+        self.session_timeout_action
+    }
+
     pub(crate) fn app_lock_preference(&self) -> &AppLockPreference {
         &self.app_lock_preference
     }
@@ -504,93 +1124,67 @@ impl Preference {
     }
 }
 
-////////////   Previous version ////////////
-///
-#[derive(Clone, Serialize, Deserialize, Default)]
-pub struct RecentlyUsedV400 {
-    // Just the file name part
-    pub(crate) file_name: String,
+////////////   Previous versions ////////////
+
+// The preference.json versions and the changes made in each version
+// 0.0.2 - recent_dbs_info, db_session_timeout, clipboard_timeout
+// 0.0.3 - Added theme, language and default_entry_category_groupings
+// 4.0.0 - Added backup_history_count and database_preferences (started using 4.0.0 instead of 0.0.4)
+// 5.0.0 - Added the optional fields of RecentlyUsed and app_lock_preference
+const PREFERENCE_JSON: VersionedJson = VersionedJson {
+    name: PREFERENCE_JSON_FILE_NAME,
+    current_version: PREFERENCE_JSON_FILE_VERSION,
+    initial_version: "0.0.2",
+    migrations: &[
+        Migration {
+            from_version: "0.0.2",
+            to_version: "0.0.3",
+            migrate: migrate_v002_to_v003,
+        },
+        Migration {
+            from_version: "0.0.3",
+            to_version: "4.0.0",
+            migrate: migrate_v003_to_v400,
+        },
+        Migration {
+            from_version: "4.0.0",
+            to_version: "5.0.0",
+            migrate: migrate_v400_to_v500,
+        },
+    ],
+};
 
-    // This is full file url str.
-    // It will start with "content://" for android, "file://" in case of ios
-    // or "Sftp" or "Webdav"
-    // This is also db_key
-    pub(crate) db_file_path: String,
+fn migrate_v002_to_v003(pref: &mut Map<String, Value>) -> OkpResult<()> {
+    let defaults = Preference::default();
+    insert_if_missing(pref, "theme", Value::String(defaults.theme));
+    insert_if_missing(pref, "language", Value::String(defaults.language));
+    insert_if_missing(
+        pref,
+        "default_entry_category_groupings",
+        Value::String(defaults.default_entry_category_groupings),
+    );
+    Ok(())
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub(crate) struct PreferenceV400 {
-    version: String,
-
-    recent_dbs_info: Vec<RecentlyUsedV400>,
-
-    // Session will time out in these milli seconds
-    db_session_timeout: i64,
-
-    // clipboard will be cleared in these milli seconds
-    clipboard_timeout: i64,
-
-    // Determines the theme colors etc
-    theme: String,
-
-    // Should be a two letters language id
-    language: String,
-
-    // Valid values one of Types,Categories,Groups,Tags
-    default_entry_category_groupings: String,
-
-    // All dbs that can be opened using biometric
-    // biometric_enabled_dbs: Vec<String>,
-
-    // Number of backs to keep for all databases
-    backup_history_count: u8,
-
-    // All databases specific preferences
-    database_preferences: Vec<DatabasePreference>,
+fn migrate_v003_to_v400(pref: &mut Map<String, Value>) -> OkpResult<()> {
+    let defaults = Preference::default();
+    insert_if_missing(
+        pref,
+        "backup_history_count",
+        Value::from(defaults.backup_history_count),
+    );
+    insert_if_missing(pref, "database_preferences", Value::Array(vec![]));
+    Ok(())
 }
 
-impl From<PreferenceV400> for Preference {
-    fn from(old_pref: PreferenceV400) -> Self {
-        // Destructuring of old struct
-        let PreferenceV400 {
-            version: _,
-            recent_dbs_info,
-            db_session_timeout,
-            clipboard_timeout,
-            theme,
-            backup_history_count,
-            language,
-            database_preferences,
-            default_entry_category_groupings,
-        } = old_pref;
-
-        let mut current_pref = Preference {
-            version: PREFERENCE_JSON_FILE_VERSION.into(),
-            recent_dbs_info: vec![],
-            db_session_timeout,
-            clipboard_timeout,
-            theme,
-            language,
-            default_entry_category_groupings,
-            backup_history_count,
-            database_preferences,
-            app_lock_preference: AppLockPreference::default(),
-        };
-
-        // RecentlyUsed is changed in the new Preference struct
-        let info: Vec<RecentlyUsed> = recent_dbs_info
-            .iter()
-            .map(|p: &RecentlyUsedV400| {
-                let mut dbs = RecentlyUsed::default();
-                dbs.file_name = p.file_name.clone();
-                dbs.db_file_path = p.db_file_path.clone();
-                dbs
-            })
-            .collect();
-        current_pref.recent_dbs_info = info;
-
-        current_pref
-    }
+// The new fields of RecentlyUsed are all optional and the entries of 4.0.0 need no change
+fn migrate_v400_to_v500(pref: &mut Map<String, Value>) -> OkpResult<()> {
+    insert_if_missing(
+        pref,
+        "app_lock_preference",
+        serde_json::to_value(AppLockPreference::default())?,
+    );
+    Ok(())
 }
 
 ////////////
@@ -671,62 +1265,356 @@ impl Preference {
 
 */
 
-/*
-fn from_prev_version_v400_to_recent(new_pref: &mut Preference, old_pref: &PreferenceV400) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFERENCE_V002: &str = r#"{
+        "version": "0.0.2",
+        "recent_dbs_info": [{"file_name": "Test1.kdbx", "db_file_path": "file:///tmp/Test1.kdbx"}],
+        "db_session_timeout": 900000,
+        "clipboard_timeout": 5000
+    }"#;
+
+    const PREFERENCE_V003: &str = r#"{
+        "version": "0.0.3",
+        "recent_dbs_info": [{"file_name": "Test1.kdbx", "db_file_path": "file:///tmp/Test1.kdbx"}],
+        "db_session_timeout": 900000,
+        "clipboard_timeout": 5000,
+        "theme": "dark",
+        "language": "fr",
+        "default_entry_category_groupings": "Types"
+    }"#;
+
+    const PREFERENCE_V400: &str = r#"{
+        "version": "4.0.0",
+        "recent_dbs_info": [{"file_name": "Test1.kdbx", "db_file_path": "file:///tmp/Test1.kdbx"}],
+        "db_session_timeout": 900000,
+        "clipboard_timeout": 5000,
+        "theme": "dark",
+        "language": "fr",
+        "default_entry_category_groupings": "Types",
+        "backup_history_count": 5,
+        "database_preferences": [{"db_key": "file:///tmp/Test1.kdbx", "db_open_biometric_enabled": true, "db_unlock_biometric_enabled": false}]
+    }"#;
+
+    fn migrated_step(json_str: &str, migration: &Migration) -> Map<String, Value> {
+        let mut value: Value = serde_json::from_str(json_str).unwrap();
+        let obj = value.as_object_mut().unwrap();
+        (migration.migrate)(obj).unwrap();
+        obj.clone()
+    }
 
-        // old_pref uses RecentlyUsedV400
-        let info: Vec<RecentlyUsed> = old_pref
-            .recent_dbs_info
+    fn migration_from(version: &str) -> &'static Migration {
+        PREFERENCE_JSON
+            .migrations
             .iter()
-            .map(|p: &RecentlyUsedV400| {
-                let mut dbs = RecentlyUsed::default();
-                dbs.file_name = p.file_name.clone();
-                dbs.db_file_path = p.db_file_path.clone();
-                dbs
-            })
-            .collect();
-        new_pref.recent_dbs_info = info;
+            .find(|m| m.from_version == version)
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_migration_v002_to_v003() {
+        let obj = migrated_step(PREFERENCE_V002, migration_from("0.0.2"));
+        assert_eq!(Some("system"), obj["theme"].as_str());
+        assert_eq!(Some("Groups"), obj["default_entry_category_groupings"].as_str());
+        assert!(obj["language"].is_string());
+        assert_eq!(Some(900000), obj["db_session_timeout"].as_i64());
+    }
+
+    #[test]
+    fn verify_migration_v003_to_v400() {
+        let obj = migrated_step(PREFERENCE_V003, migration_from("0.0.3"));
+        assert_eq!(Some(3), obj["backup_history_count"].as_u64());
+        assert_eq!(Some(0), obj["database_preferences"].as_array().map(|a| a.len()));
+        // Existing values are kept
+        assert_eq!(Some("dark"), obj["theme"].as_str());
+    }
+
+    #[test]
+    fn verify_migration_v400_to_v500() {
+        let obj = migrated_step(PREFERENCE_V400, migration_from("4.0.0"));
+        let app_lock: AppLockPreference =
+            serde_json::from_value(obj["app_lock_preference"].clone()).unwrap();
+        assert!(!app_lock.pin_lock_enabled);
+        assert_eq!(10, app_lock.attempts_allowed);
+    }
+
+    #[test]
+    fn verify_preference_migration_chain() {
+        for json_str in [PREFERENCE_V002, PREFERENCE_V003, PREFERENCE_V400] {
+            let (value, status) = PREFERENCE_JSON.migrate_json_str(json_str).unwrap();
+            assert!(matches!(status, MigrationStatus::Upgraded(_)));
+            let pref: Preference = serde_json::from_value(value).unwrap();
+            assert_eq!(PREFERENCE_JSON_FILE_VERSION, pref.version);
+            assert_eq!(900000, pref.db_session_timeout);
+            assert_eq!(1, pref.recent_dbs_info.len());
+        }
+    }
+
+    #[test]
+    fn verify_duress_view() {
+        let mut pref = Preference::default();
+        for db_key in ["file:///tmp/Team.kdbx", "file:///tmp/Travel.kdbx"] {
+            pref.recent_dbs_info.push(RecentlyUsed {
+                db_file_path: db_key.into(),
+                ..Default::default()
+            });
+        }
+
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"db_key": "file:///tmp/Team.kdbx", "db_hide_under_duress": true}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        pref.apply_app_lock_duress(true);
+
+        // Nothing is hidden till the duress PIN is used
+        assert!(!pref.hidden_under_duress("file:///tmp/Team.kdbx"));
+        assert_eq!(2, pref.view().recent_dbs_info.len());
+        assert!(pref.view().app_lock_preference.duress_pin_enabled);
+
+        pref.duress_active = true;
+        assert!(pref.hidden_under_duress("file:///tmp/Team.kdbx"));
+        assert!(!pref.hidden_under_duress("file:///tmp/Travel.kdbx"));
+        assert!(!pref.resolved_db_settings("file:///tmp/Team.kdbx").allow_in_autofill);
+
+        let view = pref.view();
+        assert_eq!(1, view.recent_dbs_info.len());
+        assert_eq!("file:///tmp/Travel.kdbx", view.recent_dbs_info[0].db_file_path);
+        assert!(view.database_preferences.is_empty());
+        assert!(!view.duress_active);
+        assert!(!view.app_lock_preference.duress_pin_enabled);
+
+        // The flag is kept when the UI sends the db preference
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"database_preference": {"db_key": "file:///tmp/Team.kdbx", "db_open_biometric_enabled": true, "db_unlock_biometric_enabled": false}}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        assert_eq!(vec!["file:///tmp/Team.kdbx".to_string()], pref.duress_hidden_db_keys());
+
+        // Removing the duress PIN ends the duress mode
+        pref.apply_app_lock_duress(false);
+        assert!(!pref.hidden_under_duress("file:///tmp/Team.kdbx"));
+    }
 
-        new_pref.default_entry_category_groupings = old_pref.default_entry_category_groupings
+    #[test]
+    fn verify_default_keeping_app_lock() {
+        let mut pref = Preference::from_archived_json(PREFERENCE_V400).unwrap();
+        pref.app_lock_preference.pin_lock_enabled = true;
+        pref.apply_app_lock_duress(true);
+        pref.duress_active = true;
+
+        let reset = pref.default_keeping_app_lock();
+        assert!(reset.recent_dbs_info.is_empty());
+        assert!(reset.database_preferences.is_empty());
+        assert_eq!(Preference::default().backup_history_count, reset.backup_history_count);
+        assert!(reset.app_lock_preference.pin_lock_enabled);
+        assert!(reset.app_lock_preference.duress_pin_enabled);
+        assert!(reset.duress_active);
     }
-fn from_prev_version_to_recent(new_pref: &mut Preference, old_pref: &Preference2) {
-        let info: Vec<RecentlyUsed> = old_pref
+
+    #[test]
+    fn verify_db_key_files_used() {
+        let mut pref = Preference::default();
+        pref.recent_dbs_info.push(RecentlyUsed {
+            db_file_path: "file:///tmp/Team.kdbx".into(),
+            ..Default::default()
+        });
+
+        let key_file_used = DbKeyFileUse {
+            file_name: "team.keyx".into(),
+            fingerprint: "ab01".into(),
+        };
+
+        // No db preference is added for a db opened without a key file
+        pref.apply_db_key_file_used("file:///tmp/Travel.kdbx", None);
+        assert!(pref.database_preferences.is_empty());
+
+        pref.apply_db_key_file_used("file:///tmp/Team.kdbx", Some(key_file_used.clone()));
+        pref.apply_db_key_file_used("file:///tmp/Travel.kdbx", Some(key_file_used.clone()));
+        assert_eq!(1, pref.db_key_files_used(true).len());
+        assert_eq!(2, pref.db_key_files_used(false).len());
+
+        // The key file used is kept when the UI sends the db preference
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"database_preference": {"db_key": "file:///tmp/Team.kdbx", "db_open_biometric_enabled": true, "db_unlock_biometric_enabled": false}}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        assert_eq!(
+            vec![("file:///tmp/Team.kdbx".to_string(), key_file_used)],
+            pref.db_key_files_used(true)
+        );
+
+        pref.apply_db_key_file_used("file:///tmp/Team.kdbx", None);
+        assert!(pref.db_key_files_used(true).is_empty());
+    }
+
+    #[test]
+    fn verify_remapped_for_import() {
+        let archived = Preference::from_archived_json(PREFERENCE_V400).unwrap();
+        assert!(
+            Preference::from_archived_json(r#"{"version": "5.0.0", "recent_dbs_info": ["#).is_err()
+        );
+
+        let mut current = Preference::default();
+        current.recent_dbs_info.push(RecentlyUsed {
+            db_file_path: "file:///tmp/Travel.kdbx".into(),
+            ..Default::default()
+        });
+        current.apply_add_db_mirror(
+            "file:///tmp/Test1.kdbx",
+            "Sftp-264226dc-be96-462a-a386-79adb6291ad7-/Test1.kdbx",
+        );
+        current.duress_active = true;
+
+        let imported = archived.remapped_for_import(&current, |k| Some(k.to_string()));
+
+        let recent: Vec<&str> = imported
             .recent_dbs_info
             .iter()
-            .map(|p| RecentlyUsed {
-                file_name: p.file_name.clone(),
-                db_file_path: p.db_file_path.clone(),
-            })
+            .map(|r| r.db_file_path.as_str())
             .collect();
-        new_pref.recent_dbs_info = info;
+        assert_eq!(vec!["file:///tmp/Test1.kdbx", "file:///tmp/Travel.kdbx"], recent);
+
+        // The db preference of this device is kept for the db that is in both
+        assert_eq!(1, imported.database_preferences.len());
+        assert_eq!(1, imported.db_mirrors("file:///tmp/Test1.kdbx").len());
+        assert!(imported.duress_active);
+        assert_eq!(5, imported.backup_history_count);
     }
 
-// This struct matches any previous verion (0.0.3) of preference.json
-// This uses the old struct 'RecentlyUsed1'
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Preference2 {
-    pub version: String,
-    pub recent_dbs_info: Vec<RecentlyUsed>,
-    // Session will time out in these milli seconds
-    pub db_session_timeout: i64,
-    // clipboard will be cleared in these milli seconds
-    pub clipboard_timeout: i64,
-    // Determines the theme colors etc
-    pub theme: String,
-    // Should be a two letters language id
-    pub language: String,
-    // Valid values one of Types,Categories,Groups,Tags
-    pub default_entry_category_groupings: String,
-}
-*/
+    #[test]
+    fn verify_db_mirrors() {
+        let mut pref = Preference::default();
+        let db_key = "file:///tmp/Team.kdbx";
+        let mirror = "Sftp-264226dc-be96-462a-a386-79adb6291ad7-/backups/Team.kdbx";
+
+        assert!(pref.apply_add_db_mirror(db_key, mirror));
+        // The same mirror is not added again
+        assert!(!pref.apply_add_db_mirror(db_key, mirror));
+        assert_eq!(1, pref.db_mirrors(db_key).len());
+
+        // A failed push keeps the last synced time
+        assert!(pref.apply_db_mirror_status(db_key, mirror, None, 1_000));
+        assert!(pref.apply_db_mirror_status(db_key, mirror, Some("Connection failed".into()), 2_000));
+        let m = &pref.db_mirrors(db_key)[0];
+        assert_eq!(Some(1_000), m.last_synced);
+        assert_eq!(Some(2_000), m.last_attempted);
+        assert_eq!(Some("Connection failed".to_string()), m.last_error);
+
+        assert!(pref.apply_db_mirror_status(db_key, mirror, None, 3_000));
+        let m = &pref.db_mirrors(db_key)[0];
+        assert_eq!(Some(3_000), m.last_synced);
+        assert!(m.last_error.is_none());
+
+        // Unknown db or mirror
+        assert!(!pref.apply_db_mirror_status("file:///tmp/Travel.kdbx", mirror, None, 4_000));
+        assert!(!pref.apply_db_mirror_status(db_key, "Sftp-x-/Team.kdbx", None, 4_000));
+
+        // The mirrors are kept when the UI sends the db preference
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"database_preference": {"db_key": "file:///tmp/Team.kdbx", "db_open_biometric_enabled": true, "db_unlock_biometric_enabled": false}}"#,
+        )
+        .unwrap();
+        pref.apply_update(data);
+        assert_eq!(1, pref.db_mirrors(db_key).len());
+
+        assert!(pref.apply_remove_db_mirror(db_key, mirror));
+        assert!(!pref.apply_remove_db_mirror(db_key, mirror));
+        assert!(!pref.apply_remove_db_mirror("file:///tmp/Travel.kdbx", mirror));
+        assert!(pref.db_mirrors(db_key).is_empty());
+    }
 
-/*
-// This struct matches any previous verion (0.0.2) of preference.json
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Preference1 {
-    pub version: String,
-    pub recent_dbs_info: Vec<RecentlyUsed>,
-    pub db_session_timeout: i64,
-    pub clipboard_timeout: i64,
+    #[test]
+    fn verify_resolved_db_settings() {
+        let mut pref = Preference::default();
+
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"db_key": "file:///tmp/Team.kdbx", "db_session_timeout": 120000, "db_allow_in_autofill": false}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+
+        let team = pref.resolved_db_settings("file:///tmp/Team.kdbx");
+        assert_eq!(120000, team.db_session_timeout);
+        assert_eq!(pref.clipboard_timeout, team.clipboard_timeout);
+        assert!(!team.allow_in_autofill);
+        // The global value is not changed
+        assert_eq!(1_800_000, pref.db_session_timeout);
+
+        let notes = pref.resolved_db_settings("file:///tmp/Notes.kdbx");
+        assert_eq!(1_800_000, notes.db_session_timeout);
+        assert!(notes.allow_in_autofill);
+        assert!(!notes.lock_on_background);
+
+        // The db preference from UI keeps the overrides
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"database_preference": {"db_key": "file:///tmp/Team.kdbx", "db_open_biometric_enabled": true, "db_unlock_biometric_enabled": false}}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        assert_eq!(team, pref.resolved_db_settings("file:///tmp/Team.kdbx"));
+
+        // Only the passed override is cleared
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"db_key": "file:///tmp/Team.kdbx", "db_clear_overrides": ["SessionTimeout"]}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        let team = pref.resolved_db_settings("file:///tmp/Team.kdbx");
+        assert_eq!(1_800_000, team.db_session_timeout);
+        assert!(!team.allow_in_autofill);
+
+        let data: PreferenceData = serde_json::from_str(
+            r#"{"db_key": "file:///tmp/Team.kdbx", "db_reset_overrides": true}"#,
+        )
+        .unwrap();
+        assert!(pref.apply_update(data));
+        assert_eq!(
+            pref.resolved_db_settings("file:///tmp/Notes.kdbx"),
+            pref.resolved_db_settings("file:///tmp/Team.kdbx")
+        );
+    }
+
+    #[test]
+    fn verify_preference_read_upgrade_and_future_version() {
+        let dir = std::env::temp_dir().join("okp_preference_migration_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let pref_file = dir.join(PREFERENCE_JSON_FILE_NAME);
+
+        fs::write(&pref_file, PREFERENCE_V400).unwrap();
+        let pref = Preference::read(&dir);
+        assert!(!pref.read_only);
+        assert_eq!(5, pref.backup_history_count);
+        // The original is backed up and the upgraded one is written
+        assert_eq!(
+            PREFERENCE_V400,
+            fs::read_to_string(dir.join("preference.json.4.0.0.bak")).unwrap()
+        );
+        let written: Value = serde_json::from_str(&fs::read_to_string(&pref_file).unwrap()).unwrap();
+        assert_eq!(Some(PREFERENCE_JSON_FILE_VERSION), written["version"].as_str());
+
+        // A newer version is loaded read only and not changed
+        let future = r#"{"version": "99.0.0", "some_new_field": true}"#;
+        fs::write(&pref_file, future).unwrap();
+        let pref = Preference::read(&dir);
+        assert!(pref.read_only);
+        pref.write(&dir);
+        assert_eq!(future, fs::read_to_string(&pref_file).unwrap());
+
+        // A truncated preference file is recovered from the previous copy
+        fs::write(&pref_file, PREFERENCE_V400).unwrap();
+        let mut pref = Preference::read(&dir);
+        pref.backup_history_count = 7;
+        pref.write(&dir);
+        fs::write(&pref_file, r#"{"version": "5.0.0", "recent_dbs_info": ["#).unwrap();
+        let pref = Preference::read(&dir);
+        assert_eq!(5, pref.backup_history_count);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
-*/
@@ -2,7 +2,7 @@ use log::{debug, error, info};
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
@@ -11,9 +11,10 @@ use std::{
 use onekeepass_core::{db_service as kp_service, service_util};
 
 use crate::{
+    app_lock::AppLockSecretKind,
     app_preference::{
-        AppLockPreference, DatabasePreference, Preference, PreferenceData, RecentlyUsed,
-        PREFERENCE_JSON_FILE_NAME,
+        AppLockPreference, BiometricPolicy, DatabaseMirror, DatabasePreference, DbKeyFileUse, Preference, PreferenceData, RecentlyUsed,
+        ResolvedDbSettings, SessionTimeoutAction, PREFERENCE_JSON_FILE_NAME,
     },
     remote_storage,
     udl_types::SecureKeyOperation,
@@ -39,6 +40,24 @@ pub(crate) const OKP_SHARED_DIR: &str = "okp_shared";
 
 pub(crate) const KEY_FILES_DIR: &str = "key_files";
 
+#[derive(Clone, Debug)]
+pub(crate) struct DbActivity {
+    // In milli seconds
+    pub(crate) last_activity: i64,
+    // Set when the session of the db is timed out with the lock action. Any command on this db is
+    // rejected till the db is unlocked again (see 'AppState::unlock_db_activity')
+    pub(crate) timed_out: bool,
+}
+
+impl DbActivity {
+    fn now() -> Self {
+        Self {
+            last_activity: service_util::now_utc_milli_seconds(),
+            timed_out: false,
+        }
+    }
+}
+
 // Any mutable field needs to be behind Mutex
 pub struct AppState {
     app_home_dir: String,
@@ -70,6 +89,14 @@ pub struct AppState {
     // This is reset to empty when the app starts
     last_backup_on_error: Mutex<HashMap<String, String>>,
 
+    // The last activity time of each opened db which is used to enforce the session timeout
+    // See 'session_timeout' module
+    db_activities: Mutex<HashMap<String, DbActivity>>,
+
+    // The dbs whose stored biometric credentials were removed as these failed to open the db
+    // See 'biometric_auth::StoredCredential::remove_stale_credentials'
+    stale_biometric_db_keys: Mutex<HashSet<String>>,
+
     preference: Mutex<Preference>,
 
     // Callback service implemented in Swift/Kotlin and called from rust side
@@ -157,6 +184,8 @@ impl AppState {
             export_data_dir_path,
             key_files_dir_path,
             last_backup_on_error: Mutex::new(HashMap::default()),
+            db_activities: Mutex::new(HashMap::default()),
+            stale_biometric_db_keys: Mutex::new(HashSet::default()),
             preference: Mutex::new(preference),
 
             common_device_service,
@@ -347,6 +376,85 @@ impl AppState {
         bkp.remove(full_file_name_uri)
     }
 
+    // Called on each command call that has the db_key
+    // The timed out (locked) state is not changed here. See 'unlock_db_activity'
+    pub(crate) fn touch_db_activity(db_key: &str) {
+        let mut activities = Self::shared().db_activities.lock().unwrap();
+        match activities.get_mut(db_key) {
+            Some(a) => a.last_activity = service_util::now_utc_milli_seconds(),
+            None => {
+                activities.insert(db_key.into(), DbActivity::now());
+            }
+        }
+    }
+
+    // Called after a successful open or unlock of a db with its credentials and a new session starts
+    pub(crate) fn unlock_db_activity(db_key: &str) {
+        let mut activities = Self::shared().db_activities.lock().unwrap();
+        activities.insert(db_key.into(), DbActivity::now());
+    }
+
+    // A timed out db is locked till it is unlocked again with its credentials
+    pub(crate) fn db_session_locked(db_key: &str) -> bool {
+        Self::shared()
+            .db_activities
+            .lock()
+            .unwrap()
+            .get(db_key)
+            .is_some_and(|a| a.timed_out)
+    }
+
+    pub(crate) fn remove_db_activity(db_key: &str) {
+        Self::shared().db_activities.lock().unwrap().remove(db_key);
+    }
+
+    pub(crate) fn db_activity(db_key: &str) -> Option<DbActivity> {
+        Self::shared()
+            .db_activities
+            .lock()
+            .unwrap()
+            .get(db_key)
+            .cloned()
+    }
+
+    // Marks the session of this db as timed out so that it is reported only once till the next activity
+    pub(crate) fn set_db_session_timed_out(db_key: &str) {
+        // The activity may not be there yet for a db opened without any command call
+        Self::shared()
+            .db_activities
+            .lock()
+            .unwrap()
+            .entry(db_key.into())
+            .or_insert_with(DbActivity::now)
+            .timed_out = true;
+    }
+
+    // Removes the activities of the dbs that are no longer opened
+    pub(crate) fn retain_db_activities(opened_db_keys: &[String]) {
+        Self::shared()
+            .db_activities
+            .lock()
+            .unwrap()
+            .retain(|k, _| opened_db_keys.contains(k));
+    }
+
+    pub(crate) fn add_stale_biometric_db_key(db_key: &str) {
+        Self::shared()
+            .stale_biometric_db_keys
+            .lock()
+            .unwrap()
+            .insert(db_key.into());
+    }
+
+    // Returns true if the stale credentials of this db were removed earlier
+    pub(crate) fn remove_stale_biometric_db_key(db_key: &str) -> bool {
+        Self::shared()
+            .stale_biometric_db_keys
+            .lock()
+            .unwrap()
+            .remove(db_key)
+    }
+
     // Used in android and ios specific module
     // See ios::copy_last_backup_to_temp_file, android::complete_save_as_on_error
     pub fn get_last_backup_on_error(full_file_name_uri: &str) -> Option<String> {
@@ -413,11 +521,67 @@ impl AppState {
         *store_pref = new_pref;
     }
 
+    // Only the preference is reset and the app lock settings are kept as the PIN entries
+    // in the key store are still valid
+    pub(crate) fn reset_preference_keeping_app_lock() {
+        let mut store_pref = Self::shared().preference.lock().unwrap();
+        let new_pref = store_pref.default_keeping_app_lock();
+        new_pref.write_to_app_dir();
+        *store_pref = new_pref;
+    }
+
+    // Replaces the current preference with the one restored from an app archive and writes the pref file
+    pub(crate) fn replace_preference(preference: Preference) {
+        let mut store_pref = Self::shared().preference.lock().unwrap();
+        preference.write_to_app_dir();
+        *store_pref = preference;
+    }
+
     pub fn preference_clone() -> Preference {
         let store_pref = Self::shared().preference.lock().unwrap();
         store_pref.clone()
     }
 
+    // Used for the preference and the recent dbs list sent to the UI
+    pub(crate) fn preference_view() -> Preference {
+        Self::shared().preference.lock().unwrap().view()
+    }
+
+    pub(crate) fn visible_recent_dbs_info() -> Vec<RecentlyUsed> {
+        Self::preference_view().recent_dbs_info()
+    }
+
+    #[inline]
+    pub(crate) fn db_hidden_under_duress(db_key: &str) -> bool {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .hidden_under_duress(db_key)
+    }
+
+    pub(crate) fn duress_hidden_db_keys() -> Vec<String> {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .duress_hidden_db_keys()
+    }
+
+    pub(crate) fn duress_active() -> bool {
+        Self::shared().preference.lock().unwrap().duress_active()
+    }
+
+    pub(crate) fn set_duress_active(duress_active: bool) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.set_duress_active(duress_active);
+    }
+
+    pub(crate) fn update_app_lock_with_duress(duress_pin_enabled: bool) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.update_app_lock_with_duress(duress_pin_enabled);
+    }
+
     pub fn update_preference(preference_data: PreferenceData) -> OkpResult<()> {
         let mut store_pref = Self::shared().preference.lock().unwrap();
         store_pref.update(preference_data)
@@ -425,11 +589,12 @@ impl AppState {
 
     // TODO: Need to change UI side to use 'update_preference' and then deprecate this method
     pub fn update_session_timeout(
+        db_key: Option<&str>,
         db_session_timeout: Option<i64>,
         clipboard_timeout: Option<i64>,
     ) -> OkpResult<()> {
         let mut pref = Self::shared().preference.lock().unwrap();
-        pref.update_session_timeout(db_session_timeout, clipboard_timeout)
+        pref.update_session_timeout(db_key, db_session_timeout, clipboard_timeout)
     }
 
     // Updates PIN lock enable / disbale flag and also writes the pref file
@@ -438,6 +603,11 @@ impl AppState {
         pref.update_app_lock_with_pin_enabled(pin_lock_enabled);
     }
 
+    pub(crate) fn update_app_lock_with_secret(secret_kind: AppLockSecretKind) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.update_app_lock_with_secret(secret_kind);
+    }
+
     #[inline]
     pub fn backup_history_count() -> u8 {
         Self::shared()
@@ -484,6 +654,41 @@ impl AppState {
         pref.remove_recent_db_use_info(db_key, delete_db_pref);
     }
 
+    #[inline]
+    pub(crate) fn rename_db_key(db_key: &str, new_db_key: &str, new_file_name: &str) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.rename_db_key(db_key, new_db_key, new_file_name);
+
+        let mut activities = Self::shared().db_activities.lock().unwrap();
+        if let Some(a) = activities.remove(db_key) {
+            activities.insert(new_db_key.into(), a);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn db_mirrors(db_key: &str) -> Vec<DatabaseMirror> {
+        let pref = Self::shared().preference.lock().unwrap();
+        pref.db_mirrors(db_key)
+    }
+
+    #[inline]
+    pub(crate) fn add_db_mirror(db_key: &str, mirror_db_key: &str) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.add_db_mirror(db_key, mirror_db_key);
+    }
+
+    #[inline]
+    pub(crate) fn remove_db_mirror(db_key: &str, mirror_db_key: &str) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.remove_db_mirror(db_key, mirror_db_key);
+    }
+
+    #[inline]
+    pub(crate) fn update_db_mirror_status(db_key: &str, mirror_db_key: &str, error: Option<String>) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.update_db_mirror_status(db_key, mirror_db_key, error);
+    }
+
     #[inline]
     pub fn file_name_in_recently_used(db_key: &str) -> Option<String> {
         Self::shared()
@@ -502,6 +707,54 @@ impl AppState {
             .db_open_biometeric_enabled(db_key)
     }
 
+    #[inline]
+    pub(crate) fn biometric_policy(db_key: &str) -> BiometricPolicy {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .biometric_policy(db_key)
+    }
+
+    #[inline]
+    pub(crate) fn set_db_key_file_used(db_key: &str, key_file_used: Option<DbKeyFileUse>) {
+        let mut pref = Self::shared().preference.lock().unwrap();
+        pref.set_db_key_file_used(db_key, key_file_used);
+    }
+
+    #[inline]
+    pub(crate) fn db_key_files_used(recent_only: bool) -> Vec<(String, DbKeyFileUse)> {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .db_key_files_used(recent_only)
+    }
+
+    #[inline]
+    pub(crate) fn session_timeout_action() -> SessionTimeoutAction {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .session_timeout_action()
+    }
+
+    // The session and clipboard timeouts and other settings of this db after applying its overrides
+    #[inline]
+    pub(crate) fn resolved_db_settings(db_key: &str) -> ResolvedDbSettings {
+        Self::shared()
+            .preference
+            .lock()
+            .unwrap()
+            .resolved_db_settings(db_key)
+    }
+
+    #[inline]
+    pub(crate) fn db_allowed_in_autofill(db_key: &str) -> bool {
+        Self::resolved_db_settings(db_key).allow_in_autofill
+    }
+
     // Finds the recently used info for a given uri
     #[inline]
     pub fn get_recently_used(db_key: &str) -> Option<RecentlyUsed> {
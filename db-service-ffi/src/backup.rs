@@ -1,11 +1,15 @@
 use filetime::FileTime;
+use once_cell::sync::Lazy;
 use onekeepass_core::db_service::{self, service_util};
 use onekeepass_core::util::string_to_simple_hash;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, DirEntry, Metadata};
-use std::io::{Read, Seek};
+use std::io::{BufReader, Read, Seek};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use log::debug;
+use log::{debug, error, info};
+use sha2::{Digest, Sha256};
 
 use crate::{app_state::AppState, util::create_sub_dir_path};
 use crate::{util, OkpError, OkpResult};
@@ -41,7 +45,11 @@ pub(crate) fn generate_backup_history_file_name(
         .map(|s| s.to_string())
 }
 
-pub fn generate_and_open_backup_file(db_key: &str, kdbx_file_name: &str) -> OkpResult<fs::File> {
+// Returns the opened backup file and its full path name
+pub fn generate_and_open_backup_file(
+    db_key: &str,
+    kdbx_file_name: &str,
+) -> OkpResult<(fs::File, String)> {
     let backup_file_path = generate_backup_history_file_name(db_key, kdbx_file_name);
 
     let bk_file_name = backup_file_path.ok_or(OkpError::DataError("Opening backup file failed"))?;
@@ -52,9 +60,9 @@ pub fn generate_and_open_backup_file(db_key: &str, kdbx_file_name: &str) -> OkpR
         .read(true)
         .write(true)
         .create(true)
-        .open(bk_file_name)?;
+        .open(&bk_file_name)?;
 
-    Ok(file)
+    Ok((file, bk_file_name))
 }
 
 // Gets the latest the full path name of backup file if available. Otherwise new backup file name is generated
@@ -77,19 +85,33 @@ pub(crate) fn remove_backup_history_file(db_key: &str, full_backup_file_name: &s
 
     // debug!("Removing backup file {}", &full_backup_file_name);
 
-    // Remove this backup file and remove the backup dir for this 'full_file_uri_str' if the dir is empty
-    let r = fs::remove_file(full_backup_file_name)
-        .and_then(|_| fs::read_dir(&file_hist_root))
-        .and_then(|d| Ok(d.count()));
-    if let Ok(c) = r {
-        if c == 0 {
-            let _r = fs::remove_dir(&file_hist_root);
-        }
+    let _r = fs::remove_file(full_backup_file_name);
+
+    let Some(name) = Path::new(full_backup_file_name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+    else {
+        return;
+    };
+
+    // Remove the backup dir for this 'full_file_uri_str' if there are no more backups
+    let mut no_backups = false;
+    update_manifest(&file_hist_root, |manifest| {
+        manifest.entries.retain(|e| e.file_name != name);
+        no_backups = manifest.entries.is_empty() && manifest.quarantined.is_empty();
+    });
+    if no_backups {
+        let _r = fs::remove_dir_all(&file_hist_root);
     }
 
     // debug!("Backup dir for this full uri {}  exists {}",&db_key,&file_hist_root.exists());
 }
 
+// Same as 'backup_file_history_root' without creating the dir
+pub(crate) fn backup_history_root_path(db_key: &str) -> PathBuf {
+    AppState::backup_history_dir_path().join(string_to_simple_hash(db_key).to_string())
+}
+
 // Deletes all backup of the files found for this full uri
 pub(crate) fn delete_backup_history_dir(db_key: &str) {
     let file_hist_root = backup_file_history_root(db_key);
@@ -98,43 +120,75 @@ pub(crate) fn delete_backup_history_dir(db_key: &str) {
     // debug!("Deleted all files under root {:?} with status {:?}",&file_hist_root, &r);
 }
 
-pub(crate) fn prune_backup_history_files(db_key: &str) {
-    let limit = AppState::backup_history_count() as usize;
-    let file_hist_root = backup_file_history_root(db_key);
-    let mut buffer: Vec<(DirEntry, i64)> = list_of_files_with_modified_times(&file_hist_root);
+// Moves all backup files of a db to the backup history root of its new db_key
+// Called when a db file is renamed so that the previous backups are available for the renamed db
+pub(crate) fn move_backup_history_files(db_key: &str, new_db_key: &str) -> OkpResult<()> {
+    let old_hist_root = backup_file_history_root(db_key);
+    let new_hist_root = backup_file_history_root(new_db_key);
 
-    let files_count = buffer.len();
+    let _lock = MANIFEST_LOCK.lock().unwrap();
 
-    // (files_count - limit) will panic if it results in -ve number as both are usize
+    move_history_dir(&old_hist_root, &new_hist_root)
+}
 
-    // debug!(
-    //     "History files count {} , limit {} and  excess {}",
-    //     &files_count, limit, (files_count - limit)
-    // );
+fn move_history_dir(old_hist_root: &Path, new_hist_root: &Path) -> OkpResult<()> {
+    let old_manifest = BackupManifest::load(old_hist_root);
+    let mut new_manifest = BackupManifest::load(new_hist_root);
+
+    for entry in old_manifest.entries {
+        fs::rename(
+            old_hist_root.join(&entry.file_name),
+            new_hist_root.join(&entry.file_name),
+        )?;
+        new_manifest.entries.retain(|e| e.file_name != entry.file_name);
+        new_manifest.entries.push(entry);
+    }
 
-    if files_count > limit {
-        // debug!( "History files count {} exceeded the limit {} ", &files_count, limit);
-        
-        buffer.sort_by_key(|k| k.1);
+    // The quarantined backups and their records are moved along with the history files
+    let old_quarantine_dir = old_hist_root.join(QUARANTINE_DIR_NAME);
+    for q in old_manifest.quarantined {
+        // A quarantined file is left in the history dir itself when its move to the quarantine dir failed
+        let in_quarantine_dir = old_quarantine_dir.join(&q.file_name);
+        if in_quarantine_dir.exists() {
+            let new_quarantine_dir = create_sub_dir_path(new_hist_root, QUARANTINE_DIR_NAME);
+            fs::rename(&in_quarantine_dir, new_quarantine_dir.join(&q.file_name))?;
+        } else if old_hist_root.join(&q.file_name).exists() {
+            fs::rename(old_hist_root.join(&q.file_name), new_hist_root.join(&q.file_name))?;
+        }
+        new_manifest.quarantined.retain(|e| e.file_name != q.file_name);
+        new_manifest.quarantined.push(q);
+    }
+
+    new_manifest.write(new_hist_root)?;
 
-        // (files_count - limit) will panic if it results in -ve number as both are usize
-        // The files_count > limit check ensures that this does not happen
-        let excess = files_count - limit;
+    let _r = fs::remove_dir_all(old_hist_root);
 
-        for (file, _) in &buffer[0..excess] {
-            let _r = fs::remove_file(file.path());
-            // debug!("Removing file {:?} and the result is {:?} ",&file.path(),&r);
+    Ok(())
+}
+
+pub(crate) fn prune_backup_history_files(db_key: &str) {
+    let limit = AppState::backup_history_count() as usize;
+    let file_hist_root = backup_file_history_root(db_key);
+
+    update_manifest(&file_hist_root, |manifest| {
+        let pruned = manifest.entries_to_prune(limit);
+        for name in &pruned {
+            let _r = fs::remove_file(file_hist_root.join(name));
+            // debug!("Removing file {:?} and the result is {:?} ",&name,&r);
         }
-    }
+        manifest.entries.retain(|e| !pruned.contains(&e.file_name));
+    });
 }
 
 // Gets the latest backup file path for this uri.
-// The files are sorted based on the last modified time and the recent one is picked
+// The latest one is picked based on the created time recorded in the backup manifest.
+// The quarantined backups are not in the manifest entries and are skipped
 pub(crate) fn latest_backup_file_path(full_file_uri_str: &str) -> Option<PathBuf> {
     let file_hist_root = backup_file_history_root(full_file_uri_str);
-    let buffer: Vec<(DirEntry, i64)> = list_of_files_with_modified_times(&file_hist_root);
-    // Find the file with the latest modified time
-    buffer.iter().max_by_key(|k| k.1).map(|e| e.0.path())
+    let manifest = read_manifest(&file_hist_root);
+    manifest
+        .latest_entry()
+        .map(|e| file_hist_root.join(&e.file_name))
 }
 
 #[inline]
@@ -142,6 +196,18 @@ pub(crate) fn latest_backup_full_file_name(full_file_uri_str: &str) -> Option<St
     latest_backup_file_path(full_file_uri_str).map(|p| p.to_string_lossy().to_string())
 }
 
+// Gets all backup file paths of this uri with the latest one first
+pub(crate) fn backup_file_paths(full_file_uri_str: &str) -> Vec<PathBuf> {
+    let file_hist_root = backup_file_history_root(full_file_uri_str);
+    let manifest = read_manifest(&file_hist_root);
+    manifest
+        .entries
+        .iter()
+        .rev()
+        .map(|e| file_hist_root.join(&e.file_name))
+        .collect()
+}
+
 // Checks whether the last backup has the same checksum as the db file that is opened
 pub(crate) fn matching_db_reader_backup_exists(
     full_file_uri_str: &str,
@@ -166,14 +232,28 @@ pub(crate) fn matching_backup_exists(
     full_file_uri_str: &str,
     checksum_hash: Vec<u8>,
 ) -> OkpResult<Option<PathBuf>> {
-    if let Some(bkp_file_path) = latest_backup_file_path(full_file_uri_str) {
-        let mut bkp_file = fs::File::open(&bkp_file_path)?;
-        let backup_cksum = db_service::calculate_db_file_checksum(&mut bkp_file)?;
-        if backup_cksum == checksum_hash {
-            return Ok(Some(bkp_file_path));
+    let file_hist_root = backup_file_history_root(full_file_uri_str);
+    let manifest = read_manifest(&file_hist_root);
+
+    let Some(entry) = manifest.latest_entry() else {
+        return Ok(None);
+    };
+    let bkp_file_path = file_hist_root.join(&entry.file_name);
+
+    // The checksum stored in the manifest is used when available instead of reading the whole backup file
+    let backup_cksum = match entry.checksum.as_deref().and_then(|c| hex::decode(c).ok()) {
+        Some(c) => c,
+        None => {
+            let mut bkp_file = fs::File::open(&bkp_file_path)?;
+            db_service::calculate_db_file_checksum(&mut bkp_file)?
         }
+    };
+
+    if backup_cksum == checksum_hash {
+        Ok(Some(bkp_file_path))
+    } else {
+        Ok(None)
     }
-    Ok(None)
 }
 
 // Forms the backup history root for this db file uri and returns
@@ -186,12 +266,13 @@ fn backup_file_history_root(db_key: &str) -> PathBuf {
 }
 
 // Gets all backup history files found under the historty root of a db file 'file_hist_root'
+// The manifest file is excluded
 fn list_of_files_with_modified_times<P: AsRef<Path>>(file_hist_root: P) -> Vec<(DirEntry, i64)> {
     let mut buffer: Vec<(DirEntry, i64)> = vec![];
     if let Ok(entries) = fs::read_dir(file_hist_root) {
         for entry in entries {
             if let Ok(e) = entry {
-                if e.path().is_file() {
+                if e.path().is_file() && !is_manifest_file(&e.file_name().to_string_lossy()) {
                     if let Some(t) = e
                         .metadata()
                         .iter()
@@ -206,3 +287,685 @@ fn list_of_files_with_modified_times<P: AsRef<Path>>(file_hist_root: P) -> Vec<(
     }
     buffer
 }
+
+//////////////////////////////////  Backup manifest  /////////////////////////////
+
+// Each backup history dir has a manifest that records why and when each backup was created.
+// All backup queries, pruning and the remote file conflict checks use this manifest instead of the
+// file system timestamps of the backup files
+const BACKUP_MANIFEST_FILE_NAME: &str = "manifest.json";
+
+// The manifest and its prev and temp files (see util::atomic_write) are not backup files
+fn is_manifest_file(file_name: &str) -> bool {
+    file_name == BACKUP_MANIFEST_FILE_NAME
+        || file_name == format!("{}.prev", BACKUP_MANIFEST_FILE_NAME)
+        || file_name == format!(".{}.tmp", BACKUP_MANIFEST_FILE_NAME)
+}
+
+// Guards the read-modify-write of the manifest files
+static MANIFEST_LOCK: Lazy<Mutex<()>> = Lazy::new(Default::default);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum BackupReason {
+    OnRead,
+    OnSave,
+    OnSaveError,
+    OnCreate,
+    // Backups created before the manifest was introduced
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct BackupManifestEntry {
+    // Just the file name part of the backup file found in the history dir
+    pub(crate) file_name: String,
+    // In seconds
+    pub(crate) created: i64,
+    pub(crate) reason: BackupReason,
+    // The db_key of the database from which this backup is made
+    pub(crate) source_db_key: String,
+    pub(crate) app_version: String,
+    // Hex encoded checksum of the backup file content
+    pub(crate) checksum: Option<String>,
+    // The modified time (in seconds) of the db file (remote or local) whose content is in this backup.
+    // None when the db file was not updated with this content (e.g save error)
+    pub(crate) remote_modified: Option<i64>,
+}
+
+// A backup that failed the integrity check and moved to the quarantine dir of the history dir
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct QuarantinedBackup {
+    pub(crate) file_name: String,
+    pub(crate) source_db_key: String,
+    // In seconds
+    pub(crate) detected: i64,
+    pub(crate) reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BackupManifest {
+    entries: Vec<BackupManifestEntry>,
+    #[serde(default)]
+    quarantined: Vec<QuarantinedBackup>,
+}
+
+impl BackupManifest {
+    // Loads the manifest found in the history dir and reconciles it with the backup files found there.
+    // Entries of missing files are dropped and any file without an entry (e.g backups made by the
+    // previous app versions) is added using its modified time which was set to the db file's modified time
+    fn load(file_hist_root: &Path) -> Self {
+        let mut manifest = util::read_with_prev_fallback(
+            file_hist_root.join(BACKUP_MANIFEST_FILE_NAME),
+            |data| Ok(serde_json::from_slice::<BackupManifest>(data)?),
+        )
+        .inspect_err(|e| error!("Backup manifest parsing failed {}", e))
+        .ok()
+        .flatten()
+        .map(|(m, _)| m)
+        .unwrap_or_default();
+
+        let files = list_of_files_with_modified_times(file_hist_root)
+            .into_iter()
+            .map(|(e, t)| (e.file_name().to_string_lossy().to_string(), t))
+            .collect::<Vec<_>>();
+
+        manifest.reconcile(&files);
+        manifest
+    }
+
+    fn write(&self, file_hist_root: &Path) -> OkpResult<()> {
+        let json_str = serde_json::to_string_pretty(self)?;
+        // The quarantine list is lost if the manifest is left unparseable by a partial write
+        util::atomic_write(file_hist_root.join(BACKUP_MANIFEST_FILE_NAME), json_str.as_bytes())?;
+        Ok(())
+    }
+
+    // The arg 'files' has the backup file names with their modified times
+    fn reconcile(&mut self, files: &[(String, i64)]) {
+        self.entries
+            .retain(|e| files.iter().any(|(name, _)| name == &e.file_name));
+
+        // A quarantined file that could not be moved is not added back
+        let mut unknowns = files
+            .iter()
+            .filter(|(name, _)| !self.entries.iter().any(|e| &e.file_name == name))
+            .filter(|(name, _)| !self.quarantined.iter().any(|q| &q.file_name == name))
+            .map(|(name, mtime)| BackupManifestEntry {
+                file_name: name.clone(),
+                created: *mtime,
+                reason: BackupReason::Unknown,
+                source_db_key: String::default(),
+                app_version: String::default(),
+                checksum: None,
+                remote_modified: Some(*mtime),
+            })
+            .collect::<Vec<_>>();
+
+        if !unknowns.is_empty() {
+            self.entries.append(&mut unknowns);
+            self.sort();
+        }
+    }
+
+    // Entries are kept in the created order and the stable sort keeps the insertion order of
+    // backups created in the same second
+    fn sort(&mut self) {
+        self.entries.sort_by_key(|e| e.created);
+    }
+
+    fn latest_entry(&self) -> Option<&BackupManifestEntry> {
+        self.entries.last()
+    }
+
+    // The latest backup whose content is known to be the same as the db file content
+    fn latest_synced_entry(&self) -> Option<&BackupManifestEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.reason != BackupReason::OnSaveError && e.remote_modified.is_some())
+    }
+
+    fn entry_mut(&mut self, file_name: &str) -> Option<&mut BackupManifestEntry> {
+        self.entries.iter_mut().find(|e| e.file_name == file_name)
+    }
+
+    // Returns the names of the oldest backups that exceed the limit
+    // The latest synced backup is always kept as that is required for the conflict checks
+    fn entries_to_prune(&self, limit: usize) -> Vec<String> {
+        if self.entries.len() <= limit {
+            return vec![];
+        }
+        let keep = self.latest_synced_entry().map(|e| e.file_name.clone());
+        let excess = self.entries.len() - limit;
+        self.entries
+            .iter()
+            .filter(|e| Some(&e.file_name) != keep.as_ref())
+            .take(excess)
+            .map(|e| e.file_name.clone())
+            .collect()
+    }
+}
+
+fn read_manifest(file_hist_root: &Path) -> BackupManifest {
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+    BackupManifest::load(file_hist_root)
+}
+
+fn update_manifest<F: FnOnce(&mut BackupManifest)>(file_hist_root: &Path, f: F) {
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+    let mut manifest = BackupManifest::load(file_hist_root);
+    f(&mut manifest);
+    if let Err(e) = manifest.write(file_hist_root) {
+        error!("Writing the backup manifest failed {}", e);
+    }
+}
+
+// Records the backup file that is just written in the manifest
+// The arg 'remote_modified' is the modified time (in seconds) of the db file that has the same content as the backup
+pub(crate) fn record_backup(
+    db_key: &str,
+    full_backup_file_name: &str,
+    reason: BackupReason,
+    remote_modified: Option<i64>,
+) {
+    let backup_path = Path::new(full_backup_file_name);
+    let Some(file_name) = backup_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+    else {
+        return;
+    };
+
+    let checksum = fs::File::open(backup_path)
+        .map_err(OkpError::from)
+        .and_then(|mut f| db_service::calculate_db_file_checksum(&mut f))
+        .map(hex::encode)
+        .inspect_err(|e| error!("Checksum calculation of the backup file failed {}", e))
+        .ok();
+
+    let file_hist_root = backup_file_history_root(db_key);
+    update_manifest(&file_hist_root, |manifest| {
+        manifest.entries.retain(|e| e.file_name != file_name);
+        manifest.entries.push(BackupManifestEntry {
+            file_name,
+            created: service_util::now_utc_seconds(),
+            reason,
+            source_db_key: db_key.to_string(),
+            app_version: AppState::app_version().to_string(),
+            checksum,
+            remote_modified,
+        });
+    });
+
+    debug!("Recorded the backup {} with reason {:?}", full_backup_file_name, reason);
+}
+
+// Sets the db file's modified time in the latest synced backup's entry
+// Used when the db file is changed without a new content (e.g rename)
+pub(crate) fn set_latest_backup_remote_modified(db_key: &str, remote_modified: Option<i64>) {
+    let file_hist_root = backup_file_history_root(db_key);
+    update_manifest(&file_hist_root, |manifest| {
+        let Some(name) = manifest
+            .latest_synced_entry()
+            .or(manifest.latest_entry())
+            .map(|e| e.file_name.clone())
+        else {
+            return;
+        };
+        if let Some(e) = manifest.entry_mut(&name) {
+            e.remote_modified = remote_modified;
+        }
+    });
+}
+
+// Gets the manifest entry of the latest backup
+pub(crate) fn latest_backup_entry(db_key: &str) -> Option<BackupManifestEntry> {
+    read_manifest(&backup_file_history_root(db_key))
+        .latest_entry()
+        .cloned()
+}
+
+// Gets the manifest entry of the latest backup that has the same content as the db file
+// The remote file conflict check uses the modified time found in this entry
+pub(crate) fn latest_synced_backup_entry(db_key: &str) -> Option<BackupManifestEntry> {
+    read_manifest(&backup_file_history_root(db_key))
+        .latest_synced_entry()
+        .cloned()
+}
+
+// Gets the backup files of a db with their manifest entries (see 'app_archive' module)
+pub(crate) fn backup_files_with_entries(db_key: &str) -> Vec<(BackupManifestEntry, PathBuf)> {
+    let file_hist_root = backup_file_history_root(db_key);
+    read_manifest(&file_hist_root)
+        .entries
+        .into_iter()
+        .map(|e| {
+            let path = file_hist_root.join(&e.file_name);
+            (e, path)
+        })
+        .collect()
+}
+
+// Writes the backups restored from an app archive to the history dir of the db_key used in this device
+pub(crate) fn restore_backup_files(
+    db_key: &str,
+    backups: Vec<(BackupManifestEntry, Vec<u8>)>,
+) -> OkpResult<usize> {
+    let file_hist_root = backup_file_history_root(db_key);
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+    let mut manifest = BackupManifest::load(&file_hist_root);
+
+    let mut restored = 0;
+    for (entry, content) in backups {
+        // The file name is from the archive and only the name part is used
+        let Some(file_name) = Path::new(&entry.file_name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+        else {
+            continue;
+        };
+        fs::write(file_hist_root.join(&file_name), content)?;
+        manifest.entries.retain(|e| e.file_name != file_name);
+        manifest.entries.push(BackupManifestEntry { file_name, ..entry });
+        restored += 1;
+    }
+    manifest.sort();
+    manifest.write(&file_hist_root)?;
+
+    Ok(restored)
+}
+
+//////////////////////////////////  Backup verification  /////////////////////////////
+
+// Backups that fail the integrity checks are moved to this sub dir of the history dir
+const QUARANTINE_DIR_NAME: &str = "quarantine";
+
+// KDBX file signatures and the supported major versions
+const KDBX_SIG1: u32 = 0x9AA2D903;
+const KDBX_SIG2: u32 = 0xB54BFB67;
+const KDBX3_MAJOR_VERSION: u16 = 3;
+const KDBX4_MAJOR_VERSION: u16 = 4;
+
+// The header fields are small and a larger size is taken as a corrupt header instead of reading that much data
+const KDBX_MAX_HEADER_FIELD_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct BackupVerificationReport {
+    pub(crate) checked: usize,
+    pub(crate) valid: usize,
+    // Backups quarantined in this verification
+    pub(crate) quarantined: Vec<QuarantinedBackup>,
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, String> {
+    let mut buf = [0u8; 2];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| "Unexpected end of the header".to_string())?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, String> {
+    let mut buf = [0u8; 4];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| "Unexpected end of the header".to_string())?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+// Checks the unencrypted outer header of a KDBX file without the credentials.
+// For KDBX 4, the SHA-256 hash of the header stored right after the header is also verified.
+// The header HMAC and the payload can only be checked with the credentials
+fn check_kdbx_header<R: Read>(reader: &mut R) -> Result<(), String> {
+    // The header bytes read so far. Used for the KDBX 4 header hash check
+    let mut header = Vec::new();
+
+    let mut fixed = [0u8; 12];
+    reader
+        .read_exact(&mut fixed)
+        .map_err(|_| "File is too short to be a KDBX file".to_string())?;
+    header.extend_from_slice(&fixed);
+
+    let mut fixed_reader = &fixed[..];
+    let sig1 = read_u32(&mut fixed_reader)?;
+    let sig2 = read_u32(&mut fixed_reader)?;
+    let _minor = read_u16(&mut fixed_reader)?;
+    let major = read_u16(&mut fixed_reader)?;
+
+    if sig1 != KDBX_SIG1 || sig2 != KDBX_SIG2 {
+        return Err("Invalid KDBX file signature".into());
+    }
+
+    if major != KDBX3_MAJOR_VERSION && major != KDBX4_MAJOR_VERSION {
+        return Err(format!("Unsupported KDBX version {}", major));
+    }
+
+    // Each header field is id (1 byte), size (2 bytes in KDBX 3 and 4 bytes in KDBX 4) and the data.
+    // The field with id 0 ends the header
+    loop {
+        let mut id = [0u8; 1];
+        reader
+            .read_exact(&mut id)
+            .map_err(|_| "Unexpected end of the header".to_string())?;
+        header.extend_from_slice(&id);
+
+        let size = if major == KDBX4_MAJOR_VERSION {
+            let size = read_u32(reader)?;
+            header.extend_from_slice(&size.to_le_bytes());
+            size as usize
+        } else {
+            let size = read_u16(reader)?;
+            header.extend_from_slice(&size.to_le_bytes());
+            size as usize
+        };
+
+        if size > KDBX_MAX_HEADER_FIELD_SIZE {
+            return Err(format!("Header field size {} is too large", size));
+        }
+
+        let read = reader
+            .by_ref()
+            .take(size as u64)
+            .read_to_end(&mut header)
+            .map_err(|_| "Unexpected end of the header field".to_string())?;
+        if read != size {
+            return Err("Unexpected end of the header field".into());
+        }
+
+        if id[0] == 0 {
+            break;
+        }
+    }
+
+    if major == KDBX4_MAJOR_VERSION {
+        let mut stored_hash = [0u8; 32];
+        reader
+            .read_exact(&mut stored_hash)
+            .map_err(|_| "Header hash is missing".to_string())?;
+        if Sha256::digest(&header).as_slice() != stored_hash {
+            return Err("Header hash does not match".into());
+        }
+    }
+
+    // Some encrypted content should follow the header
+    let mut next = [0u8; 1];
+    reader
+        .read_exact(&mut next)
+        .map_err(|_| "No content found after the header".to_string())?;
+
+    Ok(())
+}
+
+// Verifies a backup file and returns its checksum (hex) on success or the reason of the failure
+fn verify_backup_file(path: &Path, entry: &BackupManifestEntry) -> Result<String, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Opening the file failed {}", e))?;
+    check_kdbx_header(&mut BufReader::new(file))?;
+
+    let mut file = fs::File::open(path).map_err(|e| format!("Opening the file failed {}", e))?;
+    let checksum = db_service::calculate_db_file_checksum(&mut file)
+        .map(hex::encode)
+        .map_err(|e| format!("Checksum calculation failed {}", e))?;
+
+    match entry.checksum.as_deref() {
+        Some(stored) if stored != checksum => {
+            Err("Checksum does not match the one recorded in the manifest".into())
+        }
+        _ => Ok(checksum),
+    }
+}
+
+// Verifies all backups found in a history dir and quarantines the corrupt ones
+fn verify_history_dir(file_hist_root: &Path, report: &mut BackupVerificationReport) {
+    update_manifest(file_hist_root, |manifest| {
+        let mut corrupt = vec![];
+        for entry in manifest.entries.iter_mut() {
+            report.checked += 1;
+            match verify_backup_file(&file_hist_root.join(&entry.file_name), entry) {
+                Ok(checksum) => {
+                    // The backups made before the manifest was introduced get their checksum stored here
+                    // so that any later change to the file is detected
+                    entry.checksum.get_or_insert(checksum);
+                    report.valid += 1;
+                }
+                Err(reason) => corrupt.push((entry.file_name.clone(), reason)),
+            }
+        }
+
+        for (file_name, reason) in corrupt {
+            let q = quarantine(file_hist_root, manifest, &file_name, reason);
+            report.quarantined.push(q);
+        }
+    });
+}
+
+// Moves the backup file to the quarantine dir and removes it from the backup entries
+fn quarantine(
+    file_hist_root: &Path,
+    manifest: &mut BackupManifest,
+    file_name: &str,
+    reason: String,
+) -> QuarantinedBackup {
+    info!("Quarantining the backup {} as {}", file_name, &reason);
+
+    let quarantine_dir = create_sub_dir_path(file_hist_root, QUARANTINE_DIR_NAME);
+    if let Err(e) = fs::rename(file_hist_root.join(file_name), quarantine_dir.join(file_name)) {
+        // The file is left in place. It is still excluded as it is listed in the manifest's quarantined list
+        error!("Moving the backup {} to quarantine failed {}", file_name, e);
+    }
+
+    let source_db_key = manifest
+        .entries
+        .iter()
+        .find(|e| e.file_name == file_name)
+        .map(|e| e.source_db_key.clone())
+        .unwrap_or_default();
+    manifest.entries.retain(|e| e.file_name != file_name);
+
+    let q = QuarantinedBackup {
+        file_name: file_name.to_string(),
+        source_db_key,
+        detected: service_util::now_utc_seconds(),
+        reason,
+    };
+    manifest.quarantined.push(q.clone());
+    q
+}
+
+// Verifies the backups of all databases
+pub(crate) fn verify_backups() -> OkpResult<BackupVerificationReport> {
+    let mut report = BackupVerificationReport::default();
+
+    for entry in fs::read_dir(AppState::backup_history_dir_path())?.flatten() {
+        if entry.path().is_dir() {
+            verify_history_dir(&entry.path(), &mut report);
+        }
+    }
+
+    debug!(
+        "Verified {} backups and {} are quarantined",
+        report.checked,
+        report.quarantined.len()
+    );
+
+    Ok(report)
+}
+
+// Verifies a single backup of a db before it is used. The backup is quarantined if it is corrupt
+pub(crate) fn verify_backup(db_key: &str, backup_path: &Path) -> OkpResult<()> {
+    let file_hist_root = backup_file_history_root(db_key);
+    let Some(file_name) = backup_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+    else {
+        return Err(OkpError::DataError("Invalid backup file path"));
+    };
+
+    let mut result = Ok(());
+    update_manifest(&file_hist_root, |manifest| {
+        let Some(entry) = manifest.entries.iter_mut().find(|e| e.file_name == file_name) else {
+            result = Err(OkpError::DataError("Backup is not found in the manifest"));
+            return;
+        };
+        match verify_backup_file(backup_path, entry) {
+            Ok(checksum) => {
+                entry.checksum.get_or_insert(checksum);
+            }
+            Err(reason) => {
+                let q = quarantine(&file_hist_root, manifest, &file_name, reason);
+                result = Err(OkpError::UnexpectedError(format!(
+                    "The backup {} is corrupt. {}",
+                    q.file_name, q.reason
+                )));
+            }
+        }
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file_name: &str, created: i64, reason: BackupReason, remote_modified: Option<i64>) -> BackupManifestEntry {
+        BackupManifestEntry {
+            file_name: file_name.into(),
+            created,
+            reason,
+            source_db_key: "Sftp-id-/db/Test1.kdbx".into(),
+            app_version: "0.16.0".into(),
+            checksum: None,
+            remote_modified,
+        }
+    }
+
+    #[test]
+    fn verify_backup_manifest_queries_and_pruning() {
+        let mut manifest = BackupManifest {
+            entries: vec![
+                entry("Test1_100.kdbx", 100, BackupReason::OnRead, Some(90)),
+                entry("Test1_200.kdbx", 200, BackupReason::OnSave, Some(200)),
+                entry("Test1_300.kdbx", 300, BackupReason::OnSaveError, None),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!("Test1_300.kdbx", manifest.latest_entry().unwrap().file_name);
+        assert_eq!(
+            "Test1_200.kdbx",
+            manifest.latest_synced_entry().unwrap().file_name
+        );
+
+        // The latest synced backup is not pruned even when it is older
+        manifest.entries.push(entry("Test1_400.kdbx", 400, BackupReason::OnSaveError, None));
+        assert_eq!(
+            vec!["Test1_100.kdbx".to_string(), "Test1_300.kdbx".to_string()],
+            manifest.entries_to_prune(2)
+        );
+        assert!(manifest.entries_to_prune(4).is_empty());
+
+        // Missing files are dropped and the unknown files are added using their modified time
+        manifest.reconcile(&[
+            ("Test1_200.kdbx".into(), 200),
+            ("Test1_400.kdbx".into(), 400),
+            ("Test1_50.kdbx".into(), 150),
+        ]);
+        let names = manifest
+            .entries
+            .iter()
+            .map(|e| e.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Test1_50.kdbx", "Test1_200.kdbx", "Test1_400.kdbx"], names);
+        assert_eq!(BackupReason::Unknown, manifest.entries[0].reason);
+        assert_eq!(Some(150), manifest.entries[0].remote_modified);
+
+        let json = serde_json::to_string(&manifest.entries[2]).unwrap();
+        assert!(json.contains("\"reason\":\"on-save-error\""));
+    }
+
+    #[test]
+    fn verify_history_dir_move_keeps_quarantined() {
+        let root = std::env::temp_dir().join("okp_backup_move_test");
+        let _ = fs::remove_dir_all(&root);
+        let (old_root, new_root) = (root.join("old"), root.join("new"));
+        fs::create_dir_all(old_root.join(QUARANTINE_DIR_NAME)).unwrap();
+        fs::create_dir_all(&new_root).unwrap();
+
+        fs::write(old_root.join("Test1_100.kdbx"), b"backup").unwrap();
+        fs::write(old_root.join(QUARANTINE_DIR_NAME).join("Test1_50.kdbx"), b"corrupt").unwrap();
+
+        let quarantined = QuarantinedBackup {
+            file_name: "Test1_50.kdbx".into(),
+            source_db_key: "Sftp-id-/db/Test1.kdbx".into(),
+            detected: 100,
+            reason: "Header hash check failed".into(),
+        };
+        let manifest = BackupManifest {
+            entries: vec![entry("Test1_100.kdbx", 100, BackupReason::OnRead, Some(90))],
+            quarantined: vec![quarantined],
+        };
+        manifest.write(&old_root).unwrap();
+
+        move_history_dir(&old_root, &new_root).unwrap();
+
+        assert!(!old_root.exists());
+        assert!(new_root.join("Test1_100.kdbx").exists());
+        assert!(new_root.join(QUARANTINE_DIR_NAME).join("Test1_50.kdbx").exists());
+
+        let moved = BackupManifest::load(&new_root);
+        assert_eq!(1, moved.entries.len());
+        assert_eq!(1, moved.quarantined.len());
+        assert_eq!("Test1_50.kdbx", moved.quarantined[0].file_name);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    fn kdbx4_header(fields: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&KDBX_SIG1.to_le_bytes());
+        header.extend_from_slice(&KDBX_SIG2.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&KDBX4_MAJOR_VERSION.to_le_bytes());
+        for (id, data) in fields {
+            header.push(*id);
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(data);
+        }
+        header
+    }
+
+    #[test]
+    fn verify_kdbx_header_check() {
+        let header = kdbx4_header(&[(2, vec![1; 16]), (0, vec![13, 10, 13, 10])]);
+        let mut file = header.clone();
+        file.extend_from_slice(Sha256::digest(&header).as_slice());
+        // Header HMAC and some content
+        file.extend_from_slice(&[7u8; 48]);
+
+        assert!(check_kdbx_header(&mut file.as_slice()).is_ok());
+
+        // Truncated file
+        assert!(check_kdbx_header(&mut &file[..20]).is_err());
+        assert!(check_kdbx_header(&mut &file[..header.len() + 32]).is_err());
+
+        // A changed header byte fails the header hash check
+        let mut changed = file.clone();
+        changed[20] ^= 0xFF;
+        assert_eq!(
+            Err("Header hash does not match".to_string()),
+            check_kdbx_header(&mut changed.as_slice())
+        );
+
+        let mut not_kdbx = file.clone();
+        not_kdbx[0] = 0;
+        assert!(check_kdbx_header(&mut not_kdbx.as_slice()).is_err());
+
+        // A corrupt field size is rejected without reading that much data
+        let mut huge_field = kdbx4_header(&[]);
+        huge_field.push(2);
+        huge_field.extend_from_slice(&u32::MAX.to_le_bytes());
+        huge_field.extend_from_slice(&[7u8; 64]);
+        assert!(check_kdbx_header(&mut huge_field.as_slice()).is_err());
+    }
+}
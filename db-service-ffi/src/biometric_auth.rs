@@ -1,15 +1,47 @@
 use log::{self, debug};
-use onekeepass_core::{error, service_util::string_to_simple_hash};
+use onekeepass_core::{
+    error,
+    service_util::{self, string_to_simple_hash},
+};
 use serde::{Deserialize, Serialize};
 
-use crate::{app_state::AppState, udl_types::SecureKeyOperationError, OkpResult};
+use crate::{
+    app_preference::BiometricPolicy, app_state::AppState, udl_types::SecureKeyOperationError,
+    OkpError, OkpResult,
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub(crate) struct StoredCredential {
     pub(crate) password: Option<String>,
     pub(crate) key_file_name: Option<String>,
+    // In milli seconds. The time the password was last entered for this db.
+    // This is None for the credentials stored by the earlier app versions till the next biometric open
+    #[serde(default)]
+    pub(crate) password_entered_time: Option<i64>,
+    // Number of biometric opens since the password was last entered
+    #[serde(default)]
+    pub(crate) biometric_unlocks: u32,
 }
 
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PasswordRequiredReason {
+    MaxBiometricUnlocksReached,
+    MaxDaysSincePasswordReached,
+}
+
+// Returned to the UI after a successful biometric authentication
+#[derive(Serialize, Debug)]
+pub(crate) struct BiometricCredentials {
+    // None when the password entry is mandatory
+    #[serde(flatten)]
+    credentials: Option<StoredCredential>,
+    // The UI needs to ask for the password even though the biometric authentication is successful
+    password_required: bool,
+    password_required_reason: Option<PasswordRequiredReason>,
+}
+
+const DAY_IN_MILLI_SECONDS: i64 = 24 * 60 * 60 * 1000;
+
 #[inline]
 fn key_store_formatted_key(db_key: &str) -> String {
     format!("OKP-DB-OPEN-{}", string_to_simple_hash(db_key))
@@ -21,30 +53,50 @@ fn key_store_formatted_key(db_key: &str) -> String {
 const OKP_DB_OPEN_TAG: &str = "OKP_DB_OPEN_KEY";
 
 impl StoredCredential {
+    // Returns true when the credentials are stored again after the stale ones were removed
     pub(crate) fn store_credentials_on_check(
         db_key: &str,
         password: &Option<String>,
         key_file_name: &Option<String>,
         biometric_auth_used: bool,
-    ) -> OkpResult<()> {
+    ) -> OkpResult<bool> {
         let bio_enabled = AppState::db_open_biometeric_enabled(db_key);
 
         // debug!("Flags are {}, {}", bio_enabled, !biometric_auth_used);
 
         if bio_enabled && !biometric_auth_used {
+            // The biometric use limits start again with the password entry
             let mut sc = StoredCredential {
                 password: password.clone(),
                 key_file_name: key_file_name.clone(),
+                password_entered_time: Some(service_util::now_utc_milli_seconds()),
+                biometric_unlocks: 0,
             };
 
-            let r = sc.store_credentials(db_key).inspect_err(|e| {
+            sc.store_credentials(db_key).inspect_err(|e| {
                 log::debug!("Storing credentials failed with eroor {e}");
-            });
+            })?;
             // debug!("store_credentials_on_check done {:?}", &r);
-            return r;
+            Ok(AppState::remove_stale_biometric_db_key(db_key))
+        } else if bio_enabled {
+            // The db is opened with the stored credentials
+            Self::record_biometric_unlock(db_key).map(|_| false)
         } else {
             // debug!("No credentials stored for this key {} as bio_enabled {} and biometric_auth_used {}", &db_key, bio_enabled, biometric_auth_used);
-            Ok(())
+            Ok(false)
+        }
+    }
+
+    // Called when the db open with the stored credentials fails. This happens when the master password is changed
+    // in another device. The stale credentials are removed so that the UI asks for the password instead of failing
+    // on every biometric open and these are stored again on the next successful open with the password
+    pub(crate) fn remove_stale_credentials(db_key: &str) {
+        match remove_credentials_from_key_store(db_key) {
+            Ok(()) => {
+                log::info!("Stale biometric credentials of the db_key {} are removed", db_key);
+                AppState::add_stale_biometric_db_key(db_key);
+            }
+            Err(e) => log::error!("Removing the stale biometric credentials failed {}", e),
         }
     }
 
@@ -68,6 +120,84 @@ impl StoredCredential {
         Ok(())
     }
 
+    // The stored credentials are not made available to the UI for a db hidden under the duress mode
+    // The credentials are removed when the biometric use limits of the db are reached and the UI is
+    // informed that the password entry is mandatory
+    pub(crate) fn available_credentials(db_key: &str) -> Option<BiometricCredentials> {
+        if AppState::db_hidden_under_duress(db_key) {
+            return None;
+        }
+        let sc = Self::get_credentials(db_key)?;
+
+        let policy = AppState::biometric_policy(db_key);
+        let now = service_util::now_utc_milli_seconds();
+        if let Some(reason) = sc.password_required_reason(&policy, now) {
+            log::info!("Stored credentials of the db_key {} are removed as {:?}", db_key, reason);
+            let _ = remove_credentials_from_key_store(db_key);
+            return Some(BiometricCredentials {
+                credentials: None,
+                password_required: true,
+                password_required_reason: Some(reason),
+            });
+        }
+
+        Some(BiometricCredentials {
+            credentials: Some(sc),
+            password_required: false,
+            password_required_reason: None,
+        })
+    }
+
+    fn password_required_reason(
+        &self,
+        policy: &BiometricPolicy,
+        now: i64,
+    ) -> Option<PasswordRequiredReason> {
+        if let Some(max) = policy.max_unlocks {
+            if self.biometric_unlocks >= max {
+                return Some(PasswordRequiredReason::MaxBiometricUnlocksReached);
+            }
+        }
+        if let (Some(days), Some(entered)) = (policy.max_days, self.password_entered_time) {
+            if now - entered >= days as i64 * DAY_IN_MILLI_SECONDS {
+                return Some(PasswordRequiredReason::MaxDaysSincePasswordReached);
+            }
+        }
+        None
+    }
+
+    // Called before a locked db is unlocked with the biometric authentication. The use limits of the stored credentials
+    // apply to these unlocks as well and the unlock is counted the same way as a biometric open
+    pub(crate) fn check_and_record_biometric_unlock(db_key: &str) -> OkpResult<()> {
+        let Some(sc) = Self::get_credentials(db_key) else {
+            return Ok(());
+        };
+
+        let policy = AppState::biometric_policy(db_key);
+        let now = service_util::now_utc_milli_seconds();
+        if let Some(reason) = sc.password_required_reason(&policy, now) {
+            log::info!("Stored credentials of the db_key {} are removed as {:?}", db_key, reason);
+            let _ = remove_credentials_from_key_store(db_key);
+            return Err(OkpError::DataError(
+                "The biometric use limit of this database is reached. Please unlock with the password",
+            ));
+        }
+
+        Self::record_biometric_unlock(db_key)
+    }
+
+    // Also called from the iOS autofill extension as the db open there uses the stored credentials of the main app
+    pub(crate) fn record_biometric_unlock(db_key: &str) -> OkpResult<()> {
+        let Some(mut sc) = Self::get_credentials(db_key) else {
+            return Ok(());
+        };
+        sc.biometric_unlocks = sc.biometric_unlocks.saturating_add(1);
+        // The days limit is counted from now for the credentials stored by the earlier app versions
+        sc.password_entered_time
+            .get_or_insert(service_util::now_utc_milli_seconds());
+        sc.store_credentials(db_key)
+    }
+
     // Any previously stored credentials are retrieved if found. It is expected this is called
     // after a successful biometric authentication from the UI side
     pub(crate) fn get_credentials(db_key: &str) -> Option<Self> {
@@ -146,9 +276,25 @@ impl StoredCredential {
         }
     }
 
+    // The key store entry name and the size of the stored credentials of this db if any
+    pub(crate) fn key_store_entry(db_key: &str) -> Option<(String, usize)> {
+        let ks_key = key_store_formatted_key(db_key);
+        let size = KeyStoreServiceImpl {}.get_key(&ks_key)?.len();
+        Some((ks_key, size))
+    }
+
     pub(crate) fn remove_credentials(db_key: &str) -> OkpResult<()> {
         remove_credentials_from_key_store(db_key)
     }
+
+    // Called when a db file is renamed so that the biometric open continues to work with the new db_key
+    pub(crate) fn move_credentials(db_key: &str, new_db_key: &str) -> OkpResult<()> {
+        if let Some(mut sc) = Self::get_credentials(db_key) {
+            sc.store_credentials(new_db_key)?;
+            remove_credentials_from_key_store(db_key)?;
+        }
+        Ok(())
+    }
 }
 
 // TODO: Need to combine this with key_secure::KeyStoreServiceImpl
@@ -252,3 +398,72 @@ fn remove_credentials_from_key_store(db_key: &str) -> OkpResult<()> {
 //     }
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_password_required_reason() {
+        // Credentials stored by the earlier app version
+        let mut sc: StoredCredential =
+            serde_json::from_str(r#"{"password":"pwd","key_file_name":null}"#).unwrap();
+        assert_eq!(0, sc.biometric_unlocks);
+        assert_eq!(None, sc.password_entered_time);
+
+        let policy = BiometricPolicy {
+            max_unlocks: Some(3),
+            max_days: Some(2),
+        };
+        let now = 10 * DAY_IN_MILLI_SECONDS;
+
+        // The days limit is not checked without the password entry time
+        assert_eq!(None, sc.password_required_reason(&policy, now));
+
+        sc.biometric_unlocks = 3;
+        assert_eq!(
+            Some(PasswordRequiredReason::MaxBiometricUnlocksReached),
+            sc.password_required_reason(&policy, now)
+        );
+
+        sc.biometric_unlocks = 1;
+        sc.password_entered_time = Some(now - DAY_IN_MILLI_SECONDS);
+        assert_eq!(None, sc.password_required_reason(&policy, now));
+        sc.password_entered_time = Some(now - 2 * DAY_IN_MILLI_SECONDS);
+        assert_eq!(
+            Some(PasswordRequiredReason::MaxDaysSincePasswordReached),
+            sc.password_required_reason(&policy, now)
+        );
+
+        // No limits
+        sc.biometric_unlocks = 1000;
+        assert_eq!(None, sc.password_required_reason(&BiometricPolicy::default(), now));
+    }
+
+    #[test]
+    fn verify_biometric_credentials_json() {
+        let bc = BiometricCredentials {
+            credentials: None,
+            password_required: true,
+            password_required_reason: Some(PasswordRequiredReason::MaxDaysSincePasswordReached),
+        };
+        let v = serde_json::to_value(&bc).unwrap();
+        assert_eq!(true, v["password_required"]);
+        assert!(v.get("password").is_none());
+
+        let bc = BiometricCredentials {
+            credentials: Some(StoredCredential {
+                password: Some("pwd".into()),
+                key_file_name: None,
+                password_entered_time: Some(1),
+                biometric_unlocks: 2,
+            }),
+            password_required: false,
+            password_required_reason: None,
+        };
+        // The credential fields are at the top level as before
+        let v = serde_json::to_value(&bc).unwrap();
+        assert_eq!("pwd", v["password"]);
+        assert_eq!(false, v["password_required"]);
+    }
+}
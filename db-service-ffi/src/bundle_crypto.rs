@@ -0,0 +1,242 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+
+use onekeepass_core::db_service::error::{self, Result};
+
+// Password based encryption of the files exported from the app so that these can be imported in another device
+// e.g the remote connections bundle and the app archive
+
+// Argon2id parameters used for the new bundles. The values used are stored in the bundle
+const KDF_MEMORY_KIB: u32 = 64 * 1024;
+const KDF_ITERATIONS: u32 = 3;
+const KDF_PARALLELISM: u32 = 1;
+
+// The kdf params are read from the bundle file and the params outside these limits are not used
+// so that a crafted file can not make the key derivation use huge memory or run for a long time
+const KDF_MAX_MEMORY_KIB: u32 = 256 * 1024;
+const KDF_MAX_ITERATIONS: u32 = 10;
+const KDF_MAX_PARALLELISM: u32 = 4;
+
+const SALT_SIZE: usize = 32;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+
+// The format of a bundle file and the errors reported to the user while reading it
+pub(crate) struct BundleFormat {
+    pub(crate) name: &'static str,
+    pub(crate) version: u32,
+    pub(crate) invalid_file_error: &'static str,
+    pub(crate) newer_version_error: &'static str,
+    pub(crate) decryption_error: &'static str,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    // Base64 encoded
+    salt: String,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::new(KDF_MEMORY_KIB, KDF_ITERATIONS, KDF_PARALLELISM)
+    }
+}
+
+impl KdfParams {
+    pub(crate) fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            memory_kib,
+            iterations,
+            parallelism,
+            salt: BASE64.encode(&salt),
+        }
+    }
+
+    fn within_limits(&self) -> bool {
+        (1..=KDF_MAX_PARALLELISM).contains(&self.parallelism)
+            && (1..=KDF_MAX_ITERATIONS).contains(&self.iterations)
+            && (8 * self.parallelism..=KDF_MAX_MEMORY_KIB).contains(&self.memory_kib)
+    }
+
+    fn derive_key(&self, password: &str) -> Result<[u8; KEY_SIZE]> {
+        let salt = BASE64
+            .decode(self.salt.as_bytes())
+            .map_err(|_| error::Error::DataError("Invalid salt in the bundle"))?;
+
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_SIZE),
+        )
+        .map_err(|e| error::Error::UnexpectedError(format!("Invalid kdf params {}", e)))?;
+
+        let mut key = [0u8; KEY_SIZE];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, &mut key)
+            .map_err(|e| error::Error::UnexpectedError(format!("Key derivation failed {}", e)))?;
+
+        Ok(key)
+    }
+}
+
+// This is the json content of the exported bundle file
+#[derive(Serialize, Deserialize, Debug)]
+struct EncryptedBundle {
+    format: String,
+    version: u32,
+    kdf: KdfParams,
+    // Base64 encoded
+    nonce: String,
+    // Base64 encoded encrypted data
+    data: String,
+}
+
+impl EncryptedBundle {
+    // The header fields are used as the associated data so that any change to these is detected in the decryption
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            self.format,
+            self.version,
+            self.kdf.memory_kib,
+            self.kdf.iterations,
+            self.kdf.parallelism,
+            self.kdf.salt
+        )
+        .into_bytes()
+    }
+}
+
+pub(crate) fn encrypt(
+    format: &BundleFormat,
+    plain_data: &[u8],
+    password: &str,
+    kdf: KdfParams,
+) -> Result<Vec<u8>> {
+    let key = kdf.derive_key(password)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut bundle = EncryptedBundle {
+        format: format.name.into(),
+        version: format.version,
+        kdf,
+        nonce: BASE64.encode(&nonce),
+        data: String::default(),
+    };
+
+    let aad = bundle.associated_data();
+    let encrypted_data = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plain_data,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| {
+            error::Error::UnexpectedError(format!("Encryption of the {} failed", format.name))
+        })?;
+    bundle.data = BASE64.encode(&encrypted_data);
+
+    Ok(serde_json::to_vec_pretty(&bundle)?)
+}
+
+pub(crate) fn decrypt(format: &BundleFormat, bundle_data: &[u8], password: &str) -> Result<Vec<u8>> {
+    let bundle: EncryptedBundle = serde_json::from_slice(bundle_data)
+        .map_err(|_| error::Error::DataError(format.invalid_file_error))?;
+
+    if bundle.format != format.name {
+        return Err(error::Error::DataError(format.invalid_file_error));
+    }
+
+    if bundle.version > format.version {
+        return Err(error::Error::DataError(format.newer_version_error));
+    }
+
+    if !bundle.kdf.within_limits() {
+        return Err(error::Error::DataError(format.invalid_file_error));
+    }
+
+    let key = bundle.kdf.derive_key(password)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+
+    let nonce = BASE64
+        .decode(bundle.nonce.as_bytes())
+        .map_err(|_| error::Error::DataError(format.invalid_file_error))?;
+    if nonce.len() != NONCE_SIZE {
+        return Err(error::Error::DataError(format.invalid_file_error));
+    }
+    let encrypted_data = BASE64
+        .decode(bundle.data.as_bytes())
+        .map_err(|_| error::Error::DataError(format.invalid_file_error))?;
+
+    let aad = bundle.associated_data();
+    cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &encrypted_data,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| error::Error::DataError(format.decryption_error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FORMAT: BundleFormat = BundleFormat {
+        name: "OneKeePass-Test",
+        version: 1,
+        invalid_file_error: "Invalid file",
+        newer_version_error: "Newer version",
+        decryption_error: "Decryption failed",
+    };
+
+    fn bundle_with<F: FnOnce(&mut EncryptedBundle)>(data: &[u8], f: F) -> Vec<u8> {
+        let mut bundle: EncryptedBundle = serde_json::from_slice(data).unwrap();
+        f(&mut bundle);
+        serde_json::to_vec(&bundle).unwrap()
+    }
+
+    #[test]
+    fn verify_header_and_kdf_limits() {
+        let data = encrypt(&TEST_FORMAT, b"content", "pwd", KdfParams::new(64, 1, 1)).unwrap();
+        assert_eq!(b"content".to_vec(), decrypt(&TEST_FORMAT, &data, "pwd").unwrap());
+
+        // Any change to the header is detected
+        let changed = bundle_with(&data, |b| b.kdf.iterations = 2);
+        assert!(matches!(
+            decrypt(&TEST_FORMAT, &changed, "pwd"),
+            Err(error::Error::DataError("Decryption failed"))
+        ));
+
+        // The params outside the limits are not used for the key derivation
+        for (memory_kib, iterations, parallelism) in
+            [(u32::MAX, 1, 1), (64, u32::MAX, 1), (64, 1, 64), (64, 0, 1), (4, 1, 1)]
+        {
+            let changed = bundle_with(&data, |b| {
+                b.kdf.memory_kib = memory_kib;
+                b.kdf.iterations = iterations;
+                b.kdf.parallelism = parallelism;
+            });
+            assert!(matches!(
+                decrypt(&TEST_FORMAT, &changed, "pwd"),
+                Err(error::Error::DataError("Invalid file"))
+            ));
+        }
+    }
+}
@@ -3,8 +3,16 @@ use crate::app_state::AppState;
 use crate::auto_open::AutoOpenProperties;
 use crate::file_util::PickedFileHandler;
 use crate::remote_storage::{self, RemoteStorageOperation};
-use crate::{android, file_util::KeyFileInfo, ios};
-use crate::{app_lock, backup, biometric_auth, util, OkpError, OkpResult};
+use crate::selective_reset::{self, ResetCategory};
+use crate::{
+    android,
+    file_util::KeyFileInfo,
+    ios,
+    key_file::{self, KeyFileFormat},
+};
+use crate::{
+    app_archive, app_lock, backup, biometric_auth, db_mirror, db_relocate, util, OkpError, OkpResult,
+};
 use onekeepass_core::async_service::{self, OtpTokenTtlInfoByField, TimerID};
 use onekeepass_core::db_content::AttachmentHashValue;
 use onekeepass_core::db_service::{
@@ -26,10 +34,10 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
-struct ExportDataInfo {
-    full_file_name_uri: Option<String>,
-    file_name: Option<String>,
-    exported_data_full_file_name: Option<String>,
+pub(crate) struct ExportDataInfo {
+    pub(crate) full_file_name_uri: Option<String>,
+    pub(crate) file_name: Option<String>,
+    pub(crate) exported_data_full_file_name: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -78,6 +86,8 @@ pub enum CommandArg {
         timeout_type: u8,
         db_session_timeout: Option<i64>,
         clipboard_timeout: Option<i64>,
+        // The timeouts are used as the overrides of this db when passed
+        db_key: Option<String>,
     },
     PrefefenceUpdateArg {
         preference_data: PreferenceData,
@@ -85,6 +95,9 @@ pub enum CommandArg {
     AppLockCredentialArg {
         pin: usize,
     },
+    AppLockPassphraseArg {
+        passphrase: String,
+    },
 
     // Not used
     // OpenDbArgWithFileName {
@@ -98,6 +111,8 @@ pub enum CommandArg {
         password: Option<String>,
         key_file_name: Option<String>,
         biometric_auth_used: bool,
+        // Used only for the remote storage db read so that the UI can cancel the transfer
+        request_id: Option<String>,
     },
     NewDbArgWithFileName {
         file_name: String,
@@ -137,6 +152,16 @@ pub enum CommandArg {
         new_db_key: String,
         file_name: String,
     },
+    DbMirrorArg {
+        db_key: String,
+        // The remote storage db_key of the mirror location
+        mirror_db_key: String,
+    },
+    GitVersionArg {
+        db_key: String,
+        // A commit found in the git history of the db file
+        commit_id: String,
+    },
     GroupArg {
         db_key: String,
         group: Group,
@@ -167,6 +192,13 @@ pub enum CommandArg {
     SaveDbArg {
         db_key: String,
         overwrite: bool,
+        // Used only for the remote storage db save so that the UI can cancel the transfer
+        request_id: Option<String>,
+    },
+
+    // Should come after OpenDbArg and SaveDbArg as they also have the field 'request_id'
+    TransferRequestArg {
+        request_id: String,
     },
 
     MergeDbs {
@@ -200,6 +232,8 @@ pub enum CommandArg {
         protected: bool,
         //The field 'cleanup_after' has clipboard timeout in seconds and 0 sec menas no timeout
         cleanup_after: u32,
+        // When passed, the clipboard timeout of this db is used instead of 'cleanup_after'
+        db_key: Option<String>,
     },
 
     // Should come after StartTimerArg
@@ -211,10 +245,72 @@ pub enum CommandArg {
         language_ids: Vec<String>,
     },
 
+    // Should come before RemoteServerOperationArg. The required field 'max_depth' ensures that
+    // RemoteServerOperationArg is not matched to this variant
+    FindKdbxFilesArg {
+        rs_operation_type: remote_storage::RemoteStorageOperationType,
+        max_depth: usize,
+        start_dir: Option<String>,
+        max_entries: Option<usize>,
+    },
+
     RemoteServerOperationArg {
         rs_operation_type: remote_storage::RemoteStorageOperationType,
     },
 
+    RsConfigsExportArg {
+        sftp_connection_ids: Vec<Uuid>,
+        webdav_connection_ids: Vec<Uuid>,
+        bundle_password: String,
+    },
+
+    // Should come before RsConfigsBundleArg as this has the additional required field 'resolutions'
+    RsConfigsImportArg {
+        bundle_file_name: String,
+        bundle_password: String,
+        // User's choice for each incoming config whose connection_id is already used
+        resolutions: HashMap<Uuid, remote_storage::CollisionResolution>,
+    },
+
+    RsConfigsBundleArg {
+        bundle_file_name: String,
+        bundle_password: String,
+    },
+
+    // Should come before AppArchiveArg as this has the additional required field 'db_key_remaps'
+    AppArchiveImportArg {
+        archive_file_name: String,
+        archive_password: String,
+        // The archived local db_keys mapped to the db_keys of the same db files picked in this device
+        db_key_remaps: HashMap<String, String>,
+    },
+
+    AppArchiveArg {
+        archive_file_name: String,
+        archive_password: String,
+    },
+
+    AppArchiveExportArg {
+        archive_password: String,
+        #[serde(default)]
+        include_backups: bool,
+    },
+
+    SelectiveResetArg {
+        categories: Vec<ResetCategory>,
+        // The db specific categories are cleared only for these dbs when passed
+        db_keys: Option<Vec<String>>,
+        dry_run: bool,
+    },
+
+    // Should come before DbKey. The field 'delete_source' is required so that only db_key is not matched here
+    RelocateDbArg {
+        db_key: String,
+        delete_source: bool,
+        // The remote storage db_key when the db is relocated to a remote storage
+        target_db_key: Option<String>,
+    },
+
     PickedFileHandlerArg {
         picked_file_handler: PickedFileHandler,
     },
@@ -443,6 +539,12 @@ impl Commands {
             return InvokeResult::<()>::with_error("Command name is empty").json_str();
         }
 
+        // Any command call on a db is an activity that extends its session. The commands on a db locked on
+        // its session timeout are rejected till it is unlocked
+        if let Err(e) = crate::session_timeout::check_and_touch_from_args(&command_name, &args) {
+            return InvokeResult::<()>::with_error(e).json_str();
+        }
+
         let r = match command_name.as_str() {
             "new_entry_form_data" => {
                 db_service_call!(args,NewEntryArg{db_key,entry_type_uuid,parent_group_uuid} =>
@@ -450,7 +552,7 @@ impl Commands {
             }
 
             "unlock_kdbx" => {
-                service_call!(args, OpenDbArg{db_file_name,password,key_file_name,biometric_auth_used: _} =>
+                service_call!(args, OpenDbArg{db_file_name,password,key_file_name,biometric_auth_used: _,request_id: _} =>
                     Self unlock_kdbx(&db_file_name,password.as_deref(),key_file_name.as_deref()))
             }
 
@@ -458,7 +560,7 @@ impl Commands {
                 service_call!(args, DbKey{db_key} => Self unlock_kdbx_on_biometric_authentication(&db_key))
             }
 
-            "close_kdbx" => db_service_call!(args, DbKey{db_key} => close_kdbx(&db_key)),
+            "close_kdbx" => service_call!(args, DbKey{db_key} => Self close_kdbx(&db_key)),
 
             "combined_category_details" => {
                 db_service_call! (args, CategoryDetailArg{db_key,grouping_kind} => combined_category_details(&db_key,&grouping_kind))
@@ -592,8 +694,12 @@ impl Commands {
             }
 
             "update_session_timeout" => {
-                service_call!(args, SessionTimeoutArg {timeout_type: _,db_session_timeout,clipboard_timeout} =>
-                    Self update_session_timeout(db_session_timeout,clipboard_timeout))
+                service_call!(args, SessionTimeoutArg {timeout_type: _,db_session_timeout,clipboard_timeout,db_key} =>
+                    Self update_session_timeout(db_key,db_session_timeout,clipboard_timeout))
+            }
+
+            "resolved_db_settings" => {
+                service_ok_call!(args, DbKey {db_key} => AppState resolved_db_settings(&db_key))
             }
 
             "update_preference" => {
@@ -645,6 +751,13 @@ impl Commands {
 
             "list_key_files" => ok_json_str(util::list_key_files()),
 
+            "unused_key_files" => ok_json_str(key_file::unused_key_files()),
+
+            "app_entered_background" => {
+                crate::session_timeout::lock_dbs_on_app_background();
+                ok_json_str(())
+            }
+
             "clean_export_data_dir" => result_json_str(util::clean_export_data_dir()),
 
             "clipboard_copy_string" => Self::clipboard_copy_string(&args),
@@ -660,7 +773,7 @@ impl Commands {
             // }
             "stored_db_credentials" => {
                 service_call_closure!(args,DbKey {db_key}  => move || {
-                    ok_json_str(biometric_auth::StoredCredential::get_credentials(&db_key))
+                    ok_json_str(biometric_auth::StoredCredential::available_credentials(&db_key))
                 })
             }
 
@@ -676,6 +789,33 @@ impl Commands {
                 })
             }
 
+            // Returns the remaining attempts and any wait required before the next attempt along with the result
+            "pin_verify_attempt" => {
+                service_call_closure!(args,AppLockCredentialArg {pin}  => move || {
+                    result_json_str(app_lock::pin_verify_attempt(pin))
+                })
+            }
+
+            "passphrase_entered" => {
+                service_call_closure!(args,AppLockPassphraseArg {passphrase}  => move || {
+                    result_json_str(app_lock::passphrase_entered(&passphrase))
+                })
+            }
+
+            "passphrase_verify_attempt" => {
+                service_call_closure!(args,AppLockPassphraseArg {passphrase}  => move || {
+                    result_json_str(app_lock::passphrase_verify_attempt(&passphrase))
+                })
+            }
+
+            "duress_pin_entered" => {
+                service_call_closure!(args,AppLockCredentialArg {pin}  => move || {
+                    result_json_str(app_lock::duress_pin_entered(pin))
+                })
+            }
+
+            "duress_pin_removed" => result_json_str(app_lock::duress_pin_removed()),
+
             "pin_removed" => result_json_str(app_lock::pin_removed()),
 
             "app_reset" => result_json_str(app_lock::app_reset()),
@@ -723,18 +863,87 @@ impl Commands {
 
             "rs_create_kdbx" => crate::remote_storage::rs_create_kdbx(&args),
 
+            // Searches all .kdbx files under the start dir
+            "rs_find_kdbx_files" => crate::remote_storage::rs_find_kdbx_files(&args),
+
+            "rs_create_dir" => crate::remote_storage::rs_create_dir(&args),
+
+            "rs_rename" => crate::remote_storage::rs_rename(&args),
+
+            "rs_delete_file" => crate::remote_storage::rs_delete_file(&args),
+
+            "rs_copy" => crate::remote_storage::rs_copy(&args),
+
+            // Lists the versions of a db file stored in a git repository
+            "rs_git_file_history" => crate::remote_storage::rs_git_file_history(&args),
+
+            // Commits a previous version of a db file stored in a git repository as its latest version
+            "rs_git_restore_version" => crate::remote_storage::rs_git_restore_version(&args),
+
+            // Cancels an in-flight 'rs_read_kdbx' or 'rs_save_kdbx' call started with this request id
+            "rs_cancel_transfer" => {
+                service_call_closure!(args,TransferRequestArg {request_id} => move || {
+                    ok_json_str(remote_storage::cancel_transfer(&request_id))
+                })
+            }
+
+            // Runs each connection stage (dns, tcp, tls/ssh, auth, listing and write) and reports the result of each
+            "rs_diagnose" => {
+                service_call_closure!(args,RemoteServerOperationArg {rs_operation_type} => move || {
+                    result_json_str(rs_operation_type.diagnose())
+                })
+            }
+
             "rs_read_configs" => result_json_str(remote_storage::read_configs()),
 
+            // Exports the selected connection configs to a password encrypted bundle file
+            "rs_export_connection_configs" => {
+                crate::remote_storage::rs_export_connection_configs(&args)
+            }
+
+            // Lists the connections found in a picked bundle file along with any collision with existing configs
+            "rs_read_connection_configs_bundle" => {
+                crate::remote_storage::rs_read_connection_configs_bundle(&args)
+            }
+
+            "rs_import_connection_configs" => {
+                crate::remote_storage::rs_import_connection_configs(&args)
+            }
+
             "rs_delete_config" => {
                 service_call_closure!(args,RemoteServerOperationArg {rs_operation_type} => move || {
                     result_json_str(rs_operation_type.delete_config())
                 })
             }
+            // Mirrors are the remote locations to which a db is copied after each save
+            "db_mirrors" => result_json_str(db_mirror::db_mirrors(&args)),
+
+            "add_db_mirror" => result_json_str(db_mirror::add_db_mirror(&args)),
+
+            "remove_db_mirror" => result_json_str(db_mirror::remove_db_mirror(&args)),
+
             ////
             "read_latest_backup" => {
                 result_json_str(crate::db_backup_read::read_latest_backup(&args))
             }
 
+            // Exports the preference, remote connections, key files and optionally backups to a password encrypted archive
+            "export_app_archive" => result_json_str(app_archive::export_app_archive(&args)),
+
+            // Lists the content of a picked app archive file so that the user can remap the local dbs before the import
+            "read_app_archive" => result_json_str(app_archive::read_app_archive(&args)),
+
+            "import_app_archive" => result_json_str(app_archive::import_app_archive(&args)),
+
+            // Checks the header and checksum of all backup files and quarantines the corrupt ones
+            "verify_backups" => result_json_str(backup::verify_backups()),
+
+            // Relocates an opened db to a remote storage location. See 'udl_uniffi_exports::relocate_database' for a local file
+            "relocate_database" => result_json_str(db_relocate::relocate_to_remote(&args)),
+
+            // Returns the files and key store entries that are removed or would be removed in the dry run
+            "selective_app_reset" => result_json_str(selective_reset::selective_app_reset(&args)),
+
             // "list_backup_files" => ok_json_str(util::list_backup_files()),
             // "delete_key_file" => Self::delete_key_file(&args),
             "test_call" => Self::test_call(&args),
@@ -784,12 +993,22 @@ impl Commands {
         // In case of mobile, the file uri is just some handle and need to get the file name using mobile api
         kdbx_loaded.file_name = AppState::common_device_service().uri_to_file_name(db_key.into());
 
+        AppState::unlock_db_activity(db_key);
+
         Ok(kdbx_loaded)
     }
 
+    fn close_kdbx(db_key: &str) -> OkpResult<()> {
+        let r = db_service::close_kdbx(db_key);
+        remote_storage::remove_entries_snapshot(db_key);
+        r
+    }
+
     fn unlock_kdbx_on_biometric_authentication(db_key: &str) -> OkpResult<KdbxLoaded> {
+        biometric_auth::StoredCredential::check_and_record_biometric_unlock(db_key)?;
         let mut kdbx_loaded = db_service::unlock_kdbx_on_biometric_authentication(db_key)?;
         kdbx_loaded.file_name = AppState::common_device_service().uri_to_file_name(db_key.into());
+        AppState::unlock_db_activity(db_key);
         Ok(kdbx_loaded)
     }
 
@@ -819,7 +1038,16 @@ impl Commands {
                 "Full Key file name could not be formed",
             ));
         };
-        db_service::generate_key_file(full_file_name_str)?;
+
+        // The format is optional and the default one generated by db_service is used when it is not passed
+        if let Some(format) = key_vals.get("key_file_format") {
+            let format: KeyFileFormat =
+                serde_json::from_value(serde_json::Value::String(format.clone()))
+                    .map_err(|_| OkpError::DataError("Unsupported key file format"))?;
+            key_file::write_key_file(&path, format)?;
+        } else {
+            db_service::generate_key_file(full_file_name_str)?;
+        }
 
         debug!("Generated key file is {}", full_file_name_str);
 
@@ -834,7 +1062,9 @@ impl Commands {
         let Some(key_file_name) = key_vals.get("file_name") else {
             return Err(OkpError::DataError("Key file name to delete is not found"));
         };
-        util::delete_key_file(key_file_name);
+        // A key file used by a recent db is deleted only when 'force' is passed as "true"
+        let force = key_vals.get("force").is_some_and(|f| f == "true");
+        key_file::delete_key_file(key_file_name, force)?;
         Ok(util::list_key_files())
     }
 
@@ -854,14 +1084,27 @@ impl Commands {
 
     // TODO: Need to change UI side to use 'update_preference' and then deprecate this method
     fn update_session_timeout(
+        db_key: Option<String>,
         db_session_timeout: Option<i64>,
         clipboard_timeout: Option<i64>,
     ) -> OkpResult<()> {
-        AppState::update_session_timeout(db_session_timeout, clipboard_timeout)
+        AppState::update_session_timeout(db_key.as_deref(), db_session_timeout, clipboard_timeout)
     }
 
     fn update_preference(preference_data: PreferenceData) -> OkpResult<()> {
-        AppState::update_preference(preference_data)
+        let db_key = preference_data.db_key().map(|s| s.to_string());
+
+        AppState::update_preference(preference_data)?;
+
+        // Any autofill copy of the db is removed when the db is no longer allowed in autofill
+        #[cfg(target_os = "ios")]
+        if let Some(db_key) = db_key {
+            if !AppState::db_allowed_in_autofill(&db_key) {
+                let _ = crate::ios::autofill_app_group::delete_copied_autofill_details(&db_key);
+            }
+        }
+
+        Ok(())
     }
 
     fn prepare_export_kdbx_data(args: &str) -> String {
@@ -966,7 +1209,7 @@ impl Commands {
 
     // Gets the recent files list
     fn recently_used_dbs_info() -> ResponseJson {
-        ok_json_str(AppState::recent_dbs_info())
+        ok_json_str(AppState::visible_recent_dbs_info())
     }
 
     // Called to remove any backup files created during save kdbx call that resulted in some
@@ -980,7 +1223,7 @@ impl Commands {
     }
 
     fn app_preference() -> ResponseJson {
-        ok_json_str(AppState::preference_clone())
+        ok_json_str(AppState::preference_view())
     }
 
     fn load_language_translations(language_ids: Vec<String>) -> OkpResult<TranslationResource> {
@@ -1036,15 +1279,22 @@ impl Commands {
 
     fn clipboard_copy_string(json_args: &str) -> ResponseJson {
         let inner_fn = || -> OkpResult<()> {
-            let (field_name, field_value, protected, cleanup_after) = parse_command_args_or_err!(
+            let (field_name, field_value, protected, mut cleanup_after, db_key) = parse_command_args_or_err!(
                 json_args,
                 ClipboardCopyArg {
                     field_name,
                     field_value,
                     protected,
-                    cleanup_after
+                    cleanup_after,
+                    db_key
                 }
             );
+
+            if let Some(db_key) = db_key {
+                // The resolved timeout is in milli seconds and any value <= 0 means no timeout
+                let timeout = AppState::resolved_db_settings(&db_key).clipboard_timeout;
+                cleanup_after = if timeout > 0 { (timeout / 1000) as u32 } else { 0 };
+            }
             let cd = crate::udl_uniffi_exports::AppClipboardCopyData {
                 field_name,
                 field_value,
@@ -1310,10 +1560,265 @@ mod tests {
             timeout_type: _,
             db_session_timeout,
             clipboard_timeout,
+            db_key,
         }) = r
         {
             assert_eq!(Some(-1), db_session_timeout);
             assert_eq!(None, clipboard_timeout);
+            assert_eq!(None, db_key);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+    }
+
+    #[test]
+    fn verify_parsing_db_timeout_override_args() {
+        let in_json_str = r#"{ "timeout_type":1, "db_session_timeout":120000, "db_key":"file:///tmp/Team.kdbx"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::SessionTimeoutArg { db_key: Some(_), .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+
+        let in_json_str = r#"{"field_name":"Password","field_value":"secret","protected":true,"cleanup_after":10,"db_key":"file:///tmp/Team.kdbx"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::ClipboardCopyArg { db_key: Some(_), .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+    }
+
+    #[test]
+    fn verify_parsing_transfer_request_arg() {
+        let in_json_str = r#"{"request_id":"1234"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::TransferRequestArg { request_id }) = r {
+            assert_eq!("1234", request_id);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        // SaveDbArg should be matched before TransferRequestArg
+        let in_json_str = r#"{"db_key":"Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/Test1.kdbx","overwrite":false,"request_id":"1234"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::SaveDbArg { request_id, .. }) = r {
+            assert_eq!(Some("1234".to_string()), request_id);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+    }
+
+    #[test]
+    fn verify_parsing_rs_rename_arg() {
+        let in_json_str = r#"{"rs_operation_type":{"type":"Sftp","connection_id":"264226dc-be96-462a-a386-79adb6291ad7","file_path":"/dav/Test1.kdbx","target_path":"/dav/Test2.kdbx"}}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::RemoteServerOperationArg { rs_operation_type }) = r {
+            use crate::remote_storage::RemoteStorageOperation;
+            assert_eq!(Some("/dav/Test2.kdbx"), rs_operation_type.target_path());
+            assert_eq!(
+                Some("Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/Test2.kdbx".to_string()),
+                rs_operation_type.db_key_for("/dav/Test2.kdbx")
+            );
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+    }
+
+    #[test]
+    fn verify_parsing_find_kdbx_files_arg() {
+        let in_json_str = r#"{"rs_operation_type":{"type":"Webdav","connection_id":"264226dc-be96-462a-a386-79adb6291ad7"},"max_depth":3}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::FindKdbxFilesArg {
+            max_depth,
+            start_dir,
+            max_entries,
+            ..
+        }) = r
+        {
+            assert_eq!(3, max_depth);
+            assert_eq!(None, start_dir);
+            assert_eq!(None, max_entries);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        // Without max_depth, the arg should be parsed as RemoteServerOperationArg
+        let in_json_str = r#"{"rs_operation_type":{"type":"Webdav","connection_id":"264226dc-be96-462a-a386-79adb6291ad7"}}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::RemoteServerOperationArg { .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+    }
+
+    #[test]
+    fn verify_parsing_rs_configs_import_arg() {
+        let in_json_str = r#"{"bundle_file_name":"/tmp/OneKeePass-Connections.okpconn","bundle_password":"pwd","resolutions":{"264226dc-be96-462a-a386-79adb6291ad7":{"action":"Rename","name":"Office"}}}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::RsConfigsImportArg { resolutions, .. }) = r {
+            let id = uuid::Uuid::parse_str("264226dc-be96-462a-a386-79adb6291ad7").unwrap();
+            assert!(matches!(
+                resolutions.get(&id),
+                Some(crate::remote_storage::CollisionResolution::Rename { name }) if name == "Office"
+            ));
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        // Without resolutions, the arg should be parsed as RsConfigsBundleArg
+        let in_json_str = r#"{"bundle_file_name":"/tmp/OneKeePass-Connections.okpconn","bundle_password":"pwd"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::RsConfigsBundleArg { .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+    }
+
+    #[test]
+    fn verify_parsing_selective_reset_args() {
+        use crate::selective_reset::ResetCategory;
+
+        let in_json_str = r#"{"categories":["Backups","BiometricCredentials"],"db_keys":["file:///Users/test/Test1.kdbx"],"dry_run":true}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::SelectiveResetArg {
+            categories,
+            db_keys,
+            dry_run,
+        }) = r
+        {
+            assert_eq!(
+                vec![ResetCategory::Backups, ResetCategory::BiometricCredentials],
+                categories
+            );
+            assert_eq!(Some(vec!["file:///Users/test/Test1.kdbx".to_string()]), db_keys);
+            assert!(dry_run);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        let r = serde_json::from_str::<CommandArg>(r#"{"categories":["KeyFiles"],"dry_run":false}"#);
+        assert!(matches!(r, Ok(CommandArg::SelectiveResetArg { db_keys: None, .. })));
+    }
+
+    #[test]
+    fn verify_parsing_relocate_db_args() {
+        let in_json_str = r#"{"db_key":"file:///old/Test1.kdbx","delete_source":true,"target_db_key":"Sftp-264226dc-be96-462a-a386-79adb6291ad7-/home/user/Test1.kdbx"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::RelocateDbArg {
+            delete_source,
+            target_db_key,
+            ..
+        }) = r
+        {
+            assert!(delete_source);
+            assert!(target_db_key.is_some());
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        // target_db_key is optional for a local file target
+        let in_json_str = r#"{"db_key":"file:///old/Test1.kdbx","delete_source":false}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(
+                r,
+                Ok(CommandArg::RelocateDbArg {
+                    target_db_key: None,
+                    ..
+                })
+            ),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+
+        // Only db_key should still be parsed as DbKey
+        let in_json_str = r#"{"db_key":"file:///old/Test1.kdbx"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::DbKey { .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+    }
+
+    #[test]
+    fn verify_parsing_app_archive_args() {
+        let in_json_str = r#"{"archive_file_name":"/tmp/OneKeePass-AppArchive.okparchive","archive_password":"pwd","db_key_remaps":{"content://old/Test1.kdbx":"file:///new/Test1.kdbx"}}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::AppArchiveImportArg { db_key_remaps, .. }) = r {
+            assert_eq!(
+                Some(&"file:///new/Test1.kdbx".to_string()),
+                db_key_remaps.get("content://old/Test1.kdbx")
+            );
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        let in_json_str = r#"{"archive_file_name":"/tmp/OneKeePass-AppArchive.okparchive","archive_password":"pwd"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(r, Ok(CommandArg::AppArchiveArg { .. })),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+
+        // include_backups is optional
+        let in_json_str = r#"{"archive_password":"pwd"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        assert!(
+            matches!(
+                r,
+                Ok(CommandArg::AppArchiveExportArg {
+                    include_backups: false,
+                    ..
+                })
+            ),
+            "Invalid parsing of json str as  {:?} ",
+            &r
+        );
+    }
+
+    #[test]
+    fn verify_parsing_db_mirror_arg() {
+        let in_json_str = r#"{"db_key":"file:///Users/test/Test1.kdbx","mirror_db_key":"Sftp-264226dc-be96-462a-a386-79adb6291ad7-/backups/Test1.kdbx"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::DbMirrorArg {
+            db_key,
+            mirror_db_key,
+        }) = r
+        {
+            assert_eq!("file:///Users/test/Test1.kdbx", db_key);
+            assert!(mirror_db_key.starts_with("Sftp-"));
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+    }
+
+    #[test]
+    fn verify_parsing_app_lock_passphrase_arg() {
+        let r = serde_json::from_str::<CommandArg>(r#"{"passphrase":"My pass 123"}"#);
+        if let Ok(CommandArg::AppLockPassphraseArg { passphrase }) = r {
+            assert_eq!("My pass 123", passphrase);
+        } else {
+            assert!(false, "Invalid parsing of json str as  {:?} ", &r);
+        }
+
+        // Numeric PIN is still parsed as before
+        let r = serde_json::from_str::<CommandArg>(r#"{"pin":1234}"#);
+        assert!(matches!(r, Ok(CommandArg::AppLockCredentialArg { pin: 1234 })));
+    }
+
+    #[test]
+    fn verify_parsing_git_version_arg() {
+        let in_json_str = r#"{"db_key":"Git-264226dc-be96-462a-a386-79adb6291ad7-/databases/Test1.kdbx","commit_id":"3f5c2a9e8d7b6a5c4e3f2a1b0c9d8e7f6a5b4c3d"}"#;
+        let r = serde_json::from_str::<CommandArg>(in_json_str);
+        if let Ok(CommandArg::GitVersionArg { db_key, commit_id }) = r {
+            assert!(db_key.starts_with("Git-"));
+            assert_eq!(40, commit_id.len());
         } else {
             assert!(false, "Invalid parsing of json str as  {:?} ", &r);
         }
@@ -1,7 +1,7 @@
 use std::fs;
 
 use crate::app_state::AppState;
-use crate::backup::latest_backup_file_path;
+use crate::backup::{self, latest_backup_file_path};
 use crate::CommandArg;
 use crate::{parse_command_args_or_err, OkpError, OkpResult};
 use onekeepass_core::db_service::KdbxLoaded;
@@ -24,9 +24,16 @@ pub(crate) struct KdbxLoadedEx {
     file_name: Option<String>,
     key_file_name: Option<String>,
     rs_additional_info: Option<RsAdditionalInfo>,
+    // Set when the stale biometric credentials are replaced with the credentials used in this open
+    biometric_credentials_refreshed: bool,
 }
 
 impl KdbxLoadedEx {
+    pub(crate) fn set_biometric_credentials_refreshed(mut self, refreshed: bool) -> Self {
+        self.biometric_credentials_refreshed = refreshed;
+        self
+    }
+
     pub(crate) fn set_no_read_connection(mut self) -> Self {
         self.rs_additional_info = Some(RsAdditionalInfo {
             no_connection: true,
@@ -50,18 +57,20 @@ impl From<KdbxLoaded> for KdbxLoadedEx {
             file_name,
             key_file_name,
             rs_additional_info: None,
+            biometric_credentials_refreshed: false,
         }
     }
 }
 
 pub(crate) fn read_latest_backup(json_args: &str) -> OkpResult<KdbxLoadedEx> {
-    let (db_file_name, password, key_file_name, _) = parse_command_args_or_err!(
+    let (db_file_name, password, key_file_name, _, _) = parse_command_args_or_err!(
         json_args,
         OpenDbArg {
             db_file_name,
             password,
             key_file_name,
-            biometric_auth_used
+            biometric_auth_used,
+            request_id
         }
     );
     let file_name = AppState::file_name_in_recently_used(&db_file_name);
@@ -74,9 +83,17 @@ pub(crate) fn read_latest_backup_db_arg(
     key_file_name: &Option<String>,
     file_name: &Option<String>,
 ) -> OkpResult<KdbxLoadedEx> {
-    let path = latest_backup_file_path(&db_file_name).ok_or(error::Error::UnexpectedError(
-        format!("Getting latest backup file failed and read only db call failed"),
-    ))?;
+    // The backups are tried starting from the latest one. Any corrupt backup is quarantined and the next good one is used
+    let path = backup::backup_file_paths(&db_file_name)
+        .into_iter()
+        .find(|p| {
+            backup::verify_backup(&db_file_name, p)
+                .inspect_err(|e| log::error!("Skipping the backup {:?} as {}", p, e))
+                .is_ok()
+        })
+        .ok_or(error::Error::UnexpectedError(format!(
+            "Getting latest backup file failed and read only db call failed"
+        )))?;
     log::debug!("Read only mode: Reading the latest backup file {:?}", &path);
 
     let mut reader = fs::File::open(path)?;
@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use log::{debug, error};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::{
+    app_preference::DatabaseMirror,
+    app_state::AppState,
+    commands::{ok_json_str, CommandArg},
+    parse_command_args_or_err, remote_storage, OkpError, OkpResult,
+};
+use onekeepass_core::error;
+
+// A db can have one or more remote mirrors. After each successful save of the db to its primary location
+// (local or remote), the same saved content is written to each mirror in a background thread.
+
+// One save's content to be written to the mirrors of a db
+struct MirrorPush {
+    db_key: String,
+    mirrors: Vec<DatabaseMirror>,
+    data: Arc<Vec<u8>>,
+}
+
+// All pushes are done one after the other in a single worker thread in the same order as the saves.
+// This ensures that an older save's content never overwrites a newer one in a mirror
+static MIRROR_PUSH_SENDER: Lazy<Mutex<mpsc::Sender<MirrorPush>>> = Lazy::new(|| {
+    let (tx, rx) = mpsc::channel::<MirrorPush>();
+    std::thread::spawn(move || {
+        while let Ok(push) = rx.recv() {
+            // Any queued push of a db is replaced by its latest save as only the latest content is kept in the mirrors
+            let pending = std::iter::once(push).chain(rx.try_iter()).collect::<Vec<_>>();
+            for push in latest_pushes(pending) {
+                push_to_db_mirrors(push);
+            }
+        }
+    });
+    Mutex::new(tx)
+});
+
+// Sent to UI when a push to a mirror fails
+#[derive(Serialize, Debug)]
+struct MirrorPushFailed {
+    db_key: String,
+    mirror_db_key: String,
+    error: String,
+}
+
+pub(crate) fn db_mirrors(json_args: &str) -> OkpResult<Vec<DatabaseMirror>> {
+    let (db_key,) = parse_command_args_or_err!(json_args, DbKey { db_key });
+    Ok(AppState::db_mirrors(&db_key))
+}
+
+// Returns the updated mirrors list of the db
+pub(crate) fn add_db_mirror(json_args: &str) -> OkpResult<Vec<DatabaseMirror>> {
+    let (db_key, mirror_db_key) =
+        parse_command_args_or_err!(json_args, DbMirrorArg { db_key, mirror_db_key });
+
+    if db_key == mirror_db_key {
+        return Err(error::Error::DataError(
+            "The mirror location should be different from the database location",
+        ));
+    }
+
+    if !remote_storage::is_remote_db_key(&mirror_db_key) {
+        return Err(error::Error::DataError(
+            "Only a remote storage location can be used as a mirror",
+        ));
+    }
+
+    AppState::add_db_mirror(&db_key, &mirror_db_key);
+
+    Ok(AppState::db_mirrors(&db_key))
+}
+
+pub(crate) fn remove_db_mirror(json_args: &str) -> OkpResult<Vec<DatabaseMirror>> {
+    let (db_key, mirror_db_key) =
+        parse_command_args_or_err!(json_args, DbMirrorArg { db_key, mirror_db_key });
+
+    AppState::remove_db_mirror(&db_key, &mirror_db_key);
+
+    Ok(AppState::db_mirrors(&db_key))
+}
+
+// Called after a successful local file save. The saved db content is in the backup file
+pub(crate) fn push_file_to_mirrors(db_key: &str, backup_file_name: Option<&String>) {
+    if AppState::db_mirrors(db_key).is_empty() {
+        return;
+    }
+
+    let Some(file_name) = backup_file_name else {
+        error!("No backup file is available to push the db content to the mirrors");
+        return;
+    };
+
+    match fs::read(file_name) {
+        Ok(data) => push_to_mirrors(db_key, Arc::new(data)),
+        Err(e) => error!("Reading the backup file for the mirror push failed {}", e),
+    }
+}
+
+// Called after a successful save of the db to its primary location
+pub(crate) fn push_to_mirrors(db_key: &str, data: Arc<Vec<u8>>) {
+    let mirrors = AppState::db_mirrors(db_key);
+    if mirrors.is_empty() {
+        return;
+    }
+
+    let push = MirrorPush {
+        db_key: db_key.to_string(),
+        mirrors,
+        data,
+    };
+
+    // The remote calls block till they complete and we do not want to delay the save call's response
+    if let Err(e) = MIRROR_PUSH_SENDER.lock().unwrap().send(push) {
+        error!("Queuing the mirror push of db {} failed {}", db_key, e);
+    }
+}
+
+// Keeps only the last push of each db and the order of the dbs' first pushes
+fn latest_pushes(pending: Vec<MirrorPush>) -> Vec<MirrorPush> {
+    let mut pushes: Vec<MirrorPush> = vec![];
+    for push in pending {
+        if let Some(p) = pushes.iter_mut().find(|p| p.db_key == push.db_key) {
+            *p = push;
+        } else {
+            pushes.push(push);
+        }
+    }
+    pushes
+}
+
+fn push_to_db_mirrors(push: MirrorPush) {
+    let MirrorPush {
+        db_key,
+        mirrors,
+        data,
+    } = push;
+
+    for mirror in mirrors {
+        // The mirror file is created if it is not yet there (first push or removed on the server)
+        let r = remote_storage::remote_db_key_exists(&mirror.mirror_db_key).and_then(|exists| {
+            remote_storage::write_to_remote_db_key(&mirror.mirror_db_key, data.clone(), !exists)
+        });
+
+        debug!(
+            "Push to the mirror {} of db {} is done with result {:?}",
+            &mirror.mirror_db_key,
+            &db_key,
+            r.as_ref().map(|_| ())
+        );
+
+        let error = r.err().map(|e| format!("{}", e));
+
+        AppState::update_db_mirror_status(&db_key, &mirror.mirror_db_key, error.clone());
+
+        if let Some(error) = error {
+            let failed = MirrorPushFailed {
+                db_key: db_key.clone(),
+                mirror_db_key: mirror.mirror_db_key,
+                error,
+            };
+            let _r = AppState::event_dispatcher().send_db_mirror_push_failed(ok_json_str(failed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(db_key: &str, content: u8) -> MirrorPush {
+        MirrorPush {
+            db_key: db_key.into(),
+            mirrors: vec![],
+            data: Arc::new(vec![content]),
+        }
+    }
+
+    #[test]
+    fn verify_latest_pushes() {
+        let pushes = latest_pushes(vec![push("db1", 1), push("db2", 2), push("db1", 3), push("db1", 4)]);
+
+        let r = pushes
+            .iter()
+            .map(|p| (p.db_key.as_str(), p.data[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(r, vec![("db1", 4), ("db2", 2)]);
+    }
+}
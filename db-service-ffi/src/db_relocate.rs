@@ -0,0 +1,284 @@
+use std::{
+    fs::File,
+    io::{Cursor, Write},
+    os::fd::IntoRawFd,
+    sync::Arc,
+};
+
+use log::{debug, error, info};
+use serde::Serialize;
+
+use onekeepass_core::db_service::{self, KdbxLoaded};
+
+use crate::{
+    app_state::AppState,
+    backup::{self, BackupReason},
+    biometric_auth,
+    commands::{full_path_file_to_create, CommandArg},
+    open_backup_file, parse_command_args_or_err, remote_storage,
+    udl_types::FileArgs,
+    util, OkpError, OkpResult,
+};
+
+// A db opened in the app is written to another location (local file or remote storage) and all the state
+// that is keyed by its db_key (recent list, db preference, biometric credentials, backups and autofill copies)
+// is moved to the new db_key so that the db keeps its identity in the new location
+
+#[derive(Serialize, Debug)]
+pub(crate) struct RelocatedDb {
+    kdbx_loaded: KdbxLoaded,
+    // The source file is not deleted when the source location does not allow it (e.g Android content uri)
+    source_deleted: bool,
+}
+
+// Called with the file picked or created by the user to relocate the db to a local file
+pub(crate) fn relocate_to_file(file_args: FileArgs, json_args: &str) -> OkpResult<RelocatedDb> {
+    let (db_key, delete_source) = parse_command_args_or_err!(
+        json_args,
+        RelocateDbArg {
+            db_key,
+            delete_source
+        }
+    );
+
+    // Picking the same file as the target would write over the source and then delete it
+    if let FileArgs::FileDecriptorWithFullFileName { full_file_name, .. }
+    | FileArgs::FullFileName { full_file_name } = &file_args
+    {
+        check_not_same_location(&db_key, full_file_name)?;
+    }
+
+    let data = Arc::new(db_content(&db_key)?);
+
+    let (mut file, new_db_key, fd_used) = match file_args {
+        FileArgs::FileDecriptorWithFullFileName {
+            fd, full_file_name, ..
+        } => (unsafe { util::get_file_from_fd(fd) }, full_file_name, true),
+        FileArgs::FullFileName { full_file_name } => {
+            (full_path_file_to_create(&full_file_name)?, full_file_name, false)
+        }
+        _ => return Err(OkpError::DataError("Unsupported file args passed")),
+    };
+
+    let write_result = write_to_file(&mut file, &data);
+
+    let kdbx_loaded = write_result.and_then(|_| {
+        let new_file_name = AppState::uri_to_file_name(&new_db_key);
+        move_db_state(&db_key, &new_db_key, &new_file_name, &data, None)
+    });
+
+    if let Err(e) = &kdbx_loaded {
+        error!("Relocating the db to the file failed {}", e);
+        // The partially written or not used target file is emptied or removed so that no broken db is left there
+        remove_target_file(&mut file, &new_db_key);
+    }
+
+    if fd_used {
+        // The caller closes the file. See the comments in 'udl_functions::save_kdbx'
+        let _fd = file.into_raw_fd();
+    }
+    let kdbx_loaded = kdbx_loaded?;
+
+    let source_deleted = delete_source && delete_source_file(&db_key);
+
+    Ok(RelocatedDb {
+        kdbx_loaded,
+        source_deleted,
+    })
+}
+
+fn check_not_same_location(db_key: &str, new_db_key: &str) -> OkpResult<()> {
+    if db_key == new_db_key {
+        return Err(OkpError::DataError(
+            "The target location is the same as the current location of the database",
+        ));
+    }
+    Ok(())
+}
+
+// Relocates the db to the remote storage location given by the arg 'target_db_key'
+// e.g Webdav-264226dc-be96-462a-a386-79adb6291ad7-/dav/db1/Test1.kdbx
+pub(crate) fn relocate_to_remote(json_args: &str) -> OkpResult<RelocatedDb> {
+    let (db_key, delete_source, target_db_key) = parse_command_args_or_err!(
+        json_args,
+        RelocateDbArg {
+            db_key,
+            delete_source,
+            target_db_key
+        }
+    );
+
+    let Some(new_db_key) = target_db_key else {
+        return Err(OkpError::DataError(
+            "The target location is required to relocate the database",
+        ));
+    };
+
+    if !remote_storage::is_remote_db_key(&new_db_key) {
+        return Err(OkpError::DataError(
+            "The target location is not a valid remote storage location",
+        ));
+    }
+
+    check_not_same_location(&db_key, &new_db_key)?;
+
+    let data = Arc::new(db_content(&db_key)?);
+
+    // An existing file in the target location is not overwritten. Some storages (e.g WebDav) replace the
+    // file on create and we need to check before writing
+    if remote_storage::remote_db_key_exists(&new_db_key)? {
+        return Err(OkpError::DataError(
+            "A file with the same name already exists in the target location",
+        ));
+    }
+
+    let meta = remote_storage::write_to_remote_db_key(&new_db_key, data.clone(), true)?;
+
+    let new_file_name = remote_storage::uri_to_file_name(&new_db_key)
+        .unwrap_or_default()
+        .to_string();
+    let remote_modified = meta.modified.map(|t| t as i64);
+
+    let kdbx_loaded =
+        match move_db_state(&db_key, &new_db_key, &new_file_name, &data, remote_modified) {
+            Ok(k) => k,
+            Err(e) => {
+                // The new remote file created above is removed so that the relocation can be tried again
+                let _ = remote_storage::delete_remote_db_key(&new_db_key)
+                    .inspect_err(|de| error!("Removing the relocated remote file failed {}", de));
+                return Err(e);
+            }
+        };
+
+    let source_deleted = delete_source && delete_source_file(&db_key);
+
+    Ok(RelocatedDb {
+        kdbx_loaded,
+        source_deleted,
+    })
+}
+
+// Gets the content of the opened db in the kdbx format
+fn db_content(db_key: &str) -> OkpResult<Vec<u8>> {
+    let opened = db_service::all_kdbx_cache_keys()?
+        .iter()
+        .any(|k| k == db_key);
+    if !opened {
+        return Err(OkpError::DataError(
+            "The database should be opened to relocate it",
+        ));
+    }
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    db_service::save_kdbx_to_writer(&mut buf, db_key)?;
+    Ok(buf.into_inner())
+}
+
+fn write_to_file(file: &mut File, data: &[u8]) -> OkpResult<()> {
+    file.set_len(0)?;
+    file.write_all(data)?;
+    // sync_all ensures the file is created and synced in case of dropbbox and one drive
+    file.sync_all()?;
+    Ok(())
+}
+
+// The target file is picked or created by the user for this relocation. A local file is removed and
+// any other file (e.g Android content uri that we can not delete here) is truncated
+fn remove_target_file(file: &mut File, new_db_key: &str) {
+    let r = if new_db_key.starts_with("file://") {
+        std::fs::remove_file(util::url_to_unix_file_name(new_db_key))
+    } else {
+        file.set_len(0)
+    };
+    let _ = r.inspect_err(|e| error!("Cleaning up the relocation target file failed {}", e));
+}
+
+// Moves all db_key keyed state to the new db_key. The opened db and the backups are moved first as these may fail
+// and are reverted if any of the later steps fails
+fn move_db_state(
+    db_key: &str,
+    new_db_key: &str,
+    new_file_name: &str,
+    data: &Arc<Vec<u8>>,
+    remote_modified: Option<i64>,
+) -> OkpResult<KdbxLoaded> {
+    let mut kdbx_loaded = db_service::rename_db_key(db_key, new_db_key)?;
+    kdbx_loaded.file_name = Some(new_file_name.to_string());
+
+    if let Err(e) = backup::move_backup_history_files(db_key, new_db_key) {
+        error!("Moving the backups to the new db_key failed {}", e);
+        let _ = db_service::rename_db_key(new_db_key, db_key);
+        return Err(e);
+    }
+
+    if let Err(e) = add_backup(new_db_key, new_file_name, data, remote_modified) {
+        error!("Creating the backup of the relocated db failed {}", e);
+        let _ = backup::move_backup_history_files(new_db_key, db_key);
+        let _ = db_service::rename_db_key(new_db_key, db_key);
+        return Err(e);
+    }
+
+    // The checksum is set for the content written to the new location
+    let _ = db_service::calculate_and_set_db_file_checksum(
+        new_db_key,
+        &mut Cursor::new(data.as_slice()),
+    )
+    .inspect_err(|e| error!("Setting the checksum of the relocated db failed {}", e));
+
+    // Recent db info, db preference and its mirrors
+    AppState::rename_db_key(db_key, new_db_key, new_file_name);
+
+    let _ = biometric_auth::StoredCredential::move_credentials(db_key, new_db_key)
+        .inspect_err(|e| info!("Moving the stored credentials failed with error {}", e));
+
+    #[cfg(target_os = "ios")]
+    {
+        let _ = crate::ios::autofill_app_group::move_copied_autofill_details(db_key, new_db_key)
+            .inspect_err(|e| info!("Moving the autofill copy failed with error {}", e));
+    }
+
+    // Any previous ref to the backup file stored for the save error is not valid after the move
+    AppState::remove_last_backup_name_on_error(db_key);
+
+    AppState::update_recent_db_file_info(new_db_key);
+
+    debug!("Relocated db_key {} to {}", db_key, new_db_key);
+
+    Ok(kdbx_loaded)
+}
+
+// The content written to the new location is the latest backup of the relocated db
+fn add_backup(
+    db_key: &str,
+    file_name: &str,
+    data: &[u8],
+    remote_modified: Option<i64>,
+) -> OkpResult<()> {
+    let backup_file_name = backup::generate_backup_history_file_name(db_key, file_name);
+    let mut backup_file = open_backup_file(backup_file_name.as_ref())
+        .ok_or(OkpError::DataError("Opening backup file failed"))?;
+    backup_file.write_all(data)?;
+    backup_file.sync_all()?;
+
+    if let Some(bk) = backup_file_name.as_deref() {
+        backup::record_backup(db_key, bk, BackupReason::OnCreate, remote_modified);
+    }
+    backup::prune_backup_history_files(db_key);
+
+    Ok(())
+}
+
+// Returns true if the source file is deleted
+fn delete_source_file(db_key: &str) -> bool {
+    let r = if remote_storage::is_remote_db_key(db_key) {
+        remote_storage::delete_remote_db_key(db_key)
+    } else if db_key.starts_with("file://") {
+        std::fs::remove_file(util::url_to_unix_file_name(db_key)).map_err(OkpError::from)
+    } else {
+        // e.g Android content uri which can only be deleted by the app using the document provider api
+        return false;
+    };
+
+    r.inspect_err(|e| error!("Deleting the source db file {} failed {}", db_key, e))
+        .is_ok()
+}
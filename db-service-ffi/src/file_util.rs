@@ -81,6 +81,8 @@ pub trait HandlePickedFile {
 #[enum_dispatch::enum_dispatch]
 pub enum PickedFileHandler {
     SftpPrivateKeyFile(SftpPrivateKeyFile),
+    ConnectionConfigsBundleFile(ConnectionConfigsBundleFile),
+    AppArchiveFile(AppArchiveFile),
     
     // TDOO: 
     //  Need to add the following variants instead of using 
@@ -96,31 +98,54 @@ pub struct SftpPrivateKeyFile {}
 
 impl HandlePickedFile for SftpPrivateKeyFile {
     fn execute(&self, file_args: &FileArgs) -> OkpResult<KeyFileInfo> {
-        let OpenedFile {
-            mut file,
-            file_name,
-            ..
-        } = OpenedFile::open_to_read(file_args)?;
-
-        // let file_full_path = AppState::sftp_private_keys_path().join(&file_name);
-
-        // User picked key file is stored in the temp dir and later copied to the 
+        // User picked key file is stored in the temp dir and later copied to the
         // local sftp connection specific path after successful connection
-        let file_full_path = AppState::temp_dir_path().join(&file_name);
-        
-        // debug!("The file_full_path in SftpPrivateKeyFile is {:?}",file_full_path);
+        copy_to_temp_dir(file_args)
+    }
+}
 
-        let mut target_file = File::create(&file_full_path)?;
-        std::io::copy(&mut file, &mut target_file).and(target_file.sync_all())?;
-        
-        // debug!("Copied the private key file {} locally", &file_name);
+// The picked remote connections bundle file (see 'rs_export_connection_configs')
+#[derive(Deserialize, Debug)]
+pub struct ConnectionConfigsBundleFile {}
 
-        let full_file_name = file_full_path.as_os_str().to_string_lossy().to_string();
+impl HandlePickedFile for ConnectionConfigsBundleFile {
+    fn execute(&self, file_args: &FileArgs) -> OkpResult<KeyFileInfo> {
+        // The bundle file is read from the temp dir in the 'rs_read_connection_configs_bundle'
+        // and 'rs_import_connection_configs' calls
+        copy_to_temp_dir(file_args)
+    }
+}
 
-        Ok(KeyFileInfo {
-            full_file_name,
-            file_name,
-            file_size: None,
-        })
+// The picked app archive file (see 'export_app_archive')
+#[derive(Deserialize, Debug)]
+pub struct AppArchiveFile {}
+
+impl HandlePickedFile for AppArchiveFile {
+    fn execute(&self, file_args: &FileArgs) -> OkpResult<KeyFileInfo> {
+        // The archive file is read from the temp dir in the 'read_app_archive' and 'import_app_archive' calls
+        copy_to_temp_dir(file_args)
     }
 }
+
+fn copy_to_temp_dir(file_args: &FileArgs) -> OkpResult<KeyFileInfo> {
+    let OpenedFile {
+        mut file,
+        file_name,
+        ..
+    } = OpenedFile::open_to_read(file_args)?;
+
+    let file_full_path = AppState::temp_dir_path().join(&file_name);
+
+    // debug!("The file_full_path in copy_to_temp_dir is {:?}",file_full_path);
+
+    let mut target_file = File::create(&file_full_path)?;
+    std::io::copy(&mut file, &mut target_file).and(target_file.sync_all())?;
+
+    let full_file_name = file_full_path.as_os_str().to_string_lossy().to_string();
+
+    Ok(KeyFileInfo {
+        full_file_name,
+        file_name,
+        file_size: None,
+    })
+}
@@ -13,14 +13,15 @@ use onekeepass_core::db_service::{
     service_util::{self, now_utc_milli_seconds, string_to_simple_hash},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use url::Url;
 
 use onekeepass_core::error;
 
 use crate::{
-    OkpError, OkpResult, app_lock, app_preference::{AppLockPreference, DatabasePreference, Preference}, app_state::{AppState, OKP_SHARED_DIR}, commands::{
+    OkpError, OkpResult, app_lock, app_preference::{AppLockPreference, DatabasePreference, Preference}, app_state::{AppState, OKP_SHARED_DIR}, biometric_auth, commands::{
         CommandArg, InvokeResult, ResponseJson, error_json_str, ok_json_str, result_json_str
-    }, parse_command_args_or_err, util::{self, remove_dir_contents}
+    }, json_migration::{insert_if_missing, Migration, MigrationStatus, VersionedJson}, parse_command_args_or_err, util::{self, remove_dir_contents}
 };
 
 use super::IosApiCallbackImpl;
@@ -112,6 +113,21 @@ pub(crate) fn copy_files_to_app_group_on_save_or_read(db_key: &str) {
     passkey_service::register_passkey_identities_for_db(db_key);
 }
 
+// Called when a db is relocated to a new db_key so that the autofill copy continues to be available
+// Nothing is done if this db is not used in autofill
+pub(crate) fn move_copied_autofill_details(db_key: &str, new_db_key: &str) -> OkpResult<()> {
+    let Some(_) = AutoFillMeta::read().find_copied_dbs_info(&db_key) else {
+        return Ok(());
+    };
+
+    delete_copied_autofill_details(db_key)?;
+    copy_files_to_app_group(new_db_key)?;
+
+    passkey_service::register_passkey_identities_for_db(new_db_key);
+
+    Ok(())
+}
+
 // Called during app reset
 // Removes all data and key files. Also the autofill config removed
 pub(crate) fn remove_all_app_extension_contents() {
@@ -161,6 +177,21 @@ fn app_extension_shared_root() -> OkpResult<PathBuf> {
     Ok(full_path_dir.to_path_buf())
 }
 
+// The dir where the db file copy of this db is kept. Used in the selective reset report
+pub(crate) fn copied_autofill_dir(db_key: &str) -> Option<PathBuf> {
+    let db_file_root = app_group_root_sub_dir(AG_DATA_FILES_DIR).ok()?;
+    Some(db_file_root.join(string_to_simple_hash(db_key).to_string()))
+}
+
+// The original db_keys of all dbs copied for the autofill
+pub(crate) fn copied_autofill_db_keys() -> Vec<String> {
+    AutoFillMeta::read()
+        .get_copied_dbs_info()
+        .iter()
+        .map(|c| c.org_db_file_path.clone())
+        .collect()
+}
+
 // Creates a sub dir with the given name under the app group root
 fn app_group_root_sub_dir(sub_dir_name: &str) -> OkpResult<PathBuf> {
     let app_group_home_dir = app_extension_root()?;
@@ -252,6 +283,10 @@ pub struct AutoFillMeta {
     // Adding a field to hold last pin auth done
     // This time is used to determine whether to ask PIN auth again or to skip for a predetermined time
     last_pin_auth_success_time: Option<i64>,
+
+    // Set when the meta file is from a newer app version
+    #[serde(skip)]
+    read_only: bool,
 }
 
 impl Default for AutoFillMeta {
@@ -260,6 +295,7 @@ impl Default for AutoFillMeta {
             version: META_JSON_FILE_VERSION.to_string(),
             copied_dbs_info: vec![],
             last_pin_auth_success_time: None,
+            read_only: false,
         }
     }
 }
@@ -274,64 +310,63 @@ impl AutoFillMeta {
 
         // debug!("AutoFillMeta is {:?} ", &pref_file_name);
 
-        let json_str = fs::read_to_string(pref_file_name).unwrap_or("".into());
-
-        // debug!("AutoFillMeta json_str is {}", &json_str);
-
-        let mut af_meta = if json_str.is_empty() {
-            // log::info!("AutoFillMeta is empty and default used ");
-            Self::default()
-        } else {
-            serde_json::from_str(&json_str).unwrap_or_else(|_| {
-                // If there is any change in struct AutoFillMeta, then we need to load earlier version as done in Preference
-                match serde_json::from_str(&json_str) {
-                    // Prior version
-                    Ok(prev_af_meta @ AutoFillMetaV100 { .. }) => {
-                        // debug!( "Returning the new AutoFillMeta with old values from AutoFillMeta100");
+        // The previous copy is used when the meta file is truncated or can not be upgraded
+        let ((af_meta, status), read_file_name) =
+            match util::read_with_prev_fallback(&pref_file_name, Self::from_json_data) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
+                    // log::info!("AutoFillMeta is empty and default used ");
+                    return Self::default();
+                }
+                Err(e) => {
+                    // Returns the latest default af_meta
+                    debug!("Returning the default af_meta because of json parsing error {}", e);
+                    return Self::default();
+                }
+            };
 
-                        let new_af_meta: Self = prev_af_meta.into();
+        match status {
+            MigrationStatus::Current => {
+                if read_file_name != pref_file_name {
+                    af_meta.write_to_app_group_dir();
+                }
+            }
+            MigrationStatus::Upgraded(from_version) => {
+                if AUTOFILL_META_JSON.backup_original(&read_file_name, &from_version) {
+                    af_meta.write_to_app_group_dir();
+                }
+            }
+            // Written by a newer app version and should not be overwritten
+            MigrationStatus::ReadOnly(_) => {}
+        }
 
-                        // debug!("Converted new_af is {:?}", &new_af_meta);
+        af_meta
+    }
 
-                        // Write the AutoFillMeta json with the copied values from old AutoFillMeta
-                        new_af_meta.write_to_app_group_dir();
-                        new_af_meta
-                    }
-                    Err(e) => {
-                        // We could not parse the existing json file even to the prior version.
-                        // This may happen if the 'autofill_meta.json' found is older than the previous version.
-                        // Returns the latest default af_meta
-                        debug!(
-                            "Returning the default af_meta because of json parsing error {}",
-                            e
-                        );
-                        Self::default()
-                    }
-                }
-            })
-        };
+    fn from_json_data(data: &[u8]) -> OkpResult<(Self, MigrationStatus)> {
+        let (value, status) = AUTOFILL_META_JSON.migrate_value(serde_json::from_slice(data)?)?;
 
-        // Ensure that version is updated if required
-        let final_af_meta = if af_meta.version == META_JSON_FILE_VERSION {
-            // debug!("Version Checked: Returning the current af_meta as version is the latest");
+        let af_meta = if let MigrationStatus::ReadOnly(_) = status {
+            let mut af_meta = serde_json::from_value::<Self>(value).unwrap_or_default();
+            af_meta.read_only = true;
             af_meta
         } else {
-            // If the new field added are only Option<> type in AutoFillMeta,
-            // then parsing may through with new AutoFillMeta itself, but the version will be old
-            af_meta.version = META_JSON_FILE_VERSION.into();
-            af_meta.write_to_app_group_dir();
-            af_meta
+            serde_json::from_value::<Self>(value)?
         };
 
-        final_af_meta
+        Ok((af_meta, status))
     }
 
     fn write_to_app_group_dir(&self) {
+        if self.read_only {
+            log::info!("AutoFillMeta is read only as it is from a newer app version and is not written");
+            return;
+        }
         if let Some(pref_file_name) = autofill_meta_json_file() {
             let json_str_result = serde_json::to_string_pretty(self);
             if let Ok(json_str) = json_str_result {
                 // log::debug!("Writing AutoFillMeta file");
-                if let Err(err) = fs::write(pref_file_name, json_str.as_bytes()) {
+                if let Err(err) = util::atomic_write(pref_file_name, json_str.as_bytes()) {
                     log::error!(
                         "AutoFillMeta file write failed and error is {}",
                         err.to_string()
@@ -348,6 +383,14 @@ impl AutoFillMeta {
             .cloned()
     }
 
+    // Finds the main app's db key of the db copied to the app group
+    fn find_org_db_file_path(&self, db_file_path: &str) -> Option<String> {
+        self.copied_dbs_info
+            .iter()
+            .find(|v| v.db_file_path == db_file_path)
+            .map(|v| v.org_db_file_path.clone())
+    }
+
     fn get_copied_dbs_info(&self) -> &Vec<CopiedDbFileInfo> {
         &self.copied_dbs_info
     }
@@ -392,29 +435,25 @@ struct AutoFillInitData {
 
 //////////////
 
-// Previous version
-
-#[derive(Clone, Serialize, Deserialize)]
-pub struct AutoFillMetaV100 {
-    pub version: String,
-    pub copied_dbs_info: Vec<CopiedDbFileInfo>,
-}
-
-impl From<AutoFillMetaV100> for AutoFillMeta {
-    fn from(old: AutoFillMetaV100) -> Self {
-        let AutoFillMetaV100 {
-            copied_dbs_info,
-            version: _,
-        } = old;
-
-        let new_af = AutoFillMeta {
-            version: META_JSON_FILE_VERSION.to_string(),
-            copied_dbs_info,
-            last_pin_auth_success_time: None,
-        };
+// Previous versions
+
+// The autofill_meta.json versions and the changes made in each version
+// 1.0.0 - copied_dbs_info
+// 2.0.0 - Added last_pin_auth_success_time
+const AUTOFILL_META_JSON: VersionedJson = VersionedJson {
+    name: META_JSON_FILE_NAME,
+    current_version: META_JSON_FILE_VERSION,
+    initial_version: "1.0.0",
+    migrations: &[Migration {
+        from_version: "1.0.0",
+        to_version: "2.0.0",
+        migrate: migrate_v100_to_v200,
+    }],
+};
 
-        new_af
-    }
+fn migrate_v100_to_v200(af_meta: &mut Map<String, Value>) -> OkpResult<()> {
+    insert_if_missing(af_meta, "last_pin_auth_success_time", Value::Null);
+    Ok(())
 }
 
 //////////////
@@ -443,6 +482,11 @@ struct IosAppGroupSupportService {}
 impl IosAppGroupSupportService {
     fn internal_copy_files_to_app_group(&self, json_args: &str) -> OkpResult<CopiedDbFileInfo> {
         let (db_key,) = parse_command_args_or_err!(json_args, DbKey { db_key });
+        if !AppState::db_allowed_in_autofill(&db_key) {
+            return Err(OkpError::DataError(
+                "This database is not allowed to be used in autofill. Please change it in the database settings",
+            ));
+        }
         let result = copy_files_to_app_group(&db_key)?;
         debug!("IosAppGroupSupportService:copy_files_to_app_group completed");
         passkey_service::register_passkey_identities_for_db(&db_key);
@@ -486,7 +530,7 @@ impl IosAppGroupSupportService {
         // so that updates made by the main app are reflected even when the extension
         // process is reused across sessions (AppState is only initialized once via OnceCell)
         let fresh_pref = Preference::read(AppState::preference_home_dir());
-        let mut app_lock_preference = fresh_pref.app_lock_preference().clone();
+        let mut app_lock_preference = fresh_pref.view().app_lock_preference().clone();
         let now = service_util::now_utc_milli_seconds();
         if let Some(t) = last_time {
             if (now - t) <= 60000 {
@@ -494,33 +538,73 @@ impl IosAppGroupSupportService {
             }
         }
 
+        // The copies of the dbs hidden under the duress mode are not listed
+        let copied_dbs_info = AutoFillMeta::read()
+            .get_copied_dbs_info()
+            .iter()
+            .filter(|c| !fresh_pref.hidden_under_duress(&c.org_db_file_path))
+            .cloned()
+            .collect();
+
         let af_data = AutoFillInitData {
-            copied_dbs_info: AutoFillMeta::read().get_copied_dbs_info().clone(),
-            database_preferences: fresh_pref.database_preferences().clone(),
+            copied_dbs_info,
+            database_preferences: fresh_pref.view().database_preferences().clone(),
             app_lock_preference,
         };
         result_json_str(Ok(af_data))
     }
 
+    // The failed attempts are counted and limited in the same way as in the main app. But the app is not reset
+    // here and the reset is done in the main app (see 'app_lock::extension_pin_verify_attempt')
     fn pin_verify(&self, json_args: &str) -> ResponseJson {
-        let inner_fn = || -> OkpResult<bool> {
-            let (pin,) = parse_command_args_or_err!(json_args, AppLockCredentialArg { pin });
-            app_lock::pin_verify(pin)
-        };
+        let r = self
+            .internal_pin_verify_attempt(json_args)
+            .and_then(|r| {
+                if r.app_reset_pending() {
+                    Err(OkpError::DataError(
+                        "Too many failed PIN attempts. Please open the app to continue",
+                    ))
+                } else {
+                    Ok(r.verified())
+                }
+            });
+        result_json_str(r)
+    }
 
-        let r = inner_fn();
+    fn pin_verify_attempt(&self, json_args: &str) -> ResponseJson {
+        result_json_str(self.internal_pin_verify_attempt(json_args))
+    }
 
-        if let Ok(success) = r {
-            if success {
-                // Store the pin verify success so that we avoid asking PIN again for certain duration
-                // Sometime user may need to launch the AutoFill again to fill additional field as iOS
-                // window may close after filling initial field
-                AutoFillMeta::read()
-                    .set_last_pin_auth_success_time(Some(service_util::now_utc_milli_seconds()));
-            }
-        }
+    fn passphrase_verify_attempt(&self, json_args: &str) -> ResponseJson {
+        result_json_str(self.internal_passphrase_verify_attempt(json_args))
+    }
 
-        result_json_str(r)
+    fn internal_passphrase_verify_attempt(
+        &self,
+        json_args: &str,
+    ) -> OkpResult<app_lock::PinVerifyResult> {
+        let (passphrase,) =
+            parse_command_args_or_err!(json_args, AppLockPassphraseArg { passphrase });
+        let r = app_lock::extension_passphrase_verify_attempt(&passphrase)?;
+        Self::record_pin_auth_success(&r);
+        Ok(r)
+    }
+
+    fn internal_pin_verify_attempt(&self, json_args: &str) -> OkpResult<app_lock::PinVerifyResult> {
+        let (pin,) = parse_command_args_or_err!(json_args, AppLockCredentialArg { pin });
+        let r = app_lock::extension_pin_verify_attempt(pin)?;
+        Self::record_pin_auth_success(&r);
+        Ok(r)
+    }
+
+    fn record_pin_auth_success(r: &app_lock::PinVerifyResult) {
+        if r.verified() {
+            // Store the pin verify success so that we avoid asking PIN again for certain duration
+            // Sometime user may need to launch the AutoFill again to fill additional field as iOS
+            // window may close after filling initial field
+            AutoFillMeta::read()
+                .set_last_pin_auth_success_time(Some(service_util::now_utc_milli_seconds()));
+        }
     }
 
     // Gets the list of database info that are enabled for autofill
@@ -543,13 +627,14 @@ impl IosAppGroupSupportService {
     // Gets the list of all entries in a database that is opened in autofill extension
     fn all_entries_on_db_open(&self, json_args: &str) -> ResponseJson {
         let inner_fn = || -> OkpResult<Vec<db_service::EntrySummary>> {
-            let (db_file_name, password, key_file_name, biometric_auth_used) = parse_command_args_or_err!(
+            let (db_file_name, password, key_file_name, biometric_auth_used, _) = parse_command_args_or_err!(
                 json_args,
                 OpenDbArg {
                     db_file_name,
                     password,
                     key_file_name,
-                    biometric_auth_used
+                    biometric_auth_used,
+                    request_id
                 }
             );
 
@@ -573,6 +658,16 @@ impl IosAppGroupSupportService {
                 _ => e,
             })?;
 
+            // The stored credentials are of the main app's db key and the biometric unlock is counted there
+            // so that the biometric use limits apply to the opens in the extension as well
+            if biometric_auth_used {
+                if let Some(org_db_key) = AutoFillMeta::read().find_org_db_file_path(&db_file_name) {
+                    if let Err(e) = biometric_auth::StoredCredential::record_biometric_unlock(&org_db_key) {
+                        log::error!("Recording the biometric unlock failed with error {}", e);
+                    }
+                }
+            }
+
             // Apply any pending passkey registrations to the in-memory KDBX so that
             // passkey_find_matching and passkey_sign_assertion can find them within
             // this extension session. The pending files are NOT deleted here; the main
@@ -677,6 +772,8 @@ impl IosAppGroupSupportService {
             // Used in extension
             "autofill_init_data" => self.autofill_init_data(),
             "pin_verify" => self.pin_verify(json_args),
+            "pin_verify_attempt" => self.pin_verify_attempt(json_args),
+            "passphrase_verify_attempt" => self.passphrase_verify_attempt(json_args),
             // "list_of_autofill_db_infos" => self.list_of_autofill_db_infos(),
             // "database_preferences" => ok_json_str(AppState::database_preferences()),
             "list_of_key_files" => self.list_of_key_files(),
@@ -725,3 +822,26 @@ impl IosAppGroupSupportService {
 //     // e.g app_group_root/okp/key_files
 //     app_group_root_sub_dir(AG_KEY_FILES_DIR)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTOFILL_META_V100: &str = r#"{
+        "version": "1.0.0",
+        "copied_dbs_info": [{"file_name": "Test1.kdbx", "db_file_path": "/ag/db_files/123/Test1.kdbx", "org_db_file_path": "file:///tmp/Test1.kdbx"}]
+    }"#;
+
+    #[test]
+    fn verify_migration_v100_to_v200() {
+        let (value, status) = AUTOFILL_META_JSON
+            .migrate_json_str(AUTOFILL_META_V100)
+            .unwrap();
+        assert_eq!(MigrationStatus::Upgraded("1.0.0".into()), status);
+        assert!(value["last_pin_auth_success_time"].is_null());
+
+        let af_meta: AutoFillMeta = serde_json::from_value(value).unwrap();
+        assert_eq!(META_JSON_FILE_VERSION, af_meta.version);
+        assert_eq!(1, af_meta.copied_dbs_info.len());
+    }
+}
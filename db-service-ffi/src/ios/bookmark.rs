@@ -23,7 +23,7 @@ pub(crate) fn delete_book_mark_data(full_file_name_uri: &str) {
     );
 }
 
-fn form_bookmark_file_path(full_file_name_uri: &str) -> PathBuf {
+pub(crate) fn form_bookmark_file_path(full_file_name_uri: &str) -> PathBuf {
     let file_name = string_to_simple_hash(&full_file_name_uri).to_string();
     let book_mark_file_path = Path::new(AppState::app_home_dir())
         .join(BOOK_MARK_FILES_DIR)
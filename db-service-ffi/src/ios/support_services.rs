@@ -9,7 +9,7 @@ use onekeepass_core::{db_service, service_util::string_to_simple_hash};
 
 use crate::{
     app_state::AppState,
-    backup,
+    backup::{self, BackupReason},
     commands::{error_json_str, remove_app_files, result_json_str, CommandArg, InvokeResult, ResponseJson},
     open_backup_file, OkpError, OkpResult,
 };
@@ -175,7 +175,7 @@ impl IosSupportService {
                     )?;
 
                     // Need to create the backup file for the newly created database
-                    let mut new_db_bk_file =
+                    let (mut new_db_bk_file, new_db_bk_file_name) =
                         backup::generate_and_open_backup_file(&new_db_key, &file_name)?;
 
                     modified_db_bkp_file.rewind()?;
@@ -184,6 +184,12 @@ impl IosSupportService {
                     let _n = std::io::copy(&mut modified_db_bkp_file, &mut new_db_bk_file)?;
                     //debug!("The new_db_bk_file copied bytes size is {}", n);
                     new_db_bk_file.sync_all()?;
+                    backup::record_backup(
+                        &new_db_key,
+                        &new_db_bk_file_name,
+                        BackupReason::OnCreate,
+                        None,
+                    );
                 } else {
                     log::error!("Expected backup file is not found. 'Save as' should have this");
                     return Err(OkpError::DataError("Expected backup file is not found"));
@@ -0,0 +1,224 @@
+use std::{cmp::Ordering, fs, path::Path};
+
+use log::{debug, error, info};
+use serde_json::{Map, Value};
+
+use crate::{OkpError, OkpResult};
+
+// Upgrades the app's persisted json files (e.g preference.json, autofill_meta.json) written by an earlier app
+// version to the current struct by applying an ordered chain of migrations keyed on the 'version' field.
+// Each migration works on the json object and not on the structs so that the old structs need not be kept around
+
+const VERSION_FIELD: &str = "version";
+
+// One step in the chain. The json object of 'from_version' is changed in place to match 'to_version'
+pub(crate) struct Migration {
+    pub(crate) from_version: &'static str,
+    pub(crate) to_version: &'static str,
+    pub(crate) migrate: fn(&mut Map<String, Value>) -> OkpResult<()>,
+}
+
+pub(crate) struct VersionedJson {
+    // Used in logs and errors
+    pub(crate) name: &'static str,
+    pub(crate) current_version: &'static str,
+    // The version assumed for a json that has no version field
+    pub(crate) initial_version: &'static str,
+    // Should be in order starting from 'initial_version' and ending with 'current_version'
+    pub(crate) migrations: &'static [Migration],
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum MigrationStatus {
+    Current,
+    // Upgraded from this version. The original file should be backed up before writing the upgraded one
+    Upgraded(String),
+    // Written by a newer app version. The loaded content should not be written back
+    // as the fields unknown to this app version will be lost
+    ReadOnly(String),
+}
+
+impl VersionedJson {
+    // Parses the json str and applies all migrations required to bring it to the current version
+    pub(crate) fn migrate_json_str(&self, json_str: &str) -> OkpResult<(Value, MigrationStatus)> {
+        self.migrate_value(serde_json::from_str(json_str)?)
+    }
+
+    pub(crate) fn migrate_value(&self, mut value: Value) -> OkpResult<(Value, MigrationStatus)> {
+        let Some(obj) = value.as_object_mut() else {
+            return Err(OkpError::UnexpectedError(format!(
+                "The {} content is not a json object",
+                self.name
+            )));
+        };
+
+        let org_version = obj
+            .get(VERSION_FIELD)
+            .and_then(|v| v.as_str())
+            .unwrap_or(self.initial_version)
+            .to_string();
+
+        match compare_versions(&org_version, self.current_version) {
+            Ordering::Equal => return Ok((value, MigrationStatus::Current)),
+            Ordering::Greater => {
+                info!(
+                    "The {} version {} is newer than the supported version {} and loaded as read only",
+                    self.name, &org_version, self.current_version
+                );
+                return Ok((value, MigrationStatus::ReadOnly(org_version)));
+            }
+            Ordering::Less => {}
+        }
+
+        let mut version = org_version.clone();
+        while version != self.current_version {
+            let Some(m) = self.migrations.iter().find(|m| m.from_version == version) else {
+                return Err(OkpError::UnexpectedError(format!(
+                    "No migration found for the {} version {}",
+                    self.name, &version
+                )));
+            };
+
+            (m.migrate)(obj)?;
+            obj.insert(VERSION_FIELD.into(), Value::String(m.to_version.into()));
+            debug!("Migrated {} from {} to {}", self.name, &version, m.to_version);
+
+            version = m.to_version.to_string();
+        }
+
+        Ok((value, MigrationStatus::Upgraded(org_version)))
+    }
+
+    // Copies the original file to '<file name>.<version>.bak' before the upgraded content is written to the file.
+    // Returns false if the copy failed so that the caller can skip the write and keep the original file intact
+    pub(crate) fn backup_original(&self, file_path: &Path, from_version: &str) -> bool {
+        let Some(file_name) = file_path.file_name() else {
+            return false;
+        };
+        let backup_file_path = file_path.with_file_name(format!(
+            "{}.{}.bak",
+            file_name.to_string_lossy(),
+            from_version
+        ));
+
+        match fs::copy(file_path, &backup_file_path) {
+            Ok(_) => {
+                info!(
+                    "The {} version {} is backed up to {:?}",
+                    self.name, from_version, &backup_file_path
+                );
+                true
+            }
+            Err(e) => {
+                error!("Backing up the {} file failed {}", self.name, e);
+                false
+            }
+        }
+    }
+}
+
+// Used by the migrations to add a field introduced in the next version
+pub(crate) fn insert_if_missing(obj: &mut Map<String, Value>, field: &str, value: Value) {
+    obj.entry(field).or_insert(value);
+}
+
+// Compares the versions of form "5.0.0" numerically. Any non numeric part is taken as 0
+fn compare_versions(v1: &str, v2: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<u32> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    let (mut p1, mut p2) = (parts(v1), parts(v2));
+    let len = p1.len().max(p2.len());
+    p1.resize(len, 0);
+    p2.resize(len, 0);
+    p1.cmp(&p2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn add_b(obj: &mut Map<String, Value>) -> OkpResult<()> {
+        insert_if_missing(obj, "b", json!(2));
+        Ok(())
+    }
+
+    fn add_c(obj: &mut Map<String, Value>) -> OkpResult<()> {
+        insert_if_missing(obj, "c", json!(3));
+        Ok(())
+    }
+
+    const TEST_JSON: VersionedJson = VersionedJson {
+        name: "test",
+        current_version: "3.0.0",
+        initial_version: "0.0.1",
+        migrations: &[
+            Migration {
+                from_version: "0.0.1",
+                to_version: "2.0.0",
+                migrate: add_b,
+            },
+            Migration {
+                from_version: "2.0.0",
+                to_version: "3.0.0",
+                migrate: add_c,
+            },
+        ],
+    };
+
+    #[test]
+    fn verify_compare_versions() {
+        assert_eq!(Ordering::Less, compare_versions("0.0.3", "4.0.0"));
+        assert_eq!(Ordering::Less, compare_versions("4.0.0", "10.0.0"));
+        assert_eq!(Ordering::Equal, compare_versions("5.0", "5.0.0"));
+        assert_eq!(Ordering::Greater, compare_versions("5.1.0", "5.0.0"));
+    }
+
+    #[test]
+    fn verify_migration_chain() {
+        // No version field is taken as the initial version
+        let (v, status) = TEST_JSON.migrate_json_str(r#"{"a":1}"#).unwrap();
+        assert_eq!(MigrationStatus::Upgraded("0.0.1".into()), status);
+        assert_eq!(json!({"version":"3.0.0","a":1,"b":2,"c":3}), v);
+
+        // Existing values are not changed
+        let (v, status) = TEST_JSON
+            .migrate_json_str(r#"{"version":"2.0.0","a":1,"b":5}"#)
+            .unwrap();
+        assert_eq!(MigrationStatus::Upgraded("2.0.0".into()), status);
+        assert_eq!(json!({"version":"3.0.0","a":1,"b":5,"c":3}), v);
+
+        let (_, status) = TEST_JSON
+            .migrate_json_str(r#"{"version":"3.0.0","a":1}"#)
+            .unwrap();
+        assert_eq!(MigrationStatus::Current, status);
+
+        // Future version is not changed
+        let (v, status) = TEST_JSON
+            .migrate_json_str(r#"{"version":"4.0.0","d":4}"#)
+            .unwrap();
+        assert_eq!(MigrationStatus::ReadOnly("4.0.0".into()), status);
+        assert_eq!(json!({"version":"4.0.0","d":4}), v);
+
+        // Unknown old version
+        assert!(TEST_JSON
+            .migrate_json_str(r#"{"version":"1.0.0"}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn verify_backup_original() {
+        let dir = std::env::temp_dir().join("okp_json_migration_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("test.json");
+        fs::write(&file_path, r#"{"a":1}"#).unwrap();
+
+        assert!(TEST_JSON.backup_original(&file_path, "0.0.1"));
+        assert_eq!(
+            r#"{"a":1}"#,
+            fs::read_to_string(dir.join("test.json.0.0.1.bak")).unwrap()
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
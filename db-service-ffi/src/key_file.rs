@@ -0,0 +1,555 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use data_encoding::BASE64;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_preference::DbKeyFileUse,
+    app_state::AppState,
+    file_util::{KeyFileInfo, OpenedFile},
+    udl_types::FileArgs,
+    util, OkpError, OkpResult,
+};
+
+// The key file formats as supported by KeePass 2.x and KeePassXC
+// XML formats are checked first. A file with any other content is still usable as a key file and
+// in that case the SHA-256 of the full content is used as the key. This is also what KeePass does
+// with an XML file that it can not parse as a key file
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum KeyFileFormat {
+    // KeePass XML key file version 2.0 where the key data is hex encoded and
+    // the 'Hash' attribute has the first 4 bytes of the SHA-256 of the key data
+    XmlV2,
+    // KeePass XML key file version 1.0 where the key data is base64 encoded
+    XmlV1,
+    // 64 hex chars
+    Hex,
+    // 32 bytes
+    Binary,
+    // Any other content
+    Hashed,
+}
+
+impl KeyFileFormat {
+    // Only these formats can be used when we generate a new key file
+    fn can_generate(&self) -> bool {
+        matches!(self, Self::XmlV2 | Self::Hex | Self::Binary)
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct KeyFileFormatInfo {
+    pub(crate) file_name: String,
+    pub(crate) file_size: u64,
+    pub(crate) format: KeyFileFormat,
+    // Set only for XmlV2 format. It is false when the 'Hash' does not match the key data
+    pub(crate) hash_valid: Option<bool>,
+    // When this is set, the file can not be used as a key file
+    pub(crate) invalid_reason: Option<&'static str>,
+    // The file can be used as a key file. But it is not in the format it looks like (e.g an incomplete XML key file)
+    pub(crate) warning: Option<&'static str>,
+}
+
+impl KeyFileFormatInfo {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.invalid_reason.is_none()
+    }
+}
+
+const KEY_DATA_SIZE: usize = 32;
+
+// Only the files up to this size are checked for the format. Any larger file is used as a hashed key file
+const FORMAT_CHECK_MAX_SIZE: usize = 64 * 1024;
+
+const XML_V2_VERSION: &str = "2.0";
+
+// Finds the first element with this tag and returns its attributes part and the inner text
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+
+    let mut from = 0;
+    while let Some(pos) = xml[from..].find(&open) {
+        let start = from + pos + open.len();
+        // Need to make sure that it is not a tag with the same prefix e.g <DataX>
+        match xml[start..].chars().next() {
+            Some(c) if c == '>' || c.is_whitespace() => {
+                let attrs_end = start + xml[start..].find('>')?;
+                let inner_end = attrs_end + xml[attrs_end..].find(&close)?;
+                return Some((&xml[start..attrs_end], &xml[attrs_end + 1..inner_end]));
+            }
+            _ => from = start,
+        }
+    }
+    None
+}
+
+// Gets the value of an attribute e.g Hash="A1B2C3D4"
+fn xml_attribute<'a>(attrs: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}=\"", name);
+    let start = attrs.find(&key)? + key.len();
+    let end = start + attrs[start..].find('"')?;
+    Some(&attrs[start..end])
+}
+
+fn key_data_hash(key_data: &[u8]) -> String {
+    hex::encode_upper(&Sha256::digest(key_data)[..4])
+}
+
+fn is_hex(data: &[u8]) -> bool {
+    data.iter().all(|b| b.is_ascii_hexdigit())
+}
+
+// The format found and the result of its check
+struct FormatCheck {
+    format: KeyFileFormat,
+    hash_valid: Option<bool>,
+    invalid_reason: Option<&'static str>,
+    warning: Option<&'static str>,
+}
+
+impl FormatCheck {
+    fn valid(format: KeyFileFormat, hash_valid: Option<bool>) -> Self {
+        Self {
+            format,
+            hash_valid,
+            invalid_reason: None,
+            warning: None,
+        }
+    }
+
+    fn invalid(hash_valid: Option<bool>, reason: &'static str) -> Self {
+        Self {
+            format: KeyFileFormat::XmlV2,
+            hash_valid,
+            invalid_reason: Some(reason),
+            warning: None,
+        }
+    }
+
+    // KeePass uses the hash of the full content when the XML is not a complete key file
+    fn hashed_with_warning(warning: &'static str) -> Self {
+        Self {
+            format: KeyFileFormat::Hashed,
+            hash_valid: None,
+            invalid_reason: None,
+            warning: Some(warning),
+        }
+    }
+}
+
+// Only a version 2.0 key file with a key data that is not hex or does not match its hash is rejected.
+// Any other XML is used as a hashed key file
+fn xml_format(xml: &str) -> FormatCheck {
+    let Some((_, version)) = xml_element(xml, "Version") else {
+        return FormatCheck::hashed_with_warning(
+            "The version is not found in the XML key file and the file is used as a hashed key file",
+        );
+    };
+    let version = version.trim();
+
+    let Some((attrs, data)) = xml_element(xml, "Data") else {
+        return FormatCheck::hashed_with_warning(
+            "The key data is not found in the XML key file and the file is used as a hashed key file",
+        );
+    };
+
+    if version.starts_with("1.") {
+        let data: String = data.split_whitespace().collect();
+        return match BASE64.decode(data.as_bytes()) {
+            Ok(d) if !d.is_empty() => FormatCheck::valid(KeyFileFormat::XmlV1, None),
+            _ => FormatCheck::hashed_with_warning(
+                "The key data in the XML key file is not valid base64 and the file is used as a hashed key file",
+            ),
+        };
+    }
+
+    if !version.starts_with("2.") {
+        return FormatCheck::hashed_with_warning(
+            "The XML key file version is not known and the file is used as a hashed key file",
+        );
+    }
+
+    let data: String = data.split_whitespace().collect();
+    let key_data = match hex::decode(&data) {
+        Ok(d) if !d.is_empty() => d,
+        _ => return FormatCheck::invalid(None, "The key data in the XML key file is not valid hex"),
+    };
+
+    // The 'Hash' attribute is optional as per the KeePass spec. When it is present, we need to verify the key data
+    match xml_attribute(attrs, "Hash") {
+        Some(hash) if hash.trim().eq_ignore_ascii_case(&key_data_hash(&key_data)) => {
+            FormatCheck::valid(KeyFileFormat::XmlV2, Some(true))
+        }
+        Some(_) => FormatCheck::invalid(
+            Some(false),
+            "The hash in the XML key file does not match its key data. The file may be mistyped or corrupted",
+        ),
+        None => FormatCheck::valid(KeyFileFormat::XmlV2, None),
+    }
+}
+
+// Determines the format of the key file content and validates it
+pub(crate) fn key_file_format_info(file_name: &str, data: &[u8]) -> KeyFileFormatInfo {
+    let check = if data.is_empty() {
+        FormatCheck {
+            invalid_reason: Some("The key file is empty"),
+            ..FormatCheck::valid(KeyFileFormat::Hashed, None)
+        }
+    } else if let Some(xml) = std::str::from_utf8(data)
+        .ok()
+        .filter(|s| s.contains("<KeyFile>"))
+    {
+        xml_format(xml)
+    } else if data.len() == KEY_DATA_SIZE {
+        FormatCheck::valid(KeyFileFormat::Binary, None)
+    } else if data.len() == 2 * KEY_DATA_SIZE && is_hex(data) {
+        FormatCheck::valid(KeyFileFormat::Hex, None)
+    } else {
+        FormatCheck::valid(KeyFileFormat::Hashed, None)
+    };
+
+    KeyFileFormatInfo {
+        file_name: file_name.to_string(),
+        file_size: data.len() as u64,
+        format: check.format,
+        hash_valid: check.hash_valid,
+        invalid_reason: check.invalid_reason,
+        warning: check.warning,
+    }
+}
+
+// Forms the key file content in the requested format using a random key data
+pub(crate) fn generate_key_file_content(format: KeyFileFormat) -> OkpResult<Vec<u8>> {
+    if !format.can_generate() {
+        return Err(OkpError::DataError(
+            "The key file can be generated only in XmlV2, Hex or Binary format",
+        ));
+    }
+
+    let mut key_data = [0u8; KEY_DATA_SIZE];
+    OsRng.fill_bytes(&mut key_data);
+
+    let content = match format {
+        KeyFileFormat::Binary => key_data.to_vec(),
+        KeyFileFormat::Hex => hex::encode_upper(key_data).into_bytes(),
+        _ => {
+            // Same layout as KeePass uses. The hex encoded key data is in groups of 8 chars, 4 groups in a line
+            let hex_data = hex::encode_upper(key_data);
+            let lines = hex_data
+                .as_bytes()
+                .chunks(32)
+                .map(|line| {
+                    let groups: Vec<&str> = line
+                        .chunks(8)
+                        .map(|g| std::str::from_utf8(g).unwrap_or_default())
+                        .collect();
+                    format!("\t\t\t{}", groups.join(" "))
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<KeyFile>\n\t<Meta>\n\t\t<Version>{}</Version>\n\t</Meta>\n\t<Key>\n\t\t<Data Hash=\"{}\">\n{}\n\t\t</Data>\n\t</Key>\n</KeyFile>\n",
+                XML_V2_VERSION,
+                key_data_hash(&key_data),
+                lines
+            )
+            .into_bytes()
+        }
+    };
+
+    Ok(content)
+}
+
+pub(crate) fn write_key_file(path: &Path, format: KeyFileFormat) -> OkpResult<()> {
+    let content = generate_key_file_content(format)?;
+    fs::write(path, content)?;
+    debug!("Generated key file {:?} in the format {:?}", path, format);
+    Ok(())
+}
+
+// The user picked file. Only its first part is read to find the format and the rest is
+// streamed when the file is copied
+pub(crate) struct PickedKeyFile {
+    pub(crate) file_name: String,
+    file: File,
+    head: Vec<u8>,
+}
+
+impl PickedKeyFile {
+    pub(crate) fn open(file_args: &FileArgs) -> OkpResult<Self> {
+        let OpenedFile {
+            mut file,
+            file_name,
+            ..
+        } = OpenedFile::open_to_read(file_args)?;
+
+        let mut head = vec![];
+        file.by_ref()
+            .take(FORMAT_CHECK_MAX_SIZE as u64 + 1)
+            .read_to_end(&mut head)?;
+
+        Ok(Self {
+            file_name,
+            file,
+            head,
+        })
+    }
+
+    fn fully_read(&self) -> bool {
+        self.head.len() <= FORMAT_CHECK_MAX_SIZE
+    }
+
+    // The file size of a large file is not known till it is copied
+    pub(crate) fn format_info(&self) -> KeyFileFormatInfo {
+        if self.fully_read() {
+            key_file_format_info(&self.file_name, &self.head)
+        } else {
+            KeyFileFormatInfo {
+                file_name: self.file_name.clone(),
+                file_size: self.head.len() as u64,
+                format: KeyFileFormat::Hashed,
+                hash_valid: None,
+                invalid_reason: None,
+                warning: None,
+            }
+        }
+    }
+
+    // Returns the number of bytes copied
+    pub(crate) fn copy_to<W: Write>(mut self, writer: &mut W) -> OkpResult<u64> {
+        writer.write_all(&self.head)?;
+        let rest = io::copy(&mut self.file, writer)?;
+        Ok(self.head.len() as u64 + rest)
+    }
+}
+
+// Reads the user picked file and reports its format before it is copied to the app's key files dir
+pub(crate) fn check_picked_key_file(file_args: &FileArgs) -> OkpResult<KeyFileFormatInfo> {
+    let picked = PickedKeyFile::open(file_args)?;
+    let mut info = picked.format_info();
+    if !picked.fully_read() {
+        info.file_size = picked.copy_to(&mut io::sink())?;
+    }
+    Ok(info)
+}
+
+// Hex encoded SHA-256 of the key file content
+pub(crate) fn key_file_fingerprint(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn file_fingerprint(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|d| key_file_fingerprint(&d))
+}
+
+// Called after a successful open of a db to record the key file used in its preference
+// The arg 'key_file_name' is the full path of the key file in the app's key files dir
+pub(crate) fn record_key_file_used(db_key: &str, key_file_name: &Option<String>) {
+    let key_file_used = key_file_name.as_ref().and_then(|name| {
+        let path = Path::new(name);
+        Some(DbKeyFileUse {
+            file_name: path.file_name()?.to_string_lossy().to_string(),
+            fingerprint: file_fingerprint(path)?,
+        })
+    });
+    AppState::set_db_key_file_used(db_key, key_file_used);
+}
+
+// The dbs that depend on this key file. A key file is matched by its fingerprint and
+// by its name if the fingerprint can not be formed
+fn dependent_db_keys(
+    file_name: &str,
+    fingerprint: Option<&str>,
+    key_files_used: &[(String, DbKeyFileUse)],
+) -> Vec<String> {
+    key_files_used
+        .iter()
+        .filter(|(_, k)| match fingerprint {
+            Some(f) => k.fingerprint == f,
+            None => k.file_name == file_name,
+        })
+        .map(|(db_key, _)| db_key.clone())
+        .collect()
+}
+
+// All key files in the app's key files dir that are not used by any known db
+// The key files used by the dbs opened before the key file use was recorded are also listed here
+pub(crate) fn unused_key_files() -> Vec<KeyFileInfo> {
+    let key_files_used = AppState::db_key_files_used(false);
+    util::list_key_files()
+        .into_iter()
+        .filter(|k| {
+            let fingerprint = file_fingerprint(Path::new(&k.full_file_name));
+            dependent_db_keys(&k.file_name, fingerprint.as_deref(), &key_files_used).is_empty()
+        })
+        .collect()
+}
+
+// The key file is deleted only when no db in the recent list depends on it unless 'force' is true
+pub(crate) fn delete_key_file(file_name: &str, force: bool) -> OkpResult<()> {
+    if !force {
+        let path = AppState::key_files_dir_path().join(file_name);
+        let fingerprint = file_fingerprint(&path);
+        let db_keys =
+            dependent_db_keys(file_name, fingerprint.as_deref(), &AppState::db_key_files_used(true));
+        if !db_keys.is_empty() {
+            debug!("Key file {} is used by the dbs {:?}", file_name, db_keys);
+            return Err(OkpError::DataError(
+                "The key file is used by a recently opened database and it is not deleted",
+            ));
+        }
+    }
+
+    util::delete_key_file(file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_generated_formats() {
+        for format in [KeyFileFormat::XmlV2, KeyFileFormat::Hex, KeyFileFormat::Binary] {
+            let content = generate_key_file_content(format).unwrap();
+            let info = key_file_format_info("test.keyx", &content);
+            assert_eq!(format, info.format);
+            assert!(info.is_valid());
+        }
+
+        let content = generate_key_file_content(KeyFileFormat::XmlV2).unwrap();
+        let info = key_file_format_info("test.keyx", &content);
+        assert_eq!(Some(true), info.hash_valid);
+
+        assert!(generate_key_file_content(KeyFileFormat::Hashed).is_err());
+        assert!(generate_key_file_content(KeyFileFormat::XmlV1).is_err());
+    }
+
+    #[test]
+    fn verify_xml_v2_hash_check() {
+        let content = String::from_utf8(generate_key_file_content(KeyFileFormat::XmlV2).unwrap()).unwrap();
+
+        // Changing one hex digit of the key data should fail the hash check
+        let (_, data) = xml_element(&content, "Data").unwrap();
+        let first = data.trim().chars().next().unwrap();
+        let changed = if first == 'A' { 'B' } else { 'A' };
+        let start = content.find(data).unwrap() + data.find(first).unwrap();
+        let mut tampered = content.clone();
+        tampered.replace_range(start..start + 1, &changed.to_string());
+
+        let info = key_file_format_info("test.keyx", tampered.as_bytes());
+        assert_eq!(KeyFileFormat::XmlV2, info.format);
+        assert_eq!(Some(false), info.hash_valid);
+        assert!(!info.is_valid());
+
+        // No hash attribute
+        let xml = "<?xml version=\"1.0\"?><KeyFile><Meta><Version>2.0</Version></Meta><Key><Data>0102 0304</Data></Key></KeyFile>";
+        let info = key_file_format_info("test.keyx", xml.as_bytes());
+        assert_eq!(None, info.hash_valid);
+        assert!(info.is_valid());
+
+        let xml = "<KeyFile><Meta><Version>2.0</Version></Meta><Key><Data>01XY</Data></Key></KeyFile>";
+        assert!(!key_file_format_info("test.keyx", xml.as_bytes()).is_valid());
+
+        // An unknown version or an incomplete XML is used as a hashed key file as KeePass does
+        for xml in [
+            "<KeyFile><Meta><Version>3.0</Version></Meta><Key><Data>0102</Data></Key></KeyFile>",
+            "<KeyFile><Key><Data>0102</Data></Key></KeyFile>",
+            "<KeyFile><Meta><Version>2.0</Version></Meta></KeyFile>",
+            "<KeyFile><Meta><Version>1.0</Version></Meta><Key><Data>!!</Data></Key></KeyFile>",
+        ] {
+            let info = key_file_format_info("test.keyx", xml.as_bytes());
+            assert_eq!(KeyFileFormat::Hashed, info.format);
+            assert!(info.is_valid());
+            assert!(info.warning.is_some());
+        }
+    }
+
+    #[test]
+    fn verify_other_formats() {
+        let xml = "<?xml version=\"1.0\"?><KeyFile><Meta><Version>1.00</Version></Meta><Key><Data>AQIDBA==</Data></Key></KeyFile>";
+        let info = key_file_format_info("test.key", xml.as_bytes());
+        assert_eq!(KeyFileFormat::XmlV1, info.format);
+        assert!(info.is_valid());
+
+        let hex_data = "ab".repeat(32);
+        assert_eq!(
+            KeyFileFormat::Hex,
+            key_file_format_info("test.key", hex_data.as_bytes()).format
+        );
+
+        // 64 chars but not hex
+        let not_hex = "zz".repeat(32);
+        assert_eq!(
+            KeyFileFormat::Hashed,
+            key_file_format_info("test.key", not_hex.as_bytes()).format
+        );
+
+        assert_eq!(
+            KeyFileFormat::Binary,
+            key_file_format_info("test.key", &[7u8; 32]).format
+        );
+
+        let info = key_file_format_info("photo.jpg", &[1u8; 100]);
+        assert_eq!(KeyFileFormat::Hashed, info.format);
+        assert!(info.is_valid());
+
+        assert!(!key_file_format_info("empty", &[]).is_valid());
+    }
+
+    #[test]
+    fn verify_dependent_db_keys() {
+        let fingerprint = key_file_fingerprint(b"key data");
+        let key_files_used = vec![
+            (
+                "file:///tmp/Team.kdbx".to_string(),
+                DbKeyFileUse {
+                    file_name: "team.keyx".into(),
+                    fingerprint: fingerprint.clone(),
+                },
+            ),
+            (
+                "file:///tmp/Travel.kdbx".to_string(),
+                DbKeyFileUse {
+                    file_name: "travel.keyx".into(),
+                    fingerprint: key_file_fingerprint(b"other key data"),
+                },
+            ),
+        ];
+
+        // A renamed key file is still matched by its fingerprint
+        assert_eq!(
+            vec!["file:///tmp/Team.kdbx".to_string()],
+            dependent_db_keys("renamed.keyx", Some(&fingerprint), &key_files_used)
+        );
+        assert!(dependent_db_keys(
+            "team.keyx",
+            Some(&key_file_fingerprint(b"changed")),
+            &key_files_used
+        )
+        .is_empty());
+        assert_eq!(
+            vec!["file:///tmp/Travel.kdbx".to_string()],
+            dependent_db_keys("travel.keyx", None, &key_files_used)
+        );
+    }
+
+    #[test]
+    fn verify_xml_element() {
+        let xml = "<Key><DataX>1</DataX><Data Hash=\"AB\">22</Data></Key>";
+        let (attrs, inner) = xml_element(xml, "Data").unwrap();
+        assert_eq!("22", inner);
+        assert_eq!(Some("AB"), xml_attribute(attrs, "Hash"));
+        assert!(xml_element(xml, "Version").is_none());
+    }
+}
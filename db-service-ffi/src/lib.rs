@@ -1,18 +1,26 @@
 #![allow(dead_code, unused_imports)]
 mod android;
+mod app_archive;
 mod app_lock;
 mod app_preference;
 mod auto_open;
 mod app_state;
 mod backup;
 mod biometric_auth;
+mod bundle_crypto;
 mod commands;
 mod event_dispatcher;
 mod file_util;
 mod ios;
+mod json_migration;
+mod key_file;
 mod key_secure;
 mod db_backup_read;
+mod db_mirror;
+mod db_relocate;
 mod remote_storage;
+mod selective_reset;
+mod session_timeout;
 mod util;
 
 mod udl_functions;
@@ -1,6 +1,6 @@
 use std::{path::PathBuf, sync::{Arc, OnceLock}};
 use onekeepass_core::error::Result;
-use super::storage_service::RemoteStorageType;
+use super::storage_service::{RemoteStorageType, TransferProgress};
 
 // TODO: 
 // Moved this module along with 'storage' module from onekeepass_core crate as it is not yet used for desktop app. 
@@ -25,6 +25,8 @@ pub trait CommonCallbackService: Send + Sync {
     fn sftp_private_key_file_full_path(&self,connection_id:&str,file_name:&str) -> PathBuf;
     fn sftp_copy_from_temp_key_file(&self,connection_id:&str,file_name:&str) -> Result<()>;
     fn remote_storage_config_deleted(&self,remote_type:RemoteStorageType,connection_id:&str,) -> Result<()>;
+    // Called from the async read/write calls as the file content is transferred in chunks
+    fn rs_transfer_progress(&self,progress:&TransferProgress);
 }
 
 static CALLBACK_PROVIDER: OnceLock<CallbackServiceProvider> = OnceLock::new();
@@ -1,5 +1,5 @@
 use crate::{
-    app_state::AppState, remote_storage::{callback_service::{CallbackServiceProvider, CommonCallbackService}, RemoteStorageType}, util
+    app_state::AppState, commands::ok_json_str, remote_storage::{callback_service::{CallbackServiceProvider, CommonCallbackService}, storage_service::TransferProgress, RemoteStorageType}, util
 };
 use log::debug;
 use std::{
@@ -77,4 +77,11 @@ impl CommonCallbackService for CommonCallbackServiceImpl {
 
         Ok(())
     }
+
+    // Sends the progress to the UI
+    fn rs_transfer_progress(&self, progress: &TransferProgress) {
+        let json_string = ok_json_str(progress);
+        let _r = AppState::event_dispatcher().send_rs_transfer_progress(json_string);
+        // debug!("send_rs_transfer_progress r is {:?}", &_r);
+    }
 }
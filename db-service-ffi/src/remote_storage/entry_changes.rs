@@ -0,0 +1,227 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use log::debug;
+use once_cell::sync::Lazy;
+use onekeepass_core::db_service;
+
+// Used to form the git commit message of a db save. The titles of the entries added, modified or deleted
+// since the last read or save of the db are named in the message
+
+// Only these many titles are named in each category of the changes
+const MAX_TITLES_IN_MESSAGE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+struct EntryState {
+    title: String,
+    modified_time: Option<i64>,
+}
+
+// Entries of a db (keyed by the entry uuid) as found at its last read or save
+type EntriesSnapshot = HashMap<String, EntryState>;
+
+static SNAPSHOTS: Lazy<Mutex<HashMap<String, EntriesSnapshot>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Default)]
+struct EntryChanges {
+    added: Vec<String>,
+    modified: Vec<String>,
+    deleted: Vec<String>,
+}
+
+impl EntryChanges {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+fn current_entries(db_key: &str) -> Option<EntriesSnapshot> {
+    let summaries =
+        db_service::entry_summary_data(db_key, db_service::EntryCategory::AllEntries).ok()?;
+
+    let snapshot = summaries
+        .iter()
+        .map(|s| {
+            let state = EntryState {
+                title: s.title.clone().unwrap_or_default(),
+                modified_time: s.modified_time,
+            };
+            (s.uuid.to_string(), state)
+        })
+        .collect();
+
+    Some(snapshot)
+}
+
+// Called after the db is read or saved
+pub(crate) fn record_snapshot(db_key: &str) {
+    if let Some(snapshot) = current_entries(db_key) {
+        SNAPSHOTS.lock().unwrap().insert(db_key.to_string(), snapshot);
+    }
+}
+
+// The snapshot has the decrypted entry titles and it is removed when the db is closed or locked
+pub(crate) fn remove_snapshot(db_key: &str) {
+    SNAPSHOTS.lock().unwrap().remove(db_key);
+}
+
+// Removes the snapshots of the dbs that are not opened anymore (e.g closed on app reset)
+pub(crate) fn retain_snapshots(opened_db_keys: &[String]) {
+    SNAPSHOTS
+        .lock()
+        .unwrap()
+        .retain(|k, _| opened_db_keys.contains(k));
+}
+
+// Forms the commit message using the changes of the entries since the last snapshot
+pub(crate) fn commit_message(db_key: &str, file_name: &str) -> String {
+    let changes = match (
+        SNAPSHOTS.lock().unwrap().get(db_key),
+        current_entries(db_key),
+    ) {
+        (Some(previous), Some(current)) => find_changes(previous, &current),
+        _ => EntryChanges::default(),
+    };
+
+    debug!("Entry changes for the commit message {:?}", &changes);
+
+    format_message(file_name, &changes)
+}
+
+fn find_changes(previous: &EntriesSnapshot, current: &EntriesSnapshot) -> EntryChanges {
+    let mut changes = EntryChanges::default();
+
+    for (uuid, state) in current {
+        match previous.get(uuid) {
+            None => changes.added.push(state.title.clone()),
+            Some(p) if p != state => changes.modified.push(state.title.clone()),
+            _ => {}
+        }
+    }
+
+    for (uuid, state) in previous {
+        if !current.contains_key(uuid) {
+            changes.deleted.push(state.title.clone());
+        }
+    }
+
+    changes.added.sort();
+    changes.modified.sort();
+    changes.deleted.sort();
+
+    changes
+}
+
+fn titles_line(label: &str, titles: &[String]) -> Option<String> {
+    if titles.is_empty() {
+        return None;
+    }
+
+    let mut names = titles
+        .iter()
+        .take(MAX_TITLES_IN_MESSAGE)
+        .map(|t| if t.is_empty() { "(No title)" } else { t.as_str() })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if titles.len() > MAX_TITLES_IN_MESSAGE {
+        names.push_str(&format!(" and {} more", titles.len() - MAX_TITLES_IN_MESSAGE));
+    }
+
+    Some(format!("{}: {}", label, names))
+}
+
+// e.g
+// Update Test1.kdbx (1 added, 2 modified)
+//
+// Added: Bank
+// Modified: Email, Github
+fn format_message(file_name: &str, changes: &EntryChanges) -> String {
+    if changes.is_empty() {
+        return format!("Update {}", file_name);
+    }
+
+    let counts = [
+        (changes.added.len(), "added"),
+        (changes.modified.len(), "modified"),
+        (changes.deleted.len(), "deleted"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, label)| format!("{} {}", n, label))
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    let lines = [
+        titles_line("Added", &changes.added),
+        titles_line("Modified", &changes.modified),
+        titles_line("Deleted", &changes.deleted),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("\n");
+
+    format!("Update {} ({})\n\n{}", file_name, counts, lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, modified_time: i64) -> EntryState {
+        EntryState {
+            title: title.into(),
+            modified_time: Some(modified_time),
+        }
+    }
+
+    #[test]
+    fn verify_commit_message_of_entry_changes() {
+        let previous: EntriesSnapshot = [
+            ("1".to_string(), entry("Email", 10)),
+            ("2".to_string(), entry("Github", 10)),
+            ("3".to_string(), entry("Old", 10)),
+        ]
+        .into_iter()
+        .collect();
+
+        let current: EntriesSnapshot = [
+            ("1".to_string(), entry("Email", 20)),
+            ("2".to_string(), entry("Github", 10)),
+            ("4".to_string(), entry("Bank", 20)),
+        ]
+        .into_iter()
+        .collect();
+
+        let changes = find_changes(&previous, &current);
+        assert_eq!(vec!["Bank".to_string()], changes.added);
+        assert_eq!(vec!["Email".to_string()], changes.modified);
+        assert_eq!(vec!["Old".to_string()], changes.deleted);
+
+        assert_eq!(
+            "Update Test1.kdbx (1 added, 1 modified, 1 deleted)\n\nAdded: Bank\nModified: Email\nDeleted: Old",
+            format_message("Test1.kdbx", &changes)
+        );
+
+        assert_eq!(
+            "Update Test1.kdbx",
+            format_message("Test1.kdbx", &find_changes(&current, &current))
+        );
+    }
+
+    #[test]
+    fn verify_snapshots_removed() {
+        let snapshot: EntriesSnapshot = [("1".to_string(), entry("Email", 10))].into_iter().collect();
+        for db_key in ["snapshot_db1", "snapshot_db2", "snapshot_db3"] {
+            SNAPSHOTS.lock().unwrap().insert(db_key.into(), snapshot.clone());
+        }
+
+        remove_snapshot("snapshot_db1");
+        assert!(!SNAPSHOTS.lock().unwrap().contains_key("snapshot_db1"));
+
+        retain_snapshots(&["snapshot_db2".to_string()]);
+        let snapshots = SNAPSHOTS.lock().unwrap();
+        assert!(snapshots.contains_key("snapshot_db2"));
+        assert!(!snapshots.contains_key("snapshot_db3"));
+    }
+}
@@ -1,25 +1,30 @@
 mod callback_service;
 pub(crate) mod callback_service_provider;
+mod entry_changes;
 pub(crate) mod secure_store;
 mod storage_service;
 
 pub use storage_service::{
-    read_configs, RemoteStorageOperation, RemoteStorageOperationType, RemoteStorageType,
+    cancel_transfer, export_all_connection_configs, import_all_connection_configs, read_configs,
+    CollisionResolution, FindLimits, ImportedConnectionConfigs, RemoteStorageOperation,
+    RemoteStorageOperationType, RemoteStorageType,
 };
 
 use std::fs;
 use std::io::{Cursor, Read, Seek, Write};
-use std::path::Path;
 use std::sync::Arc;
 
 use crate::app_state::AppState;
 use crate::backup::{
     self, latest_backup_file_path, latest_backup_full_file_name, matching_backup_exists,
+    BackupReason,
 };
-use crate::commands::{result_json_str, CommandArg, ResponseJson};
+use crate::commands::{result_json_str, CommandArg, ExportDataInfo, ResponseJson};
 use crate::db_backup_read::{read_latest_backup_db_arg, KdbxLoadedEx};
 use crate::udl_types::FileInfo;
-use crate::{biometric_auth, open_backup_file, parse_command_args_or_err};
+use crate::{
+    biometric_auth, db_mirror, key_file, open_backup_file, parse_command_args_or_err, util,
+};
 use crate::{OkpError, OkpResult};
 use nom::Err;
 
@@ -42,7 +47,11 @@ use nom::{
 use onekeepass_core::db_service::{KdbxLoaded, KdbxSaved};
 use onekeepass_core::{db_service, error, service_util};
 use serde::Serialize;
-use storage_service::ParsedDbKey;
+use storage_service::{
+    git::{Git, GitFileVersion},
+    ConnectionConfigsBundleInfo, FoundKdbxFiles, ParsedDbKey,
+    RemoteFileMetadata, TransferDirection, TransferRequest,
+};
 
 /// -------   All public functions   -------
 
@@ -59,20 +68,18 @@ pub(crate) fn uri_to_file_info(db_key: &str) -> Option<FileInfo> {
     }
 
     let mut file_info = FileInfo::default();
-    // We use the backup file to form this instead of making remote call
+    // We use the backup file and its manifest entry to form this instead of making remote call
+    let latest = backup::latest_backup_entry(db_key);
     let file_info_opt = backup::latest_backup_file_path(db_key)
         .and_then(|p| p.as_path().metadata().ok())
         .map(|md| {
             //debug!("RS Bk modified Metadata is {:?} ", &md);
             file_info.file_size = Some(md.len() as i64);
-            file_info.last_modified = md.modified().ok().map(|t| {
-                //debug!(" RS file_info.last_modified (SystemTime) {:?} ",&t);
-                let mut r = service_util::system_time_to_seconds(t) as i64;
-                // Need to be in milliseconds
-                r = r * 1000;
-                //debug!(" RS file_info.last_modified since epoch {} ",&r);
-                r
-            });
+            // Need to be in milliseconds
+            file_info.last_modified = backup::latest_synced_backup_entry(db_key)
+                .and_then(|e| e.remote_modified)
+                .or(latest.map(|e| e.created))
+                .map(|t| t * 1000);
             file_info.file_name = Some(parsed.file_name.to_string());
             file_info.location = Some(parsed.rs_type_name.to_string());
             file_info
@@ -106,6 +113,66 @@ pub(crate) fn rs_create_kdbx(json_args: &str) -> ResponseJson {
     result_json_str(rs_create_file(json_args))
 }
 
+#[inline]
+pub(crate) fn rs_find_kdbx_files(json_args: &str) -> ResponseJson {
+    result_json_str(rs_find_files(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_create_dir(json_args: &str) -> ResponseJson {
+    result_json_str(connected_rs_operation_type(json_args).and_then(|rs| rs.create_dir()))
+}
+
+#[inline]
+pub(crate) fn rs_copy(json_args: &str) -> ResponseJson {
+    result_json_str(connected_rs_operation_type(json_args).and_then(|rs| rs.copy()))
+}
+
+#[inline]
+pub(crate) fn rs_rename(json_args: &str) -> ResponseJson {
+    result_json_str(rs_rename_file(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_delete_file(json_args: &str) -> ResponseJson {
+    result_json_str(rs_delete_remote_file(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_git_file_history(json_args: &str) -> ResponseJson {
+    result_json_str(git_file_history(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_git_restore_version(json_args: &str) -> ResponseJson {
+    result_json_str(git_restore_version(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_export_connection_configs(json_args: &str) -> ResponseJson {
+    result_json_str(export_connection_configs_bundle(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_read_connection_configs_bundle(json_args: &str) -> ResponseJson {
+    result_json_str(read_connection_configs_bundle(json_args))
+}
+
+#[inline]
+pub(crate) fn rs_import_connection_configs(json_args: &str) -> ResponseJson {
+    result_json_str(import_connection_configs_bundle(json_args))
+}
+
+#[inline]
+pub(crate) fn remove_entries_snapshot(db_key: &str) {
+    entry_changes::remove_snapshot(db_key)
+}
+
+#[inline]
+pub(crate) fn retain_entries_snapshots(opened_db_keys: &[String]) {
+    entry_changes::retain_snapshots(opened_db_keys)
+}
+
 /// ----------------------------------------------------------------------
 
 // We need to parse the passed db_key and extracts the remote operation type, connection_id and the file path part
@@ -193,14 +260,292 @@ fn parse_db_key_to_rs_type_opertaion(db_key: &str) -> OkpResult<RemoteStorageOpe
     Ok(rt)
 }
 
+// Parses the remote operation arg and ensures that the remote connection is established before any
+// file or dir management call
+fn connected_rs_operation_type(json_args: &str) -> OkpResult<RemoteStorageOperationType> {
+    let (rs_operation_type,) = parse_command_args_or_err!(
+        json_args,
+        RemoteServerOperationArg { rs_operation_type }
+    );
+
+    rs_operation_type.connect_by_id()?;
+
+    Ok(rs_operation_type)
+}
+
+fn rs_find_files(json_args: &str) -> OkpResult<FoundKdbxFiles> {
+    let (rs_operation_type, max_depth, start_dir, max_entries) = parse_command_args_or_err!(
+        json_args,
+        FindKdbxFilesArg {
+            rs_operation_type,
+            max_depth,
+            start_dir,
+            max_entries
+        }
+    );
+
+    rs_operation_type.connect_by_id()?;
+
+    rs_operation_type.find_kdbx_files(start_dir, FindLimits::new(max_depth, max_entries))
+}
+
+#[inline]
+fn is_db_opened(db_key: &str) -> bool {
+    db_service::all_kdbx_cache_keys().map_or(false, |v| v.iter().any(|k| k == db_key))
+}
+
+// Returns the git storage of the db file
+fn git_storage(db_key: &str) -> OkpResult<Git> {
+    let RemoteStorageOperationType::Git(git) = parse_db_key_to_rs_type_opertaion(db_key)? else {
+        return Err(error::Error::DataError(
+            "The database is not stored in a git repository",
+        ));
+    };
+    Ok(git)
+}
+
+fn git_file_history(json_args: &str) -> OkpResult<Vec<GitFileVersion>> {
+    let (db_key,) = parse_command_args_or_err!(json_args, DbKey { db_key });
+    git_storage(&db_key)?.file_history()
+}
+
+// The UI needs to reload the db after the restore. Any save of the db opened before the restore
+// is reported as a save conflict
+fn git_restore_version(json_args: &str) -> OkpResult<RemoteFileMetadata> {
+    let (db_key, commit_id) =
+        parse_command_args_or_err!(json_args, GitVersionArg { db_key, commit_id });
+    git_storage(&db_key)?.restore_version(&commit_id)
+}
+
+#[derive(Serialize)]
+struct RemoteFileRenamed {
+    // UI needs to use this new db_key in place of the old db_key if the renamed file is an opened db
+    new_db_key: String,
+    meta: RemoteFileMetadata,
+}
+
+fn rs_rename_file(json_args: &str) -> OkpResult<RemoteFileRenamed> {
+    let rs_operation_type = connected_rs_operation_type(json_args)?;
+
+    let (Some(db_key), Some(new_db_key)) = (
+        rs_operation_type
+            .file_path()
+            .and_then(|p| rs_operation_type.db_key_for(p)),
+        rs_operation_type
+            .target_path()
+            .and_then(|p| rs_operation_type.db_key_for(p)),
+    ) else {
+        return Err(error::Error::DataError(
+            "The file path and the target path are required for the rename",
+        ));
+    };
+
+    let meta = rs_operation_type.rename()?;
+
+    update_renamed_db_key(&db_key, &new_db_key, &meta)?;
+
+    Ok(RemoteFileRenamed { new_db_key, meta })
+}
+
+// All references of the old db_key are changed to use the new db_key when the renamed file
+// is an opened db or a db found in the recent list
+fn update_renamed_db_key(
+    db_key: &str,
+    new_db_key: &str,
+    meta: &RemoteFileMetadata,
+) -> OkpResult<()> {
+    let db_opened = is_db_opened(db_key);
+
+    if !db_opened && AppState::get_recently_used(db_key).is_none() {
+        return Ok(());
+    }
+
+    if db_opened {
+        db_service::rename_db_key(db_key, new_db_key)?;
+    }
+
+    // The remote file is already renamed at this point. So a failure in moving the backups should not stop
+    // the remaining updates. Otherwise the recent list and the stored credentials will continue to use the old db_key
+    if let Err(e) = backup::move_backup_history_files(db_key, new_db_key) {
+        error!(
+            "Moving the backup history files from {} to {} failed with error {}",
+            db_key, new_db_key, e
+        );
+    }
+
+    let new_file_name = new_db_key.rsplit_once("/").map_or(new_db_key, |p| p.1);
+    AppState::rename_db_key(db_key, new_db_key, new_file_name);
+
+    let _ = biometric_auth::StoredCredential::move_credentials(db_key, new_db_key)
+        .inspect_err(|e| info!("Moving the stored credentials failed with error {}", e));
+
+    // Any previous ref to the backup file stored for the save error is not valid after the move
+    AppState::remove_last_backup_name_on_error(db_key);
+
+    // The latest backup's recorded modified time should match the remote file's modified time after the rename.
+    // Otherwise the next save will be reported as a content change conflict
+    let file_modified_time = meta.modified.map(|t| t as i64);
+    backup::set_latest_backup_remote_modified(new_db_key, file_modified_time);
+
+    AppState::update_recent_db_file_info(new_db_key);
+
+    debug!("Renamed db_key {} to {}", db_key, new_db_key);
+
+    Ok(())
+}
+
+fn rs_delete_remote_file(json_args: &str) -> OkpResult<()> {
+    let rs_operation_type = connected_rs_operation_type(json_args)?;
+
+    let db_key = rs_operation_type
+        .file_path()
+        .and_then(|p| rs_operation_type.db_key_for(p));
+
+    if db_key.as_deref().map_or(false, is_db_opened) {
+        return Err(error::Error::DataError(
+            "The database is opened. Please close the database before deleting the file",
+        ));
+    }
+
+    rs_operation_type.delete_file()
+}
+
+#[inline]
+pub(crate) fn is_remote_db_key(db_key: &str) -> bool {
+    parse_db_key_to_rs_type_opertaion(db_key).is_ok()
+}
+
+// Checks whether the remote file of this db_key exists. Only a not found file gives false and any other
+// connection or server error is returned as error
+pub(crate) fn remote_db_key_exists(db_key: &str) -> OkpResult<bool> {
+    let rs_operation_type = parse_db_key_to_rs_type_opertaion(db_key)?;
+
+    rs_operation_type.connect_by_id()?;
+
+    rs_operation_type.file_exists()
+}
+
+// The Sftp and Webdav db_keys work in any device once their connections are available.
+// The git repositories are cloned locally and their db_keys are device specific
+pub(crate) fn is_portable_remote_db_key(db_key: &str) -> bool {
+    matches!(
+        parse_db_key_to_rs_type_opertaion(db_key),
+        Ok(RemoteStorageOperationType::Sftp(_) | RemoteStorageOperationType::Webdav(_))
+    )
+}
+
+// Writes the db content to the remote file of this db_key. Used to push the saved db content to its mirrors.
+// The file is created when 'create' is true
+pub(crate) fn write_to_remote_db_key(
+    db_key: &str,
+    data: Arc<Vec<u8>>,
+    create: bool,
+) -> OkpResult<RemoteFileMetadata> {
+    let rs_operation_type = parse_db_key_to_rs_type_opertaion(db_key)?;
+
+    rs_operation_type.connect_by_id()?;
+
+    if create {
+        rs_operation_type.create_file(data)
+    } else {
+        // No request id is used as the UI does not cancel a mirror push
+        let transfer_request = TransferRequest::register(None, TransferDirection::Upload);
+        rs_operation_type.write_file(data, transfer_request.context())
+    }
+}
+
+// Deletes the remote file of this db_key. Used when a db is relocated to another location
+pub(crate) fn delete_remote_db_key(db_key: &str) -> OkpResult<()> {
+    let rs_operation_type = parse_db_key_to_rs_type_opertaion(db_key)?;
+
+    rs_operation_type.connect_by_id()?;
+
+    rs_operation_type.delete_file()
+}
+
+// The bundle file name used for the exported connection configs
+const CONNECTIONS_BUNDLE_FILE_NAME: &str = "OneKeePass-Connections.okpconn";
+
+fn export_connection_configs_bundle(json_args: &str) -> OkpResult<ExportDataInfo> {
+    let (sftp_connection_ids, webdav_connection_ids, bundle_password) = parse_command_args_or_err!(
+        json_args,
+        RsConfigsExportArg {
+            sftp_connection_ids,
+            webdav_connection_ids,
+            bundle_password
+        }
+    );
+
+    let data = storage_service::export_connection_configs(
+        &sftp_connection_ids,
+        &webdav_connection_ids,
+        &bundle_password,
+    )?;
+
+    // Any previously exported files are removed
+    let _ = util::clean_export_data_dir();
+
+    let export_file_path = AppState::export_data_dir_path().join(CONNECTIONS_BUNDLE_FILE_NAME);
+    fs::write(&export_file_path, data)?;
+
+    let export_file_path_opt = export_file_path.to_str().map(|s| s.to_string());
+    let exported_data_full_file_name = if cfg!(target_os = "ios") {
+        crate::ios::to_ios_file_uri_str(&export_file_path_opt)
+    } else {
+        export_file_path_opt
+    };
+
+    Ok(ExportDataInfo {
+        full_file_name_uri: None,
+        file_name: Some(CONNECTIONS_BUNDLE_FILE_NAME.into()),
+        exported_data_full_file_name,
+    })
+}
+
+// The 'bundle_file_name' is the full path of the picked bundle file copied to the temp dir
+// (see PickedFileHandler::ConnectionConfigsBundleFile)
+fn read_connection_configs_bundle(json_args: &str) -> OkpResult<ConnectionConfigsBundleInfo> {
+    let (bundle_file_name, bundle_password) = parse_command_args_or_err!(
+        json_args,
+        RsConfigsBundleArg {
+            bundle_file_name,
+            bundle_password
+        }
+    );
+
+    let data = fs::read(&bundle_file_name)?;
+    storage_service::read_connection_configs_bundle(&data, &bundle_password)
+}
+
+fn import_connection_configs_bundle(json_args: &str) -> OkpResult<ImportedConnectionConfigs> {
+    let (bundle_file_name, bundle_password, resolutions) = parse_command_args_or_err!(
+        json_args,
+        RsConfigsImportArg {
+            bundle_file_name,
+            bundle_password,
+            resolutions
+        }
+    );
+
+    let data = fs::read(&bundle_file_name)?;
+    let imported =
+        storage_service::import_connection_configs(&data, &bundle_password, &resolutions)?;
+
+    // The temp copy of the picked bundle file is not required after the import
+    let _ = fs::remove_file(&bundle_file_name);
+
+    Ok(imported)
+}
+
 fn rs_read_file(json_args: &str) -> OkpResult<KdbxLoadedEx> {
-    let (db_file_name, password, key_file_name, biometric_auth_used) = parse_command_args_or_err!(
+    let (db_file_name, password, key_file_name, biometric_auth_used, request_id) = parse_command_args_or_err!(
         json_args,
         OpenDbArg {
             db_file_name,
             password,
             key_file_name,
-            biometric_auth_used
+            biometric_auth_used,
+            request_id
         }
     );
 
@@ -220,7 +565,10 @@ fn rs_read_file(json_args: &str) -> OkpResult<KdbxLoadedEx> {
 
     debug!("Remote server connected");
 
-    let r = rs_operation_type.read()?;
+    // The UI may cancel this read using the request id till the transfer is completed
+    let transfer_request = TransferRequest::register(request_id, TransferDirection::Download);
+    let r = rs_operation_type.read(transfer_request.context())?;
+    drop(transfer_request);
 
     let file_modified_time = r.meta.modified.map(|x| x as i64);
 
@@ -243,29 +591,12 @@ fn rs_read_file(json_args: &str) -> OkpResult<KdbxLoadedEx> {
         &file_modified_time,
     )?;
 
-    Ok(kdbx_loaded.into())
-}
-
-// Sets the modified time of the backup file to that of the db file
-// The arg file_modified_time is in seconds
-fn set_backup_modified_time(backup_file_name: &Option<&String>, file_modified_time: &Option<i64>) {
-    if let Some(bk_file_name) = backup_file_name.as_deref() {
-        debug!(
-            "Before setting mtime {:?}",
-            Path::new(bk_file_name).metadata()
-        );
-        if let Some(mtime) = file_modified_time {
-            let r = filetime::set_file_mtime(
-                bk_file_name,
-                filetime::FileTime::from_unix_time(*mtime, 0),
-            );
-            debug!("Setting modified time of backup file status is {:?}", r);
-            debug!(
-                "After setting mtime {:?}",
-                Path::new(bk_file_name).metadata()
-            )
-        }
+    // The entries read are used to name the changed entries in the next commit message
+    if rs_operation_type.is_git() {
+        entry_changes::record_snapshot(&db_file_name);
     }
+
+    Ok(kdbx_loaded)
 }
 
 // This is based on a part of the fn 'udl_functions::internal_read_kdbx'
@@ -279,7 +610,7 @@ fn read_with_backup<R: Read + Seek>(
     biometric_auth_used: bool,
     file_name: &str,
     file_modified_time: &Option<i64>,
-) -> OkpResult<db_service::KdbxLoaded> {
+) -> OkpResult<KdbxLoadedEx> {
     // First we read the db file
     let kdbx_loaded =
         db_service::read_kdbx(reader, db_key, password, key_file_name, Some(file_name)).map_err(
@@ -287,6 +618,7 @@ fn read_with_backup<R: Read + Seek>(
                 // Need this when db login fails while using the previously stored credentials
                 // and the UI will then popup the usual dialog
                 error::Error::HeaderHmacHashCheckFailed if biometric_auth_used => {
+                    biometric_auth::StoredCredential::remove_stale_credentials(db_key);
                     error::Error::BiometricCredentialsAuthenticationFailed
                 }
                 _ => e,
@@ -321,7 +653,9 @@ fn read_with_backup<R: Read + Seek>(
             .and(backup_file.sync_all())
             .and(backup_file.rewind())?;
 
-        set_backup_modified_time(&backup_file_name.as_ref(), file_modified_time);
+        if let Some(bk) = backup_file_name.as_deref() {
+            backup::record_backup(&db_key, bk, BackupReason::OnRead, *file_modified_time);
+        }
 
         debug!("Created backup file for the db_key {}", &db_key);
     }
@@ -338,21 +672,29 @@ fn read_with_backup<R: Read + Seek>(
     }
 
     // Store the crdentials if we will be using biometric
-    let _r = biometric_auth::StoredCredential::store_credentials_on_check(
+    let refreshed = biometric_auth::StoredCredential::store_credentials_on_check(
         db_key,
         &password.map(|s| s.to_string()),
         &key_file_name.map(|s| s.to_string()),
         biometric_auth_used,
-    );
+    )
+    .unwrap_or(false);
 
-    Ok(kdbx_loaded)
+    key_file::record_key_file_used(db_key, &key_file_name.map(|s| s.to_string()));
+
+    // Any previous locked session of this db ends with this open
+    AppState::unlock_db_activity(db_key);
+
+    Ok(KdbxLoadedEx::from(kdbx_loaded).set_biometric_credentials_refreshed(refreshed))
 }
 
 fn is_rs_file_modified(
     db_key: &str,
     rs_operation_type: &RemoteStorageOperationType,
 ) -> OkpResult<bool> {
-    let Some(bk_full_path) = backup::latest_backup_file_path(db_key) else {
+    // The remote file's modified time recorded when the latest backup was read from or saved to the
+    // remote storage is used for this check
+    let Some(synced_entry) = backup::latest_synced_backup_entry(db_key) else {
         return Err(error::Error::UnRecoverableError(format!(
             "Expected a backup file and it is not found"
         )));
@@ -365,23 +707,12 @@ fn is_rs_file_modified(
         ))
     })?;
 
-    let md = bk_full_path.metadata().map_err(|e| {
-        error::Error::UnRecoverableError(format!(
-            "Geting backup file metadata failed. Error details {:?}",
-            e
-        ))
-    })?;
-
-    let md = md.modified().map_err(|e| {
-        error::Error::UnexpectedError(format!(
-            "Geting modified time backup file failed. Error details {:?}",
-            e
-        ))
-    })?;
-
-    debug!(" Rmd is {:?} and md is {:?} ", &rmd, &md);
+    debug!(
+        " Rmd is {:?} and the backup entry is {:?} ",
+        &rmd, &synced_entry
+    );
 
-    if rmd.modified == Some(service_util::system_time_to_seconds(md)) {
+    if rmd.modified.map(|t| t as i64) == synced_entry.remote_modified {
         Ok(false)
     } else {
         Ok(true)
@@ -389,16 +720,23 @@ fn is_rs_file_modified(
 }
 
 fn rs_write_file(json_args: &str) -> OkpResult<KdbxSaved> {
-    let (db_key, overwrite) =
-        parse_command_args_or_err!(json_args, SaveDbArg { db_key, overwrite });
+    let (db_key, overwrite, request_id) = parse_command_args_or_err!(
+        json_args,
+        SaveDbArg {
+            db_key,
+            overwrite,
+            request_id
+        }
+    );
 
-    let rs_operation_type = parse_db_key_to_rs_type_opertaion(&db_key)?;
+    let mut rs_operation_type = parse_db_key_to_rs_type_opertaion(&db_key)?;
 
     let file_name = rs_operation_type
         .file_name()
         .ok_or(error::Error::DataError(
             "File name is not found in the rs operation type formed from the db key parsing",
-        ))?;
+        ))?
+        .to_string();
 
     // Ensure that the remote connection is established
     // TODO: What to do when there is no connection?
@@ -425,7 +763,27 @@ fn rs_write_file(json_args: &str) -> OkpResult<KdbxSaved> {
         }
     }
 
-    let backup_file_name = backup::generate_backup_history_file_name(&db_key, file_name);
+    // When enabled in the connection config, the existing remote file is copied to the server backup dir
+    // before it is overwritten
+    match rs_operation_type.backup_before_write() {
+        Ok(backup) => debug!("Server backup of the existing remote file {:?}", backup),
+        Err(e) => {
+            error!("Server backup before the save failed {}", e);
+            crate::udl_functions::write_to_backup_on_error(db_key.clone());
+            return Err(error::Error::RemoteStorageCallError(format!(
+                "Backup of the existing file on the server failed and the database is not saved. {}",
+                e
+            )));
+        }
+    }
+
+    // The git storage commits the db file with a message naming the entries changed since the last read or save
+    if rs_operation_type.is_git() {
+        let message = entry_changes::commit_message(&db_key, &file_name);
+        rs_operation_type.set_save_options(message, overwrite);
+    }
+
+    let backup_file_name = backup::generate_backup_history_file_name(&db_key, &file_name);
 
     // First we write the db content to memory and db_content_mem_buff provides Read+Write fns
     let mut db_content_mem_buff = Cursor::new(Vec::<u8>::new());
@@ -441,20 +799,40 @@ fn rs_write_file(json_args: &str) -> OkpResult<KdbxSaved> {
     // TODO:
     // There is a possibility the remote storage call may fail. However we would have created the backup file
     // and need to send an error to the UI accordingly.
+    // If the user cancels the upload using the request id, an error is returned here and the last backup
+    // ref is kept in app state as in any other save error
     let data = Arc::new(db_content_mem_buff.into_inner());
-    let file_modified_time = rs_operation_type
-        .write_file(data.clone())?
-        .modified
-        .map(|t| t as i64);
+    let transfer_request = TransferRequest::register(request_id, TransferDirection::Upload);
+    let write_result = rs_operation_type.write_file(data.clone(), transfer_request.context());
+    drop(transfer_request);
+
+    let file_modified_time = match write_result {
+        Ok(meta) => meta.modified.map(|t| t as i64),
+        Err(e) => {
+            if let Some(bk) = backup_file_name.as_deref() {
+                backup::record_backup(&db_key, bk, BackupReason::OnSaveError, None);
+            }
+            return Err(e);
+        }
+    };
 
-    // We set the backup file's modified time to the same as the remote file modified time
-    set_backup_modified_time(&backup_file_name.as_ref(), &file_modified_time);
+    // The backup's manifest entry records the remote file modified time which is used in the next conflict check
+    if let Some(bk) = backup_file_name.as_deref() {
+        backup::record_backup(&db_key, bk, BackupReason::OnSave, file_modified_time);
+    }
 
     // Any previous ref stored meant for error resolution is not required
     AppState::remove_last_backup_name_on_error(&db_key);
 
     backup::prune_backup_history_files(&db_key);
 
+    if rs_operation_type.is_git() {
+        entry_changes::record_snapshot(&db_key);
+    }
+
+    // The same content is copied to the mirrors of this db if any
+    db_mirror::push_to_mirrors(&db_key, data);
+
     // Call file info to get modified time and update recent info
     // let md_in_milli = file_modified_time.map(|t| t*1000);
     // AppState::update_recent_db_modified_time(&db_key, &md_in_milli);
@@ -585,8 +963,14 @@ fn rs_create_file(json_args: &str) -> OkpResult<KdbxLoaded> {
 
     let file_modified_time = meta_data.modified.map(|t| t as i64);
 
-    // We set the backup file's modified time to the same as the remote file modified time
-    set_backup_modified_time(&backup_file_name.as_ref(), &file_modified_time);
+    // The backup's manifest entry records the remote file modified time
+    if let Some(bk) = backup_file_name.as_deref() {
+        backup::record_backup(&db_key, bk, BackupReason::OnCreate, file_modified_time);
+    }
+
+    if rs_operation_type.is_git() {
+        entry_changes::record_snapshot(&db_key);
+    }
 
     // Add this newly created db file to the recent list
     // This uses 'uri_to_file_info'
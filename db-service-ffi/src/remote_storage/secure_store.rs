@@ -1,11 +1,11 @@
-use std::{fs, path::Path, sync::Arc};
+use std::{path::Path, sync::Arc};
 
 use log::debug;
 use onekeepass_core::db_service::{
     self as kp_service,
 };
 
-use crate::{app_state::AppState, remote_storage::storage_service};
+use crate::{app_state::AppState, remote_storage::storage_service, util};
 
 use super::storage_service::ConnectionConfigReaderWriter;
 
@@ -31,20 +31,27 @@ impl ConnectionConfigReaderWriter for ConnectionConfigReaderWriterImpl {
     fn read_string(&self) -> kp_service::Result<String> {
         let full_file_path = Path::new(&AppState::remote_storage_path()).join(RS_CONFIG_FILE);
         debug!("Remote storage full_file_path is {:?}", &full_file_path);
-        if full_file_path.exists() {
-            let encrypted_data = fs::read(full_file_path)?;
+
+        // The previous copy is used when the config file is truncated and can not be decrypted or parsed
+        let read = util::read_with_prev_fallback(&full_file_path, |encrypted_data| {
             debug!("Read encrypted_data and size is {}", &encrypted_data.len());
 
             let decrypted_data = AppState::secure_enclave_cb_service()
-                .decrypt_bytes(SECURE_TAG.to_string(), encrypted_data)?;
+                .decrypt_bytes(SECURE_TAG.to_string(), encrypted_data.to_vec())?;
 
             debug!("Uncrypted and size is {}", &decrypted_data.len());
 
-            let s = String::from_utf8_lossy(&decrypted_data);
+            let s = String::from_utf8_lossy(&decrypted_data).to_string();
 
-            debug!("Uncrypted string data is  {}", &s);
+            // Ensures that the content is a valid json before using it
+            serde_json::from_str::<serde_json::Value>(&s)?;
+
+            Ok(s)
+        })?;
 
-            Ok(s.to_string())
+        if let Some((s, _)) = read {
+            debug!("Uncrypted string data is  {}", &s);
+            Ok(s)
         } else {
             debug!(
                 "Remote storage full_file_path {:?} is not found and returning empty string",
@@ -65,9 +72,7 @@ impl ConnectionConfigReaderWriter for ConnectionConfigReaderWriterImpl {
             &encrypted_data.len()
         );
 
-        // let file = OpenOptions::new().read(true).write(true).create(true).open(full_file_path)?;
-
-        fs::write(full_file_path, encrypted_data)?;
+        util::atomic_write(full_file_path, &encrypted_data)?;
 
         Ok(())
     }
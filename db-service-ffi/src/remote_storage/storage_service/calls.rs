@@ -3,7 +3,7 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 use super::{
-    server_connection_config::{RemoteStorageTypeConfig, RemoteStorageTypeConfigs}, sftp::Sftp, webdav::Webdav, ConnectStatus, ParsedDbKey, RemoteFileMetadata, RemoteReadData, ServerDirEntry
+    diagnose::DiagnosticReport, git::Git, kdbx_search::{FindLimits, FoundKdbxFiles}, server_connection_config::{RemoteStorageTypeConfig, RemoteStorageTypeConfigs}, sftp::Sftp, transfer::TransferContext, webdav::Webdav, ConnectStatus, ParsedDbKey, RemoteFileMetadata, RemoteReadData, ServerDirEntry
 };
 use crate::db_service::error::{self, Result};
 
@@ -26,10 +26,33 @@ pub trait RemoteStorageOperation {
     // requires connect_id, parent dir and sub dir
     fn list_sub_dir(&self) -> Result<ServerDirEntry>;
 
-    fn read(&self) -> Result<RemoteReadData>;
-    fn write_file(&self,data:Arc<Vec<u8>>) -> Result<RemoteFileMetadata>;
+    // The file content is transferred in chunks and the progress is reported using the 'transfer' context
+    fn read(&self,transfer:TransferContext) -> Result<RemoteReadData>;
+    fn write_file(&self,data:Arc<Vec<u8>>,transfer:TransferContext) -> Result<RemoteFileMetadata>;
     fn create_file(&self,data:Arc<Vec<u8>>) -> Result<RemoteFileMetadata>;
     fn file_metadata (&self) -> Result<RemoteFileMetadata>;
+    // requires connect_id and file_path
+    // Returns Ok(false) only when the file is not found and any other error is returned as error
+    fn file_exists(&self) -> Result<bool>;
+
+    // requires connect_id and file_path
+    // Copies the existing file to the server backup dir if that is enabled in the connection config and
+    // returns the backup file path. None is returned when there is no backup made
+    fn backup_before_write(&self) -> Result<Option<String>>;
+
+    // requires connect_id, parent dir and sub dir (the name of the new dir)
+    fn create_dir(&self) -> Result<()>;
+    // requires connect_id, file_path and target_path
+    fn rename(&self) -> Result<RemoteFileMetadata>;
+    // requires connect_id and file_path
+    fn delete_file(&self) -> Result<()>;
+    // requires connect_id, file_path and target_path
+    fn copy(&self) -> Result<RemoteFileMetadata>;
+
+    // requires connect_id
+    // Searches the kdbx files in all sub dirs of the 'start_dir'. The start dir from the connection config is used
+    // if 'start_dir' is None
+    fn find_kdbx_files(&self, start_dir: Option<String>, limits: FindLimits) -> Result<FoundKdbxFiles>;
 
     // Gets a list of connection configuartaions for Sftp or Webdav
     fn remote_storage_configs(&self) -> Result<RemoteStorageTypeConfigs>;
@@ -42,6 +65,14 @@ pub trait RemoteStorageOperation {
 
     fn file_path(&self) -> Option<&str>;
 
+    fn target_path(&self) -> Option<&str>;
+
+    fn connection_id(&self) -> Option<&str>;
+
+    // requires connection_info (not yet saved config) or connect_id
+    // Each connection stage is run separately and the result of all stages are returned
+    fn diagnose(&self) -> Result<DiagnosticReport>;
+
 }
 
 // Sftp inside the enum variant 'Sftp(Sftp)' is the struct that implements the trait 'RemoteStorageOperation'
@@ -62,11 +93,12 @@ pub trait RemoteStorageOperation {
 pub enum RemoteStorageOperationType {
     Sftp(Sftp),
     Webdav(Webdav),
+    Git(Git),
 }
 
 impl RemoteStorageOperationType {
     pub fn try_from_parsed_db_key(parsed_output: ParsedDbKey) -> Result<Self> {
-        // Note: It is expected the str value of 'rs_type_name' should match enum variants Sftp or Webdav 
+        // Note: It is expected the str value of 'rs_type_name' should match enum variants Sftp, Webdav or Git
         match parsed_output.rs_type_name {
             "Sftp" => Ok(RemoteStorageOperationType::Sftp(Sftp::from_parsed_db_key(
                 parsed_output.connection_id,
@@ -78,12 +110,40 @@ impl RemoteStorageOperationType {
                     parsed_output.file_path_part,
                 ),
             )),
+            "Git" => Ok(RemoteStorageOperationType::Git(Git::from_parsed_db_key(
+                parsed_output.connection_id,
+                parsed_output.file_path_part,
+            ))),
             _ => Err(error::Error::DataError(
                 "Invalid remote storage prefix found in the db key",
             )),
         }
     }
 
+    // Forms the db_key of a file found in the same remote storage
+    // e.g Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/db1/Test1-Sp.kdbx
+    pub fn db_key_for(&self, file_path: &str) -> Option<String> {
+        let rs_type_name = match self {
+            Self::Sftp(_) => "Sftp",
+            Self::Webdav(_) => "Webdav",
+            Self::Git(_) => "Git",
+        };
+        self.connection_id()
+            .map(|c| format!("{}-{}-{}", rs_type_name, c, file_path))
+    }
+
+    #[inline]
+    pub fn is_git(&self) -> bool {
+        matches!(self, Self::Git(_))
+    }
+
+    // Only the git storage uses these options in the next write call
+    pub fn set_save_options(&mut self, commit_message: String, overwrite: bool) {
+        if let Self::Git(git) = self {
+            git.set_save_options(commit_message, overwrite);
+        }
+    }
+
     // pub fn file_name(&self) -> Option<&str> {
     //     match self {
     //         Self::Sftp(m) => m.file_name.as_ref().map(|x| x.as_str()),
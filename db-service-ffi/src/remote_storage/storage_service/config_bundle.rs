@@ -0,0 +1,443 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use data_encoding::BASE64;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use onekeepass_core::db_service::error::{self, Result};
+
+use crate::bundle_crypto::{self, BundleFormat, KdfParams};
+use crate::remote_storage::callback_service::CallbackServiceProvider;
+
+use super::{
+    server_connection_config::{ConnectionConfigs, SftpConnectionConfig, WebdavConnectionConfig},
+    RemoteStorageType,
+};
+
+// The remote connection configs are stored encrypted using a device specific key (see 'secure_store' module)
+// and that file can not be used in another device. To share the connections, the selected configs along with
+// any sftp private key files are exported to a bundle encrypted using a user provided password.
+
+const BUNDLE_FORMAT: BundleFormat = BundleFormat {
+    name: "OneKeePass-RemoteConnections",
+    version: 1,
+    invalid_file_error: "The file is not a valid connections bundle",
+    newer_version_error: "The connections bundle is created by a newer version of the app",
+    decryption_error: "Invalid password or the connections bundle is corrupted",
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BundledPrivateKey {
+    connection_id: Uuid,
+    file_name: String,
+    // Base64 encoded content of the private key file
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BundleContent {
+    sftp_connections: Vec<SftpConnectionConfig>,
+    webdav_connections: Vec<WebdavConnectionConfig>,
+    sftp_private_keys: Vec<BundledPrivateKey>,
+}
+
+impl BundleContent {
+    fn encrypt(&self, password: &str, kdf: KdfParams) -> Result<Vec<u8>> {
+        let plain_data = serde_json::to_vec(self)?;
+        bundle_crypto::encrypt(&BUNDLE_FORMAT, &plain_data, password, kdf)
+    }
+
+    fn decrypt(bundle_data: &[u8], password: &str) -> Result<Self> {
+        let plain_data = bundle_crypto::decrypt(&BUNDLE_FORMAT, bundle_data, password)?;
+        Ok(serde_json::from_slice(&plain_data)?)
+    }
+
+    // The private key file names come from the bundle and these are used to form the file paths in the
+    // app's private key dir. Any name with a dir part is rejected so that a bundle can not write outside that dir
+    fn verify_private_key_file_names(&self) -> Result<()> {
+        let names = self
+            .sftp_connections
+            .iter()
+            .filter_map(|c| c.private_key_file_name.as_deref())
+            .chain(self.sftp_private_keys.iter().map(|k| k.file_name.as_str()));
+
+        for name in names {
+            if !is_plain_file_name(name) {
+                return Err(error::Error::DataError(
+                    "Invalid private key file name in the bundle",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn private_key(&self, connection_id: &Uuid) -> Option<&BundledPrivateKey> {
+        self.sftp_private_keys
+            .iter()
+            .find(|k| &k.connection_id == connection_id)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BundledConnection {
+    pub storage_type: RemoteStorageType,
+    pub connection_id: Uuid,
+    pub name: String,
+    // The name of the existing config when a config with the same connection_id is already present
+    // The UI then asks the user whether to replace, skip or rename the incoming config
+    pub existing_name: Option<String>,
+}
+
+// The configs found in a bundle. Returned so that the user can see the collisions before the import
+#[derive(Serialize, Debug, Default)]
+pub struct ConnectionConfigsBundleInfo {
+    pub connections: Vec<BundledConnection>,
+}
+
+// User's choice for an incoming config whose connection_id is already used by an existing config
+// Json str {"action": "Rename", "name": "Office Server"} deserializes to the variant Rename
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action")]
+pub enum CollisionResolution {
+    Replace,
+    Skip,
+    // The incoming config is added as a new connection with this name and a new connection_id
+    Rename { name: String },
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportedConnectionConfigs {
+    pub added: usize,
+    pub replaced: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+}
+
+// Forms the password encrypted bundle data of the selected configs
+pub fn export_connection_configs(
+    sftp_connection_ids: &[Uuid],
+    webdav_connection_ids: &[Uuid],
+    password: &str,
+) -> Result<Vec<u8>> {
+    if password.is_empty() {
+        return Err(error::Error::DataError(
+            "A password is required to export the connections",
+        ));
+    }
+
+    let (mut sftp_connections, webdav_connections) =
+        ConnectionConfigs::configs_by_ids(sftp_connection_ids, webdav_connection_ids);
+
+    if sftp_connections.is_empty() && webdav_connections.is_empty() {
+        return Err(error::Error::DataError("No connection is selected to export"));
+    }
+
+    let mut sftp_private_keys = vec![];
+    for config in sftp_connections.iter_mut() {
+        // The full path is device specific and is formed again when the connection is used
+        config.private_key_full_file_name = None;
+
+        if let Some(ref file_name) = config.private_key_file_name {
+            let connection_id = config.connection_id.to_string();
+            let key_file_path = CallbackServiceProvider::common_callback_service()
+                .sftp_private_key_file_full_path(&connection_id, file_name);
+
+            let content = fs::read(&key_file_path).map_err(|e| {
+                error::Error::UnexpectedError(format!(
+                    "Reading the private key file {} of the connection {} failed: {}",
+                    file_name, &connection_id, e
+                ))
+            })?;
+
+            sftp_private_keys.push(BundledPrivateKey {
+                connection_id: config.connection_id,
+                file_name: file_name.clone(),
+                content: BASE64.encode(&content),
+            });
+        }
+    }
+
+    debug!(
+        "Exporting {} sftp and {} webdav connections",
+        sftp_connections.len(),
+        webdav_connections.len()
+    );
+
+    let content = BundleContent {
+        sftp_connections,
+        webdav_connections,
+        sftp_private_keys,
+    };
+
+    content.encrypt(password, KdfParams::default())
+}
+
+// Decrypts the bundle and returns the configs found along with any collision with the existing configs
+pub fn read_connection_configs_bundle(
+    bundle_data: &[u8],
+    password: &str,
+) -> Result<ConnectionConfigsBundleInfo> {
+    let content = BundleContent::decrypt(bundle_data, password)?;
+
+    let mut connections = vec![];
+    for config in &content.sftp_connections {
+        let existing_name = existing_config_name(&config.connection_id, RemoteStorageType::Sftp);
+        connections.push(BundledConnection {
+            storage_type: RemoteStorageType::Sftp,
+            connection_id: config.connection_id,
+            name: sftp_config_name(config),
+            existing_name,
+        });
+    }
+
+    for config in &content.webdav_connections {
+        let existing_name =
+            existing_config_name(&config.connection_id, RemoteStorageType::Webdav);
+        connections.push(BundledConnection {
+            storage_type: RemoteStorageType::Webdav,
+            connection_id: config.connection_id,
+            name: config.name.clone(),
+            existing_name,
+        });
+    }
+
+    Ok(ConnectionConfigsBundleInfo { connections })
+}
+
+// Merges the configs from the bundle with the existing configs by connection_id
+// A config that collides with an existing one is skipped unless the user has chosen to replace or rename it
+pub fn import_connection_configs(
+    bundle_data: &[u8],
+    password: &str,
+    resolutions: &HashMap<Uuid, CollisionResolution>,
+) -> Result<ImportedConnectionConfigs> {
+    let content = BundleContent::decrypt(bundle_data, password)?;
+    content.verify_private_key_file_names()?;
+
+    let mut imported = ImportedConnectionConfigs::default();
+
+    let mut sftp_configs = vec![];
+    for mut config in content.sftp_connections.iter().cloned() {
+        let bundled_connection_id = config.connection_id;
+        let exists = ConnectionConfigs::find_remote_storage_config(
+            &bundled_connection_id,
+            RemoteStorageType::Sftp,
+        )
+        .is_some();
+
+        if exists {
+            match resolutions.get(&bundled_connection_id) {
+                Some(CollisionResolution::Replace) => {
+                    // Removes the previous private key file of the existing config
+                    CallbackServiceProvider::common_callback_service().remote_storage_config_deleted(
+                        RemoteStorageType::Sftp,
+                        &bundled_connection_id.to_string(),
+                    )?;
+                    imported.replaced += 1;
+                }
+                Some(CollisionResolution::Rename { name }) => {
+                    config.connection_id = Uuid::new_v4();
+                    config.name = Some(name.clone());
+                    imported.renamed += 1;
+                }
+                Some(CollisionResolution::Skip) | None => {
+                    imported.skipped += 1;
+                    continue;
+                }
+            }
+        } else {
+            imported.added += 1;
+        }
+
+        config.private_key_full_file_name = None;
+        if let Some(ref file_name) = config.private_key_file_name {
+            if let Some(key) = content.private_key(&bundled_connection_id) {
+                let full_path = write_private_key(&config.connection_id, file_name, key)?;
+                config.private_key_full_file_name = Some(full_path);
+            }
+        }
+
+        sftp_configs.push(config);
+    }
+
+    let mut webdav_configs = vec![];
+    for mut config in content.webdav_connections.into_iter() {
+        let exists = ConnectionConfigs::find_remote_storage_config(
+            &config.connection_id,
+            RemoteStorageType::Webdav,
+        )
+        .is_some();
+
+        if exists {
+            match resolutions.get(&config.connection_id) {
+                Some(CollisionResolution::Replace) => {
+                    imported.replaced += 1;
+                }
+                Some(CollisionResolution::Rename { name }) => {
+                    config.connection_id = Uuid::new_v4();
+                    config.name = name.clone();
+                    imported.renamed += 1;
+                }
+                Some(CollisionResolution::Skip) | None => {
+                    imported.skipped += 1;
+                    continue;
+                }
+            }
+        } else {
+            imported.added += 1;
+        }
+
+        webdav_configs.push(config);
+    }
+
+    ConnectionConfigs::add_or_update_configs(sftp_configs, webdav_configs)?;
+
+    debug!("Imported connection configs {:?}", &imported);
+
+    Ok(imported)
+}
+
+// Exports all sftp and webdav configs (see 'app_archive' module). Returns None when there is no config
+pub fn export_all_connection_configs(password: &str) -> Result<Option<Vec<u8>>> {
+    use super::server_connection_config::RemoteStorageTypeConfigs;
+
+    let sftp_connection_ids = match ConnectionConfigs::remote_storage_configs(RemoteStorageType::Sftp) {
+        RemoteStorageTypeConfigs::Sftp(v) => v.iter().map(|c| c.connection_id).collect(),
+        _ => vec![],
+    };
+    let webdav_connection_ids =
+        match ConnectionConfigs::remote_storage_configs(RemoteStorageType::Webdav) {
+            RemoteStorageTypeConfigs::Webdav(v) => v.iter().map(|c| c.connection_id).collect(),
+            _ => vec![],
+        };
+
+    if sftp_connection_ids.is_empty() && webdav_connection_ids.is_empty() {
+        return Ok(None);
+    }
+
+    export_connection_configs(&sftp_connection_ids, &webdav_connection_ids, password).map(Some)
+}
+
+// Imports all configs of the bundle. Any existing config with the same connection_id is replaced so that
+// the remote db_keys using these connection ids continue to work
+pub fn import_all_connection_configs(
+    bundle_data: &[u8],
+    password: &str,
+) -> Result<ImportedConnectionConfigs> {
+    let resolutions = read_connection_configs_bundle(bundle_data, password)?
+        .connections
+        .iter()
+        .map(|c| (c.connection_id, CollisionResolution::Replace))
+        .collect::<HashMap<_, _>>();
+
+    import_connection_configs(bundle_data, password, &resolutions)
+}
+
+fn sftp_config_name(config: &SftpConnectionConfig) -> String {
+    config
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{}@{}", &config.user_name, &config.host))
+}
+
+fn existing_config_name(connection_id: &Uuid, storage_type: RemoteStorageType) -> Option<String> {
+    use super::server_connection_config::RemoteStorageTypeConfig;
+
+    match ConnectionConfigs::find_remote_storage_config(connection_id, storage_type) {
+        Some(RemoteStorageTypeConfig::Sftp(c)) => Some(sftp_config_name(&c)),
+        Some(RemoteStorageTypeConfig::Webdav(c)) => Some(c.name),
+        _ => None,
+    }
+}
+
+fn is_plain_file_name(name: &str) -> bool {
+    Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name)
+}
+
+// Writes the private key file content to the connection specific private key dir
+fn write_private_key(
+    connection_id: &Uuid,
+    file_name: &str,
+    key: &BundledPrivateKey,
+) -> Result<String> {
+    if !is_plain_file_name(file_name) {
+        return Err(error::Error::DataError(
+            "Invalid private key file name in the bundle",
+        ));
+    }
+
+    let content = BASE64
+        .decode(key.content.as_bytes())
+        .map_err(|_| error::Error::DataError("Invalid private key data in the bundle"))?;
+
+    let full_path = CallbackServiceProvider::common_callback_service()
+        .sftp_private_key_file_full_path(&connection_id.to_string(), file_name);
+
+    if let Some(parent) = full_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&full_path, content)?;
+
+    Ok(full_path.as_path().to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_bundle_encrypt_decrypt() {
+        let content = BundleContent {
+            webdav_connections: vec![WebdavConnectionConfig {
+                connection_id: Uuid::new_v4(),
+                name: "Test Server".into(),
+                root_url: "https://server.com/dav".into(),
+                user_name: "user1".into(),
+                password: "secret".into(),
+                allow_untrusted_cert: false,
+                start_dir: None,
+                proxy: None,
+                server_backup: None,
+            }],
+            sftp_private_keys: vec![BundledPrivateKey {
+                connection_id: Uuid::new_v4(),
+                file_name: "id_ed25519".into(),
+                content: BASE64.encode(b"private key content"),
+            }],
+            ..Default::default()
+        };
+
+        // Small kdf params are used to keep the test fast
+        let data = content.encrypt("bundle pwd", KdfParams::new(64, 1, 1)).unwrap();
+
+        let decrypted = BundleContent::decrypt(&data, "bundle pwd").unwrap();
+        assert_eq!(1, decrypted.webdav_connections.len());
+        assert_eq!("secret", decrypted.webdav_connections[0].password);
+        assert_eq!(
+            "id_ed25519",
+            decrypted.sftp_private_keys[0].file_name.as_str()
+        );
+
+        assert!(BundleContent::decrypt(&data, "wrong pwd").is_err());
+        assert!(BundleContent::decrypt(b"{}", "bundle pwd").is_err());
+    }
+
+    #[test]
+    fn verify_private_key_file_names() {
+        let mut content = BundleContent {
+            sftp_private_keys: vec![BundledPrivateKey {
+                connection_id: Uuid::new_v4(),
+                file_name: "id_ed25519".into(),
+                content: BASE64.encode(b"private key content"),
+            }],
+            ..Default::default()
+        };
+        assert!(content.verify_private_key_file_names().is_ok());
+
+        for name in ["../preference.json", "/tmp/id_rsa", "keys/id_rsa", "..", ""] {
+            content.sftp_private_keys[0].file_name = name.into();
+            assert!(content.verify_private_key_file_names().is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use log::debug;
+use serde::Serialize;
+use tokio::{
+    io::AsyncReadExt,
+    net::{lookup_host, TcpStream},
+    time::{timeout, Instant},
+};
+
+use onekeepass_core::db_service::error::{self, Result};
+
+// Each network stage is given this much time before it is reported as failed
+const STAGE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Used as the file name prefix for the write permission probe. The probe file is removed immediately
+pub(crate) const WRITE_PROBE_FILE_PREFIX: &str = ".okp_write_probe_";
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub enum DiagnosticStageType {
+    DnsResolution,
+    TcpConnect,
+    TlsHandshake,
+    SshBanner,
+    SshHostKey,
+    Authentication,
+    // Used for the local git repository
+    OpenRepository,
+    ListStartDir,
+    WritePermission,
+}
+
+#[derive(Serialize, Debug)]
+pub enum DiagnosticStageStatus {
+    Passed,
+    Failed,
+    // A stage is skipped when any of its previous stages failed
+    Skipped,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CertificateDetails {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    // In seconds since epoch
+    pub not_before: i64,
+    pub not_after: i64,
+    // Set to false when the certificate is not trusted by the device
+    pub trusted: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiagnosticStage {
+    pub stage: DiagnosticStageType,
+    pub status: DiagnosticStageStatus,
+    pub elapsed_millis: u64,
+    // Some info on success (e.g resolved addresses) or the error message on failure
+    pub details: Option<String>,
+    pub certificate: Option<CertificateDetails>,
+}
+
+// The result of 'rs_diagnose' call
+// The stages are in the order they are run
+#[derive(Serialize, Debug, Default)]
+pub struct DiagnosticReport {
+    pub stages: Vec<DiagnosticStage>,
+}
+
+impl DiagnosticReport {
+    pub(crate) fn has_failed(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|s| matches!(s.status, DiagnosticStageStatus::Failed))
+    }
+
+    // Records the stage as skipped. Used when a stage can not be run as its previous stage failed
+    pub(crate) fn skip<T>(&mut self, stage: DiagnosticStageType) -> Option<T> {
+        self.stages.push(DiagnosticStage {
+            stage,
+            status: DiagnosticStageStatus::Skipped,
+            elapsed_millis: 0,
+            details: None,
+            certificate: None,
+        });
+        None
+    }
+
+    // Runs the stage future only if no previous stage failed and records its result and timing
+    // The returned value is the Ok value of the stage future
+    pub(crate) async fn run<T, F>(&mut self, stage: DiagnosticStageType, stage_fn: F) -> Option<T>
+    where
+        F: Future<Output = Result<(T, Option<String>)>>,
+    {
+        if self.has_failed() {
+            return self.skip(stage);
+        }
+
+        let start = Instant::now();
+        let r = match timeout(STAGE_TIMEOUT, stage_fn).await {
+            Ok(r) => r,
+            Err(_) => Err(error::Error::RemoteStorageCallError(format!(
+                "Timed out after {} seconds",
+                STAGE_TIMEOUT.as_secs()
+            ))),
+        };
+        let elapsed_millis = start.elapsed().as_millis() as u64;
+
+        debug!("Diagnostic stage {:?} completed in {} ms", &stage, elapsed_millis);
+
+        match r {
+            Ok((v, details)) => {
+                self.stages.push(DiagnosticStage {
+                    stage,
+                    status: DiagnosticStageStatus::Passed,
+                    elapsed_millis,
+                    details,
+                    certificate: None,
+                });
+                Some(v)
+            }
+            Err(e) => {
+                self.stages.push(DiagnosticStage {
+                    stage,
+                    status: DiagnosticStageStatus::Failed,
+                    elapsed_millis,
+                    details: Some(format!("{}", e)),
+                    certificate: None,
+                });
+                None
+            }
+        }
+    }
+
+    // Adds the certificate details to the last run stage
+    pub(crate) fn set_certificate(&mut self, certificate: Option<CertificateDetails>) {
+        if let Some(s) = self.stages.last_mut() {
+            s.certificate = certificate;
+        }
+    }
+}
+
+pub(crate) async fn resolve_host(
+    host: &str,
+    port: u16,
+) -> Result<(Vec<SocketAddr>, Option<String>)> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+    if addrs.is_empty() {
+        return Err(error::Error::RemoteStorageCallError(format!(
+            "No address is found for the host {}",
+            host
+        )));
+    }
+    let details = addrs
+        .iter()
+        .map(|a| a.ip().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok((addrs, Some(details)))
+}
+
+// Tries each resolved address till a connection is made
+pub(crate) async fn tcp_connect(addrs: &[SocketAddr]) -> Result<(TcpStream, Option<String>)> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok((stream, Some(format!("Connected to {}", addr)))),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.map_or_else(
+        || error::Error::RemoteStorageCallError("No address to connect".into()),
+        |e| e.into(),
+    ))
+}
+
+// Reads the identification string (e.g SSH-2.0-OpenSSH_9.6) that the ssh server sends first
+pub(crate) async fn read_ssh_banner(mut stream: TcpStream) -> Result<((), Option<String>)> {
+    let mut banner = vec![];
+    let mut buf = [0u8; 1];
+    // As per RFC 4253, the identification string is at most 255 chars and the server may send
+    // other lines before that line
+    while banner.len() < 1024 {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        banner.push(buf[0]);
+        if buf[0] == b'\n' {
+            if banner.starts_with(b"SSH-") {
+                break;
+            }
+            banner.clear();
+        }
+    }
+
+    let banner = String::from_utf8_lossy(&banner).trim().to_string();
+    if banner.starts_with("SSH-") {
+        Ok(((), Some(banner)))
+    } else {
+        Err(error::Error::RemoteStorageCallError(
+            "The server did not send a valid SSH identification. Please check the host and port".into(),
+        ))
+    }
+}
+
+pub(crate) fn parse_certificate(der: &[u8], trusted: bool) -> Option<CertificateDetails> {
+    use x509_parser::prelude::{FromDer, X509Certificate};
+
+    let (_, cert) = X509Certificate::from_der(der)
+        .map_err(|e| debug!("Parsing the server certificate failed {}", e))
+        .ok()?;
+
+    Some(CertificateDetails {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.raw_serial_as_string(),
+        not_before: cert.validity().not_before.timestamp(),
+        not_after: cert.validity().not_after.timestamp(),
+        trusted,
+    })
+}
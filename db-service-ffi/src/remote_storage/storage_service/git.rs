@@ -0,0 +1,1124 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use git2::{
+    build::TreeUpdateBuilder, Commit, ErrorCode, FileMode, Oid, Repository, Signature, Sort, Status,
+};
+use log::debug;
+use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::{
+    parse_operation_fields_if, receive_from_async_fn,
+    remote_storage::callback_service::CallbackServiceProvider,
+};
+
+use onekeepass_core::async_service::async_runtime;
+use onekeepass_core::db_service::error::{self, Error, Result};
+use onekeepass_core::service_util::system_time_to_seconds;
+
+pub use super::server_connection_config::GitRepositoryConfig;
+use super::{
+    calls::RemoteStorageOperation,
+    diagnose::{DiagnosticReport, DiagnosticStageType, WRITE_PROBE_FILE_PREFIX},
+    filter_entry,
+    kdbx_search::{join_path, FindLimits, FoundKdbxFiles, KdbxFilesCollector},
+    server_connection_config::{
+        ConnectionConfigs, RemoteStorageTypeConfig, RemoteStorageTypeConfigs,
+    },
+    transfer::TransferContext,
+    ConnectStatus, RemoteFileMetadata, RemoteReadData, RemoteStorageType, ServerDirEntry,
+};
+
+// The git storage works on a repository in the device's local file system. The db file path part of the db_key
+// is relative to the repository's working dir
+// e.g Git-264226dc-be96-462a-a386-79adb6291ad7-/databases/Test1.kdbx
+
+const GIT_ROOT_DIR: &str = "/";
+
+const GIT_DIR_NAME: &str = ".git";
+
+// Used when neither the connection config nor the repository's git config has the author info
+const DEFAULT_AUTHOR_NAME: &str = "OneKeePass";
+const DEFAULT_AUTHOR_EMAIL: &str = "onekeepass@localhost";
+
+// Only these many recent versions of a db file are listed in its history
+const MAX_HISTORY_VERSIONS: usize = 100;
+
+// The commit at which a db file was last read or saved by this app is kept as a ref under this prefix in the
+// repository so that it is available after the app restart. The ref name has the hash of the file path.
+// This is used to find whether the db file is changed by any other commit (e.g a pull from another device) since then
+const BASE_COMMIT_REF_PREFIX: &str = "refs/onekeepass/base";
+
+#[inline]
+fn is_git_dir_name(name: &str) -> bool {
+    name.eq_ignore_ascii_case(GIT_DIR_NAME)
+}
+
+#[inline]
+fn git_error(e: git2::Error) -> Error {
+    Error::RemoteStorageCallError(format!("Git repository error: {}", e.message()))
+}
+
+// A version of a db file found in the repository history
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GitFileVersion {
+    pub commit_id: String,
+    // The first line of the commit message
+    pub summary: String,
+    pub author: String,
+    // In seconds
+    pub committed: i64,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Git {
+    connection_info: Option<GitRepositoryConfig>,
+    connection_id: Option<String>,
+    parent_dir: Option<String>,
+    sub_dir: Option<String>,
+    file_path: Option<String>,
+    pub(crate) file_name: Option<String>,
+    // The new path used in rename and copy
+    target_path: Option<String>,
+    // The following are set by the db save call and not by the UI
+    // The message used for the commit made in the next write
+    #[serde(skip)]
+    commit_message: Option<String>,
+    // When true, the db file is committed even if it is changed by other commits since it was read
+    #[serde(skip)]
+    overwrite: bool,
+}
+
+impl Git {
+    pub(crate) fn from_parsed_db_key(connection_id: &str, file_path_part: &str) -> Git {
+        let mut git = Git::default();
+        git.file_path = Some(file_path_part.to_string());
+        if let Some(parts) = file_path_part.rsplit_once("/") {
+            let v = if parts.0.is_empty() { "/" } else { parts.0 };
+            git.parent_dir = Some(v.to_string());
+            git.connection_id = Some(connection_id.to_string());
+            git.file_name = Some(parts.1.to_string());
+        }
+        git
+    }
+
+    pub(crate) fn set_save_options(&mut self, commit_message: String, overwrite: bool) {
+        self.commit_message = Some(commit_message);
+        self.overwrite = overwrite;
+    }
+
+    async fn send_diagnose(tx: oneshot::Sender<Result<DiagnosticReport>>, config: GitRepositoryConfig) {
+        let report = diagnose(config).await;
+        let r = tx.send(Ok(report));
+        if let Err(_) = r {
+            log::error!("In send_diagnose send channel failed ");
+        }
+    }
+
+    // Lists the versions of the db file that can be restored. The latest version is the first one
+    pub fn file_history(&self) -> Result<Vec<GitFileVersion>> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+        GitRepository::open_by_id(connection_id)?.file_history(file_path)
+    }
+
+    // The content of the db file as found in the commit 'commit_id' is committed as its latest version.
+    // The base commit of the file is not changed so that any save of the currently opened (now stale) db
+    // is detected as a conflict. The UI is expected to reload the db after this
+    pub fn restore_version(&self, commit_id: &str) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, file_name) =
+            parse_operation_fields_if!(self, connection_id, file_path, file_name);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        let data = repo.read_version(file_path, commit_id)?;
+        let message = format!(
+            "Restore {} to the version {}",
+            file_name,
+            &commit_id[..commit_id.len().min(7)]
+        );
+        repo.commit_file(file_path, &data, &message)?;
+        repo.file_metadata(file_path)
+    }
+}
+
+impl RemoteStorageOperation for Git {
+    fn connect_and_retrieve_root_dir(&self) -> Result<ConnectStatus> {
+        #[allow(unused_parens)]
+        let (connection_info) = parse_operation_fields_if!(self, connection_info);
+
+        let mut connection_info = connection_info.clone();
+        connection_info.connection_id =
+            ConnectionConfigs::generate_config_id_on_check(connection_info.connection_id);
+
+        let repo = GitRepository::open_or_init(&connection_info)?;
+        let dirs = repo.list_dir(GIT_ROOT_DIR)?;
+
+        let conn_status = ConnectStatus {
+            connection_id: connection_info.connection_id,
+            dir_entries: Some(dirs),
+        };
+
+        // The repository is available at this point. So we add the new or update the existing config
+        ConnectionConfigs::add_or_update_config(RemoteStorageTypeConfig::Git(connection_info))?;
+
+        Ok(conn_status)
+    }
+
+    fn connect_by_id_and_retrieve_root_dir(&self) -> Result<ConnectStatus> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        let dirs = repo.list_dir(GIT_ROOT_DIR)?;
+
+        Ok(ConnectStatus {
+            connection_id: uuid::Uuid::parse_str(connection_id)?,
+            dir_entries: Some(dirs),
+        })
+    }
+
+    // There is no connection to keep for a local repository. We just ensure that the repository is available
+    fn connect_by_id(&self) -> Result<RemoteStorageTypeConfig> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let config = repository_config(connection_id)?;
+        let _repo = GitRepository::open(&config)?;
+
+        Ok(RemoteStorageTypeConfig::Git(config))
+    }
+
+    fn list_dir(&self) -> Result<ServerDirEntry> {
+        let (connection_id, parent_dir) = parse_operation_fields_if!(self, connection_id, parent_dir);
+        GitRepository::open_by_id(connection_id)?.list_dir(parent_dir)
+    }
+
+    fn list_sub_dir(&self) -> Result<ServerDirEntry> {
+        let (connection_id, parent_dir, sub_dir) =
+            parse_operation_fields_if!(self, connection_id, parent_dir, sub_dir);
+
+        let full_dir = [parent_dir.as_str(), sub_dir.as_str()].join("/");
+        GitRepository::open_by_id(connection_id)?.list_dir(&full_dir)
+    }
+
+    // The content is read from the local file and there is no transfer progress to report
+    fn read(&self, _transfer: TransferContext) -> Result<RemoteReadData> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        let data = fs::read(repo.file_full_path(file_path)?)?;
+        let meta = repo.file_metadata(file_path)?;
+
+        repo.set_base_commit(file_path, repo.head_commit()?.map(|c| c.id()));
+
+        Ok(RemoteReadData { data, meta })
+    }
+
+    fn write_file(&self, data: Arc<Vec<u8>>, _transfer: TransferContext) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, file_name) =
+            parse_operation_fields_if!(self, connection_id, file_path, file_name);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+
+        if !self.overwrite {
+            repo.check_not_diverged(file_path, repo.base_commit(file_path))?;
+        }
+
+        let message = self
+            .commit_message
+            .clone()
+            .unwrap_or_else(|| format!("Update {}", file_name));
+
+        let commit_id = repo.commit_file(file_path, &data, &message)?;
+        repo.set_base_commit(file_path, Some(commit_id));
+
+        repo.file_metadata(file_path)
+    }
+
+    fn create_file(&self, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, file_name) =
+            parse_operation_fields_if!(self, connection_id, file_path, file_name);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        if repo.file_full_path(file_path)?.exists() {
+            return Err(Error::DataError(
+                "A file with the same name already exists in the repository",
+            ));
+        }
+
+        let commit_id = repo.commit_file(file_path, &data, &format!("Add {}", file_name))?;
+        repo.set_base_commit(file_path, Some(commit_id));
+
+        repo.file_metadata(file_path)
+    }
+
+    fn file_metadata(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+        GitRepository::open_by_id(connection_id)?.file_metadata(file_path)
+    }
+
+    fn file_exists(&self) -> Result<bool> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+        Ok(GitRepository::open_by_id(connection_id)?
+            .file_full_path(file_path)?
+            .is_file())
+    }
+
+    // Each save is a commit and any previous version can be restored from the history
+    fn backup_before_write(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    // Git does not track empty dirs and the new dir is committed only when a file is added to it
+    fn create_dir(&self) -> Result<()> {
+        let (connection_id, parent_dir, sub_dir) =
+            parse_operation_fields_if!(self, connection_id, parent_dir, sub_dir);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        let full_dir = [parent_dir.as_str(), sub_dir.as_str()].join("/");
+        fs::create_dir(repo.dir_full_path(&full_dir)?)?;
+        Ok(())
+    }
+
+    fn rename(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        let commit_id = repo.rename(file_path, target_path)?;
+
+        repo.set_base_commit(file_path, None);
+        repo.set_base_commit(target_path, Some(commit_id));
+
+        repo.file_metadata(target_path)
+    }
+
+    fn delete_file(&self) -> Result<()> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        repo.delete_file(file_path)?;
+
+        repo.set_base_commit(file_path, None);
+
+        Ok(())
+    }
+
+    fn copy(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let repo = GitRepository::open_by_id(connection_id)?;
+        repo.copy(file_path, target_path)?;
+
+        repo.file_metadata(target_path)
+    }
+
+    fn find_kdbx_files(&self, start_dir: Option<String>, limits: FindLimits) -> Result<FoundKdbxFiles> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let config = repository_config(connection_id)?;
+        let start_dir = start_dir
+            .or_else(|| config.start_dir.clone())
+            .unwrap_or_else(|| GIT_ROOT_DIR.to_string());
+
+        let repo = GitRepository::open(&config)?;
+        let mut collector = KdbxFilesCollector::new(limits);
+        repo.walk_kdbx_files(&start_dir, 0, &mut collector);
+
+        Ok(collector.into_found_files(config.connection_id, &start_dir))
+    }
+
+    fn remote_storage_configs(&self) -> Result<RemoteStorageTypeConfigs> {
+        Ok(ConnectionConfigs::remote_storage_configs(RemoteStorageType::Git))
+    }
+
+    fn update_config(&self) -> Result<()> {
+        #[allow(unused_parens)]
+        let (connection_info) = parse_operation_fields_if!(self, connection_info);
+        ConnectionConfigs::update_config(RemoteStorageTypeConfig::Git(connection_info.clone()))
+    }
+
+    // Only the config is removed and the repository is left as it is
+    fn delete_config(&self) -> Result<()> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let u_id = uuid::Uuid::parse_str(connection_id)?;
+        let r = ConnectionConfigs::delete_config_by_id(RemoteStorageType::Git, &u_id);
+        CallbackServiceProvider::common_callback_service()
+            .remote_storage_config_deleted(RemoteStorageType::Git, connection_id)?;
+
+        r
+    }
+
+    fn file_name(&self) -> Option<&str> {
+        self.file_name.as_ref().map(|x| x.as_str())
+    }
+
+    fn file_path(&self) -> Option<&str> {
+        self.file_path.as_ref().map(|x| x.as_str())
+    }
+
+    fn target_path(&self) -> Option<&str> {
+        self.target_path.as_ref().map(|x| x.as_str())
+    }
+
+    fn connection_id(&self) -> Option<&str> {
+        self.connection_id.as_ref().map(|x| x.as_str())
+    }
+
+    fn diagnose(&self) -> Result<DiagnosticReport> {
+        // The connection_info is used when the user is yet to save the config (e.g from the connection form)
+        // Otherwise the previously saved config is used
+        let config = if let Some(c) = self.connection_info.as_ref() {
+            c.clone()
+        } else {
+            #[allow(unused_parens)]
+            let (connection_id) = parse_operation_fields_if!(self, connection_id);
+            repository_config(connection_id)?
+        };
+
+        receive_from_async_fn!(Git::send_diagnose(config), DiagnosticReport)?
+    }
+}
+
+fn repository_config(connection_id: &str) -> Result<GitRepositoryConfig> {
+    let u_id = uuid::Uuid::parse_str(connection_id)?;
+    match ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Git) {
+        Some(RemoteStorageTypeConfig::Git(c)) => Ok(c),
+        _ => Err(Error::DataError(
+            "Previously saved Git repository config is not found in configs for this id",
+        )),
+    }
+}
+
+// Each stage is run on the local repository. Only the stage types that are relevant for a local repository are used
+async fn diagnose(config: GitRepositoryConfig) -> DiagnosticReport {
+    let mut report = DiagnosticReport::default();
+
+    let repo = report
+        .run(DiagnosticStageType::OpenRepository, async {
+            let repo = GitRepository::open(&config)?;
+            let details = repo
+                .repo
+                .head()
+                .ok()
+                .and_then(|h| h.shorthand().map(|s| format!("Current branch {}", s)));
+            Ok((repo, details))
+        })
+        .await;
+
+    let start_dir = config
+        .start_dir
+        .clone()
+        .unwrap_or_else(|| GIT_ROOT_DIR.to_string());
+
+    match repo {
+        Some(repo) => {
+            // The git2 repository can not be shared between threads and the stage futures need to be Send
+            let repo = Mutex::new(repo);
+
+            report
+                .run(DiagnosticStageType::ListStartDir, async {
+                    let entries = repo.lock().unwrap().list_dir(&start_dir)?;
+                    let details = format!(
+                        "{} dirs and {} files found in {}",
+                        entries.sub_dirs.len(),
+                        entries.files.len(),
+                        &start_dir
+                    );
+                    Ok(((), Some(details)))
+                })
+                .await;
+
+            report
+                .run(DiagnosticStageType::WritePermission, async {
+                    let probe_dir = repo.lock().unwrap().dir_full_path(&start_dir)?;
+                    let probe_file = probe_dir.join(format!(
+                        "{}{}",
+                        WRITE_PROBE_FILE_PREFIX,
+                        Uuid::new_v4()
+                    ));
+                    fs::write(&probe_file, Vec::<u8>::new())?;
+                    fs::remove_file(&probe_file)?;
+                    Ok(((), None))
+                })
+                .await;
+        }
+        None => {
+            report.skip::<()>(DiagnosticStageType::ListStartDir);
+            report.skip::<()>(DiagnosticStageType::WritePermission);
+        }
+    }
+
+    report
+}
+
+pub(crate) struct GitRepository {
+    repo: Repository,
+    connection_id: Uuid,
+    author_name: Option<String>,
+    author_email: Option<String>,
+}
+
+impl GitRepository {
+    fn open_by_id(connection_id: &str) -> Result<Self> {
+        Self::open(&repository_config(connection_id)?)
+    }
+
+    fn open(config: &GitRepositoryConfig) -> Result<Self> {
+        let repo = Repository::open(&config.repo_dir).map_err(git_error)?;
+        Self::from_repo(repo, config)
+    }
+
+    // A new repository is created if the dir is not yet a git repository
+    fn open_or_init(config: &GitRepositoryConfig) -> Result<Self> {
+        let repo = match Repository::open(&config.repo_dir) {
+            Ok(r) => r,
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                debug!("No git repository is found in {} and creating one", &config.repo_dir);
+                fs::create_dir_all(&config.repo_dir)?;
+                Repository::init(&config.repo_dir).map_err(git_error)?
+            }
+            Err(e) => return Err(git_error(e)),
+        };
+        Self::from_repo(repo, config)
+    }
+
+    fn from_repo(repo: Repository, config: &GitRepositoryConfig) -> Result<Self> {
+        if repo.is_bare() {
+            return Err(Error::DataError(
+                "A bare git repository can not be used as it has no working dir",
+            ));
+        }
+        Ok(Self {
+            repo,
+            connection_id: config.connection_id,
+            author_name: config.author_name.clone(),
+            author_email: config.author_email.clone(),
+        })
+    }
+
+    fn work_dir(&self) -> &Path {
+        // Bare repositories are rejected on open and the work dir is always available
+        self.repo.workdir().unwrap_or_else(|| self.repo.path())
+    }
+
+    // The path of a file relative to the work dir. This is the path used in the index and trees
+    // e.g "/databases/Test1.kdbx" -> "databases/Test1.kdbx"
+    fn relative_path(file_path: &str) -> Result<PathBuf> {
+        let rel_path = PathBuf::from(file_path.trim_start_matches('/'));
+
+        let valid = rel_path.components().next().is_some()
+            && rel_path.components().all(|c| match c {
+                // The file system may be case insensitive (e.g APFS) and '.GIT' is the same as '.git'
+                Component::Normal(n) => n.to_str().is_some_and(|n| !is_git_dir_name(n)),
+                _ => false,
+            });
+
+        if valid {
+            Ok(rel_path)
+        } else {
+            Err(Error::DataError("Invalid file path in the git repository"))
+        }
+    }
+
+    fn file_full_path(&self, file_path: &str) -> Result<PathBuf> {
+        Ok(self.work_dir().join(Self::relative_path(file_path)?))
+    }
+
+    fn dir_full_path(&self, dir: &str) -> Result<PathBuf> {
+        if dir.trim_matches('/').is_empty() {
+            Ok(self.work_dir().to_path_buf())
+        } else {
+            self.file_full_path(dir)
+        }
+    }
+
+    // Returns None when no commit is yet made in the current branch
+    fn head_commit(&self) -> Result<Option<Commit<'_>>> {
+        match self.repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_commit().map_err(git_error)?)),
+            Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+                Ok(None)
+            }
+            Err(e) => Err(git_error(e)),
+        }
+    }
+
+    fn blob_id_at(commit: &Commit, rel_path: &Path) -> Option<Oid> {
+        commit
+            .tree()
+            .ok()
+            .and_then(|t| t.get_path(rel_path).ok())
+            .map(|e| e.id())
+    }
+
+    // Walks the history from the head and calls 'f' with each commit that added or changed the file
+    // till 'f' returns false
+    fn walk_file_commits<F>(&self, rel_path: &Path, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Commit, Oid) -> bool,
+    {
+        if self.head_commit()?.is_none() {
+            return Ok(());
+        }
+
+        let mut walk = self.repo.revwalk().map_err(git_error)?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+            .map_err(git_error)?;
+        walk.push_head().map_err(git_error)?;
+
+        for oid in walk {
+            let commit = self.repo.find_commit(oid.map_err(git_error)?).map_err(git_error)?;
+            let Some(blob_id) = Self::blob_id_at(&commit, rel_path) else {
+                continue;
+            };
+            let parent_blob_id = commit
+                .parent(0)
+                .ok()
+                .and_then(|p| Self::blob_id_at(&p, rel_path));
+
+            if parent_blob_id != Some(blob_id) && !f(&commit, blob_id) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn list_dir(&self, parent_dir: &str) -> Result<ServerDirEntry> {
+        let mut sub_dirs: Vec<String> = vec![];
+        let mut files: Vec<String> = vec![];
+
+        for e in fs::read_dir(self.dir_full_path(parent_dir)?)? {
+            let e = e?;
+            let name = e.file_name().to_string_lossy().to_string();
+            if !filter_entry(&name) || is_git_dir_name(&name) {
+                continue;
+            }
+            if e.file_type()?.is_dir() {
+                sub_dirs.push(name);
+            } else {
+                files.push(name);
+            }
+        }
+
+        sub_dirs.sort();
+        files.sort();
+
+        Ok(ServerDirEntry {
+            parent_dir: parent_dir.into(),
+            sub_dirs,
+            files,
+        })
+    }
+
+    // The modified time is the time of the last commit that changed the file so that any new commit of the file
+    // (made here or pulled from elsewhere) is seen as a modification. The file system time is used for a file
+    // not yet committed
+    fn file_metadata(&self, file_path: &str) -> Result<RemoteFileMetadata> {
+        let rel_path = Self::relative_path(file_path)?;
+        let md = fs::metadata(self.work_dir().join(&rel_path))?;
+
+        let mut modified = None;
+        self.walk_file_commits(&rel_path, |c, _| {
+            modified = Some(c.time().seconds() as u64);
+            false
+        })?;
+
+        if modified.is_none() {
+            modified = md.modified().ok().map(system_time_to_seconds);
+        }
+
+        Ok(RemoteFileMetadata {
+            connection_id: self.connection_id,
+            storage_type: RemoteStorageType::Git,
+            full_file_name: file_path.to_string(),
+            size: Some(md.len()),
+            created: None,
+            modified,
+            accessed: None,
+        })
+    }
+
+    // Returns an error if the file is changed by any commit after the 'base' commit or
+    // if the file has any uncommitted changes
+    fn check_not_diverged(&self, file_path: &str, base: Option<Oid>) -> Result<()> {
+        let rel_path = Self::relative_path(file_path)?;
+
+        if let (Some(base), Some(head)) = (base, self.head_commit()?) {
+            if base != head.id() {
+                // The base commit may not be found when the history is rewritten (e.g reset)
+                let base_blob_id = self
+                    .repo
+                    .find_commit(base)
+                    .map_err(|_| Error::DbFileContentChangeDetected)
+                    .map(|c| Self::blob_id_at(&c, &rel_path))?;
+
+                if base_blob_id != Self::blob_id_at(&head, &rel_path) {
+                    debug!("Db file {} is changed by other commits since the base commit {}", file_path, base);
+                    return Err(Error::DbFileContentChangeDetected);
+                }
+            }
+        }
+
+        match self.repo.status_file(&rel_path) {
+            Ok(status)
+                if status.intersects(
+                    Status::WT_MODIFIED
+                        | Status::WT_DELETED
+                        | Status::INDEX_MODIFIED
+                        | Status::INDEX_DELETED
+                        | Status::CONFLICTED,
+                ) =>
+            {
+                debug!("Db file {} has uncommitted changes {:?}", file_path, status);
+                Err(Error::DbFileContentChangeDetected)
+            }
+            Ok(_) => Ok(()),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(()),
+            Err(e) => Err(git_error(e)),
+        }
+    }
+
+    fn base_commit_ref_name(file_path: &str) -> Result<String> {
+        let rel_path = Self::relative_path(file_path)?;
+        let hash = Sha256::digest(rel_path.to_string_lossy().as_bytes());
+        Ok(format!("{}/{}", BASE_COMMIT_REF_PREFIX, hex::encode(hash)))
+    }
+
+    fn base_commit(&self, file_path: &str) -> Option<Oid> {
+        let ref_name = Self::base_commit_ref_name(file_path).ok()?;
+        self.repo.refname_to_id(&ref_name).ok()
+    }
+
+    // Any error here is only logged as the read or the commit is already done
+    fn set_base_commit(&self, file_path: &str, commit_id: Option<Oid>) {
+        let r = Self::base_commit_ref_name(file_path).and_then(|ref_name| {
+            match commit_id {
+                Some(id) => self
+                    .repo
+                    .reference(&ref_name, id, true, "OneKeePass base commit")
+                    .map(|_| ()),
+                None => match self.repo.find_reference(&ref_name) {
+                    Ok(mut r) => r.delete(),
+                    Err(e) if e.code() == ErrorCode::NotFound => Ok(()),
+                    Err(e) => Err(e),
+                },
+            }
+            .map_err(git_error)
+        });
+        if let Err(e) = r {
+            log::error!("Setting the base commit of {} failed {}", file_path, e);
+        }
+    }
+
+    // The checks that need to pass before any file in the work dir is changed for a commit
+    fn check_can_commit(&self) -> Result<()> {
+        if self.repo.head_detached().unwrap_or(false) {
+            return Err(Error::DataError(
+                "The git repository is in a detached HEAD state. Please checkout a branch",
+            ));
+        }
+        Ok(())
+    }
+
+    fn signature(&self) -> Result<Signature<'static>> {
+        let repo_signature = self.repo.signature().ok();
+
+        let name = self
+            .author_name
+            .clone()
+            .filter(|n| !n.is_empty())
+            .or_else(|| repo_signature.as_ref().and_then(|s| s.name().map(|n| n.to_string())))
+            .unwrap_or_else(|| DEFAULT_AUTHOR_NAME.to_string());
+        let email = self
+            .author_email
+            .clone()
+            .filter(|e| !e.is_empty())
+            .or_else(|| repo_signature.as_ref().and_then(|s| s.email().map(|e| e.to_string())))
+            .unwrap_or_else(|| DEFAULT_AUTHOR_EMAIL.to_string());
+
+        Signature::now(&name, &email).map_err(git_error)
+    }
+
+    // Commits the added/changed and removed files in the current branch. The committed tree is the head's tree
+    // with only these paths changed so that any other change staged in the index is not committed.
+    // No commit is made if the tree is the same as the head's tree
+    fn commit_paths(&self, added: &[&Path], removed: &[&Path], message: &str) -> Result<Oid> {
+        self.check_can_commit()?;
+
+        let head = self.head_commit()?;
+        let base_tree = match head {
+            Some(ref h) => h.tree().map_err(git_error)?,
+            None => {
+                // No commit yet and the empty tree is used
+                let empty_tree_id = self
+                    .repo
+                    .treebuilder(None)
+                    .and_then(|b| b.write())
+                    .map_err(git_error)?;
+                self.repo.find_tree(empty_tree_id).map_err(git_error)?
+            }
+        };
+
+        let mut updates = TreeUpdateBuilder::new();
+        for p in removed {
+            // The file may not be committed yet
+            if base_tree.get_path(p).is_ok() {
+                updates.remove(*p);
+            }
+        }
+        for p in added {
+            let blob_id = self
+                .repo
+                .blob_path(&self.work_dir().join(p))
+                .map_err(git_error)?;
+            updates.upsert(*p, blob_id, FileMode::Blob);
+        }
+        let tree_id = updates
+            .create_updated(&self.repo, &base_tree)
+            .map_err(git_error)?;
+
+        // Only these paths are updated in the index so that it matches the new commit for them
+        let mut index = self.repo.index().map_err(git_error)?;
+        for p in removed {
+            index.remove_path(p).map_err(git_error)?;
+        }
+        for p in added {
+            index.add_path(p).map_err(git_error)?;
+        }
+        index.write().map_err(git_error)?;
+
+        if let Some(ref h) = head {
+            if h.tree_id() == tree_id {
+                debug!("No change to commit");
+                return Ok(h.id());
+            }
+        }
+
+        let tree = self.repo.find_tree(tree_id).map_err(git_error)?;
+        let signature = self.signature()?;
+        let parents = head.iter().collect::<Vec<_>>();
+
+        let commit_id = self
+            .repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .map_err(git_error)?;
+
+        debug!("Committed {} with message {}", commit_id, message);
+
+        Ok(commit_id)
+    }
+
+    // The file is restored to its previous content when the commit fails so that the work dir is not left
+    // with an uncommitted change that fails the next save
+    fn commit_file(&self, file_path: &str, data: &[u8], message: &str) -> Result<Oid> {
+        let rel_path = Self::relative_path(file_path)?;
+        self.check_can_commit()?;
+
+        let full_path = self.work_dir().join(&rel_path);
+        if let Some(p) = full_path.parent() {
+            fs::create_dir_all(p)?;
+        }
+        let previous = fs::read(&full_path).ok();
+        fs::write(&full_path, data)?;
+
+        self.commit_paths(&[&rel_path], &[], message).inspect_err(|_| {
+            let restored = match previous {
+                Some(ref d) => fs::write(&full_path, d),
+                None => fs::remove_file(&full_path),
+            };
+            if let Err(e) = restored {
+                log::error!("Restoring {:?} after the failed commit failed {}", &full_path, e);
+            }
+        })
+    }
+
+    fn rename(&self, file_path: &str, target_path: &str) -> Result<Oid> {
+        let (rel_path, rel_target_path) =
+            (Self::relative_path(file_path)?, Self::relative_path(target_path)?);
+        let full_target_path = self.work_dir().join(&rel_target_path);
+
+        if full_target_path.exists() {
+            return Err(Error::DataError(
+                "A file with the target name already exists in the repository",
+            ));
+        }
+        self.check_can_commit()?;
+
+        fs::rename(self.work_dir().join(&rel_path), &full_target_path)?;
+
+        let message = format!("Rename {} to {}", file_path, target_path);
+        self.commit_paths(&[&rel_target_path], &[&rel_path], &message)
+    }
+
+    fn delete_file(&self, file_path: &str) -> Result<Oid> {
+        let rel_path = Self::relative_path(file_path)?;
+        self.check_can_commit()?;
+        fs::remove_file(self.work_dir().join(&rel_path))?;
+
+        self.commit_paths(&[], &[&rel_path], &format!("Delete {}", file_path))
+    }
+
+    fn copy(&self, file_path: &str, target_path: &str) -> Result<Oid> {
+        let (rel_path, rel_target_path) =
+            (Self::relative_path(file_path)?, Self::relative_path(target_path)?);
+        let full_target_path = self.work_dir().join(&rel_target_path);
+
+        if full_target_path.exists() {
+            return Err(Error::DataError(
+                "A file with the target name already exists in the repository",
+            ));
+        }
+        self.check_can_commit()?;
+
+        fs::copy(self.work_dir().join(&rel_path), &full_target_path)?;
+
+        let message = format!("Copy {} to {}", file_path, target_path);
+        self.commit_paths(&[&rel_target_path], &[], &message)
+    }
+
+    fn file_history(&self, file_path: &str) -> Result<Vec<GitFileVersion>> {
+        let rel_path = Self::relative_path(file_path)?;
+
+        let mut versions = vec![];
+        self.walk_file_commits(&rel_path, |c, blob_id| {
+            let size = self
+                .repo
+                .find_blob(blob_id)
+                .map_or(0, |b| b.size() as u64);
+            versions.push(GitFileVersion {
+                commit_id: c.id().to_string(),
+                summary: c.summary().unwrap_or_default().to_string(),
+                author: c.author().name().unwrap_or_default().to_string(),
+                committed: c.time().seconds(),
+                size,
+            });
+            versions.len() < MAX_HISTORY_VERSIONS
+        })?;
+
+        Ok(versions)
+    }
+
+    // Gets the content of the file as found in the commit 'commit_id'
+    fn read_version(&self, file_path: &str, commit_id: &str) -> Result<Vec<u8>> {
+        let rel_path = Self::relative_path(file_path)?;
+
+        let oid = Oid::from_str(commit_id).map_err(git_error)?;
+        let commit = self.repo.find_commit(oid).map_err(git_error)?;
+        let blob_id = Self::blob_id_at(&commit, &rel_path).ok_or(Error::DataError(
+            "The db file is not found in the selected version",
+        ))?;
+        let blob = self.repo.find_blob(blob_id).map_err(git_error)?;
+
+        Ok(blob.content().to_vec())
+    }
+
+    fn walk_kdbx_files(&self, dir: &str, depth: usize, collector: &mut KdbxFilesCollector) {
+        if collector.limit_reached() {
+            return;
+        }
+
+        let Ok(read_dir) = self.dir_full_path(dir).and_then(|d| Ok(fs::read_dir(d)?)) else {
+            debug!("Listing of dir {} failed and it is skipped", dir);
+            return;
+        };
+
+        let mut sub_dirs = vec![];
+        for e in read_dir.flatten() {
+            let name = e.file_name().to_string_lossy().to_string();
+            if !filter_entry(&name) || is_git_dir_name(&name) {
+                continue;
+            }
+            if !collector.count_entry() {
+                return;
+            }
+            let Ok(md) = e.metadata() else {
+                continue;
+            };
+            if md.is_dir() {
+                sub_dirs.push(name);
+            } else {
+                collector.add_if_kdbx(
+                    join_path(dir, &name),
+                    &name,
+                    Some(md.len()),
+                    md.modified().ok().map(system_time_to_seconds),
+                );
+            }
+        }
+
+        if !sub_dirs.is_empty() && collector.can_descend(depth) {
+            for sub_dir in sub_dirs {
+                self.walk_kdbx_files(&join_path(dir, &sub_dir), depth + 1, collector);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_repository() -> GitRepository {
+        let repo_dir = std::env::temp_dir().join(format!("okp-git-test-{}", Uuid::new_v4()));
+        let config = GitRepositoryConfig {
+            connection_id: Uuid::new_v4(),
+            name: "Test".into(),
+            repo_dir: repo_dir.to_string_lossy().to_string(),
+            author_name: Some("Tester".into()),
+            author_email: Some("tester@localhost".into()),
+            start_dir: None,
+        };
+        GitRepository::open_or_init(&config).unwrap()
+    }
+
+    #[test]
+    fn verify_file_history_and_version_read() {
+        let repo = test_repository();
+        let file_path = "/databases/Test1.kdbx";
+
+        repo.commit_file(file_path, b"version1", "Add Test1.kdbx").unwrap();
+        repo.commit_file("/Other.txt", b"other", "Add Other.txt").unwrap();
+        repo.commit_file(file_path, b"version2", "Update Test1.kdbx").unwrap();
+        // Same content and no commit is made
+        repo.commit_file(file_path, b"version2", "Update Test1.kdbx").unwrap();
+
+        let history = repo.file_history(file_path).unwrap();
+        assert_eq!(2, history.len());
+        assert_eq!("Update Test1.kdbx", history[0].summary);
+        assert_eq!("Tester", history[0].author);
+        assert_eq!(8, history[0].size);
+
+        let data = repo.read_version(file_path, &history[1].commit_id).unwrap();
+        assert_eq!(b"version1".to_vec(), data);
+
+        let md = repo.file_metadata(file_path).unwrap();
+        assert_eq!(Some(history[0].committed as u64), md.modified);
+
+        let entries = repo.list_dir("/").unwrap();
+        assert_eq!(vec!["databases".to_string()], entries.sub_dirs);
+        assert_eq!(vec!["Other.txt".to_string()], entries.files);
+
+        let _ = fs::remove_dir_all(repo.work_dir());
+    }
+
+    #[test]
+    fn verify_only_given_paths_committed() {
+        let repo = test_repository();
+        let file_path = "/Test1.kdbx";
+
+        repo.commit_file(file_path, b"version1", "Add Test1.kdbx").unwrap();
+
+        // A file staged by the user is not part of the commit made by the app
+        fs::write(repo.work_dir().join("Notes.txt"), b"notes").unwrap();
+        let mut index = repo.repo.index().unwrap();
+        index.add_path(Path::new("Notes.txt")).unwrap();
+        index.write().unwrap();
+
+        repo.commit_file(file_path, b"version2", "Update Test1.kdbx").unwrap();
+
+        let head = repo.head_commit().unwrap().unwrap();
+        let tree = head.tree().unwrap();
+        assert!(tree.get_path(Path::new("Test1.kdbx")).is_ok());
+        assert!(tree.get_path(Path::new("Notes.txt")).is_err());
+        // Still staged
+        assert!(repo
+            .repo
+            .status_file(Path::new("Notes.txt"))
+            .unwrap()
+            .contains(Status::INDEX_NEW));
+
+        repo.delete_file(file_path).unwrap();
+        let head = repo.head_commit().unwrap().unwrap();
+        assert!(head.tree().unwrap().get_path(Path::new("Test1.kdbx")).is_err());
+
+        let _ = fs::remove_dir_all(repo.work_dir());
+    }
+
+    #[test]
+    fn verify_divergence_detection() {
+        let repo = test_repository();
+        let file_path = "/Test1.kdbx";
+
+        let base = repo.commit_file(file_path, b"version1", "Add Test1.kdbx").unwrap();
+        assert!(repo.check_not_diverged(file_path, Some(base)).is_ok());
+
+        // Commits of other files are not a conflict
+        repo.commit_file("/Other.kdbx", b"other", "Add Other.kdbx").unwrap();
+        assert!(repo.check_not_diverged(file_path, Some(base)).is_ok());
+
+        // Uncommitted changes of the db file
+        fs::write(repo.file_full_path(file_path).unwrap(), b"changed").unwrap();
+        assert!(matches!(
+            repo.check_not_diverged(file_path, Some(base)),
+            Err(Error::DbFileContentChangeDetected)
+        ));
+
+        // The db file is changed by another commit (e.g pulled from another device)
+        repo.commit_file(file_path, b"version2", "Update Test1.kdbx").unwrap();
+        assert!(matches!(
+            repo.check_not_diverged(file_path, Some(base)),
+            Err(Error::DbFileContentChangeDetected)
+        ));
+
+        assert!(GitRepository::relative_path("/../Test1.kdbx").is_err());
+        assert!(GitRepository::relative_path("/.git/config").is_err());
+        assert!(GitRepository::relative_path("/.GIT/config").is_err());
+        assert!(GitRepository::relative_path("/db/.Git/config").is_err());
+
+        let _ = fs::remove_dir_all(repo.work_dir());
+    }
+
+    #[test]
+    fn verify_base_commit_persisted() {
+        let repo = test_repository();
+        let file_path = "/Test1.kdbx";
+
+        let base = repo.commit_file(file_path, b"version1", "Add Test1.kdbx").unwrap();
+        repo.set_base_commit(file_path, Some(base));
+
+        // The base commit is found in the repository opened again (e.g after the app restart)
+        let reopened = GitRepository {
+            repo: Repository::open(repo.work_dir()).unwrap(),
+            connection_id: repo.connection_id,
+            author_name: None,
+            author_email: None,
+        };
+        assert_eq!(Some(base), reopened.base_commit(file_path));
+        assert_eq!(None, reopened.base_commit("/Other.kdbx"));
+
+        reopened.set_base_commit(file_path, None);
+        assert_eq!(None, repo.base_commit(file_path));
+
+        let _ = fs::remove_dir_all(repo.work_dir());
+    }
+
+    #[test]
+    fn verify_failed_commit_keeps_work_dir() {
+        let repo = test_repository();
+        let file_path = "/Test1.kdbx";
+
+        let base = repo.commit_file(file_path, b"version1", "Add Test1.kdbx").unwrap();
+        repo.repo.set_head_detached(base).unwrap();
+
+        assert!(repo.commit_file(file_path, b"version2", "Update Test1.kdbx").is_err());
+        assert!(repo.commit_file("/New.kdbx", b"new", "Add New.kdbx").is_err());
+
+        // The work dir is not changed and the next save is not blocked by an uncommitted change
+        assert_eq!(b"version1".to_vec(), fs::read(repo.file_full_path(file_path).unwrap()).unwrap());
+        assert!(!repo.file_full_path("/New.kdbx").unwrap().exists());
+        assert!(repo.check_not_diverged(file_path, Some(base)).is_ok());
+
+        let _ = fs::remove_dir_all(repo.work_dir());
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::filter_entry;
+
+const DEFAULT_MAX_ENTRIES: usize = 5000;
+
+// Number of dirs listed at the same time while walking the remote dirs
+pub(crate) const CONCURRENT_DIR_LISTINGS: usize = 4;
+
+// Limits used in the recursive search of kdbx files so that a search on a large server tree completes in a reasonable time
+#[derive(Debug, Clone, Copy)]
+pub struct FindLimits {
+    // The number of dir levels below the start dir that are searched.
+    // The files directly under the start dir are at depth 0
+    pub max_depth: usize,
+    // The search stops after these many files and dirs are seen
+    pub max_entries: usize,
+}
+
+impl FindLimits {
+    pub fn new(max_depth: usize, max_entries: Option<usize>) -> Self {
+        Self {
+            max_depth,
+            max_entries: max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KdbxFileEntry {
+    // The full path of the file in the remote storage and used to form the db_key
+    pub file_path: String,
+    pub file_name: String,
+    pub size: Option<u64>,
+    // In seconds
+    pub modified: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FoundKdbxFiles {
+    pub connection_id: Uuid,
+    pub start_dir: String,
+    pub files: Vec<KdbxFileEntry>,
+    // Set to true when the search did not cover all dirs because of the depth or entries limit
+    pub truncated: bool,
+}
+
+// Collects the kdbx files while walking the remote dirs and keeps track of the limits
+pub(crate) struct KdbxFilesCollector {
+    limits: FindLimits,
+    entries_seen: usize,
+    files: Vec<KdbxFileEntry>,
+    truncated: bool,
+}
+
+impl KdbxFilesCollector {
+    pub(crate) fn new(limits: FindLimits) -> Self {
+        Self {
+            limits,
+            entries_seen: 0,
+            files: vec![],
+            truncated: false,
+        }
+    }
+
+    // Called for each file or dir entry found. Returns false when the entries limit is reached
+    // and the caller should stop the walk
+    pub(crate) fn count_entry(&mut self) -> bool {
+        self.entries_seen += 1;
+        if self.entries_seen > self.limits.max_entries {
+            self.truncated = true;
+            false
+        } else {
+            true
+        }
+    }
+
+    pub(crate) fn limit_reached(&self) -> bool {
+        self.entries_seen > self.limits.max_entries
+    }
+
+    // Checks whether the sub dirs of a dir at this depth can be searched
+    pub(crate) fn can_descend(&mut self, depth: usize) -> bool {
+        if depth < self.limits.max_depth {
+            true
+        } else {
+            self.truncated = true;
+            false
+        }
+    }
+
+    pub(crate) fn add_if_kdbx(
+        &mut self,
+        file_path: String,
+        file_name: &str,
+        size: Option<u64>,
+        modified: Option<u64>,
+    ) {
+        if is_kdbx_file_name(file_name) {
+            self.files.push(KdbxFileEntry {
+                file_path,
+                file_name: file_name.to_string(),
+                size,
+                modified,
+            });
+        }
+    }
+
+    pub(crate) fn into_found_files(self, connection_id: Uuid, start_dir: &str) -> FoundKdbxFiles {
+        FoundKdbxFiles {
+            connection_id,
+            start_dir: start_dir.to_string(),
+            files: self.files,
+            truncated: self.truncated,
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn is_kdbx_file_name(file_name: &str) -> bool {
+    filter_entry(file_name) && file_name.to_lowercase().ends_with(".kdbx")
+}
+
+// Joins a dir and an entry name without adding an extra "/" when the dir is the root "/"
+#[inline]
+pub(crate) fn join_path(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches("/"), name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_kdbx_files_collector_limits() {
+        let mut collector = KdbxFilesCollector::new(FindLimits::new(1, Some(2)));
+
+        assert!(collector.can_descend(0));
+        assert!(!collector.can_descend(1));
+
+        assert!(collector.count_entry());
+        collector.add_if_kdbx(join_path("/", "Test1.KDBX"), "Test1.KDBX", Some(10), None);
+        assert!(collector.count_entry());
+        collector.add_if_kdbx(join_path("/dav", "._Test2.kdbx"), "._Test2.kdbx", None, None);
+        assert!(!collector.count_entry());
+        assert!(collector.limit_reached());
+
+        let found = collector.into_found_files(Uuid::default(), "/");
+        assert!(found.truncated);
+        assert_eq!(1, found.files.len());
+        assert_eq!("/Test1.KDBX", found.files[0].file_path);
+    }
+}
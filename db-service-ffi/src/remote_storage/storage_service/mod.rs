@@ -1,7 +1,14 @@
 mod calls;
+mod config_bundle;
+mod diagnose;
+pub mod git;
+mod kdbx_search;
 mod macros;
+mod proxy;
+mod server_backup;
 mod server_connection_config;
 pub mod sftp;
+mod transfer;
 pub mod webdav;
 
 pub use server_connection_config::{
@@ -11,6 +18,16 @@ pub use server_connection_config::{
 
 pub use calls::{RemoteStorageOperation,RemoteStorageOperationType};
 
+pub use config_bundle::{
+    export_all_connection_configs, export_connection_configs, import_all_connection_configs,
+    import_connection_configs, read_connection_configs_bundle,
+    CollisionResolution, ConnectionConfigsBundleInfo, ImportedConnectionConfigs,
+};
+
+pub use kdbx_search::{FindLimits, FoundKdbxFiles};
+
+pub use transfer::{cancel_transfer, TransferContext, TransferDirection, TransferProgress, TransferRequest};
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -40,6 +57,7 @@ pub struct ConnectStatus {
 pub enum RemoteStorageType {
     Sftp,
     Webdav,
+    Git,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -65,6 +83,9 @@ impl RemoteFileMetadata {
             RemoteStorageType::Webdav => {
                 format!("Webdav:{}", &self.full_file_name)
             }
+            RemoteStorageType::Git => {
+                format!("Git:{}", &self.full_file_name)
+            }
         }
     }
 }
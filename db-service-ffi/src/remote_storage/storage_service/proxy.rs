@@ -0,0 +1,160 @@
+use data_encoding::BASE64;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use onekeepass_core::db_service::error::{self, Result};
+
+// The proxy's response to the CONNECT request is not expected to be larger than this
+const MAX_CONNECT_RESPONSE_SIZE: usize = 8 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ProxyType {
+    // Uses HTTP CONNECT method to tunnel the connection
+    Http,
+    // The target host name is resolved by the proxy (e.g a local Tor socks port)
+    Socks5,
+}
+
+// Optional proxy used to reach a sftp or webdav server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProxyConfig {
+    pub proxy_type: ProxyType,
+    pub host: String,
+    pub port: u16,
+    pub user_name: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    fn credentials(&self) -> Option<(&str, &str)> {
+        self.user_name
+            .as_deref()
+            .filter(|u| !u.is_empty())
+            .map(|u| (u, self.password.as_deref().unwrap_or_default()))
+    }
+
+    // Used to set the proxy of the reqwest agent used for the webdav calls
+    pub(crate) fn reqwest_proxy(&self) -> Result<reqwest::Proxy> {
+        let scheme = match self.proxy_type {
+            ProxyType::Http => "http",
+            // 'socks5h' makes the proxy to resolve the webdav server host name
+            ProxyType::Socks5 => "socks5h",
+        };
+
+        let mut proxy_url =
+            url::Url::parse(&format!("{}://{}", scheme, host_port(&self.host, self.port)))
+                .map_err(|e| {
+                    error::Error::RemoteStorageCallError(format!("Invalid proxy host {}", e))
+                })?;
+
+        if let (ProxyType::Socks5, Some((user_name, password))) =
+            (self.proxy_type, self.credentials())
+        {
+            // The socks5 credentials are taken from the proxy url
+            let _ = proxy_url.set_username(user_name);
+            let _ = proxy_url.set_password(Some(password));
+        }
+
+        let mut proxy = reqwest::Proxy::all(proxy_url.as_str())?;
+
+        if let (ProxyType::Http, Some((user_name, password))) =
+            (self.proxy_type, self.credentials())
+        {
+            proxy = proxy.basic_auth(user_name, password);
+        }
+
+        Ok(proxy)
+    }
+
+    // Returns a tcp stream to the target host that is tunneled through this proxy
+    pub(crate) async fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream> {
+        debug!(
+            "Connecting to {}:{} through the {:?} proxy {}:{}",
+            host, port, &self.proxy_type, &self.host, self.port
+        );
+        match self.proxy_type {
+            ProxyType::Http => self.http_connect(host, port).await,
+            ProxyType::Socks5 => self.socks5_connect(host, port).await,
+        }
+    }
+
+    async fn http_connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        let target = host_port(host, port);
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", &target, &target);
+        if let Some((user_name, password)) = self.credentials() {
+            let auth = BASE64.encode(format!("{}:{}", user_name, password).as_bytes());
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", auth));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // The response is read byte by byte so that no data after the headers is consumed here.
+        // Any data after the headers belongs to the tunneled connection
+        let mut response = vec![];
+        let mut buf = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > MAX_CONNECT_RESPONSE_SIZE {
+                return Err(error::Error::RemoteStorageCallError(
+                    "Invalid response from the proxy".into(),
+                ));
+            }
+            if stream.read(&mut buf).await? == 0 {
+                return Err(error::Error::RemoteStorageCallError(
+                    "The proxy closed the connection".into(),
+                ));
+            }
+            response.push(buf[0]);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        // e.g "HTTP/1.1 200 Connection established"
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(s) if s.starts_with('2') => Ok(stream),
+            Some("407") => Err(error::Error::RemoteStorageCallError(
+                "The proxy requires authentication. Please check the proxy user name and password"
+                    .into(),
+            )),
+            _ => Err(error::Error::RemoteStorageCallError(format!(
+                "The proxy refused the connection to {}: {}",
+                &target, status_line
+            ))),
+        }
+    }
+
+    async fn socks5_connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let proxy_addr = (self.host.as_str(), self.port);
+        let stream = match self.credentials() {
+            Some((user_name, password)) => {
+                tokio_socks::tcp::Socks5Stream::connect_with_password(
+                    proxy_addr,
+                    (host, port),
+                    user_name,
+                    password,
+                )
+                .await
+            }
+            None => tokio_socks::tcp::Socks5Stream::connect(proxy_addr, (host, port)).await,
+        }
+        .map_err(|e| {
+            error::Error::RemoteStorageCallError(format!("SOCKS5 proxy connection failed: {}", e))
+        })?;
+
+        Ok(stream.into_inner())
+    }
+}
+
+// IPv6 address needs to be in brackets when used with a port
+fn host_port(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
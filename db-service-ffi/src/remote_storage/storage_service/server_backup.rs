@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use onekeepass_core::db_service::error::{self, Result};
+
+use super::kdbx_search::join_path;
+
+// Opt-in per connection setting. When set, the existing remote db file is copied to the backup dir before
+// it is overwritten by a save so that a corrupted save can be recovered on any device
+// e.g The backups of /dav/db1/Test1.kdbx are /dav/db1/okp_backups/Test1.kdbx.1718000000.bak ...
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerBackupSettings {
+    // The name of the backup dir that is created next to the db file
+    pub backup_dir: String,
+    // Only these many recent backups of each db file are kept on the server
+    pub keep_count: usize,
+}
+
+impl ServerBackupSettings {
+    // The full path of the backup dir for the db file found in this parent dir
+    pub(crate) fn backup_dir_path(&self, parent_dir: &str) -> Result<String> {
+        let name = self.backup_dir.trim_matches('/');
+        if name.is_empty() || name.split('/').any(|p| p.is_empty() || p == "." || p == "..") {
+            return Err(error::Error::DataError(
+                "Invalid server backup dir name in the connection config",
+            ));
+        }
+        Ok(join_path(parent_dir, name))
+    }
+}
+
+// Splits the full path of a file to its parent dir and file name
+// e.g "/dav/db1/Test1.kdbx" -> ("/dav/db1", "Test1.kdbx"), "/Test1.kdbx" -> ("/", "Test1.kdbx")
+pub(crate) fn split_file_path(file_path: &str) -> (&str, &str) {
+    match file_path.rsplit_once("/") {
+        Some(("", name)) => ("/", name),
+        Some((parent_dir, name)) => (parent_dir, name),
+        None => ("/", file_path),
+    }
+}
+
+#[inline]
+pub(crate) fn backup_file_name(file_name: &str, secs: i64) -> String {
+    format!("{}.{}.bak", file_name, secs)
+}
+
+// Gets the time part of the backup file name if it is a backup of the file 'file_name'
+fn backup_time(file_name: &str, backup_file_name: &str) -> Option<i64> {
+    backup_file_name
+        .strip_prefix(file_name)?
+        .strip_prefix(".")?
+        .strip_suffix(".bak")?
+        .parse::<i64>()
+        .ok()
+}
+
+// Returns the backup files of the 'file_name' that need to be deleted so that only the recent 'keep_count'
+// backups are kept. At least the latest backup is always kept
+pub(crate) fn backups_to_prune(file_name: &str, files: &[String], keep_count: usize) -> Vec<String> {
+    let mut backups = files
+        .iter()
+        .filter_map(|f| backup_time(file_name, f).map(|t| (t, f)))
+        .collect::<Vec<_>>();
+
+    // Latest first
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+
+    backups
+        .into_iter()
+        .skip(keep_count.max(1))
+        .map(|(_, f)| f.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_backups_to_prune() {
+        let files = vec![
+            backup_file_name("Test1.kdbx", 100),
+            backup_file_name("Test1.kdbx", 300),
+            backup_file_name("Test1.kdbx", 200),
+            // Backup of another db in the same dir
+            backup_file_name("Test1.kdbx.old", 50),
+            "Test1.kdbx".to_string(),
+        ];
+
+        let pruned = backups_to_prune("Test1.kdbx", &files, 2);
+        assert_eq!(vec!["Test1.kdbx.100.bak".to_string()], pruned);
+
+        // The latest is kept even when keep_count is 0
+        assert_eq!(2, backups_to_prune("Test1.kdbx", &files, 0).len());
+
+        assert_eq!(("/dav/db1", "Test1.kdbx"), split_file_path("/dav/db1/Test1.kdbx"));
+        assert_eq!(("/", "Test1.kdbx"), split_file_path("/Test1.kdbx"));
+
+        let settings = ServerBackupSettings {
+            backup_dir: "okp_backups".into(),
+            keep_count: 5,
+        };
+        assert_eq!("/dav/okp_backups", settings.backup_dir_path("/dav").unwrap());
+        assert_eq!("/okp_backups", settings.backup_dir_path("/").unwrap());
+
+        let settings = ServerBackupSettings {
+            backup_dir: "../backups".into(),
+            keep_count: 5,
+        };
+        assert!(settings.backup_dir_path("/dav").is_err());
+    }
+}
@@ -6,7 +6,7 @@ use uuid::Uuid;
 
 use crate::db_service::error::{self, Result};
 
-use super::RemoteStorageType;
+use super::{proxy::ProxyConfig, server_backup::ServerBackupSettings, RemoteStorageType};
 
 pub fn read_configs() -> Result<()> {
     ConnectionConfigs::read_config()
@@ -64,6 +64,7 @@ impl ConnectionConfigReaderWriterStore {
 pub enum RemoteStorageTypeConfig {
     Sftp(SftpConnectionConfig),
     Webdav(WebdavConnectionConfig),
+    Git(GitRepositoryConfig),
 }
 
 // Adjacently tagged enum
@@ -74,6 +75,7 @@ pub enum RemoteStorageTypeConfig {
 pub enum RemoteStorageTypeConfigs {
     Sftp(Vec<SftpConnectionConfig>),
     Webdav(Vec<WebdavConnectionConfig>),
+    Git(Vec<GitRepositoryConfig>),
 }
 
 trait ConnectionId {
@@ -98,6 +100,10 @@ pub struct SftpConnectionConfig {
     pub password: Option<String>,
     // All files and sub dirs from this will be shown as root
     pub start_dir: Option<String>,
+    // The ssh connection is tunneled through this proxy when set
+    pub proxy: Option<ProxyConfig>,
+    // The existing db file is copied to a backup dir on the server before each overwrite when set
+    pub server_backup: Option<ServerBackupSettings>,
 }
 
 impl ConnectionId for SftpConnectionConfig {
@@ -118,6 +124,10 @@ pub struct WebdavConnectionConfig {
     pub allow_untrusted_cert: bool,
     // All files and sub dirs from this will be shown as root
     pub start_dir: Option<String>,
+    // All webdav calls are made through this proxy when set
+    pub proxy: Option<ProxyConfig>,
+    // The existing db file is copied to a backup dir on the server before each overwrite when set
+    pub server_backup: Option<ServerBackupSettings>,
 }
 
 impl ConnectionId for WebdavConnectionConfig {
@@ -126,10 +136,34 @@ impl ConnectionId for WebdavConnectionConfig {
     }
 }
 
+// A git repository in the device's local file system. Each save of a db file in this repository is committed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitRepositoryConfig {
+    pub connection_id: Uuid,
+    // user selected name for this repository
+    pub name: String,
+    // The full path of the repository's working dir. A new repository is created if there is none in this dir
+    pub repo_dir: String,
+    // Used in the commits. The repository's git config values are used if these are not set
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    // All files and sub dirs from this will be shown as root
+    pub start_dir: Option<String>,
+}
+
+impl ConnectionId for GitRepositoryConfig {
+    fn connection_id(&self) -> &Uuid {
+        &self.connection_id
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConnectionConfigs {
     sftp_connections: Vec<SftpConnectionConfig>,
     webdav_connections: Vec<WebdavConnectionConfig>,
+    // Configs persisted before the git support will not have this field
+    #[serde(default)]
+    git_repositories: Vec<GitRepositoryConfig>,
 }
 
 impl Default for ConnectionConfigs {
@@ -137,6 +171,7 @@ impl Default for ConnectionConfigs {
         Self {
             sftp_connections: vec![],
             webdav_connections: vec![],
+            git_repositories: vec![],
         }
     }
 }
@@ -173,6 +208,9 @@ impl ConnectionConfigs {
             RemoteStorageType::Webdav => {
                 RemoteStorageTypeConfigs::Webdav(configs.webdav_connections.clone())
             }
+            RemoteStorageType::Git => {
+                RemoteStorageTypeConfigs::Git(configs.git_repositories.clone())
+            }
         }
     }
 
@@ -192,7 +230,52 @@ impl ConnectionConfigs {
                 .iter()
                 .find(|v| v.connection_id() == connection_id)
                 .map(|f| RemoteStorageTypeConfig::Webdav(f.clone())),
+            RemoteStorageType::Git => configs
+                .git_repositories
+                .iter()
+                .find(|v| v.connection_id() == connection_id)
+                .map(|f| RemoteStorageTypeConfig::Git(f.clone())),
+        }
+    }
+
+    // Returns the copies of the configs with these connection ids. Unknown ids are ignored
+    pub(crate) fn configs_by_ids(
+        sftp_connection_ids: &[Uuid],
+        webdav_connection_ids: &[Uuid],
+    ) -> (Vec<SftpConnectionConfig>, Vec<WebdavConnectionConfig>) {
+        let configs = config_store().lock().unwrap();
+        let sftp_configs = configs
+            .sftp_connections
+            .iter()
+            .filter(|c| sftp_connection_ids.contains(c.connection_id()))
+            .cloned()
+            .collect();
+        let webdav_configs = configs
+            .webdav_connections
+            .iter()
+            .filter(|c| webdav_connection_ids.contains(c.connection_id()))
+            .cloned()
+            .collect();
+        (sftp_configs, webdav_configs)
+    }
+
+    // Adds or updates all the configs (e.g imported configs) and persists the configs only once
+    pub(crate) fn add_or_update_configs(
+        sftp_configs: Vec<SftpConnectionConfig>,
+        webdav_configs: Vec<WebdavConnectionConfig>,
+    ) -> Result<()> {
+        {
+            let mut conns = config_store().lock().unwrap();
+            for config in sftp_configs {
+                Self::internal_add_or_update_config(&mut conns.sftp_connections, config);
+            }
+            for config in webdav_configs {
+                Self::internal_add_or_update_config(&mut conns.webdav_connections, config);
+            }
         }
+        Self::write_config()?;
+
+        Ok(())
     }
 
     // A new remote config is added or an existing config is updated
@@ -210,6 +293,10 @@ impl ConnectionConfigs {
                     let configs = &mut conns.webdav_connections;
                     Self::internal_add_or_update_config(configs, config);
                 }
+                RemoteStorageTypeConfig::Git(config) => {
+                    let configs = &mut conns.git_repositories;
+                    Self::internal_add_or_update_config(configs, config);
+                }
             };
         }
         Self::write_config()?;
@@ -232,6 +319,10 @@ impl ConnectionConfigs {
                     let configs = &mut conns.webdav_connections;
                     Self::internal_add_config(configs, config);
                 }
+                RemoteStorageTypeConfig::Git(config) => {
+                    let configs = &mut conns.git_repositories;
+                    Self::internal_add_config(configs, config);
+                }
             };
         }
         Self::write_config()?;
@@ -254,6 +345,10 @@ impl ConnectionConfigs {
                     let conns = &mut configs.webdav_connections;
                     Self::interal_delete_config(connection_id, conns);
                 }
+                RemoteStorageType::Git => {
+                    let conns = &mut configs.git_repositories;
+                    Self::interal_delete_config(connection_id, conns);
+                }
             }
         }
 
@@ -275,6 +370,10 @@ impl ConnectionConfigs {
                     let conns = &mut configs.webdav_connections;
                     Self::interal_update_config::<WebdavConnectionConfig>(conns, config);
                 }
+                RemoteStorageTypeConfig::Git(config) => {
+                    let conns = &mut configs.git_repositories;
+                    Self::interal_update_config::<GitRepositoryConfig>(conns, config);
+                }
             }
         }
 
@@ -1,3 +1,4 @@
+use futures_util::StreamExt;
 use log::{debug, info};
 use once_cell::sync::Lazy;
 use russh::{
@@ -8,10 +9,13 @@ use russh::{
 use russh_keys;
 
 use russh;
-use russh_sftp::client::SftpSession;
+use russh_sftp::{client::SftpSession, protocol::OpenFlags};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
-use tokio::sync::oneshot;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::oneshot,
+};
 use uuid::Uuid;
 
 use crate::{
@@ -21,19 +25,31 @@ use crate::{
 
 use onekeepass_core::async_service::async_runtime;
 use onekeepass_core::db_service::error::{self, Error, Result};
-use onekeepass_core::service_util::system_time_to_seconds;
+use onekeepass_core::service_util::{now_utc_seconds, system_time_to_seconds};
 
 pub use super::server_connection_config::SftpConnectionConfig;
 use super::{
     calls::RemoteStorageOperation,
+    diagnose::{
+        read_ssh_banner, resolve_host, tcp_connect, DiagnosticReport, DiagnosticStageType,
+        WRITE_PROBE_FILE_PREFIX,
+    },
     filter_entry,
+    kdbx_search::{join_path, FindLimits, FoundKdbxFiles, KdbxFilesCollector, CONCURRENT_DIR_LISTINGS},
+    server_backup::{backup_file_name, backups_to_prune, split_file_path, ServerBackupSettings},
     server_connection_config::{
         ConnectionConfigs, RemoteStorageTypeConfig, RemoteStorageTypeConfigs,
     },
-    string_tuple2, string_tuple3, ConnectStatus, RemoteFileMetadata, RemoteReadData,
-    RemoteStorageType, ServerDirEntry,
+    string_tuple2, string_tuple3,
+    transfer::{TransferContext, TRANSFER_CHUNK_SIZE},
+    ConnectStatus, RemoteFileMetadata, RemoteReadData, RemoteStorageType, ServerDirEntry,
 };
 
+// The temp file to which the new content is uploaded before it replaces the target file. See 'write_file'
+const TMP_UPLOAD_SUFFIX: &str = ".okp-upload";
+// The existing target file is renamed with this suffix while the temp file is renamed to the target
+const OLD_FILE_SUFFIX: &str = ".okp-old";
+
 macro_rules! reply_by_sftp_async_fn {
     ($fn_name:ident ($($arg1:tt:$arg_type:ty),*),$call:tt ($($arg:expr),*),$channel_ret_val:ty) => {
         reply_by_async_fn!(sftp_connections_store,$fn_name ($($arg1:$arg_type),*),$call ($($arg),*),$channel_ret_val);
@@ -52,6 +68,8 @@ pub struct Sftp {
     sub_dir: Option<String>,
     file_path: Option<String>,
     file_name: Option<String>,
+    // The new path used in rename and copy
+    target_path: Option<String>,
 }
 
 impl Sftp {
@@ -138,20 +156,23 @@ impl RemoteStorageOperation for Sftp {
         )?
     }
 
-    fn read(&self) -> Result<RemoteReadData> {
+    fn read(&self, transfer: TransferContext) -> Result<RemoteReadData> {
         let (connection_id, parent_dir, file_name) =
             parse_operation_fields_if!(self, connection_id, parent_dir, file_name);
 
         let (cn, pd, name) = string_tuple3(&[connection_id, parent_dir, file_name]);
-        receive_from_async_fn!(SftpConnection::send_read(cn, pd, name), RemoteReadData)?
+        receive_from_async_fn!(
+            SftpConnection::send_read(cn, pd, name, transfer),
+            RemoteReadData
+        )?
     }
 
-    fn write_file(&self, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
+    fn write_file(&self, data: Arc<Vec<u8>>, transfer: TransferContext) -> Result<RemoteFileMetadata> {
         let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
         let file_path = file_path.to_string();
         let c_id = connection_id.clone();
         receive_from_async_fn!(
-            SftpConnection::send_write_file(c_id, file_path, data),
+            SftpConnection::send_write_file(c_id, file_path, data, transfer),
             RemoteFileMetadata
         )?
     }
@@ -176,6 +197,92 @@ impl RemoteStorageOperation for Sftp {
         )?
     }
 
+    fn file_exists(&self) -> Result<bool> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+        let file_path = file_path.to_string();
+        let c_id = connection_id.clone();
+        receive_from_async_fn!(SftpConnection::send_file_exists(c_id, file_path), bool)?
+    }
+
+    fn backup_before_write(&self) -> Result<Option<String>> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let u_id = uuid::Uuid::parse_str(connection_id)?;
+        let Some(RemoteStorageTypeConfig::Sftp(SftpConnectionConfig {
+            server_backup: Some(settings),
+            ..
+        })) = ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Sftp)
+        else {
+            return Ok(None);
+        };
+
+        let (cn, fp) = string_tuple2(&[connection_id, file_path]);
+        receive_from_async_fn!(
+            SftpConnection::send_backup_existing_file(cn, fp, settings),
+            Option<String>
+        )?
+    }
+
+    fn create_dir(&self) -> Result<()> {
+        let (connection_id, parent_dir, sub_dir) =
+            parse_operation_fields_if!(self, connection_id, parent_dir, sub_dir);
+
+        let (cn, pd, sd) = string_tuple3(&[connection_id, parent_dir, sub_dir]);
+        receive_from_async_fn!(SftpConnection::send_create_dir(cn, pd, sd), ())?
+    }
+
+    fn rename(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let (cn, fp, tp) = string_tuple3(&[connection_id, file_path, target_path]);
+        receive_from_async_fn!(
+            SftpConnection::send_rename(cn, fp, tp),
+            RemoteFileMetadata
+        )?
+    }
+
+    fn delete_file(&self) -> Result<()> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let (cn, fp) = string_tuple2(&[connection_id, file_path]);
+        receive_from_async_fn!(SftpConnection::send_delete_file(cn, fp), ())?
+    }
+
+    fn copy(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let (cn, fp, tp) = string_tuple3(&[connection_id, file_path, target_path]);
+        receive_from_async_fn!(
+            SftpConnection::send_copy(cn, fp, tp),
+            RemoteFileMetadata
+        )?
+    }
+
+    fn find_kdbx_files(&self, start_dir: Option<String>, limits: FindLimits) -> Result<FoundKdbxFiles> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let start_dir = match start_dir {
+            Some(d) => d,
+            None => {
+                let u_id = uuid::Uuid::parse_str(connection_id)?;
+                match ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Sftp) {
+                    Some(RemoteStorageTypeConfig::Sftp(c)) => c.start_dir,
+                    _ => None,
+                }
+                .unwrap_or_else(|| "/".to_string())
+            }
+        };
+
+        let c_id = connection_id.clone();
+        receive_from_async_fn!(
+            SftpConnection::send_find_kdbx_files(c_id, start_dir, limits),
+            FoundKdbxFiles
+        )?
+    }
+
     fn remote_storage_configs(&self) -> Result<RemoteStorageTypeConfigs> {
         Ok(ConnectionConfigs::remote_storage_configs(
             RemoteStorageType::Sftp,
@@ -208,13 +315,61 @@ impl RemoteStorageOperation for Sftp {
     fn file_path(&self) -> Option<&str> {
         self.file_path.as_ref().map(|x| x.as_str())
     }
+
+    fn target_path(&self) -> Option<&str> {
+        self.target_path.as_ref().map(|x| x.as_str())
+    }
+
+    fn connection_id(&self) -> Option<&str> {
+        self.connection_id.as_ref().map(|x| x.as_str())
+    }
+
+    fn diagnose(&self) -> Result<DiagnosticReport> {
+        // The connection_info is used when the user is yet to save the config (e.g from the connection form)
+        // Otherwise the previously saved config is used
+        let connection_info = if let Some(c) = self.connection_info.as_ref() {
+            c.clone()
+        } else {
+            #[allow(unused_parens)]
+            let (connection_id) = parse_operation_fields_if!(self, connection_id);
+            let u_id = uuid::Uuid::parse_str(connection_id)?;
+
+            let Some(RemoteStorageTypeConfig::Sftp(mut c)) =
+                ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Sftp)
+            else {
+                return Err(Error::DataError(
+                    "Previously saved SFTP Connection config is not found in configs for this id",
+                ));
+            };
+            set_private_key_full_file_name(connection_id, &mut c);
+            c
+        };
+
+        receive_from_async_fn!(
+            SftpConnection::send_diagnose(connection_info),
+            DiagnosticReport
+        )?
+    }
+}
+
+// Need to ensure the full path points to the local key file path correctly to use in the connect call
+fn set_private_key_full_file_name(connection_id: &str, connection_info: &mut SftpConnectionConfig) {
+    if let Some(ref file_name) = connection_info.private_key_file_name {
+        let p = CallbackServiceProvider::common_callback_service()
+            .sftp_private_key_file_full_path(connection_id, file_name);
+        connection_info.private_key_full_file_name = Some(p.as_path().to_string_lossy().to_string());
+    }
 }
 
 //  Exposed functions
 
 //////////
 
-struct Client;
+#[derive(Default)]
+struct Client {
+    // Set only when diagnosing a connection so that the server host key can be reported
+    server_key_info: Option<Arc<std::sync::Mutex<Option<String>>>>,
+}
 
 // This macro is to make async fn in traits work with dyn traits
 // See https://docs.rs/async-trait/latest/async_trait/
@@ -230,6 +385,13 @@ impl client::Handler for Client {
         server_public_key: &russh::keys::PublicKey,
     ) -> std::result::Result<bool, Self::Error> {
         info!("check_server_key: {:?}", server_public_key);
+        if let Some(ref key_info) = self.server_key_info {
+            *key_info.lock().unwrap() = Some(format!(
+                "{} {}",
+                server_public_key.algorithm(),
+                server_public_key.fingerprint(russh::keys::HashAlg::Sha256)
+            ));
+        }
         Ok(true)
     }
 
@@ -369,12 +531,7 @@ impl SftpConnection {
 
         // Need to ensure the full path points to the local key file path correctly to use
         // in the following 'Self::connect' call
-        if let Some(ref file_name) = connection_info.private_key_file_name {
-            let p = CallbackServiceProvider::common_callback_service()
-                .sftp_private_key_file_full_path(connection_id, file_name);
-            connection_info.private_key_full_file_name =
-                Some(p.as_path().to_string_lossy().to_string());
-        }
+        set_private_key_full_file_name(connection_id, connection_info);
 
         let sftp_connection = Self::connect(connection_info).await?;
 
@@ -392,29 +549,69 @@ impl SftpConnection {
             connection_info
         );
 
+        let SftpConnectionConfig { connection_id, .. } = connection_info;
+
+        debug!("Sftp::connect Going to russh connect...");
+
+        let mut client_handle = Self::russh_connect(connection_info, Client::default()).await?;
+
+        debug!("Sftp::connect russh connected");
+
+        let session_authenticated = Self::authenticate(&mut client_handle, connection_info).await?;
+
+        debug!(
+            "Sftp::connect session_authenticated is {}",
+            &session_authenticated
+        );
+
+        if session_authenticated {
+            Ok(SftpConnection {
+                connection_id: *connection_id,
+                client_handle,
+            })
+        } else {
+            Err(Error::SftpServerAuthenticationFailed)
+        }
+    }
+
+    // Makes the ssh connection directly or through the proxy if one is set in the config
+    async fn russh_connect(
+        connection_info: &SftpConnectionConfig,
+        handler: Client,
+    ) -> Result<Handle<Client>> {
+        let SftpConnectionConfig {
+            host, port, proxy, ..
+        } = connection_info;
+
+        let config = Arc::new(russh::client::Config::default());
+
+        let client_handle = if let Some(proxy) = proxy {
+            let stream = proxy.connect_tcp(host, *port).await?;
+            russh::client::connect_stream(config, stream, handler)
+                .await
+                .map_err(convert_error)?
+        } else {
+            russh::client::connect(config, (host.as_str(), *port), handler)
+                .await
+                .map_err(convert_error)?
+        };
+
+        Ok(client_handle)
+    }
+
+    // Authenticates using the private key if available or using the password
+    async fn authenticate(
+        client_handle: &mut Handle<Client>,
+        connection_info: &SftpConnectionConfig,
+    ) -> Result<bool> {
         let SftpConnectionConfig {
-            connection_id,
-            host,
-            port,
             private_key_full_file_name,
-            private_key_file_name: _,
             user_name,
             password,
             // Omits the remaining fields
             ..
         } = connection_info;
 
-        debug!("Sftp::connect Going to russh connect...");
-
-        let config = russh::client::Config::default();
-        let sh = Client {};
-        let mut client_handle =
-            russh::client::connect(Arc::new(config), (host.clone(), port.clone()), sh)
-                .await
-                .map_err(|e| convert_error(e))?;
-
-        debug!("Sftp::connect russh connected");
-
         let session_authenticated = if let Some(full_file_path) = private_key_full_file_name {
             // let full_file_path = CallbackServiceProvider::common_callback_service().sftp_private_key_file_full_path(file_name);
 
@@ -440,19 +637,134 @@ impl SftpConnection {
             false
         };
 
-        debug!(
-            "Sftp::connect session_authenticated is {}",
-            &session_authenticated
-        );
+        Ok(session_authenticated)
+    }
 
-        if session_authenticated {
-            Ok(SftpConnection {
-                connection_id: *connection_id,
-                client_handle,
+    // Runs each connection stage separately so that the user can see where the connection fails
+    // The connection made here is not stored and is closed at the end
+    async fn diagnose(connection_info: SftpConnectionConfig) -> Result<DiagnosticReport> {
+        let mut report = DiagnosticReport::default();
+        let host = connection_info.host.as_str();
+        let port = connection_info.port;
+
+        // When a proxy is used, only the proxy host is resolved locally and the tcp stage
+        // checks the tunnel to the sftp server through the proxy
+        let proxy = connection_info.proxy.as_ref();
+        let (dns_host, dns_port) = proxy.map_or((host, port), |p| (p.host.as_str(), p.port));
+
+        let addrs = report
+            .run(
+                DiagnosticStageType::DnsResolution,
+                resolve_host(dns_host, dns_port),
+            )
+            .await
+            .unwrap_or_default();
+
+        let stream = match proxy {
+            Some(p) => {
+                report
+                    .run(DiagnosticStageType::TcpConnect, async {
+                        let s = p.connect_tcp(host, port).await?;
+                        let details = format!(
+                            "Connected to {}:{} through the proxy {}:{}",
+                            host, port, &p.host, p.port
+                        );
+                        Ok((s, Some(details)))
+                    })
+                    .await
+            }
+            None => {
+                report
+                    .run(DiagnosticStageType::TcpConnect, tcp_connect(&addrs))
+                    .await
+            }
+        };
+
+        match stream {
+            Some(s) => report.run(DiagnosticStageType::SshBanner, read_ssh_banner(s)).await,
+            None => report.skip(DiagnosticStageType::SshBanner),
+        };
+
+        // The key exchange is done as part of this connect and the host key is collected
+        // in 'check_server_key' of the handler
+        let key_info: Arc<std::sync::Mutex<Option<String>>> = Default::default();
+        let sh = Client {
+            server_key_info: Some(key_info.clone()),
+        };
+        let client_handle = report
+            .run(DiagnosticStageType::SshHostKey, async {
+                let h = Self::russh_connect(&connection_info, sh).await?;
+                Ok((h, key_info.lock().unwrap().take()))
+            })
+            .await;
+
+        let Some(mut client_handle) = client_handle else {
+            report.skip::<()>(DiagnosticStageType::Authentication);
+            report.skip::<()>(DiagnosticStageType::ListStartDir);
+            report.skip::<()>(DiagnosticStageType::WritePermission);
+            return Ok(report);
+        };
+
+        let authenticated = report
+            .run(DiagnosticStageType::Authentication, async {
+                if Self::authenticate(&mut client_handle, &connection_info).await? {
+                    Ok(((), Some(format!("Authenticated as {}", &connection_info.user_name))))
+                } else {
+                    Err(Error::SftpServerAuthenticationFailed)
+                }
             })
+            .await;
+
+        let start_dir = connection_info
+            .start_dir
+            .clone()
+            .map_or_else(|| "/".to_string(), |s| s);
+
+        let sftp_connection = SftpConnection {
+            connection_id: connection_info.connection_id,
+            client_handle,
+        };
+
+        if authenticated.is_some() {
+            report
+                .run(DiagnosticStageType::ListStartDir, async {
+                    let dirs = sftp_connection.list_dir(&start_dir).await?;
+                    let details = format!(
+                        "Found {} folders and {} files in {}",
+                        dirs.sub_dirs.len(),
+                        dirs.files.len(),
+                        &start_dir
+                    );
+                    Ok(((), Some(details)))
+                })
+                .await;
+
+            report
+                .run(DiagnosticStageType::WritePermission, async {
+                    let sftp_session = sftp_connection.create_sftp_session().await?;
+                    let probe_file = format!(
+                        "{}/{}{}",
+                        start_dir.trim_end_matches("/"),
+                        WRITE_PROBE_FILE_PREFIX,
+                        Uuid::new_v4()
+                    );
+                    sftp_session.create(&probe_file).await?;
+                    sftp_session.remove_file(&probe_file).await?;
+                    let _ = sftp_session.close().await;
+                    Ok(((), Some(format!("Created and removed a file in {}", &start_dir))))
+                })
+                .await;
         } else {
-            Err(Error::SftpServerAuthenticationFailed)
+            report.skip::<()>(DiagnosticStageType::ListStartDir);
+            report.skip::<()>(DiagnosticStageType::WritePermission);
         }
+
+        let _ = sftp_connection
+            .client_handle
+            .disconnect(russh::Disconnect::ByApplication, "", "English")
+            .await;
+
+        Ok(report)
     }
 
     async fn list_dir(&self, parent_dir: &str) -> Result<ServerDirEntry> {
@@ -497,14 +809,33 @@ impl SftpConnection {
         Ok(sftp)
     }
 
-    async fn read(&self, parent_dir: &str, file_name: &str) -> Result<RemoteReadData> {
+    async fn read(
+        &self,
+        parent_dir: &str,
+        file_name: &str,
+        transfer: TransferContext,
+    ) -> Result<RemoteReadData> {
         let sftp_session = self.create_sftp_session().await?;
         let full_path = [parent_dir, file_name].join("/");
 
         debug!("Sftp going to read file path {} ", &full_path);
 
-        // Copies the full file content to memory
-        let contents = sftp_session.read(&full_path).await?;
+        // Copies the full file content to memory chunk by chunk so that the progress can be reported
+        let mut file = sftp_session.open(&full_path).await?;
+        let bytes_total = file.metadata().await?.size;
+
+        let mut contents = Vec::with_capacity(bytes_total.unwrap_or_default() as usize);
+        let mut buf = vec![0u8; TRANSFER_CHUNK_SIZE];
+        loop {
+            transfer.check_cancelled()?;
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            contents.extend_from_slice(&buf[..n]);
+            transfer.report(contents.len() as u64, bytes_total);
+        }
+        let _ = file.shutdown().await;
 
         debug!("Sftp content read and size is {}", contents.len());
 
@@ -543,12 +874,26 @@ impl SftpConnection {
         })
     }
 
-    async fn write_file(&self, file_path: &str, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
+    async fn write_file(
+        &self,
+        file_path: &str,
+        data: Arc<Vec<u8>>,
+        transfer: TransferContext,
+    ) -> Result<RemoteFileMetadata> {
         let sftp_session = self.create_sftp_session().await?;
 
         debug!("Sftp going to write file path {} ", &file_path);
 
-        sftp_session.write(file_path, data.as_slice()).await?;
+        // The content is uploaded to a temp file next to the target and that temp file replaces the target
+        // only after the last chunk is written. A cancel or a network error in the middle leaves the target as it was
+        let tmp_path = format!("{}{}", file_path, TMP_UPLOAD_SUFFIX);
+
+        if let Err(e) = Self::upload_chunks(&sftp_session, &tmp_path, &data, &transfer).await {
+            let _ = sftp_session.remove_file(&tmp_path).await;
+            return Err(e);
+        }
+
+        Self::replace_with(&sftp_session, &tmp_path, file_path).await?;
 
         let md = self
             .create_remote_file_metadata(sftp_session, file_path)
@@ -557,6 +902,58 @@ impl SftpConnection {
         Ok(md)
     }
 
+    async fn upload_chunks(
+        sftp_session: &SftpSession,
+        path: &str,
+        data: &[u8],
+        transfer: &TransferContext,
+    ) -> Result<()> {
+        let mut file = sftp_session
+            .open_with_flags(
+                path,
+                OpenFlags::CREATE | OpenFlags::WRITE | OpenFlags::TRUNCATE,
+            )
+            .await?;
+
+        let bytes_total = Some(data.len() as u64);
+        let mut bytes_done = 0;
+        for chunk in data.chunks(TRANSFER_CHUNK_SIZE) {
+            transfer.check_cancelled()?;
+            file.write_all(chunk).await?;
+            bytes_done += chunk.len() as u64;
+            transfer.report(bytes_done, bytes_total);
+        }
+        file.shutdown().await?;
+        Ok(())
+    }
+
+    // SFTP v3 rename fails if the target exists. So the existing target is first moved aside and
+    // it is deleted only after the new content is in place
+    async fn replace_with(sftp_session: &SftpSession, tmp_path: &str, file_path: &str) -> Result<()> {
+        if !sftp_session.try_exists(file_path).await? {
+            sftp_session.rename(tmp_path, file_path).await?;
+            return Ok(());
+        }
+
+        let old_path = format!("{}{}", file_path, OLD_FILE_SUFFIX);
+        if sftp_session.try_exists(&old_path).await? {
+            sftp_session.remove_file(&old_path).await?;
+        }
+        sftp_session.rename(file_path, &old_path).await?;
+
+        if let Err(e) = sftp_session.rename(tmp_path, file_path).await {
+            // Puts back the original file
+            let _ = sftp_session.rename(&old_path, file_path).await;
+            let _ = sftp_session.remove_file(tmp_path).await;
+            return Err(e.into());
+        }
+
+        if let Err(e) = sftp_session.remove_file(&old_path).await {
+            log::error!("Removing the previous content file {} failed {}", &old_path, e);
+        }
+        Ok(())
+    }
+
     async fn create_file(&self, file_path: &str, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
         let sftp_session = self.create_sftp_session().await?;
 
@@ -576,6 +973,67 @@ impl SftpConnection {
         Ok(md)
     }
 
+    // Walks the dirs level by level and all dirs of a level are listed concurrently using the same sftp session
+    async fn find_kdbx_files(&self, start_dir: &str, limits: FindLimits) -> Result<FoundKdbxFiles> {
+        let sftp_session = self.create_sftp_session().await?;
+        let mut collector = KdbxFilesCollector::new(limits);
+
+        let mut current_level = vec![start_dir.to_string()];
+        let mut depth = 0;
+
+        while !current_level.is_empty() && !collector.limit_reached() {
+            let session = &sftp_session;
+            let listings = futures_util::stream::iter(current_level)
+                .map(|dir| async move {
+                    let r = session.read_dir(dir.as_str()).await;
+                    (dir, r)
+                })
+                .buffer_unordered(CONCURRENT_DIR_LISTINGS)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut next_level = vec![];
+            'listings: for (dir, listing) in listings {
+                // A dir that can not be read (e.g no permission) is skipped and the search continues
+                let entries = match listing {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        debug!("Listing the dir {} failed with error {}", &dir, e);
+                        continue;
+                    }
+                };
+
+                for e in entries {
+                    let name = e.file_name();
+                    if !filter_entry(&name) || name == "." || name == ".." {
+                        continue;
+                    }
+                    if !collector.count_entry() {
+                        break 'listings;
+                    }
+
+                    // Symbolic links are not followed to avoid any loop
+                    if e.file_type().is_dir() {
+                        if collector.can_descend(depth) {
+                            next_level.push(join_path(&dir, &name));
+                        }
+                    } else if e.file_type().is_file() {
+                        let md = e.metadata();
+                        let modified = md.modified().ok().map(system_time_to_seconds);
+                        collector.add_if_kdbx(join_path(&dir, &name), &name, md.size, modified);
+                    }
+                }
+            }
+
+            current_level = next_level;
+            depth += 1;
+        }
+
+        let _ = sftp_session.close().await;
+
+        Ok(collector.into_found_files(self.connection_id, start_dir))
+    }
+
     async fn create_remote_file_metadata(
         &self,
         sftp_session: SftpSession,
@@ -615,6 +1073,130 @@ impl SftpConnection {
             .await
     }
 
+    async fn file_exists(&self, file_path: &str) -> Result<bool> {
+        let sftp_session = self.create_sftp_session().await?;
+        Ok(sftp_session.try_exists(file_path).await?)
+    }
+
+    async fn create_dir(&self, parent_dir: &str, sub_dir: &str) -> Result<()> {
+        let sftp_session = self.create_sftp_session().await?;
+        let full_dir = [parent_dir.trim_end_matches("/"), sub_dir].join("/");
+
+        debug!("Sftp going to create dir {} ", &full_dir);
+
+        sftp_session.create_dir(&full_dir).await?;
+        let _ = sftp_session.close().await;
+        Ok(())
+    }
+
+    async fn rename(&self, file_path: &str, target_path: &str) -> Result<RemoteFileMetadata> {
+        let sftp_session = self.create_sftp_session().await?;
+
+        // SFTP v3 rename fails if the target exists and that prevents overwriting another file accidentally
+        if sftp_session.try_exists(target_path).await? {
+            return Err(Error::RemoteStorageCallError(format!(
+                "A file with the name {} already exists",
+                target_path
+            )));
+        }
+
+        sftp_session.rename(file_path, target_path).await?;
+
+        self.create_remote_file_metadata(sftp_session, target_path)
+            .await
+    }
+
+    async fn delete_file(&self, file_path: &str) -> Result<()> {
+        let sftp_session = self.create_sftp_session().await?;
+        sftp_session.remove_file(file_path).await?;
+        let _ = sftp_session.close().await;
+        Ok(())
+    }
+
+    // There is no server side copy in SFTP and the content is copied through this client in chunks
+    async fn copy(&self, file_path: &str, target_path: &str) -> Result<RemoteFileMetadata> {
+        let sftp_session = self.create_sftp_session().await?;
+
+        if sftp_session.try_exists(target_path).await? {
+            return Err(Error::RemoteStorageCallError(format!(
+                "A file with the name {} already exists",
+                target_path
+            )));
+        }
+
+        let mut source = sftp_session.open(file_path).await?;
+        let mut target = sftp_session.create(target_path).await?;
+
+        let mut buf = vec![0u8; TRANSFER_CHUNK_SIZE];
+        loop {
+            let n = source.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            target.write_all(&buf[..n]).await?;
+        }
+        target.shutdown().await?;
+        let _ = source.shutdown().await;
+
+        self.create_remote_file_metadata(sftp_session, target_path)
+            .await
+    }
+
+    // The existing file is copied (and not renamed) so that the db file is still available if the following write fails
+    async fn backup_existing_file(
+        &self,
+        file_path: &str,
+        settings: &ServerBackupSettings,
+    ) -> Result<Option<String>> {
+        let (parent_dir, file_name) = split_file_path(file_path);
+        let backup_dir = settings.backup_dir_path(parent_dir)?;
+
+        let sftp_session = self.create_sftp_session().await?;
+        if !sftp_session.try_exists(file_path).await? {
+            // A new file and nothing to backup
+            let _ = sftp_session.close().await;
+            return Ok(None);
+        }
+        if !sftp_session.try_exists(&backup_dir).await? {
+            sftp_session.create_dir(&backup_dir).await?;
+        }
+        let _ = sftp_session.close().await;
+
+        let backup_file_path = join_path(
+            &backup_dir,
+            &backup_file_name(file_name, now_utc_seconds()),
+        );
+        self.copy(file_path, &backup_file_path).await?;
+
+        // Any failure in removing the old backups should not fail the save
+        match self.list_dir(&backup_dir).await {
+            Ok(entries) => {
+                for f in backups_to_prune(file_name, &entries.files, settings.keep_count) {
+                    let r = self.delete_file(&join_path(&backup_dir, &f)).await;
+                    debug!("Removing the old server backup {} result {:?}", &f, r);
+                }
+            }
+            Err(e) => log::error!(
+                "Listing the server backup dir {} to prune failed {}",
+                &backup_dir,
+                e
+            ),
+        }
+
+        Ok(Some(backup_file_path))
+    }
+
+    pub(crate) async fn send_diagnose(
+        tx: oneshot::Sender<Result<DiagnosticReport>>,
+        connection_info: SftpConnectionConfig,
+    ) {
+        let report = SftpConnection::diagnose(connection_info).await;
+        let r = tx.send(report);
+        if let Err(_) = r {
+            log::error!("In send_diagnose send channel failed ");
+        }
+    }
+
     // All instance level send_* calls
     // This async fns are called in a spawn fn and that receives a oneshot channel and sends back the result
 
@@ -661,14 +1243,28 @@ impl SftpConnection {
     // pub(crate) async fn send_list_sub_dir(tx: oneshot::Sender<Result<ServerDirEntry>>, connection_id: String, parent_dir: String, sub_dir: String)
     reply_by_sftp_async_fn!(send_list_sub_dir (parent_dir:String,sub_dir:String), list_sub_dir (&parent_dir,&sub_dir), ServerDirEntry);
 
-    reply_by_sftp_async_fn!(send_read(parent_dir:String,file_name:String),read(&parent_dir,&file_name),RemoteReadData);
+    reply_by_sftp_async_fn!(send_read(parent_dir:String,file_name:String,transfer:TransferContext),read(&parent_dir,&file_name,transfer),RemoteReadData);
 
-    reply_by_sftp_async_fn!(send_write_file(file_path:String,data:Arc<Vec<u8>>), write_file(&file_path, data), RemoteFileMetadata);
+    reply_by_sftp_async_fn!(send_write_file(file_path:String,data:Arc<Vec<u8>>,transfer:TransferContext), write_file(&file_path, data, transfer), RemoteFileMetadata);
 
     reply_by_sftp_async_fn!(send_create_file(file_path:String,data:Arc<Vec<u8>>), create_file(&file_path, data), RemoteFileMetadata);
 
     reply_by_sftp_async_fn!(send_file_metadta(file_path:String), file_metadata(&file_path), RemoteFileMetadata);
 
+    reply_by_sftp_async_fn!(send_file_exists(file_path:String), file_exists(&file_path), bool);
+
+    reply_by_sftp_async_fn!(send_create_dir(parent_dir:String,sub_dir:String), create_dir(&parent_dir, &sub_dir), ());
+
+    reply_by_sftp_async_fn!(send_rename(file_path:String,target_path:String), rename(&file_path, &target_path), RemoteFileMetadata);
+
+    reply_by_sftp_async_fn!(send_delete_file(file_path:String), delete_file(&file_path), ());
+
+    reply_by_sftp_async_fn!(send_copy(file_path:String,target_path:String), copy(&file_path, &target_path), RemoteFileMetadata);
+
+    reply_by_sftp_async_fn!(send_backup_existing_file(file_path:String,settings:ServerBackupSettings), backup_existing_file(&file_path, &settings), Option<String>);
+
+    reply_by_sftp_async_fn!(send_find_kdbx_files(start_dir:String,limits:FindLimits), find_kdbx_files(&start_dir, limits), FoundKdbxFiles);
+
     //reply_by_sftp_async_fn!(send_metadata (parent_dir:String,fiile_name:String), metadata (&parent_dir,&fiile_name), RemoteFileMetadata);
 }
 
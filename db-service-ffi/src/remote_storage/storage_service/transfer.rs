@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::debug;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use onekeepass_core::db_service::error::{self, Result};
+
+use crate::remote_storage::callback_service::CallbackServiceProvider;
+
+// Size of each chunk used while reading from or writing to a remote file
+pub(crate) const TRANSFER_CHUNK_SIZE: usize = 64 * 1024;
+
+// Progress is reported to the UI only after this many bytes are transferred since the last report
+// so that we do not flood the event channel with too many events for a large file
+const REPORT_BYTES_INTERVAL: u64 = 256 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub enum TransferDirection {
+    Download,
+    Upload,
+}
+
+// This is sent to the UI as json through 'EventDispatch'
+#[derive(Serialize, Debug)]
+pub struct TransferProgress {
+    pub request_id: String,
+    pub direction: TransferDirection,
+    pub bytes_done: u64,
+    // The total may not be known for some servers
+    pub bytes_total: Option<u64>,
+}
+
+struct TransferState {
+    request_id: String,
+    direction: TransferDirection,
+    cancelled: AtomicBool,
+    last_reported: AtomicU64,
+}
+
+type TransferRequests = Mutex<HashMap<String, Arc<TransferState>>>;
+
+// All in-flight transfers that were started with a request id from the UI
+fn transfer_requests_store() -> &'static TransferRequests {
+    static TRANSFER_REQUESTS_STORE: Lazy<TransferRequests> = Lazy::new(Default::default);
+    &TRANSFER_REQUESTS_STORE
+}
+
+// Passed to the Sftp and Webdav read/write calls so that they can report the progress and
+// check whether the user has cancelled the transfer between chunks
+// When there is no request id, nothing is reported and the transfer can not be cancelled
+#[derive(Clone, Default)]
+pub struct TransferContext {
+    state: Option<Arc<TransferState>>,
+}
+
+impl TransferContext {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state
+            .as_ref()
+            .map_or(false, |s| s.cancelled.load(Ordering::Relaxed))
+    }
+
+    // Called before transferring each chunk
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(cancelled_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn report(&self, bytes_done: u64, bytes_total: Option<u64>) {
+        let Some(state) = self.state.as_ref() else {
+            return;
+        };
+
+        let completed = bytes_total.map_or(false, |t| bytes_done >= t);
+        let last = state.last_reported.load(Ordering::Relaxed);
+        if !completed && bytes_done < last + REPORT_BYTES_INTERVAL {
+            return;
+        }
+        state.last_reported.store(bytes_done, Ordering::Relaxed);
+
+        let progress = TransferProgress {
+            request_id: state.request_id.clone(),
+            direction: state.direction,
+            bytes_done,
+            bytes_total,
+        };
+
+        CallbackServiceProvider::common_callback_service().rs_transfer_progress(&progress);
+    }
+}
+
+// Registers a transfer with the request id sent from the UI so that it can be cancelled by 'cancel_transfer'
+// The registration is removed when this is dropped at the end of the read or save call
+pub struct TransferRequest {
+    context: TransferContext,
+}
+
+impl TransferRequest {
+    pub fn register(request_id: Option<String>, direction: TransferDirection) -> Self {
+        let Some(request_id) = request_id else {
+            return Self {
+                context: TransferContext::default(),
+            };
+        };
+
+        let state = Arc::new(TransferState {
+            request_id: request_id.clone(),
+            direction,
+            cancelled: AtomicBool::new(false),
+            last_reported: AtomicU64::new(0),
+        });
+
+        transfer_requests_store()
+            .lock()
+            .unwrap()
+            .insert(request_id, state.clone());
+
+        Self {
+            context: TransferContext { state: Some(state) },
+        }
+    }
+
+    pub fn context(&self) -> TransferContext {
+        self.context.clone()
+    }
+}
+
+impl Drop for TransferRequest {
+    fn drop(&mut self) {
+        let Some(state) = self.context.state.as_ref() else {
+            return;
+        };
+
+        // A newer transfer may have been registered with the same request id and that should not be removed
+        let mut store = transfer_requests_store().lock().unwrap();
+        if store
+            .get(&state.request_id)
+            .map_or(false, |s| Arc::ptr_eq(s, state))
+        {
+            store.remove(&state.request_id);
+        }
+    }
+}
+
+// Marks the in-flight transfer as cancelled. The transfer stops before its next chunk
+// Returns false if no transfer is found for this request id (e.g it is already completed)
+pub fn cancel_transfer(request_id: &str) -> bool {
+    let store = transfer_requests_store().lock().unwrap();
+    if let Some(state) = store.get(request_id) {
+        state.cancelled.store(true, Ordering::Relaxed);
+        debug!("Transfer with request id {} is marked as cancelled", request_id);
+        true
+    } else {
+        debug!("No in-flight transfer is found for the request id {}", request_id);
+        false
+    }
+}
+
+pub(crate) fn cancelled_error() -> error::Error {
+    error::Error::RemoteStorageCallError("The remote file transfer is cancelled".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_registered(request_id: &str) -> bool {
+        transfer_requests_store().lock().unwrap().contains_key(request_id)
+    }
+
+    #[test]
+    fn verify_register_and_drop() {
+        let request = TransferRequest::register(Some("tr-register".into()), TransferDirection::Download);
+        assert!(is_registered("tr-register"));
+        assert!(!request.context().is_cancelled());
+
+        drop(request);
+        assert!(!is_registered("tr-register"));
+        assert!(!cancel_transfer("tr-register"));
+
+        // Without a request id, nothing is registered and the transfer can not be cancelled
+        let request = TransferRequest::register(None, TransferDirection::Upload);
+        assert!(request.context().check_cancelled().is_ok());
+    }
+
+    #[test]
+    fn verify_cancel() {
+        let request = TransferRequest::register(Some("tr-cancel".into()), TransferDirection::Upload);
+        let context = request.context();
+        assert!(context.check_cancelled().is_ok());
+
+        assert!(cancel_transfer("tr-cancel"));
+        assert!(context.is_cancelled());
+        assert!(context.check_cancelled().is_err());
+        assert!(!cancel_transfer("tr-unknown"));
+    }
+
+    #[test]
+    fn verify_replaced_request_not_removed_on_drop() {
+        let older = TransferRequest::register(Some("tr-replace".into()), TransferDirection::Download);
+        let newer = TransferRequest::register(Some("tr-replace".into()), TransferDirection::Download);
+
+        // Dropping the older one keeps the newer registration and the newer transfer can still be cancelled
+        drop(older);
+        assert!(is_registered("tr-replace"));
+        assert!(cancel_transfer("tr-replace"));
+        assert!(newer.context().is_cancelled());
+
+        drop(newer);
+        assert!(!is_registered("tr-replace"));
+    }
+}
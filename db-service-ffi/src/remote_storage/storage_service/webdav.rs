@@ -1,8 +1,13 @@
 use std::{collections::HashMap, sync::Arc};
 
+use futures_util::StreamExt;
 use log::{debug, info};
 use once_cell::sync::Lazy;
-use reqwest_dav::{list_cmd::ListEntity, Auth, Client, ClientBuilder, Depth};
+use reqwest_dav::{
+    list_cmd::ListEntity,
+    re_exports::reqwest::{header, Body, Method},
+    Auth, Client, ClientBuilder, Depth,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 use uuid::Uuid;
@@ -14,17 +19,26 @@ use crate::{
 
 use onekeepass_core::async_service::async_runtime;
 use onekeepass_core::db_service::error::{self, Error, Result};
-use onekeepass_core::service_util::system_time_to_seconds;
+use onekeepass_core::service_util::{now_utc_seconds, system_time_to_seconds};
 
 pub use super::server_connection_config::WebdavConnectionConfig;
 use super::ConnectStatus;
 use super::{
     calls::RemoteStorageOperation,
+    diagnose::{
+        parse_certificate, resolve_host, tcp_connect, CertificateDetails, DiagnosticReport,
+        DiagnosticStageType, WRITE_PROBE_FILE_PREFIX,
+    },
     filter_entry,
+    kdbx_search::{join_path, FindLimits, FoundKdbxFiles, KdbxFilesCollector, CONCURRENT_DIR_LISTINGS},
+    proxy::ProxyConfig,
+    server_backup::{backup_file_name, backups_to_prune, split_file_path, ServerBackupSettings},
     server_connection_config::{
         ConnectionConfigs, RemoteStorageTypeConfig, RemoteStorageTypeConfigs,
     },
-    string_tuple3, RemoteFileMetadata, RemoteReadData, RemoteStorageType, ServerDirEntry,
+    string_tuple2, string_tuple3,
+    transfer::{cancelled_error, TransferContext, TRANSFER_CHUNK_SIZE},
+    RemoteFileMetadata, RemoteReadData, RemoteStorageType, ServerDirEntry,
 };
 
 macro_rules! reply_by_webdav_async_fn {
@@ -41,6 +55,8 @@ pub struct Webdav {
     sub_dir: Option<String>,
     file_path: Option<String>,
     pub(crate) file_name: Option<String>,
+    // The new path used in rename and copy
+    target_path: Option<String>,
 }
 
 impl Webdav {
@@ -109,26 +125,29 @@ impl RemoteStorageOperation for Webdav {
         )?
     }
 
-    fn read(&self) -> Result<RemoteReadData> {
+    fn read(&self, transfer: TransferContext) -> Result<RemoteReadData> {
         let (connection_id, parent_dir, file_name) =
             parse_operation_fields_if!(self, connection_id, parent_dir, file_name);
 
         let (cn, pd, name) = string_tuple3(&[connection_id, parent_dir, file_name]);
-        receive_from_async_fn!(WebdavConnection::send_read(cn, pd, name), RemoteReadData)?
+        receive_from_async_fn!(
+            WebdavConnection::send_read(cn, pd, name, transfer),
+            RemoteReadData
+        )?
     }
 
-    fn write_file(&self, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
+    fn write_file(&self, data: Arc<Vec<u8>>, transfer: TransferContext) -> Result<RemoteFileMetadata> {
         let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
         let file_path = file_path.to_string();
         let c_id = connection_id.clone();
         receive_from_async_fn!(
-            WebdavConnection::send_write_file(c_id, file_path, data),
+            WebdavConnection::send_write_file(c_id, file_path, data, transfer),
             RemoteFileMetadata
         )?
     }
 
     fn create_file(&self, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
-        self.write_file(data)
+        self.write_file(data, TransferContext::default())
     }
 
     fn file_metadata(&self) -> Result<RemoteFileMetadata> {
@@ -141,6 +160,90 @@ impl RemoteStorageOperation for Webdav {
         )?
     }
 
+    fn file_exists(&self) -> Result<bool> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+        let file_path = file_path.to_string();
+        let c_id = connection_id.clone();
+        receive_from_async_fn!(WebdavConnection::send_file_exists(c_id, file_path), bool)?
+    }
+
+    fn backup_before_write(&self) -> Result<Option<String>> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let u_id = uuid::Uuid::parse_str(connection_id)?;
+        let Some(RemoteStorageTypeConfig::Webdav(WebdavConnectionConfig {
+            server_backup: Some(settings),
+            ..
+        })) = ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Webdav)
+        else {
+            return Ok(None);
+        };
+
+        let (cn, fp) = string_tuple2(&[connection_id, file_path]);
+        receive_from_async_fn!(
+            WebdavConnection::send_backup_existing_file(cn, fp, settings),
+            Option<String>
+        )?
+    }
+
+    fn create_dir(&self) -> Result<()> {
+        let (connection_id, parent_dir, sub_dir) =
+            parse_operation_fields_if!(self, connection_id, parent_dir, sub_dir);
+
+        let (cn, pd, sd) = string_tuple3(&[connection_id, parent_dir, sub_dir]);
+        receive_from_async_fn!(WebdavConnection::send_create_dir(cn, pd, sd), ())?
+    }
+
+    fn rename(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let (cn, fp, tp) = string_tuple3(&[connection_id, file_path, target_path]);
+        receive_from_async_fn!(
+            WebdavConnection::send_rename(cn, fp, tp),
+            RemoteFileMetadata
+        )?
+    }
+
+    fn delete_file(&self) -> Result<()> {
+        let (connection_id, file_path) = parse_operation_fields_if!(self, connection_id, file_path);
+
+        let (cn, fp) = string_tuple2(&[connection_id, file_path]);
+        receive_from_async_fn!(WebdavConnection::send_delete_file(cn, fp), ())?
+    }
+
+    fn copy(&self) -> Result<RemoteFileMetadata> {
+        let (connection_id, file_path, target_path) =
+            parse_operation_fields_if!(self, connection_id, file_path, target_path);
+
+        let (cn, fp, tp) = string_tuple3(&[connection_id, file_path, target_path]);
+        receive_from_async_fn!(
+            WebdavConnection::send_copy(cn, fp, tp),
+            RemoteFileMetadata
+        )?
+    }
+
+    fn find_kdbx_files(&self, start_dir: Option<String>, limits: FindLimits) -> Result<FoundKdbxFiles> {
+        #[allow(unused_parens)]
+        let (connection_id) = parse_operation_fields_if!(self, connection_id);
+
+        let u_id = uuid::Uuid::parse_str(connection_id)?;
+        let start_dir = match start_dir {
+            Some(d) => d,
+            None => match ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Webdav) {
+                Some(RemoteStorageTypeConfig::Webdav(c)) => c.start_dir,
+                _ => None,
+            }
+            .unwrap_or_else(|| WEBDAV_ROOT_DIR.to_string()),
+        };
+
+        let c_id = connection_id.clone();
+        receive_from_async_fn!(
+            WebdavConnection::send_find_kdbx_files(c_id, start_dir, limits, u_id),
+            FoundKdbxFiles
+        )?
+    }
+
     fn remote_storage_configs(&self) -> Result<RemoteStorageTypeConfigs> {
         Ok(ConnectionConfigs::remote_storage_configs(
             RemoteStorageType::Webdav,
@@ -176,6 +279,40 @@ impl RemoteStorageOperation for Webdav {
     fn file_path(&self) -> Option<&str> {
         self.file_path.as_ref().map(|x| x.as_str())
     }
+
+    fn target_path(&self) -> Option<&str> {
+        self.target_path.as_ref().map(|x| x.as_str())
+    }
+
+    fn connection_id(&self) -> Option<&str> {
+        self.connection_id.as_ref().map(|x| x.as_str())
+    }
+
+    fn diagnose(&self) -> Result<DiagnosticReport> {
+        // The connection_info is used when the user is yet to save the config (e.g from the connection form)
+        // Otherwise the previously saved config is used
+        let connection_info = if let Some(c) = self.connection_info.as_ref() {
+            c.clone()
+        } else {
+            #[allow(unused_parens)]
+            let (connection_id) = parse_operation_fields_if!(self, connection_id);
+            let u_id = uuid::Uuid::parse_str(connection_id)?;
+
+            let Some(RemoteStorageTypeConfig::Webdav(c)) =
+                ConnectionConfigs::find_remote_storage_config(&u_id, RemoteStorageType::Webdav)
+            else {
+                return Err(Error::DataError(
+                    "Previously saved WebDav Connection config is not found in configs for this id",
+                ));
+            };
+            c
+        };
+
+        receive_from_async_fn!(
+            WebdavConnection::send_diagnose(connection_info),
+            DiagnosticReport
+        )?
+    }
 }
 
 struct WebdavConnection {
@@ -299,9 +436,14 @@ impl WebdavConnection {
     }
 
     async fn connect(connection_info: &WebdavConnectionConfig) -> Result<WebdavConnection> {
-        let agent = reqwest_dav::re_exports::reqwest::ClientBuilder::new()
-            .danger_accept_invalid_certs(connection_info.allow_untrusted_cert)
-            .build()?;
+        let mut agent_builder = reqwest_dav::re_exports::reqwest::ClientBuilder::new()
+            .danger_accept_invalid_certs(connection_info.allow_untrusted_cert);
+
+        if let Some(ref proxy) = connection_info.proxy {
+            agent_builder = agent_builder.proxy(proxy.reqwest_proxy()?);
+        }
+
+        let agent = agent_builder.build()?;
 
         info!("Agent is created...");
 
@@ -408,7 +550,12 @@ impl WebdavConnection {
         self.list_dir(&full_dir).await
     }
 
-    async fn read(&self, parent_dir: &str, file_name: &str) -> Result<RemoteReadData> {
+    async fn read(
+        &self,
+        parent_dir: &str,
+        file_name: &str,
+        transfer: TransferContext,
+    ) -> Result<RemoteReadData> {
         // In webdav, this is a relative path. E.g /parent_dir/file_name
         let file_path = [parent_dir, file_name].join("/");
 
@@ -417,13 +564,24 @@ impl WebdavConnection {
             &file_path
         );
 
-        let response = self
+        let mut response = self
             .client
             .get(&file_path)
             .await
             .map_err(|e| convert_error(e))?;
-        // Copies the full file content to memory
-        let contents: Vec<u8> = response.bytes().await?.into();
+
+        // Copies the full file content to memory chunk by chunk so that the progress can be reported
+        let bytes_total = response.content_length();
+        let mut contents: Vec<u8> = Vec::with_capacity(bytes_total.unwrap_or_default() as usize);
+        loop {
+            // Dropping the response on cancel closes the connection
+            transfer.check_cancelled()?;
+            let Some(chunk) = response.chunk().await? else {
+                break;
+            };
+            contents.extend_from_slice(&chunk);
+            transfer.report(contents.len() as u64, bytes_total);
+        }
 
         debug!("Webdav content read and size is {}", contents.len());
 
@@ -481,20 +639,328 @@ impl WebdavConnection {
         self.create_remote_file_metadata(file_path).await
     }
 
-    async fn write_file(&self, file_path: &str, data: Arc<Vec<u8>>) -> Result<RemoteFileMetadata> {
+    async fn write_file(
+        &self,
+        file_path: &str,
+        data: Arc<Vec<u8>>,
+        transfer: TransferContext,
+    ) -> Result<RemoteFileMetadata> {
         // let inner_data = Vec::from(data.as_slice());
         // self.client.put(file_path, inner_data).await?;
 
-        // Need to create a new Vec<u8> data as  &[u8] from data.as_slice() did not
-        // work with error: `data` does not live long enough, `data` dropped here while still borrowed
-        let inner_data = data.to_vec();
-        self.client.put(file_path, inner_data).await?;
+        // Earlier the whole content was sent using 'self.client.put(file_path, data.to_vec())'
+        // Now the body is streamed in chunks so that the progress can be reported and the upload can be cancelled
+        let bytes_total = data.len() as u64;
+        let stream_transfer = transfer.clone();
+        let body_stream = futures_util::stream::unfold(0usize, move |offset| {
+            let data = data.clone();
+            let transfer = stream_transfer.clone();
+            async move {
+                if offset >= data.len() {
+                    return None;
+                }
+                if transfer.is_cancelled() {
+                    // Returning an error aborts the request and the stream ends after this
+                    let e = std::io::Error::new(std::io::ErrorKind::Interrupted, "Cancelled");
+                    return Some((Err(e), data.len()));
+                }
+                let end = std::cmp::min(offset + TRANSFER_CHUNK_SIZE, data.len());
+                transfer.report(end as u64, Some(data.len() as u64));
+                Some((Ok::<_, std::io::Error>(data[offset..end].to_vec()), end))
+            }
+        });
+
+        // Same as in 'reqwest_dav::Client::put' except the body is a stream and the content length is set explicitly
+        // as some servers do not accept the chunked transfer encoding
+        let response = self
+            .client
+            .start_request(Method::PUT, file_path)
+            .await
+            .map_err(|e| convert_error(e))?
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, bytes_total)
+            .body(Body::wrap_stream(body_stream))
+            .send()
+            .await;
+
+        if transfer.is_cancelled() {
+            return Err(cancelled_error());
+        }
+
+        let response = response?;
+        if !response.status().is_success() {
+            return Err(error::Error::RemoteStorageCallError(format!(
+                "Writing to the remote file failed with the status code {}",
+                response.status().as_u16()
+            )));
+        }
 
         let rmd = self.create_remote_file_metadata(file_path).await?;
 
         Ok(rmd)
     }
 
+    // Uses MKCOL
+    async fn create_dir(&self, parent_dir: &str, sub_dir: &str) -> Result<()> {
+        let full_dir = [parent_dir.trim_end_matches("/"), sub_dir].join("/");
+
+        debug!("Webdav going to create dir {} ", &full_dir);
+
+        self.client
+            .mkcol(&full_dir)
+            .await
+            .map_err(|e| convert_error(e))?;
+        Ok(())
+    }
+
+    // Uses MOVE
+    async fn rename(&self, file_path: &str, target_path: &str) -> Result<RemoteFileMetadata> {
+        // MOVE overwrites any existing target and we need to prevent that
+        if self.exists(target_path).await? {
+            return Err(Error::RemoteStorageCallError(format!(
+                "A file with the name {} already exists",
+                target_path
+            )));
+        }
+
+        self.client
+            .mv(file_path, target_path)
+            .await
+            .map_err(|e| convert_error(e))?;
+
+        self.create_remote_file_metadata(target_path).await
+    }
+
+    // Uses DELETE
+    async fn delete_file(&self, file_path: &str) -> Result<()> {
+        self.client
+            .delete(file_path)
+            .await
+            .map_err(|e| convert_error(e))?;
+        Ok(())
+    }
+
+    // Uses COPY and the content is copied on the server side
+    async fn copy(&self, file_path: &str, target_path: &str) -> Result<RemoteFileMetadata> {
+        if self.exists(target_path).await? {
+            return Err(Error::RemoteStorageCallError(format!(
+                "A file with the name {} already exists",
+                target_path
+            )));
+        }
+
+        self.client
+            .cp(file_path, target_path)
+            .await
+            .map_err(|e| convert_error(e))?;
+
+        self.create_remote_file_metadata(target_path).await
+    }
+
+    // The existing file is copied on the server side (and not moved) so that the db file is still available
+    // if the following write fails
+    async fn backup_existing_file(
+        &self,
+        file_path: &str,
+        settings: &ServerBackupSettings,
+    ) -> Result<Option<String>> {
+        let (parent_dir, file_name) = split_file_path(file_path);
+        let backup_dir = settings.backup_dir_path(parent_dir)?;
+
+        if !self.exists(file_path).await? {
+            // A new file and nothing to backup
+            return Ok(None);
+        }
+        if !self.exists(&backup_dir).await? {
+            self.client
+                .mkcol(&backup_dir)
+                .await
+                .map_err(|e| convert_error(e))?;
+        }
+
+        let backup_file_path = join_path(
+            &backup_dir,
+            &backup_file_name(file_name, now_utc_seconds()),
+        );
+        self.copy(file_path, &backup_file_path).await?;
+
+        // Any failure in removing the old backups should not fail the save
+        match self.list_dir(&backup_dir).await {
+            Ok(entries) => {
+                for f in backups_to_prune(file_name, &entries.files, settings.keep_count) {
+                    let r = self.delete_file(&join_path(&backup_dir, &f)).await;
+                    debug!("Removing the old server backup {} result {:?}", &f, r);
+                }
+            }
+            Err(e) => log::error!(
+                "Listing the server backup dir {} to prune failed {}",
+                &backup_dir,
+                e
+            ),
+        }
+
+        Ok(Some(backup_file_path))
+    }
+
+    // First tries a single PROPFIND with 'Depth: infinity'. Many servers disable that and then
+    // the dirs are walked level by level using 'Depth: 1'
+    async fn find_kdbx_files(
+        &self,
+        start_dir: &str,
+        limits: FindLimits,
+        connection_id: Uuid,
+    ) -> Result<FoundKdbxFiles> {
+        let mut collector = KdbxFilesCollector::new(limits);
+        let start_parts = path_parts(start_dir);
+
+        match self.client.list(start_dir, Depth::Infinity).await {
+            Ok(entities) => {
+                debug!("PROPFIND with Depth infinity returned {} entries", entities.len());
+                for e in entities {
+                    let parts = self.relative_path_parts(&e);
+                    // Only the entries under the start dir are considered. The entry of the start dir itself is excluded
+                    if parts.len() <= start_parts.len() || !parts.starts_with(&start_parts) {
+                        continue;
+                    }
+                    // Same as in the dir walk, the entries in or under a filtered out dir are skipped
+                    if !parts[start_parts.len()..].iter().all(|p| filter_entry(p)) {
+                        continue;
+                    }
+                    // The depth of the dir where this entry is found
+                    let depth = parts.len() - start_parts.len() - 1;
+                    if depth > limits.max_depth {
+                        continue;
+                    }
+                    if !collector.count_entry() {
+                        break;
+                    }
+                    match e {
+                        ListEntity::File(f) => {
+                            collector.add_if_kdbx(
+                                to_file_path(&parts),
+                                parts.last().map_or("", |s| s.as_str()),
+                                Some(f.content_length as u64),
+                                Some(f.last_modified.timestamp() as u64),
+                            );
+                        }
+                        ListEntity::Folder(_) => {
+                            // Marks the result as truncated if this folder is at the max depth
+                            collector.can_descend(depth);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                debug!(
+                    "PROPFIND with Depth infinity failed with error {:?} and will walk the dirs",
+                    e
+                );
+                self.walk_kdbx_files(start_dir, &mut collector).await;
+            }
+        }
+
+        Ok(collector.into_found_files(connection_id, start_dir))
+    }
+
+    async fn walk_kdbx_files(&self, start_dir: &str, collector: &mut KdbxFilesCollector) {
+        let mut current_level = vec![start_dir.to_string()];
+        let mut depth = 0;
+
+        while !current_level.is_empty() && !collector.limit_reached() {
+            let listings = futures_util::stream::iter(current_level)
+                .map(|dir| async move {
+                    let r = self.client.list(&dir, Depth::Number(1)).await;
+                    (dir, r)
+                })
+                .buffer_unordered(CONCURRENT_DIR_LISTINGS)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut next_level = vec![];
+            'listings: for (dir, listing) in listings {
+                // A dir that can not be read is skipped and the search continues
+                let entities = match listing {
+                    Ok(entities) => entities,
+                    Err(e) => {
+                        debug!("Listing the dir {} failed with error {:?}", &dir, e);
+                        continue;
+                    }
+                };
+
+                let dir_parts = path_parts(&dir);
+                for e in entities {
+                    let parts = self.relative_path_parts(&e);
+                    // Excludes the entry for the listed dir itself
+                    if parts == dir_parts {
+                        continue;
+                    }
+                    let Some(name) = parts.last() else {
+                        continue;
+                    };
+                    if !filter_entry(name) {
+                        continue;
+                    }
+                    if !collector.count_entry() {
+                        break 'listings;
+                    }
+                    match e {
+                        ListEntity::File(f) => {
+                            collector.add_if_kdbx(
+                                join_path(&dir, name),
+                                name,
+                                Some(f.content_length as u64),
+                                Some(f.last_modified.timestamp() as u64),
+                            );
+                        }
+                        ListEntity::Folder(_) => {
+                            if collector.can_descend(depth) {
+                                next_level.push(join_path(&dir, name));
+                            }
+                        }
+                    }
+                }
+            }
+
+            current_level = next_level;
+            depth += 1;
+        }
+    }
+
+    // The href of an entry returned by the server includes the path of the root url
+    // (e.g '/dav/db1/Test.kdbx' for the root url 'https://host/dav') and some servers return the full url.
+    // Here we remove the root url path part so that the parts are relative to the root url as used in all
+    // other calls
+    fn relative_path_parts(&self, entity: &ListEntity) -> Vec<String> {
+        let href = match entity {
+            ListEntity::File(f) => &f.href,
+            ListEntity::Folder(f) => &f.href,
+        };
+
+        let href_path = url::Url::parse(href).map_or_else(|_| href.clone(), |u| u.path().to_string());
+        let parts = path_parts(&href_path);
+
+        let root_parts = url::Url::parse(&self.client.host)
+            .map_or_else(|_| vec![], |u| path_parts(u.path()));
+
+        if !root_parts.is_empty() && parts.starts_with(&root_parts) {
+            parts[root_parts.len()..].to_vec()
+        } else {
+            parts
+        }
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        match self.client.list(path, Depth::Number(0)).await {
+            Ok(_) => Ok(true),
+            Err(reqwest_dav::Error::Decode(reqwest_dav::DecodeError::StatusMismatched(e)))
+                if e.response_code == 404 =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(convert_error(e)),
+        }
+    }
+
     async fn create_remote_file_metadata(&self, file_path: &str) -> Result<RemoteFileMetadata> {
         // Need to use Depth::Number(0) to get the file info as Depth of "0" applies only to the resource
         let (size, modified) = if let Some(list_entity) =
@@ -537,6 +1003,151 @@ impl WebdavConnection {
         Ok(rmd)
     }
 
+    // Runs each connection stage separately so that the user can see where the connection fails
+    // The connection made here is not stored
+    async fn diagnose(connection_info: WebdavConnectionConfig) -> Result<DiagnosticReport> {
+        let mut report = DiagnosticReport::default();
+
+        let url = url::Url::parse(&connection_info.root_url).map_err(|e| {
+            Error::RemoteStorageCallError(format!("Invalid root url {}", e))
+        })?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::DataError("The root url does not have a valid host"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        // When a proxy is used, only the proxy host is resolved locally and the tcp stage
+        // checks the tunnel to the webdav server through the proxy
+        let proxy = connection_info.proxy.as_ref();
+        let (dns_host, dns_port) = proxy.map_or((host, port), |p| (p.host.as_str(), p.port));
+
+        let addrs = report
+            .run(
+                DiagnosticStageType::DnsResolution,
+                resolve_host(dns_host, dns_port),
+            )
+            .await
+            .unwrap_or_default();
+
+        // The stream is dropped as the following calls make their own connections
+        let _stream = match proxy {
+            Some(p) => {
+                report
+                    .run(DiagnosticStageType::TcpConnect, async {
+                        let s = p.connect_tcp(host, port).await?;
+                        let details = format!(
+                            "Connected to {}:{} through the proxy {}:{}",
+                            host, port, &p.host, p.port
+                        );
+                        Ok((s, Some(details)))
+                    })
+                    .await
+            }
+            None => {
+                report
+                    .run(DiagnosticStageType::TcpConnect, tcp_connect(&addrs))
+                    .await
+            }
+        };
+
+        // TLS stage is applicable only for https urls
+        if url.scheme() == "https" {
+            let root_url = connection_info.root_url.as_str();
+            let mut certificate = None;
+            report
+                .run(DiagnosticStageType::TlsHandshake, async {
+                    // We always verify the certificate first so that we can tell the user whether the
+                    // certificate is trusted or not even when untrusted certificates are allowed
+                    match tls_probe(root_url, false, proxy).await {
+                        Ok(cert) => {
+                            certificate = cert;
+                            Ok(((), Some("The server certificate is trusted".to_string())))
+                        }
+                        Err(e) => {
+                            // Connects again without the verification only to get the certificate details
+                            certificate = tls_probe(root_url, true, proxy).await.ok().flatten();
+                            if connection_info.allow_untrusted_cert && certificate.is_some() {
+                                Ok((
+                                    (),
+                                    Some("The server certificate is not trusted but allowed as per the connection setting".to_string()),
+                                ))
+                            } else {
+                                Err(e)
+                            }
+                        }
+                    }
+                })
+                .await;
+            report.set_certificate(certificate);
+        }
+
+        let webdav_connection = report
+            .run(DiagnosticStageType::Authentication, async {
+                let c = Self::connect(&connection_info).await?;
+                Ok((c, Some(format!("Authenticated as {}", &connection_info.user_name))))
+            })
+            .await;
+
+        let Some(webdav_connection) = webdav_connection else {
+            report.skip::<()>(DiagnosticStageType::ListStartDir);
+            report.skip::<()>(DiagnosticStageType::WritePermission);
+            return Ok(report);
+        };
+
+        let start_dir = connection_info
+            .start_dir
+            .clone()
+            .map_or_else(|| WEBDAV_ROOT_DIR.to_string(), |s| s);
+
+        report
+            .run(DiagnosticStageType::ListStartDir, async {
+                let dirs = webdav_connection.list_dir(&start_dir).await?;
+                let details = format!(
+                    "Found {} folders and {} files in {}",
+                    dirs.sub_dirs.len(),
+                    dirs.files.len(),
+                    &start_dir
+                );
+                Ok(((), Some(details)))
+            })
+            .await;
+
+        report
+            .run(DiagnosticStageType::WritePermission, async {
+                let probe_file = format!(
+                    "{}/{}{}",
+                    start_dir.trim_end_matches("/"),
+                    WRITE_PROBE_FILE_PREFIX,
+                    Uuid::new_v4()
+                );
+                webdav_connection
+                    .client
+                    .put(&probe_file, Vec::<u8>::new())
+                    .await
+                    .map_err(convert_error)?;
+                webdav_connection
+                    .client
+                    .delete(&probe_file)
+                    .await
+                    .map_err(convert_error)?;
+                Ok(((), Some(format!("Created and removed a file in {}", &start_dir))))
+            })
+            .await;
+
+        Ok(report)
+    }
+
+    async fn send_diagnose(
+        tx: oneshot::Sender<Result<DiagnosticReport>>,
+        connection_info: WebdavConnectionConfig,
+    ) {
+        let report = WebdavConnection::diagnose(connection_info).await;
+        let r = tx.send(report);
+        if let Err(_) = r {
+            log::error!("In send_diagnose send channel failed ");
+        }
+    }
+
     async fn send_connect_and_retrieve_root_dir(
         tx: oneshot::Sender<Result<ConnectStatus>>,
         connection_info: WebdavConnectionConfig,
@@ -575,11 +1186,77 @@ impl WebdavConnection {
 
     reply_by_webdav_async_fn!(send_list_sub_dir(parent_dir:String,sub_dir:String), list_sub_dir (&parent_dir,&sub_dir),ServerDirEntry);
 
-    reply_by_webdav_async_fn!(send_read(parent_dir:String,file_name:String),read(&parent_dir,&file_name),RemoteReadData);
+    reply_by_webdav_async_fn!(send_read(parent_dir:String,file_name:String,transfer:TransferContext),read(&parent_dir,&file_name,transfer),RemoteReadData);
 
-    reply_by_webdav_async_fn!(send_write_file(file_path:String,data:Arc<Vec<u8>>), write_file(&file_path, data), RemoteFileMetadata);
+    reply_by_webdav_async_fn!(send_write_file(file_path:String,data:Arc<Vec<u8>>,transfer:TransferContext), write_file(&file_path, data, transfer), RemoteFileMetadata);
 
     reply_by_webdav_async_fn!(send_file_metadta(file_path:String), file_metadata(&file_path), RemoteFileMetadata);
+
+    reply_by_webdav_async_fn!(send_file_exists(file_path:String), exists(&file_path), bool);
+
+    reply_by_webdav_async_fn!(send_create_dir(parent_dir:String,sub_dir:String), create_dir(&parent_dir, &sub_dir), ());
+
+    reply_by_webdav_async_fn!(send_rename(file_path:String,target_path:String), rename(&file_path, &target_path), RemoteFileMetadata);
+
+    reply_by_webdav_async_fn!(send_delete_file(file_path:String), delete_file(&file_path), ());
+
+    reply_by_webdav_async_fn!(send_copy(file_path:String,target_path:String), copy(&file_path, &target_path), RemoteFileMetadata);
+
+    reply_by_webdav_async_fn!(send_backup_existing_file(file_path:String,settings:ServerBackupSettings), backup_existing_file(&file_path, &settings), Option<String>);
+
+    reply_by_webdav_async_fn!(send_find_kdbx_files(start_dir:String,limits:FindLimits,connection_id:Uuid), find_kdbx_files(&start_dir, limits, connection_id), FoundKdbxFiles);
+}
+
+// Splits the path to its decoded parts leaving out any empty part
+// e.g "/dav/My%20Dbs/" -> ["dav", "My Dbs"]
+fn path_parts(path: &str) -> Vec<String> {
+    path.split("/")
+        .filter(|s| !s.is_empty() && *s != ".")
+        .map(|s| urlencoding::decode(s).map_or_else(|_| s.to_string(), |d| d.into_owned()))
+        .collect()
+}
+
+#[inline]
+fn to_file_path(parts: &[String]) -> String {
+    format!("/{}", parts.join("/"))
+}
+
+// Makes a request to the root url and returns the server certificate details on successful TLS handshake
+async fn tls_probe(
+    root_url: &str,
+    accept_invalid_certs: bool,
+    proxy: Option<&ProxyConfig>,
+) -> Result<Option<CertificateDetails>> {
+    let mut agent_builder = reqwest_dav::re_exports::reqwest::ClientBuilder::new()
+        .danger_accept_invalid_certs(accept_invalid_certs)
+        .tls_info(true);
+
+    if let Some(p) = proxy {
+        agent_builder = agent_builder.proxy(p.reqwest_proxy()?);
+    }
+
+    let agent = agent_builder.build()?;
+
+    // Any response status is fine here as we are checking only the TLS handshake
+    let response = agent.head(root_url).send().await.map_err(|e| {
+        // The top level reqwest error message does not say why the handshake failed
+        // and the actual reason (e.g invalid peer certificate) is in the source chain
+        let mut msg = format!("{}", e);
+        let mut source = std::error::Error::source(&e);
+        while let Some(s) = source {
+            msg = format!("{}: {}", msg, s);
+            source = s.source();
+        }
+        error::Error::RemoteStorageCallError(msg)
+    })?;
+
+    let cert = response
+        .extensions()
+        .get::<reqwest_dav::re_exports::reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .and_then(|der| parse_certificate(der, !accept_invalid_certs));
+
+    Ok(cert)
 }
 
 // For now this custom error messaging is done for reqwest_dav::types::Error
@@ -0,0 +1,446 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use onekeepass_core::db_service;
+
+use crate::{
+    app_lock,
+    app_preference::PREFERENCE_JSON_FILE_NAME,
+    app_state::AppState,
+    backup, biometric_auth,
+    commands::CommandArg,
+    parse_command_args_or_err,
+    util::{self, remove_dir_contents, remove_files},
+    OkpError, OkpResult,
+};
+
+// A granular version of 'app_lock::app_reset' where only the selected categories are cleared.
+// The db specific categories can be limited to a few dbs. In the dry run mode, nothing is removed and
+// the report lists the files and key store entries that would be removed
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ResetCategory {
+    // db specific
+    Backups,
+    BiometricCredentials,
+    // iOS only
+    Bookmarks,
+    // iOS only
+    AutofillCopies,
+    // The recent db info and the db preference when limited to dbs. Otherwise the full preference
+    // except the app lock settings which are reset only when 'AppLock' is also selected
+    Preference,
+
+    // Not db specific
+    ExportData,
+    KeyFiles,
+    SftpKeys,
+    RemoteConnections,
+    AppLock,
+}
+
+impl ResetCategory {
+    fn db_specific(&self) -> bool {
+        matches!(
+            self,
+            Self::Backups
+                | Self::BiometricCredentials
+                | Self::Bookmarks
+                | Self::AutofillCopies
+                | Self::Preference
+        )
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) enum ResetEntryKind {
+    File,
+    KeyStoreEntry,
+    // The recent db info and the db preference of a db found in the preference file
+    PreferenceEntry,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ResetEntry {
+    category: ResetCategory,
+    kind: ResetEntryKind,
+    // Full file path, key store entry name or the db_key
+    name: String,
+    // In bytes
+    size: u64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct ResetReport {
+    dry_run: bool,
+    entries: Vec<ResetEntry>,
+    total_size: u64,
+    // The categories that are not db specific are skipped when the reset is limited to dbs
+    skipped_categories: Vec<ResetCategory>,
+}
+
+impl ResetReport {
+    fn add(&mut self, category: ResetCategory, kind: ResetEntryKind, name: String, size: u64) {
+        self.total_size += size;
+        self.entries.push(ResetEntry {
+            category,
+            kind,
+            name,
+            size,
+        });
+    }
+
+    fn add_files(&mut self, category: ResetCategory, path: &Path) {
+        let mut files = vec![];
+        collect_files(path, &mut files);
+        for (p, size) in files {
+            self.add(
+                category,
+                ResetEntryKind::File,
+                p.to_string_lossy().to_string(),
+                size,
+            );
+        }
+    }
+}
+
+pub(crate) fn selective_app_reset(json_args: &str) -> OkpResult<ResetReport> {
+    let (selected, db_keys, dry_run) = parse_command_args_or_err!(
+        json_args,
+        SelectiveResetArg {
+            categories,
+            db_keys,
+            dry_run
+        }
+    );
+
+    let mut categories: Vec<ResetCategory> = vec![];
+    for c in selected {
+        if !categories.contains(&c) {
+            categories.push(c);
+        }
+    }
+
+    if categories.is_empty() {
+        return Err(OkpError::DataError(
+            "At least one category should be selected to reset",
+        ));
+    }
+
+    // The preference is cleared last as the other categories may need the recent db info
+    categories.sort_by_key(|c| *c == ResetCategory::Preference);
+
+    // The app lock settings in the preference are reset only along with the PIN entries
+    let app_lock_reset = categories.contains(&ResetCategory::AppLock);
+
+    // In the duress mode, the hidden dbs are neither listed nor touched. The db specific categories are
+    // then limited to the dbs seen by the user
+    let limited = db_keys.is_some();
+    let db_keys = if AppState::duress_active() {
+        let visible = AppState::preference_view().known_db_keys();
+        Some(match db_keys {
+            Some(keys) => keys.into_iter().filter(|k| visible.contains(k)).collect(),
+            None => visible,
+        })
+    } else {
+        db_keys
+    };
+
+    let mut report = ResetReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    for category in categories {
+        if limited && !category.db_specific() {
+            report.skipped_categories.push(category);
+            continue;
+        }
+
+        // db_keys of all known dbs are used when the reset is not limited to dbs
+        let dbs = db_keys
+            .clone()
+            .unwrap_or_else(|| AppState::preference_view().known_db_keys());
+
+        add_to_report(&mut report, category, db_keys.as_deref(), &dbs);
+
+        if !dry_run {
+            reset_category(category, db_keys.as_deref(), &dbs, app_lock_reset);
+        }
+    }
+
+    info!(
+        "Selective reset dry run {} with {} entries of total size {}",
+        dry_run,
+        report.entries.len(),
+        report.total_size
+    );
+
+    Ok(report)
+}
+
+fn add_to_report(
+    report: &mut ResetReport,
+    category: ResetCategory,
+    limited_to: Option<&[String]>,
+    dbs: &[String],
+) {
+    match category {
+        ResetCategory::Backups => {
+            if limited_to.is_some() {
+                for db_key in dbs {
+                    report.add_files(category, &backup::backup_history_root_path(db_key));
+                }
+            } else {
+                report.add_files(category, AppState::backup_history_dir_path());
+            }
+        }
+
+        ResetCategory::BiometricCredentials => {
+            for db_key in dbs {
+                if let Some((ks_key, size)) =
+                    biometric_auth::StoredCredential::key_store_entry(db_key)
+                {
+                    report.add(category, ResetEntryKind::KeyStoreEntry, ks_key, size as u64);
+                }
+            }
+        }
+
+        ResetCategory::Bookmarks => {
+            #[cfg(target_os = "ios")]
+            {
+                if limited_to.is_some() {
+                    for db_key in dbs {
+                        report.add_files(category, &crate::ios::form_bookmark_file_path(db_key));
+                    }
+                } else {
+                    report.add_files(category, &crate::ios::bookmark::bookmark_dir());
+                }
+            }
+        }
+
+        ResetCategory::AutofillCopies => {
+            #[cfg(target_os = "ios")]
+            {
+                let copied = limited_to
+                    .map(|d| d.to_vec())
+                    .unwrap_or_else(crate::ios::autofill_app_group::copied_autofill_db_keys);
+                for db_key in copied {
+                    if let Some(p) = crate::ios::autofill_app_group::copied_autofill_dir(&db_key) {
+                        report.add_files(category, &p);
+                    }
+                }
+            }
+        }
+
+        ResetCategory::Preference => {
+            if limited_to.is_some() {
+                let known = AppState::preference_view().known_db_keys();
+                for db_key in dbs.iter().filter(|k| known.contains(k)) {
+                    report.add(
+                        category,
+                        ResetEntryKind::PreferenceEntry,
+                        db_key.clone(),
+                        0,
+                    );
+                }
+            } else {
+                report.add_files(
+                    category,
+                    &AppState::preference_home_dir().join(PREFERENCE_JSON_FILE_NAME),
+                );
+            }
+        }
+
+        ResetCategory::ExportData => {
+            report.add_files(category, AppState::export_data_dir_path());
+        }
+
+        ResetCategory::KeyFiles => {
+            report.add_files(category, AppState::key_files_dir_path());
+        }
+
+        ResetCategory::SftpKeys => {
+            report.add_files(category, &AppState::sftp_private_keys_path());
+        }
+
+        ResetCategory::RemoteConnections => {
+            // Only the files directly under remote_storage. The sftp sub dir is in 'SftpKeys'
+            for (p, size) in dir_files(AppState::remote_storage_path()) {
+                report.add(
+                    category,
+                    ResetEntryKind::File,
+                    p.to_string_lossy().to_string(),
+                    size,
+                );
+            }
+        }
+
+        ResetCategory::AppLock => {
+            for (tag, size) in app_lock::key_store_entries() {
+                report.add(category, ResetEntryKind::KeyStoreEntry, tag, size as u64);
+            }
+        }
+    }
+}
+
+fn reset_category(
+    category: ResetCategory,
+    limited_to: Option<&[String]>,
+    dbs: &[String],
+    app_lock_reset: bool,
+) {
+    match category {
+        ResetCategory::Backups => {
+            if limited_to.is_some() {
+                for db_key in dbs {
+                    backup::delete_backup_history_dir(db_key);
+                }
+            } else {
+                let _ = remove_dir_contents(AppState::backup_history_dir_path());
+            }
+        }
+
+        ResetCategory::BiometricCredentials => {
+            for db_key in dbs {
+                let _ = biometric_auth::StoredCredential::remove_credentials(db_key);
+            }
+        }
+
+        ResetCategory::Bookmarks => {
+            #[cfg(target_os = "ios")]
+            {
+                if limited_to.is_some() {
+                    for db_key in dbs {
+                        crate::ios::delete_book_mark_data(db_key);
+                    }
+                } else {
+                    let _ = remove_dir_contents(crate::ios::bookmark::bookmark_dir());
+                }
+            }
+        }
+
+        ResetCategory::AutofillCopies => {
+            #[cfg(target_os = "ios")]
+            {
+                let copied = limited_to
+                    .map(|d| d.to_vec())
+                    .unwrap_or_else(crate::ios::autofill_app_group::copied_autofill_db_keys);
+                for db_key in copied {
+                    let _ = crate::ios::autofill_app_group::delete_copied_autofill_details(&db_key);
+                }
+            }
+        }
+
+        ResetCategory::Preference => {
+            // The dbs removed from the recent list are closed as in 'remove_from_recently_used'
+            for db_key in dbs {
+                let _ = db_service::close_kdbx(db_key);
+            }
+            if limited_to.is_some() {
+                for db_key in dbs {
+                    AppState::remove_recent_db_use_info(db_key, true);
+                }
+            } else if app_lock_reset {
+                AppState::reset_preference();
+            } else {
+                AppState::reset_preference_keeping_app_lock();
+            }
+        }
+
+        ResetCategory::ExportData => {
+            let _ = util::clean_export_data_dir();
+        }
+
+        ResetCategory::KeyFiles => {
+            let _ = remove_dir_contents(AppState::key_files_dir_path());
+        }
+
+        ResetCategory::SftpKeys => {
+            let _ = remove_dir_contents(AppState::sftp_private_keys_path());
+        }
+
+        ResetCategory::RemoteConnections => {
+            let _ = remove_files(AppState::remote_storage_path());
+        }
+
+        ResetCategory::AppLock => {
+            let _ = app_lock::pin_removed();
+        }
+    }
+
+    debug!("Reset of the category {:?} is done", category);
+}
+
+// Collects all files found under this path (file or dir) along with their sizes
+fn collect_files(path: &Path, files: &mut Vec<(PathBuf, u64)>) {
+    let Ok(meta) = fs::metadata(path) else {
+        return;
+    };
+    if meta.is_file() {
+        files.push((path.to_path_buf(), meta.len()));
+        return;
+    }
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten() {
+            collect_files(&entry.path(), files);
+        }
+    }
+}
+
+// Only the files directly under this dir
+fn dir_files(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            meta.is_file().then(|| (e.path(), meta.len()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_collect_files() {
+        let dir = std::env::temp_dir().join("okp_selective_reset_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), b"12345").unwrap();
+        fs::write(dir.join("sub").join("b.txt"), b"123").unwrap();
+
+        let mut files = vec![];
+        collect_files(&dir, &mut files);
+        files.sort();
+        assert_eq!(2, files.len());
+        assert_eq!(5 + 3, files.iter().map(|(_, s)| s).sum::<u64>());
+
+        // Sub dirs are not included
+        assert_eq!(vec![(dir.join("a.txt"), 5)], dir_files(&dir));
+
+        // Missing path
+        let mut files = vec![];
+        collect_files(&dir.join("missing"), &mut files);
+        assert!(files.is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_db_specific_categories() {
+        assert!(ResetCategory::Backups.db_specific());
+        assert!(ResetCategory::Preference.db_specific());
+        assert!(!ResetCategory::KeyFiles.db_specific());
+        assert!(!ResetCategory::AppLock.db_specific());
+    }
+}
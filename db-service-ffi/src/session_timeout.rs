@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use log::{debug, error, info};
+use serde::Serialize;
+
+use onekeepass_core::{
+    async_service,
+    db_service::{self, service_util},
+};
+
+use crate::{
+    app_preference::SessionTimeoutAction, app_state::AppState, commands::ok_json_str,
+    remote_storage::{self, RemoteStorageOperation, RemoteStorageOperationType},
+};
+
+// The session timeout of the opened dbs is enforced here instead of relying only on the UI side timers
+// Each command call with a db_key updates the last activity of that db (see check_and_touch_from_args) and
+// a periodic check on the async runtime locks or closes the dbs whose timeout elapsed.
+// A locked db stays in memory but all commands on it are rejected till it is unlocked with its credentials
+
+const CHECK_INTERVAL_SECS: u64 = 5;
+
+#[derive(Serialize, Debug)]
+struct DbSessionTimedOut {
+    db_key: String,
+    action: SessionTimeoutAction,
+}
+
+pub(crate) fn init_session_timeout_checker() {
+    async_service::async_runtime().spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(CHECK_INTERVAL_SECS)).await;
+            check_db_sessions();
+        }
+    });
+    info!("Session timeout checker is started");
+}
+
+// The error returned for any command on a db whose session is timed out and locked
+pub(crate) const DB_SESSION_LOCKED: &str = "DbSessionLocked";
+
+// The commands that can be called on a locked db
+const COMMANDS_ALLOWED_WHEN_LOCKED: [&str; 2] = ["close_kdbx", "unlock_kdbx_on_biometric_authentication"];
+
+// The args fields that have the db_key of an opened db
+const DB_KEY_FIELDS: [&str; 4] = ["db_key", "org_db_key", "source_db_key", "target_db_key"];
+
+// Called from all the command dispatchers (Commands::invoke and the platform specific services) with the command's args
+// Returns an error if any db used in the command is locked on its session timeout. Otherwise the command call
+// extends the db session
+pub(crate) fn check_and_touch_from_args(command_name: &str, args: &str) -> Result<(), &'static str> {
+    let db_keys = db_keys_in_args(args);
+    check_db_keys(command_name, &db_keys, AppState::db_session_locked)?;
+    for db_key in &db_keys {
+        AppState::touch_db_activity(db_key);
+    }
+    Ok(())
+}
+
+// Used by the api calls that are not dispatched by a command name (e.g save_kdbx in udl_functions)
+pub(crate) fn check_and_touch_db_key(db_key: &str) -> Result<(), &'static str> {
+    if AppState::db_session_locked(db_key) {
+        debug!("Api call is rejected as the db {} is locked", db_key);
+        return Err(DB_SESSION_LOCKED);
+    }
+    AppState::touch_db_activity(db_key);
+    Ok(())
+}
+
+fn check_db_keys(
+    command_name: &str,
+    db_keys: &[String],
+    locked: impl Fn(&str) -> bool,
+) -> Result<(), &'static str> {
+    if COMMANDS_ALLOWED_WHEN_LOCKED.contains(&command_name) {
+        return Ok(());
+    }
+    if let Some(db_key) = db_keys.iter().find(|k| locked(k)) {
+        debug!("Command {} is rejected as the db {} is locked", command_name, db_key);
+        return Err(DB_SESSION_LOCKED);
+    }
+    Ok(())
+}
+
+// Finds the db_keys used in the command args. The remote storage commands have the file paths in 'rs_operation_type'
+// and these are formed as db_keys so that an opened remote db is not renamed, copied or deleted while it is locked
+fn db_keys_in_args(args: &str) -> Vec<String> {
+    if !DB_KEY_FIELDS.iter().any(|f| args.contains(f)) && !args.contains("\"rs_operation_type\"") {
+        return vec![];
+    }
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(args) else {
+        return vec![];
+    };
+
+    let mut db_keys: Vec<String> = DB_KEY_FIELDS
+        .iter()
+        .filter_map(|f| value.get(f).and_then(|v| v.as_str()))
+        .map(|v| v.to_string())
+        .collect();
+
+    if let Some(rs_operation_type) = value
+        .get("rs_operation_type")
+        .and_then(|v| serde_json::from_value::<RemoteStorageOperationType>(v.clone()).ok())
+    {
+        db_keys.extend(
+            [rs_operation_type.file_path(), rs_operation_type.target_path()]
+                .into_iter()
+                .flatten()
+                .filter_map(|p| rs_operation_type.db_key_for(p)),
+        );
+    }
+
+    db_keys
+}
+
+fn check_db_sessions() {
+    let opened_db_keys = match db_service::all_kdbx_cache_keys() {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!("Getting the opened dbs failed {}", e);
+            return;
+        }
+    };
+
+    AppState::retain_db_activities(&opened_db_keys);
+    remote_storage::retain_entries_snapshots(&opened_db_keys);
+
+    let now = service_util::now_utc_milli_seconds();
+    let action = AppState::session_timeout_action();
+
+    for db_key in opened_db_keys {
+        let Some(activity) = AppState::db_activity(&db_key) else {
+            // The db opened without any command call (e.g read_kdbx) and the session starts now
+            AppState::touch_db_activity(&db_key);
+            continue;
+        };
+
+        let timeout = AppState::resolved_db_settings(&db_key).db_session_timeout;
+
+        // Any value <= 0 means no timeout
+        if activity.timed_out || !is_timed_out(activity.last_activity, timeout, now) {
+            continue;
+        }
+
+        debug!("Session of db {} timed out and the action is {:?}", &db_key, &action);
+
+        if action == SessionTimeoutAction::Close {
+            if let Err(e) = db_service::close_kdbx(&db_key) {
+                error!("Closing the timed out db failed {}", e);
+            }
+            AppState::remove_db_activity(&db_key);
+        } else {
+            AppState::set_db_session_timed_out(&db_key);
+        }
+        remote_storage::remove_entries_snapshot(&db_key);
+
+        let timed_out = DbSessionTimedOut { db_key, action };
+        let _r = AppState::event_dispatcher().send_db_session_timeout(ok_json_str(timed_out));
+    }
+}
+
+// Called when the app goes to background
+// The opened dbs that are set to lock on background (see the db specific settings) are locked right away
+// and the UI is informed through the same session timeout event
+pub(crate) fn lock_dbs_on_app_background() {
+    let opened_db_keys = match db_service::all_kdbx_cache_keys() {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!("Getting the opened dbs failed {}", e);
+            return;
+        }
+    };
+
+    for db_key in opened_db_keys {
+        if !AppState::resolved_db_settings(&db_key).lock_on_background || AppState::db_session_locked(&db_key) {
+            continue;
+        }
+
+        debug!("Db {} is locked as the app goes to background", &db_key);
+
+        AppState::set_db_session_timed_out(&db_key);
+        remote_storage::remove_entries_snapshot(&db_key);
+
+        let timed_out = DbSessionTimedOut {
+            db_key,
+            action: SessionTimeoutAction::Lock,
+        };
+        let _r = AppState::event_dispatcher().send_db_session_timeout(ok_json_str(timed_out));
+    }
+}
+
+fn is_timed_out(last_activity: i64, timeout: i64, now: i64) -> bool {
+    timeout > 0 && now - last_activity >= timeout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_is_timed_out() {
+        assert!(is_timed_out(1_000, 120_000, 121_000));
+        assert!(!is_timed_out(1_000, 120_000, 120_999));
+        // No timeout
+        assert!(!is_timed_out(1_000, -1, 10_000_000));
+        assert!(!is_timed_out(1_000, 0, 10_000_000));
+    }
+
+    #[test]
+    fn verify_db_keys_in_args() {
+        assert_eq!(vec!["db1".to_string()], db_keys_in_args(r#"{"db_key":"db1"}"#));
+        assert_eq!(
+            vec!["db1".to_string()],
+            db_keys_in_args(r#"{"org_db_key":"db1","rp_id":"example.com"}"#)
+        );
+        assert_eq!(
+            vec!["db2".to_string(), "db1".to_string()],
+            db_keys_in_args(r#"{"target_db_key":"db1","source_db_key":"db2"}"#)
+        );
+
+        let args = r#"{"rs_operation_type":{"type":"Sftp","connection_id":"264226dc-be96-462a-a386-79adb6291ad7","file_path":"/dav/Test1.kdbx","target_path":"/dav/Test2.kdbx"}}"#;
+        assert_eq!(
+            vec![
+                "Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/Test1.kdbx".to_string(),
+                "Sftp-264226dc-be96-462a-a386-79adb6291ad7-/dav/Test2.kdbx".to_string()
+            ],
+            db_keys_in_args(args)
+        );
+
+        assert!(db_keys_in_args(r#"{"timer_id":"t1"}"#).is_empty());
+        assert!(db_keys_in_args("").is_empty());
+    }
+
+    #[test]
+    fn verify_locked_db_commands_rejected() {
+        let locked = |db_key: &str| db_key == "locked_db";
+        let db_keys = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+
+        assert!(check_db_keys("entry_summary_data", &db_keys(&["open_db"]), locked).is_ok());
+        assert_eq!(
+            Err(DB_SESSION_LOCKED),
+            check_db_keys("entry_summary_data", &db_keys(&["locked_db"]), locked)
+        );
+        // Android autofill and passkey calls
+        assert_eq!(
+            Err(DB_SESSION_LOCKED),
+            check_db_keys("autofill_filtered_entries", &db_keys(&["locked_db"]), locked)
+        );
+        // Any locked db in the args rejects the command
+        assert_eq!(
+            Err(DB_SESSION_LOCKED),
+            check_db_keys("merge_databases", &db_keys(&["open_db", "locked_db"]), locked)
+        );
+
+        for command_name in COMMANDS_ALLOWED_WHEN_LOCKED {
+            assert!(check_db_keys(command_name, &db_keys(&["locked_db"]), locked).is_ok());
+        }
+    }
+}
@@ -6,12 +6,14 @@ use onekeepass_core::{
 //use reqwest_dav::re_exports::serde_xml_rs::de;
 
 use crate::{
-    backup::{self, matching_backup_exists},
+    backup::{self, matching_backup_exists, BackupReason},
     biometric_auth,
     commands::{self, full_path_file_to_create, CommandArg, Commands, ResponseJson},
+    db_backup_read::KdbxLoadedEx,
+    db_mirror,
     event_dispatcher,
     file_util::{KeyFileInfo, OpenedFile},
-    open_backup_file,
+    key_file, open_backup_file,
     udl_types::EventDispatch,
     InvokeResult,
 };
@@ -127,12 +129,13 @@ pub(crate) fn read_kdbx(file_args: FileArgs, json_args: String) -> ApiResponse {
     as_api_response(internal_read_kdbx(&mut file, &json_args))
 }
 
-fn internal_read_kdbx(file: &mut File, json_args: &str) -> OkpResult<db_service::KdbxLoaded> {
+fn internal_read_kdbx(file: &mut File, json_args: &str) -> OkpResult<KdbxLoadedEx> {
     let CommandArg::OpenDbArg {
         db_file_name,
         password,
         key_file_name,
         biometric_auth_used,
+        ..
     } = serde_json::from_str(json_args)?
     else {
         return Err(OkpError::UnexpectedError(format!(
@@ -163,6 +166,7 @@ fn internal_read_kdbx(file: &mut File, json_args: &str) -> OkpResult<db_service:
         // Need this when db login fails while using the previously stored credentials
         // and the UI will then popup the usual dialog
         error::Error::HeaderHmacHashCheckFailed if biometric_auth_used => {
+            biometric_auth::StoredCredential::remove_stale_credentials(&db_file_name);
             error::Error::BiometricCredentialsAuthenticationFailed
         }
         _ => e,
@@ -198,19 +202,14 @@ fn internal_read_kdbx(file: &mut File, json_args: &str) -> OkpResult<db_service:
             .and(backup_file.sync_all())
             .and(backup_file.rewind())?;
 
-        if let Some(mtime) = info.as_ref().map(|f| f.last_modified).flatten() {
-            // milli to seconds
-            let mtime = mtime / 1000;
-
-            let bp = backup_file_name.ok_or(OkpError::DataError("Invalid backup file"))?;
-
-            // debug!("Before setting mtime {:?}", Path::new(&bp).metadata());
-
-            let _r = filetime::set_file_mtime(&bp, filetime::FileTime::from_unix_time(mtime, 0));
-            // debug!("Setting modified time of backup file status is {:?}", r);
-            
-            // debug!("After setting mtime {:?}", Path::new(&bp).metadata());
-        }
+        // The db file's modified time (milli to seconds) is recorded in the backup manifest
+        let mtime = info
+            .as_ref()
+            .map(|f| f.last_modified)
+            .flatten()
+            .map(|t| t / 1000);
+        let bp = backup_file_name.ok_or(OkpError::DataError("Invalid backup file"))?;
+        backup::record_backup(&db_file_name, &bp, BackupReason::OnRead, mtime);
     } else {
         debug!("Backup file already exists for this db");
     }
@@ -229,17 +228,34 @@ fn internal_read_kdbx(file: &mut File, json_args: &str) -> OkpResult<db_service:
     // debug!("In read_kdbx: Added to recent db use list and going to store biometric credentials if needed");
 
     // Store the credentials if we will be using biometric
-    let _r = biometric_auth::StoredCredential::store_credentials_on_check(
+    let refreshed = biometric_auth::StoredCredential::store_credentials_on_check(
         &db_file_name,
         &password,
         &key_file_name,
         biometric_auth_used,
-    );
+    )
+    .unwrap_or(false);
 
-    Ok(kdbx_loaded)
+    key_file::record_key_file_used(&db_file_name, &key_file_name);
+
+    // Any previous locked session of this db ends with this open
+    AppState::unlock_db_activity(&db_file_name);
+
+    Ok(KdbxLoadedEx::from(kdbx_loaded).set_biometric_credentials_refreshed(refreshed))
 }
 
 pub(crate) fn save_kdbx(file_args: FileArgs, overwrite: bool) -> ApiResponse {
+    // A db locked on its session timeout is not saved till it is unlocked
+    if let FileArgs::FileDecriptorWithFullFileName { full_file_name, .. }
+    | FileArgs::FullFileName { full_file_name } = &file_args
+    {
+        if let Err(e) = crate::session_timeout::check_and_touch_db_key(full_file_name) {
+            return ApiResponse::Failure {
+                result: InvokeResult::<()>::with_error(e).json_str(),
+            };
+        }
+    }
+
     let mut fd_used = false;
     let (mut writer, db_key, backup_file_name) = match file_args {
         FileArgs::FileDecriptorWithFullFileName {
@@ -298,6 +314,12 @@ pub(crate) fn save_kdbx(file_args: FileArgs, overwrite: bool) -> ApiResponse {
                             log::error!("Database checksum check failed");
                             // backup_file_name should have a valid back file name
                             if let Some(bkp_file_name) = backup_file_name.as_deref() {
+                                backup::record_backup(
+                                    &db_key,
+                                    bkp_file_name,
+                                    BackupReason::OnSaveError,
+                                    None,
+                                );
                                 AppState::add_last_backup_name_on_error(&db_key, bkp_file_name);
                             }
                             return_api_response_failure!(e)
@@ -351,11 +373,19 @@ pub(crate) fn save_kdbx(file_args: FileArgs, overwrite: bool) -> ApiResponse {
         Err(e) => InvokeResult::<()>::with_error(format!("{:?}", e).as_str()).json_str(),
     };
 
+    // The saved content in the backup file is copied to the mirrors of this db if any
+    db_mirror::push_file_to_mirrors(&db_key, backup_file_name.as_ref());
+
+    // The saved db file's modified time is recorded in the backup manifest
+    if let Some(bkp_file_name) = backup_file_name.as_deref() {
+        let md = AppState::uri_to_file_info(&db_key)
+            .and_then(|f| f.last_modified)
+            .map(|t| t / 1000);
+        backup::record_backup(&db_key, bkp_file_name, BackupReason::OnSave, md);
+    }
+
     backup::prune_backup_history_files(&db_key);
 
-    // Call file info to get modified time and update recent modified time
-    // Should we update the backup file also with the modified time as done in 'remote_storage::set_backup_modified_time'
-    
     // let info = AppState::uri_to_file_info(&db_key);
     // let md = info.map(|f| f.last_modified).flatten();
     // AppState::update_recent_db_modified_time(&db_key, &md);
@@ -396,6 +426,12 @@ pub(crate) fn write_to_backup_on_error(full_file_name_uri: String) -> ApiRespons
 
         // Need to store
         if let Some(bkp_file_name) = backup_file_name.as_deref() {
+            backup::record_backup(
+                &full_file_name_uri,
+                bkp_file_name,
+                BackupReason::OnSaveError,
+                None,
+            );
             AppState::add_last_backup_name_on_error(&full_file_name_uri, bkp_file_name);
             debug!("Added the backup file key on save error")
         }
@@ -471,12 +507,15 @@ pub(crate) fn copy_picked_key_file(file_args: FileArgs) -> String {
         //     _ => return Err(OkpError::UnexpectedError("Unsupported file args passed".into())),
         // };
 
-        // We open the incoming user picked file which is copied to the key file path
-        let OpenedFile {
-            mut file,
-            file_name,
-            ..
-        } = OpenedFile::open_to_read(&file_args)?;
+        // We read the incoming user picked file which is copied to the key file path
+        let picked = key_file::PickedKeyFile::open(&file_args)?;
+        let file_name = picked.file_name.clone();
+
+        // A mistyped or corrupted key file is rejected here so that it is not used to lock the db
+        let info = picked.format_info();
+        if let Some(reason) = info.invalid_reason {
+            return Err(OkpError::DataError(reason));
+        }
 
         let key_file_full_path = AppState::key_files_dir_path().join(&file_name);
         debug!(
@@ -492,9 +531,16 @@ pub(crate) fn copy_picked_key_file(file_args: FileArgs) -> String {
 
         // Copy the user picked file to the app's key file path
         let mut target_file = File::create(&key_file_full_path)?;
-        std::io::copy(&mut file, &mut target_file).and(target_file.sync_all())?;
+        picked.copy_to(&mut target_file)?;
+        target_file.sync_all()?;
 
-        debug!("Copied the key file {} locally", &file_name);
+        debug!(
+            "Copied the key file {} of format {:?} locally",
+            &file_name, info.format
+        );
+        if let Some(warning) = info.warning {
+            debug!("Key file {} is copied with the warning: {}", &file_name, warning);
+        }
 
         let full_file_name = key_file_full_path.as_os_str().to_string_lossy().to_string();
         Ok(KeyFileInfo {
@@ -522,6 +568,8 @@ pub(crate) fn upload_attachment(file_args: FileArgs, json_args: &str) -> Respons
             )));
         };
 
+        crate::session_timeout::check_and_touch_db_key(&db_key).map_err(OkpError::DataError)?;
+
         let info = db_service::read_entry_attachment(&db_key, &file_name, &mut file)?;
 
         Ok(info)
@@ -72,6 +72,12 @@ impl From<ApiCallbackError> for kp_service::error::Error {
 pub trait EventDispatch: Send + Sync {
     fn send_otp_update(&self, json_string: String) -> ApiCallbackResult<()>;
     fn send_tick_update(&self, json_string: String) -> ApiCallbackResult<()>;
+    // Progress of a remote storage file read or write
+    fn send_rs_transfer_progress(&self, json_string: String) -> ApiCallbackResult<()>;
+    // A push of the saved db content to one of its mirrors failed
+    fn send_db_mirror_push_failed(&self, json_string: String) -> ApiCallbackResult<()>;
+    // The session of an opened db timed out and the db is locked or closed
+    fn send_db_session_timeout(&self, json_string: String) -> ApiCallbackResult<()>;
 }
 
 // This trait represents a callback declared in 'db_service.udl'
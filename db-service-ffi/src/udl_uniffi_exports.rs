@@ -188,6 +188,8 @@ pub(crate) fn db_service_initialize(
     event_dispatcher::init_async_listeners();
     log::info!("event_dispatcher::init_async_listeners call completed");
 
+    crate::session_timeout::init_session_timeout_checker();
+
     // This service uses 'secure_enclave_cb_service'
     secure_store::init_rs_connection_configs_store();
     log::info!("secure_store::init_rs_connection_configs_store call done after callback setup in initialize_callback_services");
@@ -196,6 +198,19 @@ pub(crate) fn db_service_initialize(
     log::info!("remote_storage callback_service_provider::init_callback_service_provider call completed");
 }
 
+// Called from Swift or Kotlin with the file created or picked by the user to relocate an opened db to that file
+#[uniffi::export]
+pub(crate) fn relocate_database(file_args: FileArgs, json_args: &str) -> ResponseJson {
+    commands::result_json_str(crate::db_relocate::relocate_to_file(file_args, json_args))
+}
+
+// Called from Swift or Kotlin with the user picked key file to know its format before it is copied
+// using 'copy_picked_key_file'. The XML v2 key file's hash is verified here
+#[uniffi::export]
+pub(crate) fn check_picked_key_file(file_args: FileArgs) -> ResponseJson {
+    commands::result_json_str(crate::key_file::check_picked_key_file(&file_args))
+}
+
 // Called from Swift or Kotlin
 #[uniffi::export]
 pub(crate) fn handle_picked_file(file_args: FileArgs, json_args: &str) -> ResponseJson {
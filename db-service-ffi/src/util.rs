@@ -1,8 +1,9 @@
 use log::debug;
 use onekeepass_core::db_service::service_util::{self, string_to_simple_hash};
-use onekeepass_core::error::Result;
+use onekeepass_core::error::{Error, Result};
 use serde_json::de;
 use std::fs::{self, File};
+use std::io::Write;
 use std::os::unix::io::FromRawFd;
 use std::path::{Path, PathBuf};
 
@@ -437,11 +438,126 @@ pub fn remove_backup_history_file(full_file_uri_str: &str, full_backup_file_name
 
 */
 
+// The suffix of the copy of the file content before the last atomic write
+const PREV_FILE_SUFFIX: &str = "prev";
+
+fn sibling_file_path(file_path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let file_name = file_path
+        .file_name()
+        .map_or_else(String::default, |n| n.to_string_lossy().to_string());
+    file_path.with_file_name(format!("{}{}.{}", prefix, file_name, suffix))
+}
+
+// Writes the data to a temp file in the same dir, syncs it and then renames it to the target file so that
+// a crash or the OS killing the app in the middle of a write never leaves a truncated file.
+// The existing content is kept in '<file name>.prev' which is used by 'read_with_prev_fallback'
+pub fn atomic_write<P: AsRef<Path>>(file_path: P, data: &[u8]) -> Result<()> {
+    let file_path = file_path.as_ref();
+    let temp_file_path = sibling_file_path(file_path, ".", "tmp");
+
+    let write_temp = || -> Result<()> {
+        let mut file = File::create(&temp_file_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        Ok(())
+    };
+    if let Err(e) = write_temp() {
+        let _ = fs::remove_file(&temp_file_path);
+        return Err(e);
+    }
+
+    if file_path.exists() {
+        let prev_file_path = sibling_file_path(file_path, "", PREV_FILE_SUFFIX);
+        if let Err(e) = fs::copy(file_path, &prev_file_path) {
+            log::error!("Copying {:?} to the prev file failed {}", file_path, e);
+        }
+    }
+
+    fs::rename(&temp_file_path, file_path)?;
+
+    // The rename is persisted only after the dir is synced
+    if let Some(dir) = file_path.parent() {
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+
+    Ok(())
+}
+
+// Reads the file written using 'atomic_write'. When the file is missing or its content can not be parsed,
+// the content of the prev file is used. Returns the parsed value and the path of the file used or None
+// if neither of the files is found
+pub fn read_with_prev_fallback<P, T, F>(file_path: P, parse: F) -> Result<Option<(T, PathBuf)>>
+where
+    P: AsRef<Path>,
+    F: Fn(&[u8]) -> Result<T>,
+{
+    let file_path = file_path.as_ref();
+    let prev_file_path = sibling_file_path(file_path, "", PREV_FILE_SUFFIX);
+
+    let main_result = match fs::read(file_path) {
+        Ok(data) => match parse(&data) {
+            Ok(v) => return Ok(Some((v, file_path.to_path_buf()))),
+            Err(e) => Err(e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    };
+
+    if prev_file_path.exists() {
+        match fs::read(&prev_file_path).map_err(Error::from).and_then(|d| parse(&d)) {
+            Ok(v) => {
+                log::info!("The file {:?} could not be used and is recovered from the prev file", file_path);
+                return Ok(Some((v, prev_file_path)));
+            }
+            Err(e) => log::error!("Reading the prev file {:?} failed {}", &prev_file_path, e),
+        }
+    }
+
+    main_result
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
 
     #[test]
     fn verify1() {
         println!("A test module");
     }
+
+    #[test]
+    fn verify_atomic_write_and_prev_fallback() {
+        let dir = std::env::temp_dir().join("okp_atomic_write_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("test.json");
+
+        let parse = |d: &[u8]| -> Result<serde_json::Value> { Ok(serde_json::from_slice(d)?) };
+
+        assert!(read_with_prev_fallback(&file_path, parse).unwrap().is_none());
+
+        atomic_write(&file_path, br#"{"a":1}"#).unwrap();
+        atomic_write(&file_path, br#"{"a":2}"#).unwrap();
+        assert_eq!(
+            r#"{"a":1}"#,
+            fs::read_to_string(dir.join("test.json.prev")).unwrap()
+        );
+        assert!(!dir.join(".test.json.tmp").exists());
+
+        let (v, path) = read_with_prev_fallback(&file_path, parse).unwrap().unwrap();
+        assert_eq!(2, v["a"]);
+        assert_eq!(file_path, path);
+
+        // Truncated main file
+        fs::write(&file_path, r#"{"a":"#).unwrap();
+        let (v, path) = read_with_prev_fallback(&file_path, parse).unwrap().unwrap();
+        assert_eq!(1, v["a"]);
+        assert_eq!(dir.join("test.json.prev"), path);
+
+        // Both are bad
+        fs::write(dir.join("test.json.prev"), "").unwrap();
+        assert!(read_with_prev_fallback(&file_path, parse).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
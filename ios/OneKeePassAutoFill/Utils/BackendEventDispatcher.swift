@@ -25,4 +25,16 @@ class BackendEventDispatcher: EventDispatch {
     // cmnLogger.debug("Received sendOtpUpdate jsonString \(jsonString)")
     AutoFillEvents.sendEntryOtpUpdate(jsonString)
   }
+
+  // This is not used in autofill as remote storage dbs are not read here
+  func sendRsTransferProgress(_ jsonString: String) throws {
+  }
+
+  // This is not used in autofill as dbs are not saved here
+  func sendDbMirrorPushFailed(_ jsonString: String) throws {
+  }
+
+  // This is not used in autofill as the extension session is short lived
+  func sendDbSessionTimeout(_ jsonString: String) throws {
+  }
 }
@@ -25,6 +25,12 @@ public class OkpEvents: RCTEventEmitter {
   
   static let EVENT_APP_BECOMES_INACTIVE = "onAppBecomingInActive"
   
+  static let EVENT_RS_TRANSFER_PROGRESS = "onRsTransferProgress"
+  
+  static let EVENT_DB_MIRROR_PUSH_FAILED = "onDbMirrorPushFailed"
+  
+  static let EVENT_DB_SESSION_TIMEOUT = "onDbSessionTimeout"
+  
   
   override init() {
     super.init()
@@ -54,7 +60,10 @@ public class OkpEvents: RCTEventEmitter {
             OkpEvents.EVENT_APP_BECOMES_INACTIVE,
             OkpEvents.EVENT_ON_APPLICATION_URL,
             OkpEvents.EVENT_ON_TIME_TICK,
-            OkpEvents.EVENT_ENTRY_OTP_UPDATE]
+            OkpEvents.EVENT_ENTRY_OTP_UPDATE,
+            OkpEvents.EVENT_RS_TRANSFER_PROGRESS,
+            OkpEvents.EVENT_DB_MIRROR_PUSH_FAILED,
+            OkpEvents.EVENT_DB_SESSION_TIMEOUT]
   }
   
   // Called from SceneDelegate when user presses a .kdbx file
@@ -82,6 +91,21 @@ public class OkpEvents: RCTEventEmitter {
     instance?.sendEvent(withName: EVENT_ENTRY_OTP_UPDATE, body: jsonString)
   }
   
+  // Called from rust async fn through BackendEventDispatcher class
+  public static func sendRsTransferProgress(_ jsonString:String) {
+    instance?.sendEvent(withName: EVENT_RS_TRANSFER_PROGRESS, body: jsonString)
+  }
+  
+  // Called from rust through BackendEventDispatcher class when a db mirror push fails
+  public static func sendDbMirrorPushFailed(_ jsonString:String) {
+    instance?.sendEvent(withName: EVENT_DB_MIRROR_PUSH_FAILED, body: jsonString)
+  }
+  
+  // Called from rust through BackendEventDispatcher class when the session of an opened db times out
+  public static func sendDbSessionTimeout(_ jsonString:String) {
+    instance?.sendEvent(withName: EVENT_DB_SESSION_TIMEOUT, body: jsonString)
+  }
+  
   public static func sendAppBecomesActive() {
     instance?.sendEvent(withName: EVENT_APP_BECOMES_ACTIVE, body: "{}")
   }
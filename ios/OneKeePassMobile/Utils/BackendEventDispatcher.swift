@@ -23,4 +23,16 @@ class BackendEventDispatcher: EventDispatch {
     // cmnLogger.debug("Received sendOtpUpdate jsonString \(jsonString)")
     OkpEvents.sendEntryOtpUpdate(jsonString)
   }
+
+  func sendRsTransferProgress(_ jsonString: String) throws {
+    OkpEvents.sendRsTransferProgress(jsonString)
+  }
+
+  func sendDbMirrorPushFailed(_ jsonString: String) throws {
+    OkpEvents.sendDbMirrorPushFailed(jsonString)
+  }
+
+  func sendDbSessionTimeout(_ jsonString: String) throws {
+    OkpEvents.sendDbSessionTimeout(jsonString)
+  }
 }